@@ -1,3 +1,26 @@
 fn main() {
+    embed_mqtt_pems();
     embuild::espidf::sysenv::output();
 }
+
+/// Copy the PEM files named by MQTT_CA_CERT, MQTT_CLIENT_CERT and MQTT_CLIENT_KEY
+/// into OUT_DIR, where `mqtt.rs` embeds them (empty files when a variable is unset)
+fn embed_mqtt_pems() {
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    for (var, file) in [
+        ("MQTT_CA_CERT", "mqtt_ca.pem"),
+        ("MQTT_CLIENT_CERT", "mqtt_client_cert.pem"),
+        ("MQTT_CLIENT_KEY", "mqtt_client_key.pem"),
+    ] {
+        println!("cargo:rerun-if-env-changed={}", var);
+        let pem = match std::env::var(var) {
+            Ok(path) => {
+                println!("cargo:rerun-if-changed={}", path);
+                std::fs::read(&path)
+                    .unwrap_or_else(|e| panic!("{}: cannot read {}: {}", var, path, e))
+            }
+            Err(_) => Vec::new(),
+        };
+        std::fs::write(out_dir.join(file), pem).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+use esp32_water_meter::cli::{
+    CliCommand, CommandHandler, CommandParser, MeterCommand, MeterCommandHandler,
+    MeterCommandParser, Terminal, UartIo,
+};
+use esp32_water_meter::meter::{MeterHandler, MeterStorage};
+use esp32_water_meter::mtu::{GpioMtuTimerV2, MtuConfig};
+use esp32_water_meter::role::{DeviceRole, RoleStore};
+use esp_idf_hal::delay::FreeRtos;
+use esp_idf_hal::gpio::{Input, Output, PinDriver};
+use esp_idf_hal::peripherals::Peripherals;
+use esp_idf_hal::uart::{config::Config as UartConfig, UartDriver};
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_svc::sys;
+use std::sync::Arc;
+
+/// Role-specific command handling for the shared CLI loop
+enum RoleCli {
+    Mtu(Box<CommandHandler>),
+    Meter(MeterCommandHandler),
+}
+
+impl RoleCli {
+    fn execute(&mut self, terminal: &mut Terminal<UartIo>, command_line: &str) {
+        match self {
+            RoleCli::Mtu(handler) => {
+                let command = CommandParser::parse_command(command_line);
+                let command_clone = command.clone();
+                match handler.execute_console(command) {
+                    Ok(response) => {
+                        if !response.is_empty() {
+                            let _ = terminal.write_paged(&response);
+                        }
+                    }
+                    Err(_) => {
+                        log::warn!("CLI command execution error");
+                        let _ = terminal.write_line("Command execution error.");
+                    }
+                }
+                match command_clone {
+                    CliCommand::Help => {
+                        let _ = terminal.show_help();
+                        let _ = terminal.write_line(ROLE_HELP);
+                    }
+                    CliCommand::Clear => {
+                        let _ = terminal.clear_screen();
+                    }
+                    CliCommand::Pager(lines) => {
+                        terminal.set_page_lines(lines);
+                    }
+                    CliCommand::Color(on) => {
+                        terminal.set_color(on);
+                    }
+                    _ => {}
+                }
+            }
+            RoleCli::Meter(handler) => {
+                let command = MeterCommandParser::parse_command(command_line);
+                let command_clone = command.clone();
+                match handler.execute_command(command) {
+                    Ok(response) => {
+                        if !response.is_empty() {
+                            let _ = terminal.write_paged(&response);
+                        }
+                    }
+                    Err(_) => {
+                        log::warn!("CLI command execution error");
+                        let _ = terminal.write_line("Command execution error.");
+                    }
+                }
+                match command_clone {
+                    MeterCommand::Help => {
+                        let _ = terminal.show_meter_help();
+                        let _ = terminal.write_line(ROLE_HELP);
+                    }
+                    MeterCommand::Clear => {
+                        let _ = terminal.clear_screen();
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+const ROLE_HELP: &str = "  role [mtu|meter] - Show or switch device role (saves and reboots)";
+
+/// Handle the `role` command shared by both roles. Returns false if the line isn't a role command.
+fn handle_role_command(
+    terminal: &mut Terminal<UartIo>,
+    role_store: &mut Option<RoleStore>,
+    current: DeviceRole,
+    command_line: &str,
+) -> bool {
+    let mut parts = command_line.split_whitespace();
+    if parts.next() != Some("role") {
+        return false;
+    }
+
+    match parts.next() {
+        None => {
+            let _ = terminal.write_line(&format!("Current role: {}", current.name()));
+        }
+        Some(name) => match DeviceRole::from_name(name) {
+            Some(role) if role == current => {
+                let _ = terminal.write_line(&format!("Already running as {}", role.name()));
+            }
+            Some(role) => match role_store {
+                Some(store) => match store.save(role) {
+                    Ok(_) => {
+                        let _ = terminal
+                            .write_line(&format!("Role set to {} - rebooting...", role.name()));
+                        FreeRtos::delay_ms(100); // Let the UART drain
+                        unsafe {
+                            sys::esp_restart();
+                        }
+                    }
+                    Err(e) => {
+                        let _ = terminal.write_line(&format!("Failed to save role: {:?}", e));
+                    }
+                },
+                None => {
+                    let _ = terminal.write_line("NVS unavailable - cannot change role");
+                }
+            },
+            None => {
+                let _ = terminal.write_line("Usage: role [mtu|meter]");
+            }
+        },
+    }
+    true
+}
+
+fn main() -> anyhow::Result<()> {
+    // Initialize ESP-IDF system services
+    sys::link_patches();
+
+    // Initialize logging
+    esp_idf_svc::log::EspLogger::initialize_default();
+
+    log::info!("ESP32 Water Meter Dual-Role Firmware");
+    log::info!("Initializing...");
+
+    let peripherals = Peripherals::take()?;
+    let nvs = EspDefaultNvsPartition::take()?;
+
+    log::info!("✅ ESP32 initialized with ESP-IDF");
+
+    // Select role from NVS (defaults to MTU)
+    let mut role_store = match RoleStore::new(nvs.clone()) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            log::warn!("⚠️  NVS unavailable, role fixed to MTU: {:?}", e);
+            None
+        }
+    };
+    let role = match role_store.as_ref().map(|s| s.load()) {
+        Some(Ok(Some(role))) => role,
+        Some(Err(e)) => {
+            log::warn!("⚠️  Failed to load role, defaulting to MTU: {:?}", e);
+            DeviceRole::Mtu
+        }
+        _ => DeviceRole::Mtu,
+    };
+    log::info!("🎭 Role: {}", role.name());
+
+    // Initialize UART0 for CLI (USB-C connection)
+    log::info!("Initializing UART0 for CLI (USB-C)...");
+    let uart_config = UartConfig::new().baudrate(115200.into());
+    let mut uart = UartDriver::new(
+        peripherals.uart0,
+        peripherals.pins.gpio1, // TX (U0TXD)
+        peripherals.pins.gpio3, // RX (U0RXD)
+        Option::<esp_idf_hal::gpio::Gpio0>::None,
+        Option::<esp_idf_hal::gpio::Gpio0>::None,
+        &uart_config,
+    )?;
+    let (uart_tx, uart_rx) = uart.split();
+    log::info!("✅ UART0 initialized (115200 baud)");
+
+    let mut role_cli = match role {
+        DeviceRole::Mtu => {
+            log::info!("Initializing MTU GPIO pins...");
+            log::info!("  Clock pin: GPIO4 (output, starting LOW - no power to meter)");
+            log::info!("  Data pin:  GPIO5 (input)");
+
+            let mut clock_pin = PinDriver::output(peripherals.pins.gpio4)?;
+            clock_pin.set_low()?;
+            let data_pin = PinDriver::input(peripherals.pins.gpio5)?;
+
+            // SAFETY: Pins are owned by the MTU thread for the entire program lifetime
+            let clock_pin_static: PinDriver<'static, esp_idf_hal::gpio::Gpio4, Output> =
+                unsafe { core::mem::transmute(clock_pin) };
+            let data_pin_static: PinDriver<'static, esp_idf_hal::gpio::Gpio5, Input> =
+                unsafe { core::mem::transmute(data_pin) };
+
+            let mtu = Arc::new(GpioMtuTimerV2::new(MtuConfig::default()));
+            let mtu_cmd_sender = GpioMtuTimerV2::spawn_mtu_thread(
+                Arc::clone(&mtu),
+                clock_pin_static,
+                data_pin_static,
+                peripherals.timer00,
+            );
+            log::info!("✅ MTU background thread spawned");
+
+            RoleCli::Mtu(Box::new(
+                CommandHandler::new().with_mtu(mtu, mtu_cmd_sender),
+            ))
+        }
+        DeviceRole::Meter => {
+            log::info!("Initializing Meter GPIO pins...");
+            log::info!("  Clock pin: GPIO4 (input with interrupt)");
+            log::info!("  Data pin:  GPIO5 (output, starting HIGH - idle state)");
+
+            let clock_pin = PinDriver::input(peripherals.pins.gpio4)?;
+            let mut data_pin = PinDriver::output(peripherals.pins.gpio5)?;
+            data_pin.set_high()?;
+
+            // SAFETY: Pins are owned by the Meter thread for the entire program lifetime
+            let clock_pin_static: PinDriver<'static, esp_idf_hal::gpio::Gpio4, Input> =
+                unsafe { core::mem::transmute(clock_pin) };
+            let data_pin_static: PinDriver<'static, esp_idf_hal::gpio::Gpio5, Output> =
+                unsafe { core::mem::transmute(data_pin) };
+
+            let storage = MeterStorage::new(nvs).ok();
+            let config = storage
+                .as_ref()
+                .and_then(|s| s.load().ok().flatten())
+                .unwrap_or_default();
+            let meter = Arc::new(MeterHandler::new(config));
+            MeterHandler::spawn_meter_thread(Arc::clone(&meter), clock_pin_static, data_pin_static);
+            log::info!("✅ Meter background thread spawned");
+
+            let mut handler = MeterCommandHandler::new().with_meter(meter);
+            if let Some(storage) = storage {
+                handler = handler.with_storage(storage);
+            }
+            RoleCli::Meter(handler)
+        }
+    };
+
+    // Initialize CLI components
+    let mut terminal = Terminal::new(uart_tx, uart_rx);
+    if role == DeviceRole::Meter {
+        terminal = terminal.with_completion(MeterCommandParser::autocomplete);
+    }
+    log::info!("✅ CLI initialized");
+
+    // Send welcome message
+    terminal.write_line("")?;
+    match role {
+        DeviceRole::Mtu => {
+            terminal.write_line("ESP32 Water Meter Dual-Role Firmware - MTU role")?
+        }
+        DeviceRole::Meter => {
+            terminal.write_line("ESP32 Water Meter Dual-Role Firmware - Meter role")?
+        }
+    }
+    terminal.write_line("Type 'help' for available commands")?;
+    terminal.write_line("Use 'role <mtu|meter>' to switch roles (reboots)")?;
+    terminal.print_prompt()?;
+
+    log::info!("Entering CLI loop...");
+
+    // Main CLI loop
+    loop {
+        match terminal.read_char() {
+            Ok(Some(ch)) => match terminal.handle_char(ch) {
+                Ok(Some(command_line)) => {
+                    if !handle_role_command(&mut terminal, &mut role_store, role, &command_line) {
+                        role_cli.execute(&mut terminal, &command_line);
+                    }
+                    let _ = terminal.print_prompt();
+                }
+                Ok(None) => {
+                    // Character processed but no complete command yet
+                }
+                Err(_) => {
+                    log::warn!("Terminal input error");
+                    let _ = terminal.write_line("Input error");
+                    let _ = terminal.print_prompt();
+                }
+            },
+            Ok(None) => {
+                // No data available, small delay to avoid busy loop
+                FreeRtos::delay_ms(10);
+            }
+            Err(_) => {
+                // UART error, small delay
+                FreeRtos::delay_ms(10);
+            }
+        }
+    }
+}
@@ -1,9 +1,10 @@
 use esp32_water_meter::cli::{MeterCommand, MeterCommandHandler, MeterCommandParser, Terminal};
-use esp32_water_meter::meter::{MeterConfig, MeterHandler};
+use esp32_water_meter::meter::{MeterConfig, MeterHandler, MeterStorage};
 use esp_idf_hal::delay::FreeRtos;
 use esp_idf_hal::gpio::{Input, Output, PinDriver};
 use esp_idf_hal::peripherals::Peripherals;
 use esp_idf_hal::uart::{config::Config as UartConfig, UartDriver};
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
 use esp_idf_svc::sys;
 use std::sync::Arc;
 
@@ -58,8 +59,30 @@ fn main() -> anyhow::Result<()> {
     let data_pin_static: PinDriver<'static, esp_idf_hal::gpio::Gpio5, Output> =
         unsafe { core::mem::transmute(data_pin) };
 
-    // Create Meter instance with default config
-    let config = MeterConfig::default();
+    // Load saved meter config from NVS (falls back to defaults on first boot)
+    let nvs = EspDefaultNvsPartition::take()?;
+    let storage = match MeterStorage::new(nvs) {
+        Ok(storage) => Some(storage),
+        Err(e) => {
+            log::warn!("⚠️  NVS unavailable, config changes won't persist: {:?}", e);
+            None
+        }
+    };
+
+    let config = match storage.as_ref().map(|s| s.load()) {
+        Some(Ok(Some(config))) => {
+            log::info!("✅ Meter config loaded from NVS");
+            config
+        }
+        Some(Err(e)) => {
+            log::warn!("⚠️  Failed to load meter config, using defaults: {:?}", e);
+            MeterConfig::default()
+        }
+        _ => {
+            log::info!("No saved meter config, using defaults");
+            MeterConfig::default()
+        }
+    };
     let meter = Arc::new(MeterHandler::new(config));
 
     log::info!("✅ Meter GPIO pins configured");
@@ -74,8 +97,12 @@ fn main() -> anyhow::Result<()> {
     log::info!("✅ Meter background thread spawned");
 
     // Initialize CLI components
-    let mut terminal = Terminal::new(uart_tx, uart_rx);
+    let mut terminal =
+        Terminal::new(uart_tx, uart_rx).with_completion(MeterCommandParser::autocomplete);
     let mut command_handler = MeterCommandHandler::new().with_meter(Arc::clone(&meter));
+    if let Some(storage) = storage {
+        command_handler = command_handler.with_storage(storage);
+    }
 
     log::info!("✅ CLI initialized");
 
@@ -106,7 +133,7 @@ fn main() -> anyhow::Result<()> {
                         match command_handler.execute_command(command) {
                             Ok(response) => {
                                 if !response.is_empty() {
-                                    let _ = terminal.write_line(&response);
+                                    let _ = terminal.write_paged(&response);
                                 }
                             }
                             Err(_) => {
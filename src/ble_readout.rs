@@ -0,0 +1,193 @@
+//! BLE GATT service for walk-by readout
+//!
+//! For sites without WiFi: a phone (e.g. nRF Connect) connects to
+//! "WaterMeter-XXXX" and reads or subscribes to the last reading and the
+//! statistics, and can start an MTU read by writing to the control
+//! characteristic:
+//!
+//! ```text
+//! reading   read/notify  V;RB00000200;IB61564400;...   ("none" before the first read)
+//! stats     read/notify  {"register":200,"successful":5,"corrupted":0,"baud_rate":1200,
+//!                         "reading":false,"age_secs":42,"uptime_secs":86412}
+//! control   write        "read" or "read <secs>" (default 30 s)
+//! ```
+
+use crate::mtu::{register_value, GpioMtuTimerV2, MtuCommand};
+use crate::telemetry;
+use crate::wifi::ble_device_name;
+use anyhow::Result;
+use esp32_nimble::utilities::BleUuid;
+use esp32_nimble::{uuid128, BLEAdvertisementData, BLEDevice, NimbleProperties};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const SERVICE_UUID: BleUuid = uuid128!("6e400101-7b3a-4c57-9a3e-5741544552ab");
+/// Read/notify: last meter message
+const READING_UUID: BleUuid = uuid128!("6e400102-7b3a-4c57-9a3e-5741544552ab");
+/// Read/notify: statistics JSON
+const STATS_UUID: BleUuid = uuid128!("6e400103-7b3a-4c57-9a3e-5741544552ab");
+/// Write: "read [secs]"
+const CONTROL_UUID: BleUuid = uuid128!("6e400104-7b3a-4c57-9a3e-5741544552ab");
+
+/// How often the characteristic values are refreshed
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+const DEFAULT_READ_SECS: u64 = 30;
+const MAX_READ_SECS: u64 = 300;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BleSettings {
+    /// Applied at boot; run the readout service (not while BLE provisioning is active)
+    pub readout: bool,
+    /// 6-digit passkey for pairing with BLE provisioning, None = random at
+    /// each boot (printed on the console)
+    #[serde(default)]
+    pub passkey: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct ReadoutStats {
+    register: Option<u32>,
+    successful: u32,
+    corrupted: u32,
+    baud_rate: u32,
+    reading: bool,
+    /// Seconds since the last successful read (None = none since boot)
+    age_secs: Option<u64>,
+    uptime_secs: u64,
+}
+
+pub struct BleReadout;
+
+impl BleReadout {
+    /// Register the GATT service, start advertising and keep the values up to
+    /// date on a background thread
+    pub fn start(mtu: Arc<GpioMtuTimerV2>, mtu_cmd_sender: Sender<MtuCommand>) -> Result<()> {
+        let device_name = ble_device_name();
+        let ble_device = BLEDevice::take();
+        BLEDevice::set_device_name(&device_name)
+            .map_err(|e| anyhow::anyhow!("BLE device name: {:?}", e))?;
+
+        let server = ble_device.get_server();
+        server.advertise_on_disconnect(true);
+        server.on_connect(|_, desc| {
+            log::info!("📡 BLE: Readout client connected ({})", desc.address());
+        });
+
+        let service = server.create_service(SERVICE_UUID);
+        let reading = service.lock().create_characteristic(
+            READING_UUID,
+            NimbleProperties::READ | NimbleProperties::NOTIFY,
+        );
+        reading.lock().set_value(b"none");
+        let stats = service.lock().create_characteristic(
+            STATS_UUID,
+            NimbleProperties::READ | NimbleProperties::NOTIFY,
+        );
+        let control = service
+            .lock()
+            .create_characteristic(CONTROL_UUID, NimbleProperties::WRITE);
+        let control_mtu = Arc::clone(&mtu);
+        control.lock().on_write(move |args| {
+            let command = String::from_utf8_lossy(args.recv_data())
+                .trim()
+                .to_lowercase();
+            let duration_secs = match command.strip_prefix("read") {
+                Some("") => Some(DEFAULT_READ_SECS),
+                Some(secs) => secs
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|secs| (1..=MAX_READ_SECS).contains(secs)),
+                None => None,
+            };
+            match duration_secs {
+                Some(_) if control_mtu.is_running() => {
+                    log::info!("📡 BLE: MTU read already in progress");
+                }
+                Some(duration_secs) => {
+                    log::info!("📡 BLE: Starting {}s MTU read", duration_secs);
+                    let _ = mtu_cmd_sender.send(MtuCommand::Start { duration_secs });
+                }
+                None => {
+                    log::warn!("⚠️  BLE: Unknown control command '{}'", command);
+                    args.reject();
+                }
+            }
+        });
+
+        ble_device
+            .get_advertising()
+            .lock()
+            .set_data(
+                BLEAdvertisementData::new()
+                    .name(&device_name)
+                    .add_service_uuid(SERVICE_UUID),
+            )
+            .map_err(|e| anyhow::anyhow!("BLE advertising data: {:?}", e))?;
+        ble_device
+            .get_advertising()
+            .lock()
+            .start()
+            .map_err(|e| anyhow::anyhow!("BLE advertising: {:?}", e))?;
+
+        std::thread::Builder::new()
+            .stack_size(4096)
+            .name("ble_readout".to_string())
+            .spawn(move || {
+                let (mut last_successful, _, _) = mtu.get_stats();
+                let mut last_read_at: Option<Instant> = None;
+                let mut shown_message = None;
+                let mut notified_stats = None;
+                loop {
+                    let (successful, corrupted, _) = mtu.get_stats();
+                    if successful != last_successful {
+                        last_successful = successful;
+                        last_read_at = Some(Instant::now());
+                    }
+                    let message = mtu.get_last_message();
+                    let current = ReadoutStats {
+                        register: message.as_deref().and_then(register_value),
+                        successful,
+                        corrupted,
+                        baud_rate: mtu.get_baud_rate(),
+                        reading: mtu.is_running(),
+                        age_secs: last_read_at.map(|at| at.elapsed().as_secs()),
+                        uptime_secs: telemetry::uptime_secs(),
+                    };
+
+                    if message.is_some() && message != shown_message {
+                        if let Some(ref text) = message {
+                            reading
+                                .lock()
+                                .set_value(text.trim_end().as_bytes())
+                                .notify();
+                        }
+                        shown_message = message;
+                    }
+                    // Ages and uptime tick every second: notify only when the rest changes
+                    let key = (
+                        current.register,
+                        current.successful,
+                        current.corrupted,
+                        current.baud_rate,
+                        current.reading,
+                    );
+                    if let Ok(json) = serde_json::to_string(&current) {
+                        let mut stats = stats.lock();
+                        stats.set_value(json.as_bytes());
+                        if notified_stats != Some(key) {
+                            stats.notify();
+                            notified_stats = Some(key);
+                        }
+                    }
+                    std::thread::sleep(UPDATE_INTERVAL);
+                }
+            })?;
+
+        log::info!("✅ BLE: Readout service advertising as '{}'", device_name);
+        Ok(())
+    }
+}
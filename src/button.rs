@@ -0,0 +1,120 @@
+//! Push button for field verification without a laptop
+//!
+//! A short press starts an MTU read (published like any other); holding the
+//! button for `LONG_PRESS` restarts into provisioning mode. The button pulls
+//! the pin to GND. In deep-sleep mode it also wakes the device (ext0), so it
+//! must be on an RTC-capable GPIO.
+
+use anyhow::Result;
+use esp_idf_hal::gpio::{AnyInputPin, Input, PinDriver, Pull};
+use esp_idf_svc::sys;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// RTC-capable GPIOs not used by the MTU (4, 5) or the console UART.
+/// 34-39 have no internal pull-up and need an external resistor.
+pub const BUTTON_GPIOS: &[u8] = &[
+    0, 2, 12, 13, 14, 15, 25, 26, 27, 32, 33, 34, 35, 36, 37, 38, 39,
+];
+
+/// Level changes shorter than this are contact bounce
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Hold time for the provisioning action
+pub const LONG_PRESS: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ButtonSettings {
+    /// Applied at boot; None = no button
+    pub gpio: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEvent {
+    /// Released before `LONG_PRESS`
+    ShortPress,
+    /// Held for `LONG_PRESS` (reported once, while still held)
+    LongPress,
+}
+
+pub struct Button {
+    pin: PinDriver<'static, AnyInputPin, Input>,
+    gpio: u8,
+    /// Debounced state (true = pressed)
+    pressed: bool,
+    /// Raw level at the last poll and when it last changed
+    raw: bool,
+    raw_since: Instant,
+    pressed_at: Instant,
+    /// No event for the current press (already reported, or held at boot)
+    consumed: bool,
+}
+
+impl Button {
+    pub fn new(gpio: u8) -> Result<Self> {
+        if !BUTTON_GPIOS.contains(&gpio) {
+            anyhow::bail!("GPIO{} cannot be used for the button", gpio);
+        }
+        // Safety: BUTTON_GPIOS excludes every pin claimed elsewhere by the MTU app
+        let mut pin = PinDriver::input(unsafe { AnyInputPin::new(gpio as i32) })?;
+        if gpio < 34 {
+            pin.set_pull(Pull::Up)?;
+        }
+        let pressed = pin.is_low();
+        log::info!("🔘 Button on GPIO{}", gpio);
+        Ok(Self {
+            pin,
+            gpio,
+            pressed,
+            raw: pressed,
+            raw_since: Instant::now(),
+            pressed_at: Instant::now(),
+            // A press that woke the device already triggered the scheduled read
+            consumed: pressed,
+        })
+    }
+
+    pub fn gpio(&self) -> u8 {
+        self.gpio
+    }
+
+    /// Call from the main loop (every few ms); returns debounced presses
+    pub fn poll(&mut self) -> Option<ButtonEvent> {
+        let raw = self.pin.is_low();
+        if raw != self.raw {
+            self.raw = raw;
+            self.raw_since = Instant::now();
+        }
+
+        if self.raw != self.pressed && self.raw_since.elapsed() >= DEBOUNCE {
+            self.pressed = self.raw;
+            if self.pressed {
+                self.pressed_at = Instant::now();
+                self.consumed = false;
+            } else if !self.consumed {
+                self.consumed = true;
+                return Some(ButtonEvent::ShortPress);
+            }
+        }
+
+        if self.pressed && !self.consumed && self.pressed_at.elapsed() >= LONG_PRESS {
+            self.consumed = true;
+            return Some(ButtonEvent::LongPress);
+        }
+        None
+    }
+
+    /// Wake from deep sleep when the button is pressed
+    pub fn enable_wakeup(&self) -> Result<()> {
+        let gpio = self.gpio as sys::gpio_num_t;
+        unsafe {
+            sys::esp!(sys::esp_sleep_enable_ext0_wakeup(gpio, 0))?;
+            if self.gpio < 34 {
+                // The digital pull-up is off in deep sleep
+                sys::esp!(sys::rtc_gpio_pullup_en(gpio))?;
+                sys::esp!(sys::rtc_gpio_pulldown_dis(gpio))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,389 @@
+//! Cellular fallback transport: LTE-M/NB-IoT modem over PPP on UART1
+//!
+//! For sites where WiFi is unreliable: a modem with a standard 3GPP AT
+//! command set (SIM7080G, BG95, SARA-R4 and similar) is dialled up with PPP
+//! when WiFi can't connect (see `connectivity::FailoverLink`). Wiring:
+//! modem RX on GPIO27 (ESP TX), modem TX on GPIO26 (ESP RX) and PWRKEY on
+//! GPIO25 (pulsed high to power the modem on, e.g. through an NPN stage).
+//!
+//! Connect: wake the modem → unlock the SIM (`cellular.pin`) → set the APN
+//! (`cellular.apn`) → wait for LTE registration → `ATD*99#` → PPP. Disconnect
+//! ends the PPP session and hangs up; the modem stays registered so the next
+//! connect is quick.
+
+use crate::network::NetworkLink;
+use anyhow::Result;
+use esp_idf_hal::delay::TickType;
+use esp_idf_hal::gpio::{Gpio25, Gpio26, Gpio27, Output, PinDriver};
+use esp_idf_hal::uart::{config::Config as UartConfig, UartDriver, UART1};
+use esp_idf_svc::netif::{EspNetif, EspNetifDriver, NetifConfiguration};
+use esp_idf_svc::sys::{self, EspError};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// GPIOs taken by the modem (PWRKEY, RX, TX)
+pub const CELLULAR_GPIOS: &[u8] = &[25, 26, 27];
+
+/// Plain AT commands
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+/// Network registration after power-on can take minutes on NB-IoT
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(180);
+const REGISTRATION_POLL: Duration = Duration::from_secs(2);
+/// `ATD*99#` until CONNECT
+const DIAL_TIMEOUT: Duration = Duration::from_secs(30);
+/// PPP negotiation until an IP address is assigned
+const PPP_UP_TIMEOUT: Duration = Duration::from_secs(30);
+/// PWRKEY pulse and boot time of the modem
+const POWER_ON_PULSE: Duration = Duration::from_millis(1200);
+const POWER_ON_BOOT: Duration = Duration::from_secs(6);
+/// Silence required around the `+++` escape sequence
+const ESCAPE_GUARD: Duration = Duration::from_millis(1100);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CellularSettings {
+    /// Applied at boot; empty disables the modem
+    pub apn: heapless::String<64>,
+    /// SIM PIN (empty = SIM not locked)
+    pub pin: heapless::String<8>,
+    pub baud_rate: u32,
+}
+
+impl Default for CellularSettings {
+    fn default() -> Self {
+        Self {
+            apn: heapless::String::new(),
+            pin: heapless::String::new(),
+            baud_rate: 115_200,
+        }
+    }
+}
+
+/// Modem pins
+pub struct CellularPins {
+    pub tx: Gpio27,
+    pub rx: Gpio26,
+    pub pwrkey: Gpio25,
+}
+
+type PppTx = Box<dyn FnMut(&[u8]) -> Result<(), EspError> + Send + 'static>;
+
+struct PppDriver(EspNetifDriver<'static, PppTx>);
+
+/// Read by the PPP receive thread, written by PPP output and AT commands
+struct ModemUart(UartDriver<'static>);
+
+// SAFETY: the driver only calls into the ESP-IDF netif/lwIP API and the UART
+// driver, which are thread-safe
+unsafe impl Send for PppDriver {}
+unsafe impl Sync for PppDriver {}
+unsafe impl Send for ModemUart {}
+unsafe impl Sync for ModemUart {}
+
+pub struct CellularModem {
+    settings: CellularSettings,
+    uart: Arc<ModemUart>,
+    pwrkey: PinDriver<'static, Gpio25, Output>,
+    ppp: Arc<PppDriver>,
+    /// UART bytes go to PPP while set, to the AT command parser otherwise
+    data_mode: Arc<AtomicBool>,
+    /// From `AT+CSQ` before dialling (no AT commands while in data mode)
+    rssi: Option<i8>,
+}
+
+impl CellularModem {
+    /// Set up UART1, the PWRKEY pin and the PPP interface; the modem is not
+    /// dialled until `connect`
+    pub fn new(settings: &CellularSettings, uart: UART1, pins: CellularPins) -> Result<Self> {
+        info!(
+            "📶 Cellular: Modem on UART1 at {} bps (TX GPIO27, RX GPIO26, PWRKEY GPIO25)",
+            settings.baud_rate
+        );
+        let uart = Arc::new(ModemUart(UartDriver::new(
+            uart,
+            pins.tx,
+            pins.rx,
+            Option::<Gpio26>::None,
+            Option::<Gpio27>::None,
+            &UartConfig::new().baudrate(settings.baud_rate.into()),
+        )?));
+        let mut pwrkey = PinDriver::output(pins.pwrkey)?;
+        pwrkey.set_low()?;
+
+        let tx_uart = Arc::clone(&uart);
+        let tx: PppTx = Box::new(move |data: &[u8]| tx_uart.0.write(data).map(|_| ()));
+        let netif = EspNetif::new_with_conf(&NetifConfiguration::ppp_default_client())?;
+        let ppp = Arc::new(PppDriver(EspNetifDriver::new_nonstatic(
+            netif,
+            |_| Ok(()),
+            tx,
+        )?));
+
+        let data_mode = Arc::new(AtomicBool::new(false));
+        let rx_uart = Arc::clone(&uart);
+        let rx_ppp = Arc::clone(&ppp);
+        let rx_data_mode = Arc::clone(&data_mode);
+        std::thread::Builder::new()
+            .stack_size(4096)
+            .name("ppp_rx".to_string())
+            .spawn(move || {
+                let mut buf = [0u8; 256];
+                let timeout = TickType::new_millis(50).ticks();
+                loop {
+                    if !rx_data_mode.load(Ordering::Acquire) {
+                        std::thread::sleep(Duration::from_millis(50));
+                        continue;
+                    }
+                    match rx_uart.0.read(&mut buf, timeout) {
+                        Ok(0) => {}
+                        Ok(n) => {
+                            if let Err(e) = rx_ppp.0.rx(&buf[..n]) {
+                                log::warn!("⚠️  Cellular: PPP input failed: {:?}", e);
+                            }
+                        }
+                        Err(e) => {
+                            log::warn!("⚠️  Cellular: UART read failed: {:?}", e);
+                            std::thread::sleep(Duration::from_millis(100));
+                        }
+                    }
+                }
+            })?;
+
+        info!("✅ Cellular: Modem interface ready (APN {})", settings.apn);
+        Ok(Self {
+            settings: settings.clone(),
+            uart,
+            pwrkey,
+            ppp,
+            data_mode,
+            rssi: None,
+        })
+    }
+
+    /// Send an AT command and collect the response up to OK, ERROR or
+    /// CONNECT; ERROR is returned as an error
+    fn command(&self, command: &str, timeout: Duration) -> Result<String> {
+        self.uart.0.flush_read()?;
+        self.uart.0.write(command.as_bytes())?;
+        self.uart.0.write(b"\r")?;
+
+        let deadline = Instant::now() + timeout;
+        let mut response = String::new();
+        let mut buf = [0u8; 64];
+        while Instant::now() < deadline {
+            let n = self
+                .uart
+                .0
+                .read(&mut buf, TickType::new_millis(100).ticks())?;
+            response.push_str(&String::from_utf8_lossy(&buf[..n]));
+            if response.contains("\r\nOK\r\n") || response.contains("CONNECT") {
+                return Ok(response);
+            }
+            if response.contains("ERROR") {
+                return Err(anyhow::anyhow!(
+                    "Cellular: {} failed: {}",
+                    command,
+                    response.trim()
+                ));
+            }
+        }
+        Err(anyhow::anyhow!("Cellular: No response to {}", command))
+    }
+
+    /// Check the modem answers, pulsing PWRKEY if it looks powered off
+    fn wake(&mut self) -> Result<()> {
+        for _ in 0..3 {
+            if self.command("AT", Duration::from_millis(500)).is_ok() {
+                return Ok(());
+            }
+        }
+        info!("🔌 Cellular: No answer, pulsing PWRKEY...");
+        self.pwrkey.set_high()?;
+        std::thread::sleep(POWER_ON_PULSE);
+        self.pwrkey.set_low()?;
+        std::thread::sleep(POWER_ON_BOOT);
+        for _ in 0..10 {
+            if self.command("AT", Duration::from_millis(500)).is_ok() {
+                return Ok(());
+            }
+        }
+        Err(anyhow::anyhow!("Cellular: Modem not responding"))
+    }
+
+    fn unlock_sim(&self) -> Result<()> {
+        let status = self.command("AT+CPIN?", COMMAND_TIMEOUT)?;
+        if status.contains("READY") {
+            return Ok(());
+        }
+        if !status.contains("SIM PIN") {
+            return Err(anyhow::anyhow!(
+                "Cellular: SIM not ready: {}",
+                status.trim()
+            ));
+        }
+        if self.settings.pin.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Cellular: SIM is PIN locked, set cellular.pin"
+            ));
+        }
+        info!("🔐 Cellular: Unlocking SIM...");
+        self.command(
+            &format!("AT+CPIN=\"{}\"", self.settings.pin),
+            COMMAND_TIMEOUT,
+        )?;
+        Ok(())
+    }
+
+    fn wait_registered(&self) -> Result<()> {
+        let deadline = Instant::now() + REGISTRATION_TIMEOUT;
+        info!("⏳ Cellular: Waiting for network registration...");
+        while Instant::now() < deadline {
+            // +CEREG: <n>,<stat>: 1 = home network, 5 = roaming
+            let status = self.command("AT+CEREG?", COMMAND_TIMEOUT)?;
+            let stat = status
+                .split("+CEREG:")
+                .nth(1)
+                .and_then(|rest| rest.lines().next())
+                .and_then(|fields| fields.split(',').nth(1))
+                .map(|stat| stat.trim());
+            if matches!(stat, Some("1" | "5")) {
+                info!("✅ Cellular: Registered");
+                return Ok(());
+            }
+            std::thread::sleep(REGISTRATION_POLL);
+        }
+        Err(anyhow::anyhow!(
+            "Cellular: Not registered after {}s",
+            REGISTRATION_TIMEOUT.as_secs()
+        ))
+    }
+
+    /// `+CSQ: <rssi>,<ber>`: 0-31 maps to -113..-51 dBm, 99 = unknown
+    fn read_signal(&self) -> Option<i8> {
+        let response = self.command("AT+CSQ", COMMAND_TIMEOUT).ok()?;
+        let csq: i16 = response
+            .split("+CSQ:")
+            .nth(1)?
+            .split(',')
+            .next()?
+            .trim()
+            .parse()
+            .ok()?;
+        (csq <= 31).then(|| (-113 + 2 * csq) as i8)
+    }
+
+    fn ppp_active(&self) -> bool {
+        self.data_mode.load(Ordering::Acquire)
+    }
+
+    fn start_ppp(&self) {
+        self.data_mode.store(true, Ordering::Release);
+        unsafe {
+            sys::esp_netif_action_start(
+                self.ppp.0.netif().handle() as _,
+                core::ptr::null(),
+                0,
+                core::ptr::null_mut(),
+            );
+        }
+    }
+
+    fn stop_ppp(&self) {
+        unsafe {
+            sys::esp_netif_action_stop(
+                self.ppp.0.netif().handle() as _,
+                core::ptr::null(),
+                0,
+                core::ptr::null_mut(),
+            );
+        }
+        // Let LCP terminate go out before taking the UART back
+        std::thread::sleep(Duration::from_millis(500));
+        self.data_mode.store(false, Ordering::Release);
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+impl NetworkLink for CellularModem {
+    fn name(&self) -> &'static str {
+        "cellular"
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        if self.ppp_active() {
+            if self.is_connected()? {
+                return Ok(());
+            }
+            self.disconnect()?;
+        }
+
+        info!("📶 Cellular: Connecting...");
+        self.wake()?;
+        self.command("ATE0", COMMAND_TIMEOUT)?;
+        self.command("AT+CMEE=2", COMMAND_TIMEOUT)?;
+        self.unlock_sim()?;
+        self.command(
+            &format!("AT+CGDCONT=1,\"IP\",\"{}\"", self.settings.apn),
+            COMMAND_TIMEOUT,
+        )?;
+        self.wait_registered()?;
+        self.rssi = self.read_signal();
+        if let Some(rssi) = self.rssi {
+            info!("📶 Cellular: Signal {} dBm", rssi);
+        }
+
+        info!("📞 Cellular: Dialling PPP...");
+        self.command("ATD*99#", DIAL_TIMEOUT)?;
+        self.start_ppp();
+
+        let deadline = Instant::now() + PPP_UP_TIMEOUT;
+        while Instant::now() < deadline {
+            if self.is_connected()? {
+                info!("✅ Cellular: IP address: {}", self.get_ip()?);
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(250));
+        }
+        self.disconnect()?;
+        Err(anyhow::anyhow!(
+            "Cellular: PPP not up after {}s",
+            PPP_UP_TIMEOUT.as_secs()
+        ))
+    }
+
+    fn disconnect(&mut self) -> Result<()> {
+        if !self.ppp_active() {
+            return Ok(());
+        }
+        info!("🔌 Cellular: Hanging up...");
+        self.stop_ppp();
+        // Back to command mode if PPP termination didn't drop the call
+        std::thread::sleep(ESCAPE_GUARD);
+        self.uart.0.write(b"+++")?;
+        std::thread::sleep(ESCAPE_GUARD);
+        if let Err(e) = self.command("ATH", COMMAND_TIMEOUT) {
+            log::warn!("⚠️  Cellular: Hang-up failed: {:?}", e);
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> Result<bool> {
+        Ok(self.ppp_active() && self.ppp.0.netif().is_up()?)
+    }
+
+    fn get_ip(&self) -> Result<Ipv4Addr> {
+        Ok(self.ppp.0.netif().get_ip_info()?.ip)
+    }
+
+    /// PPP has no link-layer address
+    fn get_mac(&self) -> Result<String> {
+        Err(anyhow::anyhow!("No MAC address on a PPP link"))
+    }
+
+    fn get_rssi(&self) -> Option<i8> {
+        self.rssi
+    }
+}
@@ -0,0 +1,167 @@
+//! Chip and flash identity
+//!
+//! Model, silicon revision, flash size, MAC addresses, ESP-IDF version and
+//! the partition table, for the `chipinfo` command. Otherwise these only
+//! show up in the bootloader log at power-on.
+
+use esp_idf_svc::sys;
+use std::ffi::CStr;
+
+/// Model, revision and radio features of the SoC
+#[derive(Debug, Clone)]
+pub struct ChipInfo {
+    pub model: &'static str,
+    /// Silicon revision as major*100 + minor, e.g. 301 for v3.1
+    pub revision: u16,
+    pub cores: u8,
+    pub features: Vec<&'static str>,
+}
+
+impl ChipInfo {
+    /// e.g. "v3.1"
+    pub fn revision_text(&self) -> String {
+        format!("v{}.{}", self.revision / 100, self.revision % 100)
+    }
+}
+
+pub fn chip_info() -> ChipInfo {
+    let mut info = sys::esp_chip_info_t::default();
+    unsafe { sys::esp_chip_info(&mut info) };
+    let model = match info.model {
+        sys::esp_chip_model_t_CHIP_ESP32 => "ESP32",
+        sys::esp_chip_model_t_CHIP_ESP32S2 => "ESP32-S2",
+        sys::esp_chip_model_t_CHIP_ESP32S3 => "ESP32-S3",
+        sys::esp_chip_model_t_CHIP_ESP32C3 => "ESP32-C3",
+        sys::esp_chip_model_t_CHIP_ESP32C2 => "ESP32-C2",
+        sys::esp_chip_model_t_CHIP_ESP32C6 => "ESP32-C6",
+        sys::esp_chip_model_t_CHIP_ESP32H2 => "ESP32-H2",
+        _ => "unknown",
+    };
+    let features = [
+        (sys::CHIP_FEATURE_WIFI_BGN, "WiFi"),
+        (sys::CHIP_FEATURE_BT, "BT"),
+        (sys::CHIP_FEATURE_BLE, "BLE"),
+        (sys::CHIP_FEATURE_IEEE802154, "802.15.4"),
+        (sys::CHIP_FEATURE_EMB_FLASH, "embedded flash"),
+        (sys::CHIP_FEATURE_EMB_PSRAM, "embedded PSRAM"),
+    ]
+    .into_iter()
+    .filter(|(bit, _)| info.features & bit != 0)
+    .map(|(_, name)| name)
+    .collect();
+    ChipInfo {
+        model,
+        revision: info.revision,
+        cores: info.cores,
+        features,
+    }
+}
+
+/// Size of the main flash chip in bytes, None if it cannot be read
+pub fn flash_size() -> Option<u32> {
+    let mut size = 0u32;
+    let err = unsafe { sys::esp_flash_get_size(core::ptr::null_mut(), &mut size) };
+    (err == sys::ESP_OK).then_some(size)
+}
+
+/// e.g. "v5.2.2"
+pub fn idf_version() -> String {
+    unsafe { CStr::from_ptr(sys::esp_get_idf_version()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Base MAC from eFuse; its hex form is the chip ID used in MQTT topics
+pub fn base_mac() -> [u8; 6] {
+    let mut mac = [0u8; 6];
+    unsafe {
+        sys::esp_efuse_mac_get_default(mac.as_mut_ptr());
+    }
+    mac
+}
+
+/// MAC of the WiFi station interface
+pub fn wifi_sta_mac() -> [u8; 6] {
+    let mut mac = [0u8; 6];
+    unsafe {
+        sys::esp_read_mac(mac.as_mut_ptr(), sys::esp_mac_type_t_ESP_MAC_WIFI_STA);
+    }
+    mac
+}
+
+/// e.g. "24:0a:c4:12:34:56"
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    format!(
+        "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+    )
+}
+
+/// One partition table entry
+#[derive(Debug, Clone)]
+pub struct PartitionInfo {
+    pub label: String,
+    /// "app" or "data"
+    pub kind: &'static str,
+    /// e.g. "ota_0", "nvs", "spiffs"; the raw number if unknown
+    pub subtype: String,
+    pub address: u32,
+    pub size: u32,
+    pub encrypted: bool,
+}
+
+/// All partitions in flash order
+pub fn partitions() -> Vec<PartitionInfo> {
+    let mut partitions = Vec::new();
+    let mut iter = unsafe {
+        sys::esp_partition_find(
+            sys::esp_partition_type_t_ESP_PARTITION_TYPE_ANY,
+            sys::esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_ANY,
+            core::ptr::null(),
+        )
+    };
+    while !iter.is_null() {
+        let partition = unsafe { sys::esp_partition_get(iter) };
+        if !partition.is_null() {
+            let partition = unsafe { &*partition };
+            partitions.push(PartitionInfo {
+                label: unsafe { CStr::from_ptr(partition.label.as_ptr()) }
+                    .to_string_lossy()
+                    .into_owned(),
+                kind: match partition.type_ {
+                    sys::esp_partition_type_t_ESP_PARTITION_TYPE_APP => "app",
+                    sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA => "data",
+                    _ => "other",
+                },
+                subtype: subtype_name(partition.type_, partition.subtype),
+                address: partition.address,
+                size: partition.size,
+                encrypted: partition.encrypted,
+            });
+        }
+        // Frees the iterator after the last partition
+        iter = unsafe { sys::esp_partition_next(iter) };
+    }
+    partitions.sort_by_key(|partition| partition.address);
+    partitions
+}
+
+fn subtype_name(kind: sys::esp_partition_type_t, subtype: sys::esp_partition_subtype_t) -> String {
+    let name = match (kind, subtype) {
+        (sys::esp_partition_type_t_ESP_PARTITION_TYPE_APP, 0x00) => "factory",
+        (sys::esp_partition_type_t_ESP_PARTITION_TYPE_APP, 0x10..=0x1f) => {
+            return format!("ota_{}", subtype - 0x10)
+        }
+        (sys::esp_partition_type_t_ESP_PARTITION_TYPE_APP, 0x20) => "test",
+        (sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA, 0x00) => "ota",
+        (sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA, 0x01) => "phy",
+        (sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA, 0x02) => "nvs",
+        (sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA, 0x03) => "coredump",
+        (sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA, 0x04) => "nvs_keys",
+        (sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA, 0x81) => "fat",
+        (sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA, 0x82) => "spiffs",
+        (sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA, 0x83) => "littlefs",
+        _ => return format!("{:#04x}", subtype),
+    };
+    name.to_string()
+}
@@ -0,0 +1,114 @@
+//! Console login for privileged commands
+//!
+//! Once a password is set with `passwd`, commands that change or erase the
+//! device (`reset`, `factory_reset`, configuration and credential writes, ...)
+//! are refused on the serial console until `login <password>`. The session
+//! ends with `logout` or after `SESSION_TIMEOUT` without a privileged command.
+//! Only a salted, iterated SHA-256 of the password is stored in NVS. `login`,
+//! `logout` and `passwd` only work on the console and telnet; the HTTP API
+//! asks for the password (`Authorization: Bearer`) with each config change.
+
+use super::CliCommand;
+use esp_idf_svc::sys;
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+/// Stored as salt followed by hash
+pub const PASSWORD_HASH_LEN: usize = SALT_LEN + HASH_LEN;
+/// Slows down guessing a password copied off the flash
+const HASH_ROUNDS: u32 = 1000;
+pub const MIN_PASSWORD_LEN: usize = 6;
+/// Idle time after which privileged commands need `login` again
+pub const SESSION_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+/// Delay after a wrong password
+pub const LOGIN_FAIL_DELAY: Duration = Duration::from_secs(2);
+
+/// Commands that need a login once a password is set
+pub fn is_privileged(command: &CliCommand) -> bool {
+    matches!(
+        command,
+        CliCommand::Reset
+            | CliCommand::Reboot
+            | CliCommand::FactoryReset
+            | CliCommand::Passwd(_)
+            | CliCommand::WifiSave(..)
+            | CliCommand::WifiSaveEnterprise(..)
+            | CliCommand::WifiCaCert(_)
+            | CliCommand::WifiForget(_)
+            | CliCommand::WifiStatic(_)
+            | CliCommand::MqttCert(..)
+            | CliCommand::MqttConnect(..)
+            | CliCommand::ConfigSet(..)
+            | CliCommand::ConfigSave
+            | CliCommand::MetersAdd(..)
+            | CliCommand::MetersSet(..)
+            | CliCommand::MetersRemove(_)
+            | CliCommand::AliasSet(..)
+            | CliCommand::AliasRemove(_)
+            | CliCommand::ScriptSave(..)
+            | CliCommand::ScriptDelete(_)
+    )
+}
+
+fn digest(salt: &[u8], password: &str) -> [u8; HASH_LEN] {
+    let mut hash: [u8; HASH_LEN] = Sha256::new()
+        .chain_update(salt)
+        .chain_update(password.as_bytes())
+        .finalize()
+        .into();
+    for _ in 1..HASH_ROUNDS {
+        hash = Sha256::new()
+            .chain_update(salt)
+            .chain_update(hash)
+            .finalize()
+            .into();
+    }
+    hash
+}
+
+/// Salted hash of a new password, for NVS
+pub fn hash_password(password: &str) -> [u8; PASSWORD_HASH_LEN] {
+    let mut stored = [0u8; PASSWORD_HASH_LEN];
+    unsafe {
+        sys::esp_fill_random(stored.as_mut_ptr() as *mut core::ffi::c_void, SALT_LEN);
+    }
+    let hash = digest(&stored[..SALT_LEN], password);
+    stored[SALT_LEN..].copy_from_slice(&hash);
+    stored
+}
+
+/// Whether `password` matches a stored hash (compared in constant time)
+pub fn verify_password(stored: &[u8], password: &str) -> bool {
+    if stored.len() != PASSWORD_HASH_LEN {
+        return false;
+    }
+    let hash = digest(&stored[..SALT_LEN], password);
+    hash.iter()
+        .zip(&stored[SALT_LEN..])
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Login state of the console
+#[derive(Default)]
+pub struct Session {
+    last_active: Option<Instant>,
+}
+
+impl Session {
+    pub fn is_active(&self) -> bool {
+        self.last_active
+            .is_some_and(|at| at.elapsed() < SESSION_TIMEOUT)
+    }
+
+    /// Start the session, or keep it going after a privileged command
+    pub fn touch(&mut self) {
+        self.last_active = Some(Instant::now());
+    }
+
+    pub fn end(&mut self) {
+        self.last_active = None;
+    }
+}
@@ -0,0 +1,177 @@
+//! Commands shared by the MTU and meter consoles
+//!
+//! `help`, `version`, `uptime`, `chipinfo`, `clear` and `reset` are declared
+//! once in `BASE_COMMANDS` and answered by the functions below, for both
+//! binaries. Each role's `CommandSet` lists these first and adds its own
+//! commands; a role's command enum takes them in through
+//! `From<BaseCommand>`.
+
+use super::registry::CommandSpec;
+use crate::chip;
+use crate::device;
+use crate::ota;
+use crate::telemetry;
+use std::time::{Duration, Instant};
+
+/// `reboot` and `factory_reset` wait this long, so a reply to a remote
+/// command is still published
+pub const REBOOT_DELAY: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone)]
+pub enum BaseCommand {
+    Help,
+    /// `help <command>`
+    HelpCommand(String),
+    Version,
+    Uptime,
+    ChipInfo,
+    Clear,
+    Reset,
+}
+
+/// The shared commands, in `help` order
+pub static BASE_COMMANDS: &[CommandSpec<BaseCommand>] = &[
+    CommandSpec {
+        name: "help",
+        help: &[
+            ("help", "Show this help"),
+            ("help <command>", "Show a command's usage and examples"),
+        ],
+        details: "Example: help status",
+        parse: |parts| match parts.next() {
+            Some(name) => BaseCommand::HelpCommand(name.to_string()),
+            None => BaseCommand::Help,
+        },
+    },
+    CommandSpec {
+        name: "version",
+        help: &[("version", "Show firmware version")],
+        details: "",
+        parse: |_| BaseCommand::Version,
+    },
+    CommandSpec {
+        name: "uptime",
+        help: &[("uptime", "Show system uptime")],
+        details: "",
+        parse: |_| BaseCommand::Uptime,
+    },
+    CommandSpec {
+        name: "chipinfo",
+        help: &[("chipinfo", "Show chip, flash, MAC and partition table")],
+        details: "Chip model and revision, flash size, chip ID (base MAC), ESP-IDF\nversion and the partition table with offsets and sizes.",
+        parse: |_| BaseCommand::ChipInfo,
+    },
+    CommandSpec {
+        name: "clear",
+        help: &[("clear", "Clear terminal")],
+        details: "",
+        parse: |_| BaseCommand::Clear,
+    },
+    CommandSpec {
+        name: "reset",
+        help: &[("reset", "Reset system")],
+        details: "Restarts at once; the MTU app's reboot waits for a remote reply to go out.",
+        parse: |_| BaseCommand::Reset,
+    },
+];
+
+/// `version`: `product` is the first line, e.g. "ESP32 Water Meter Simulator v1.0.0"
+pub fn version(product: &str) -> String {
+    log::info!("CLI: Version requested");
+    let mut response = format!("{}\r\n", product);
+    response.push_str(&format!("App version: {}\r\n", ota::app_version()));
+    response.push_str(&format!(
+        "Partition: {} (OTA state: {})\r\n",
+        ota::running_partition(),
+        ota::image_state().name()
+    ));
+    response.push_str(&format!("Reset reason: {}\r\n", telemetry::reset_reason()));
+    response.push_str(&format!("Built from {}", device::build_info()));
+    response
+}
+
+/// `uptime` since `start`
+pub fn uptime(start: Instant) -> String {
+    log::info!("CLI: Uptime requested");
+    let uptime_secs = start.elapsed().as_secs();
+    let hours = uptime_secs / 3600;
+    let minutes = (uptime_secs % 3600) / 60;
+    let seconds = uptime_secs % 60;
+
+    let mut response = String::from("Uptime: ");
+    if hours > 0 {
+        response.push_str(&format!("{}h ", hours));
+    }
+    if minutes > 0 || hours > 0 {
+        response.push_str(&format!("{}m ", minutes));
+    }
+    response.push_str(&format!("{}s", seconds));
+    response
+}
+
+/// `chipinfo`
+pub fn chip_info() -> String {
+    log::info!("CLI: Chip info requested");
+    let info = chip::chip_info();
+    let mut response = format!(
+        "Chip: {} rev {}, {} core(s)\r\n",
+        info.model,
+        info.revision_text(),
+        info.cores
+    );
+    if !info.features.is_empty() {
+        response.push_str(&format!("Features: {}\r\n", info.features.join(", ")));
+    }
+    match chip::flash_size() {
+        Some(size) => response.push_str(&format!("Flash: {} KB\r\n", size / 1024)),
+        None => response.push_str("Flash: unknown\r\n"),
+    }
+    response.push_str(&format!("Chip ID: {}\r\n", device::chip_id()));
+    response.push_str(&format!("WiFi MAC: {}\r\n", device::station_mac()));
+    response.push_str(&format!("ESP-IDF: {}\r\n", chip::idf_version()));
+    response.push_str(&format!(
+        "App version: {} (running from {})\r\n",
+        ota::app_version(),
+        ota::running_partition()
+    ));
+    let partitions = chip::partitions();
+    response.push_str(&format!("Partitions ({}):\r\n", partitions.len()));
+    response.push_str("  Label     Type  Subtype   Offset      Size");
+    for partition in partitions {
+        response.push_str(&format!(
+            "\r\n  {:<9} {:<5} {:<9} {:#08x}  {:>5} KB{}",
+            partition.label,
+            partition.kind,
+            partition.subtype,
+            partition.address,
+            partition.size / 1024,
+            if partition.encrypted {
+                "  encrypted"
+            } else {
+                ""
+            }
+        ));
+    }
+    response
+}
+
+/// Restart from a background thread after `delay`
+pub fn restart_after(delay: Duration) {
+    let spawned = std::thread::Builder::new()
+        .name("reboot".to_string())
+        .stack_size(4096)
+        .spawn(move || {
+            std::thread::sleep(delay);
+            unsafe { esp_idf_svc::sys::esp_restart() };
+        });
+    if spawned.is_err() {
+        unsafe { esp_idf_svc::sys::esp_restart() };
+    }
+}
+
+/// `reset`: restarts at once, without waiting for the reply to go out
+pub fn reset() -> ! {
+    log::info!("CLI: Reset requested");
+    // Perform system reset using ESP-IDF
+    unsafe { esp_idf_svc::sys::esp_restart() }
+}
@@ -1,10 +1,49 @@
+use super::auth::{self, Session, LOGIN_FAIL_DELAY, MIN_PASSWORD_LEN};
+use super::base::{self, restart_after, REBOOT_DELAY};
+use super::jobs::{JobId, Jobs};
+use super::parser::{split_commands, CommandParser, BUILTIN_ALIASES};
+use super::registry::MTU_COMMANDS;
 use super::{CliCommand, CliError};
+use crate::config_store::{ConfigStore, DeviceConfig, CONFIG_KEYS, MAX_MQTT_CERT_LEN};
+use crate::device;
+use crate::logging;
 use crate::mqtt::MqttClient;
-use crate::mtu::{GpioMtuTimerV2, MtuCommand};
-use crate::wifi::WifiManager;
-use std::sync::mpsc::Sender;
+use crate::mtu::{register_value, serial_number, GpioMtuTimerV2, MtuCommand};
+use crate::network_config::WifiConfig;
+use crate::storage::{self, DataLog, CSV_HEADER};
+use crate::telemetry;
+use crate::timekeeping;
+use crate::watchdog;
+use crate::wifi::credentials::MAX_CA_CERT_LEN;
+use crate::wifi::{disconnect_reason_name, rssi_quality, WifiCredentialStore, WifiManager};
+use std::sync::mpsc::{RecvTimeoutError, Sender};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+/// Saved scripts (`script save`) and their length limits
+const MAX_SCRIPTS: usize = 8;
+const MAX_SCRIPT_NAME_LEN: usize = 16;
+const MAX_SCRIPT_LEN: usize = 512;
+
+/// User aliases (`alias`) and their length limits
+const MAX_ALIASES: usize = 16;
+const MAX_ALIAS_NAME_LEN: usize = 16;
+const MAX_ALIAS_LEN: usize = 96;
+
+/// How long the `mqtt_connect` job waits for the broker
+const MQTT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `mtu_read` waits this long past the read duration (meter power-up hold
+/// of up to 10 s, teardown)
+const MTU_READ_GRACE: Duration = Duration::from_secs(15);
+
+/// Reply to a command that continues as a background job
+fn job_started(id: JobId, command: &str) -> String {
+    format!(
+        "\r\nJob {} started: {} (see 'jobs', 'job_status {}')",
+        id, command, id
+    )
+}
 
 pub struct CommandHandler {
     start_time: Instant,
@@ -12,6 +51,25 @@ pub struct CommandHandler {
     mtu_cmd_sender: Option<Sender<MtuCommand>>,
     wifi: Option<Arc<Mutex<WifiManager>>>,
     mqtt: Option<Arc<MqttClient>>,
+    config: Option<DeviceConfig>,
+    /// Persistent boot counter (0 if the config store is unavailable)
+    boot_count: u32,
+    config_store: Option<ConfigStore>,
+    data_log: Option<Arc<Mutex<DataLog>>>,
+    wifi_credentials: Option<WifiCredentialStore>,
+    /// CA certificate being pasted line by line with `wifi_ca_cert`
+    pending_ca_cert: String,
+    /// PEM being pasted with `mqtt_cert`, with its slot
+    pending_mqtt_cert: (String, String),
+    /// Console password (salt and hash), None = no login needed
+    password_hash: Option<Vec<u8>>,
+    session: Session,
+    /// Set while a command typed on the serial console runs
+    from_console: bool,
+    /// Set while a command from an authenticated remote caller runs (the
+    /// HTTP API with the console password, cloud desired state)
+    remote_authorized: bool,
+    jobs: Jobs,
 }
 
 impl Default for CommandHandler {
@@ -28,6 +86,18 @@ impl CommandHandler {
             mtu_cmd_sender: None,
             wifi: None,
             mqtt: None,
+            config: None,
+            boot_count: 0,
+            config_store: None,
+            data_log: None,
+            wifi_credentials: None,
+            pending_ca_cert: String::new(),
+            pending_mqtt_cert: (String::new(), String::new()),
+            password_hash: None,
+            session: Session::default(),
+            from_console: false,
+            remote_authorized: false,
+            jobs: Jobs::default(),
         }
     }
 
@@ -47,9 +117,672 @@ impl CommandHandler {
         self
     }
 
+    pub fn with_config(mut self, config: DeviceConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    pub fn with_boot_count(mut self, boot_count: u32) -> Self {
+        self.boot_count = boot_count;
+        self
+    }
+
+    pub fn with_config_store(mut self, store: ConfigStore) -> Self {
+        self.password_hash = match store.load_cli_password() {
+            Ok(hash) => hash,
+            Err(e) => {
+                log::warn!("⚠️  CLI: Failed to load console password: {:?}", e);
+                None
+            }
+        };
+        self.config_store = Some(store);
+        self
+    }
+
+    pub fn with_data_log(mut self, data_log: Arc<Mutex<DataLog>>) -> Self {
+        self.data_log = Some(data_log);
+        self
+    }
+
+    pub fn with_wifi_credentials(mut self, store: WifiCredentialStore) -> Self {
+        self.wifi_credentials = Some(store);
+        self
+    }
+
+    /// Configuration edited by `config set` (and remote config sync)
+    pub fn config(&self) -> Option<&DeviceConfig> {
+        self.config.as_ref()
+    }
+
+    pub fn config_mut(&mut self) -> Option<&mut DeviceConfig> {
+        self.config.as_mut()
+    }
+
+    /// `mqtt_connect`: a console session to `broker_url` with the stored
+    /// credentials, TLS and session settings, replacing any earlier one
+    fn handle_mqtt_connect(&mut self, broker_url: &str) -> String {
+        let mut mqtt_config = self
+            .config
+            .as_ref()
+            .map(|config| config.mqtt.clone())
+            .unwrap_or_default();
+        mqtt_config.broker_url.clear();
+        if mqtt_config.broker_url.push_str(broker_url).is_err() {
+            return "❌ Broker URL too long (max 128)".to_string();
+        }
+
+        // Own client ID, so the broker does not drop the reading session
+        let client_id = format!("{}-cli-{}", mqtt_config.client_id, device::short_id());
+        mqtt_config.client_id.clear();
+        let _ = mqtt_config.client_id.push_str(&client_id);
+
+        if let Some(old) = self.mqtt.take() {
+            let _ = old.disconnect();
+            old.shutdown();
+        }
+
+        let mqtt = match MqttClient::from_config(&mqtt_config) {
+            Ok(mqtt) => Arc::new(mqtt),
+            Err(e) => return format!("❌ MQTT connect failed: {:?}", e),
+        };
+        self.mqtt = Some(Arc::clone(&mqtt));
+
+        let broker = broker_url.to_string();
+        let command = format!("mqtt_connect {}", broker_url);
+        let started = self.jobs.spawn("mqtt", &command, move |_, _| {
+            if mqtt.wait_connected(MQTT_CONNECT_TIMEOUT) {
+                Ok(format!(
+                    "MQTT connected to {} as {}\r\nUse 'mqtt_status' and 'mqtt_publish'",
+                    broker, client_id
+                ))
+            } else {
+                Err(format!(
+                    "MQTT not connected to {} after {}s, still retrying (see 'mqtt_status')",
+                    broker,
+                    MQTT_CONNECT_TIMEOUT.as_secs()
+                ))
+            }
+        });
+        match started {
+            Ok(id) => format!(
+                "🔌 MQTT connecting to {}...{}",
+                broker_url,
+                job_started(id, &command)
+            ),
+            Err(e) => format!("❌ {}", e),
+        }
+    }
+
+    /// Track a connect begun with `connect_start` as a job; the main loop
+    /// completes it from `connect_poll` progress
+    fn start_wifi_job(&self, command: &str) -> String {
+        if let Some(previous) = self.jobs.running("wifi") {
+            self.jobs
+                .finish(previous, Err(format!("Replaced by '{}'", command)));
+        }
+        let id = self.jobs.start("wifi", command);
+        job_started(id, command)
+    }
+
+    /// Background jobs, shared with the main loop
+    pub fn jobs(&self) -> Jobs {
+        self.jobs.clone()
+    }
+
+    /// `config save`
+    pub fn save_config(&mut self) -> anyhow::Result<()> {
+        match (&self.config, &mut self.config_store) {
+            (Some(config), Some(store)) => store.save(config),
+            _ => Err(anyhow::anyhow!("Configuration storage not available")),
+        }
+    }
+
+    pub fn mtu(&self) -> Option<&Arc<GpioMtuTimerV2>> {
+        self.mtu.as_ref()
+    }
+
+    fn boot_count_text(&self) -> String {
+        match self.boot_count {
+            0 => "unknown".to_string(),
+            count => count.to_string(),
+        }
+    }
+
+    /// `wifi_ca_cert`: no argument shows the status, `clear` removes the
+    /// certificate, anything else is a PEM line. The certificate is saved
+    /// once its END line arrives.
+    fn handle_ca_cert(&mut self, line: Option<String>) -> String {
+        let store = match self.wifi_credentials {
+            Some(ref mut store) => store,
+            None => return "❌ Credential storage not available".to_string(),
+        };
+
+        let line = match line {
+            None => {
+                let stored = match store.load_ca_cert() {
+                    Ok(Some(pem)) => format!("stored ({} bytes)", pem.len()),
+                    Ok(None) => "none".to_string(),
+                    Err(e) => format!("error: {:?}", e),
+                };
+                let mut message = format!("CA certificate: {}", stored);
+                if !self.pending_ca_cert.is_empty() {
+                    message.push_str(&format!(
+                        "\r\nPaste in progress: {} bytes (waiting for END line)",
+                        self.pending_ca_cert.len()
+                    ));
+                }
+                return message;
+            }
+            Some(line) => line,
+        };
+
+        if line == "clear" {
+            self.pending_ca_cert.clear();
+            if let Err(e) = store.clear_ca_cert() {
+                return format!("❌ Failed to clear CA certificate: {:?}", e);
+            }
+            if let Some(ref wifi) = self.wifi {
+                if let Ok(mut wifi_guard) = wifi.lock() {
+                    wifi_guard.set_ca_cert(None);
+                }
+            }
+            return "✅ CA certificate cleared".to_string();
+        }
+
+        let pem = match paste_pem_line(&mut self.pending_ca_cert, &line, MAX_CA_CERT_LEN) {
+            Ok(Some(pem)) => pem,
+            Ok(None) => return format!("... {} bytes", self.pending_ca_cert.len()),
+            Err(e) => return e,
+        };
+        if let Err(e) = store.save_ca_cert(&pem) {
+            return format!("❌ Failed to save CA certificate: {:?}", e);
+        }
+        let size = pem.len();
+        if let Some(ref wifi) = self.wifi {
+            if let Ok(mut wifi_guard) = wifi.lock() {
+                wifi_guard.set_ca_cert(Some(pem));
+            }
+        }
+        format!("✅ CA certificate saved ({} bytes)", size)
+    }
+
+    /// `script save|add`: store `commands` under `name` (or append to it)
+    fn handle_script_save(&mut self, name: &str, commands: &str, append: bool) -> String {
+        if name.len() > MAX_SCRIPT_NAME_LEN
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return format!(
+                "❌ Script name: up to {} letters, digits, '_' or '-'",
+                MAX_SCRIPT_NAME_LEN
+            );
+        }
+        if let Some(nested) = split_commands(commands)
+            .into_iter()
+            .find(|command| matches!(CommandParser::parse_command(command), CliCommand::Run(_)))
+        {
+            return format!("❌ Scripts can't contain 'run' ({})", nested);
+        }
+        let Some(ref mut store) = self.config_store else {
+            return "❌ Script storage not available".to_string();
+        };
+        let mut scripts = match store.load_scripts() {
+            Ok(scripts) => scripts,
+            Err(e) => return format!("❌ Failed to load scripts: {:?}", e),
+        };
+
+        let script = match scripts.get(name) {
+            Some(existing) if append => format!("{}; {}", existing, commands),
+            _ => commands.to_string(),
+        };
+        if script.len() > MAX_SCRIPT_LEN {
+            return format!("❌ Script too long (max {} chars)", MAX_SCRIPT_LEN);
+        }
+        if !scripts.contains_key(name) && scripts.len() >= MAX_SCRIPTS {
+            return format!(
+                "❌ Script limit reached ({}), delete one first",
+                MAX_SCRIPTS
+            );
+        }
+        let count = split_commands(&script).len();
+        scripts.insert(name.to_string(), script);
+        match store.save_scripts(&scripts) {
+            Ok(_) => format!(
+                "✅ Script '{}' saved ({} commands)\r\nUse 'run {}' to run it",
+                name, count, name
+            ),
+            Err(e) => format!("❌ Failed to save script: {:?}", e),
+        }
+    }
+
+    /// `mtu_read [dur]`: start a read and wait for its result
+    fn handle_mtu_read(&self, duration_secs: u16) -> String {
+        let (Some(mtu), Some(sender)) = (&self.mtu, &self.mtu_cmd_sender) else {
+            return "MTU not configured".to_string();
+        };
+        if mtu.is_running() {
+            return "MTU is already running. Use 'mtu_stop' first.".to_string();
+        }
+        let result = mtu.subscribe_result();
+        if sender
+            .send(MtuCommand::Start {
+                duration_secs: duration_secs.into(),
+            })
+            .is_err()
+        {
+            return "Error: Failed to send command to MTU thread".to_string();
+        }
+
+        // The read stops by itself after the duration
+        let deadline = Instant::now() + Duration::from_secs(duration_secs.into()) + MTU_READ_GRACE;
+        let result = loop {
+            // The console task is watched while it waits here
+            watchdog::feed();
+            match result.recv_timeout(Duration::from_secs(1)) {
+                Ok(result) => break result,
+                Err(RecvTimeoutError::Timeout) if Instant::now() < deadline => {}
+                Err(_) => {
+                    let _ = sender.send(MtuCommand::Stop);
+                    return "❌ No result from the MTU thread".to_string();
+                }
+            }
+        };
+
+        let secs = result.duration.as_secs_f32();
+        let mut out = match (&result.message, result.corrupted) {
+            (Some(message), false) => format!("✅ Read in {:.1}s: {}", secs, message.trim_end()),
+            (Some(message), true) => {
+                format!("❌ Corrupted read in {:.1}s: {}", secs, message.trim_end())
+            }
+            (None, _) => format!("❌ No message received in {:.1}s", secs),
+        };
+        if let Some(message) = result.message.as_deref().filter(|_| !result.corrupted) {
+            if let Some(register) = register_value(message) {
+                out.push_str(&format!("\r\n  Register: {}", register));
+            }
+            if let Some(serial) = serial_number(message) {
+                out.push_str(&format!("\r\n  Serial: {}", serial));
+            }
+        }
+        out.push_str(&format!("\r\n  Frame errors: {}", result.frame_errors));
+        out.push_str(&format!("\r\n  Clock cycles: {}", result.cycles));
+        let (successful, corrupted, _) = mtu.get_stats();
+        out.push_str(&format!(
+            "\r\n  Statistics: {} successful, {} corrupted",
+            successful, corrupted
+        ));
+        out
+    }
+
+    /// Whether `password` unlocks privileged commands for a caller without a
+    /// console session (the HTTP API); anything does until a password is set
+    pub fn accepts_password(&self, password: Option<&str>) -> bool {
+        match (&self.password_hash, password) {
+            (None, _) => true,
+            (Some(hash), Some(password)) => auth::verify_password(hash, password),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// `config set` of every `(key, value)` tried on a copy of the
+    /// configuration, so a batch can be applied all or nothing. Err names the
+    /// first rejected key and why.
+    pub fn check_config(&self, settings: &[(String, String)]) -> Result<(), String> {
+        let Some(mut config) = self.config.clone() else {
+            return Err("Configuration not available".to_string());
+        };
+        for (key, value) in settings {
+            config
+                .set(key, value)
+                .map_err(|e| format!("config set {}: {}", key, e))?;
+        }
+        Ok(())
+    }
+
+    /// `login <password>`
+    fn handle_login(&mut self, password: Option<String>) -> String {
+        let Some(ref hash) = self.password_hash else {
+            return "No console password set (use 'passwd <new>')".to_string();
+        };
+        let Some(password) = password else {
+            return if self.session.is_active() {
+                "Logged in".to_string()
+            } else {
+                "Not logged in".to_string()
+            };
+        };
+        if auth::verify_password(hash, &password) {
+            log::info!("CLI: Console login");
+            self.session.touch();
+            "✅ Logged in".to_string()
+        } else {
+            log::warn!("CLI: Console login failed");
+            self.session.end();
+            std::thread::sleep(LOGIN_FAIL_DELAY);
+            "❌ Wrong password".to_string()
+        }
+    }
+
+    /// `passwd <new|clear>`
+    fn handle_passwd(&mut self, password: &str) -> String {
+        let hash = if password == "clear" {
+            None
+        } else if password.len() < MIN_PASSWORD_LEN {
+            return format!("❌ Password too short (min {} chars)", MIN_PASSWORD_LEN);
+        } else {
+            Some(auth::hash_password(password).to_vec())
+        };
+        let Some(ref mut store) = self.config_store else {
+            return "❌ Password storage not available".to_string();
+        };
+        if let Err(e) = store.save_cli_password(hash.as_deref()) {
+            return format!("❌ Failed to save password: {:?}", e);
+        }
+        let message = match hash {
+            Some(_) => {
+                self.session.touch();
+                "✅ Console password set; privileged commands now need 'login'"
+            }
+            None => "✅ Console password removed",
+        };
+        self.password_hash = hash;
+        message.to_string()
+    }
+
+    /// `alias <name> <command>`: saved to NVS and used by the parser at once
+    fn handle_alias_set(&mut self, name: &str, command: &str) -> String {
+        if name.len() > MAX_ALIAS_NAME_LEN
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return format!(
+                "❌ Alias name: up to {} letters, digits, '_' or '-'",
+                MAX_ALIAS_NAME_LEN
+            );
+        }
+        if MTU_COMMANDS.contains(name) {
+            return format!("❌ '{}' is a command", name);
+        }
+        let target = command.split(' ').next().unwrap_or("");
+        if !MTU_COMMANDS.contains(target) {
+            return format!("❌ Alias must start with a command, not '{}'", target);
+        }
+        // Keeps a remote `alias` from hiding a command that needs a login or
+        // a confirmation behind a harmless name
+        if auth::is_privileged(&CommandParser::parse_command(command)) {
+            return format!("❌ '{}' can't be aliased", target);
+        }
+        if command.len() > MAX_ALIAS_LEN {
+            return format!("❌ Alias too long (max {} chars)", MAX_ALIAS_LEN);
+        }
+        let Some(ref mut store) = self.config_store else {
+            return "❌ Alias storage not available".to_string();
+        };
+        let mut aliases = CommandParser::aliases();
+        if !aliases.contains_key(name) && aliases.len() >= MAX_ALIASES {
+            return format!("❌ Alias limit reached ({}), remove one first", MAX_ALIASES);
+        }
+        aliases.insert(name.to_string(), command.to_string());
+        if let Err(e) = store.save_aliases(&aliases) {
+            return format!("❌ Failed to save alias: {:?}", e);
+        }
+        CommandParser::set_aliases(aliases);
+        format!("✅ Alias '{}' = '{}'", name, command)
+    }
+
+    /// `run`: the commands of a saved script, or a `;`-separated list, in
+    /// order; stops at the first one that fails
+    fn handle_run(&mut self, target: &str) -> String {
+        let script = if target.contains(';') {
+            target.to_string()
+        } else {
+            let saved = match self.config_store {
+                Some(ref store) => store
+                    .load_scripts()
+                    .ok()
+                    .and_then(|scripts| scripts.get(target).cloned()),
+                None => None,
+            };
+            match saved {
+                Some(script) => script,
+                // A single command given inline
+                None if target.contains(' ') => target.to_string(),
+                None => {
+                    return format!(
+                        "❌ No script '{}' (separate inline commands with ';')",
+                        target
+                    )
+                }
+            }
+        };
+
+        let commands = split_commands(&script);
+        let mut response = String::new();
+        for (index, line) in commands.iter().enumerate() {
+            let command = CommandParser::parse_command(line);
+            response.push_str(&format!("> {}\r\n", line));
+            let (ok, output) = match command {
+                CliCommand::Run(_) => (false, "❌ 'run' can't be nested".to_string()),
+                // Over MQTT or HTTP these are sent on their own (factory_reset
+                // with its nonce), never buried in a script
+                CliCommand::FactoryReset | CliCommand::Reboot | CliCommand::Reset
+                    if !self.from_console =>
+                {
+                    (
+                        false,
+                        format!("❌ '{}' can't run from a remote script", line),
+                    )
+                }
+                CliCommand::Unknown(_) => {
+                    (false, self.execute_command(command).unwrap_or_default())
+                }
+                command => match self.execute_command(command) {
+                    Ok(output) => (!output.contains('❌'), output),
+                    Err(e) => (false, format!("Error: {}", e)),
+                },
+            };
+            if !output.is_empty() {
+                response.push_str(&output);
+                response.push_str("\r\n");
+            }
+            if !ok {
+                log::warn!("CLI: run stopped at '{}'", line);
+                response.push_str(&format!(
+                    "❌ Stopped at command {} of {}",
+                    index + 1,
+                    commands.len()
+                ));
+                return response;
+            }
+        }
+        log::info!("CLI: run completed {} commands", commands.len());
+        response.push_str(&format!("✅ Ran {} commands", commands.len()));
+        response
+    }
+
+    /// `mqtt_cert <ca|cert|key>`: same paste flow as `wifi_ca_cert`, but the
+    /// PEM goes into the MQTT TLS settings and is stored by `config save`
+    fn handle_mqtt_cert(&mut self, slot: &str, line: Option<String>) -> String {
+        let tls = match self.config {
+            Some(ref mut config) => &mut config.mqtt.tls,
+            None => return "❌ Configuration not available".to_string(),
+        };
+        let (label, target) = match slot {
+            "ca" => ("CA certificate", &mut tls.ca_cert),
+            "cert" => ("Client certificate", &mut tls.client_cert),
+            _ => ("Client key", &mut tls.client_key),
+        };
+        let (pending_slot, pending) = &mut self.pending_mqtt_cert;
+
+        let line = match line {
+            None => {
+                let mut message = match target {
+                    Some(pem) => format!("MQTT {}: {} bytes", label, pem.len()),
+                    None => format!("MQTT {}: none", label),
+                };
+                if pending_slot == slot && !pending.is_empty() {
+                    message.push_str(&format!(
+                        "\r\nPaste in progress: {} bytes (waiting for END line)",
+                        pending.len()
+                    ));
+                }
+                return message;
+            }
+            Some(line) => line,
+        };
+
+        if line == "clear" {
+            pending.clear();
+            *target = None;
+            return format!(
+                "✅ MQTT {} cleared\r\nUse 'config save' then 'reset' to apply",
+                label
+            );
+        }
+
+        if pending_slot != slot {
+            pending.clear();
+            *pending_slot = slot.to_string();
+        }
+        let pem = match paste_pem_line(pending, &line, MAX_MQTT_CERT_LEN) {
+            Ok(Some(pem)) => pem,
+            Ok(None) => return format!("... {} bytes", pending.len()),
+            Err(e) => return e,
+        };
+        let size = pem.len();
+        *target = Some(pem);
+        format!(
+            "✅ MQTT {} set ({} bytes)\r\nUse 'config save' then 'reset' to apply",
+            label, size
+        )
+    }
+
+    /// Apply a change to the saved WiFi network list, persist it and hand it
+    /// to the WiFi manager for the next reconnect
+    fn update_wifi_networks<F>(&mut self, update: F) -> String
+    where
+        F: FnOnce(&mut WifiConfig) -> Result<String, &'static str>,
+    {
+        let store = match self.wifi_credentials {
+            Some(ref mut store) => store,
+            None => return "❌ Credential storage not available".to_string(),
+        };
+
+        let mut networks = match store.load() {
+            Ok(networks) => networks.unwrap_or_default(),
+            Err(e) => return format!("❌ Failed to load saved networks: {:?}", e),
+        };
+
+        let mut message = match update(&mut networks) {
+            Ok(message) => message,
+            Err(e) => return format!("❌ {}", e),
+        };
+
+        if let Err(e) = store.save(&networks) {
+            return format!("❌ Failed to save credentials: {:?}", e);
+        }
+
+        // Use them for the next reconnect without a reboot
+        let applied = match self.wifi {
+            Some(ref wifi) => match wifi.lock() {
+                Ok(mut wifi_guard) => {
+                    wifi_guard.set_networks(networks);
+                    true
+                }
+                Err(_) => false,
+            },
+            None => false,
+        };
+        if applied {
+            message.push_str("\r\nUse 'wifi_reconnect' to connect now");
+        } else {
+            message.push_str("\r\nUse 'reset' to connect with the new credentials");
+        }
+        message
+    }
+
+    /// `execute_command` for a line typed on the serial console: privileged
+    /// commands (also inside `run`) need a login once a password is set
+    pub fn execute_console(&mut self, command: CliCommand) -> Result<String, CliError> {
+        self.from_console = true;
+        let result = self.execute_command(command);
+        self.from_console = false;
+        result
+    }
+
+    /// `execute_console` with a login session of its own (a telnet client)
+    pub fn execute_session(
+        &mut self,
+        command: CliCommand,
+        session: &mut Session,
+    ) -> Result<String, CliError> {
+        std::mem::swap(&mut self.session, session);
+        let result = self.execute_console(command);
+        std::mem::swap(&mut self.session, session);
+        result
+    }
+
+    /// `execute_command` for a remote caller that proved who it is
+    pub fn execute_authorized(&mut self, command: CliCommand) -> Result<String, CliError> {
+        self.remote_authorized = true;
+        let result = self.execute_command(command);
+        self.remote_authorized = false;
+        result
+    }
+
+    /// Why a command from MQTT or HTTP is refused, None when it may run
+    pub fn remote_refusal(&self, command: &CliCommand) -> Option<&'static str> {
+        // The login belongs to the console: remote callers can neither change
+        // the password nor log the console in or out
+        if matches!(
+            command,
+            CliCommand::Login(_) | CliCommand::Logout | CliCommand::Passwd(_)
+        ) {
+            return Some("❌ Only available on the serial console or telnet");
+        }
+        // The factory reset nonce only guards against accidents (anyone reading
+        // the response topic can confirm it), not against an unknown sender
+        if matches!(command, CliCommand::FactoryReset)
+            && self.password_hash.is_some()
+            && !self.remote_authorized
+        {
+            return Some(
+                "❌ 'factory_reset' only runs on the console while a console password is set",
+            );
+        }
+        let allowed = self.remote_authorized
+            || self
+                .config
+                .as_ref()
+                .is_some_and(|config| config.network.remote_privileged);
+        if auth::is_privileged(command) && !allowed {
+            return Some(
+                "❌ Not allowed remotely (enable with 'config set network.remote_privileged true' on the console)",
+            );
+        }
+        None
+    }
+
     pub fn execute_command(&mut self, command: CliCommand) -> Result<String, CliError> {
         let mut response = String::new();
 
+        if !self.from_console {
+            if let Some(refusal) = self.remote_refusal(&command) {
+                log::warn!("CLI: Command refused from a remote source");
+                return Ok(refusal.to_string());
+            }
+        }
+
+        if self.from_console && self.password_hash.is_some() && auth::is_privileged(&command) {
+            if !self.session.is_active() {
+                log::warn!("CLI: Privileged command refused (not logged in)");
+                return Ok("❌ Login required ('login <password>')".to_string());
+            }
+            self.session.touch();
+        }
+
         match command {
             CliCommand::Empty => {
                 // Empty command - just return empty response (no error)
@@ -58,46 +791,134 @@ impl CommandHandler {
                 // Help is handled in terminal.rs
                 response.push_str("Help displayed");
             }
+            CliCommand::HelpCommand(name) => match MTU_COMMANDS.describe(&name) {
+                Some(help) => response.push_str(&help),
+                None => {
+                    let alias = CommandParser::aliases().get(&name).cloned().or_else(|| {
+                        BUILTIN_ALIASES
+                            .iter()
+                            .find(|(alias, _)| *alias == name)
+                            .map(|(_, command)| command.to_string())
+                    });
+                    match alias {
+                        Some(command) => {
+                            response.push_str(&format!("'{}' is an alias for '{}'", name, command))
+                        }
+                        None => response
+                            .push_str(&format!("❌ Unknown command '{}' (see 'help')", name)),
+                    }
+                }
+            },
             CliCommand::Version => {
-                log::info!("CLI: Version requested");
-                response.push_str("ESP32 Water Meter MTU Interface v1.0.0\r\n");
-                response.push_str("Built with ESP-IDF");
+                response.push_str(&base::version("ESP32 Water Meter MTU Interface v1.0.0"));
+                response.push_str(&format!("\r\nBoot count: {}", self.boot_count_text()));
             }
             CliCommand::Status => {
                 log::info!("CLI: Status requested");
                 response.push_str("System Status:\r\n");
+                if let Some(ref config) = self.config {
+                    if !config.device.name.is_empty() {
+                        response.push_str(&format!("  Device: {}\r\n", config.device.name));
+                    }
+                    response.push_str(&format!("  Hostname: {}\r\n", config.device.hostname));
+                }
                 response.push_str("  Firmware: ESP32 Water Meter MTU v1.0.0\r\n");
+                response.push_str(&format!(
+                    "  Heap: {} bytes free (min {})\r\n",
+                    telemetry::free_heap(),
+                    telemetry::min_free_heap()
+                ));
+                response.push_str(&format!(
+                    "  Reset reason: {}\r\n",
+                    telemetry::reset_reason()
+                ));
+                response.push_str(&format!("  Boot count: {}\r\n", self.boot_count_text()));
                 response.push_str("  Platform: ESP32 with ESP-IDF\r\n");
                 response.push_str("  MTU: GPIO4 (clock), GPIO5 (data)\r\n");
                 response.push_str("  UART: USB-C (UART0)");
             }
-            CliCommand::Uptime => {
-                log::info!("CLI: Uptime requested");
-                let uptime = self.start_time.elapsed();
-                let uptime_secs = uptime.as_secs();
-                let hours = uptime_secs / 3600;
-                let minutes = (uptime_secs % 3600) / 60;
-                let seconds = uptime_secs % 60;
-
-                response.push_str("Uptime: ");
-                if hours > 0 {
-                    response.push_str(&format!("{}h ", hours));
+            CliCommand::Sys => {
+                log::info!("CLI: System diagnostics requested");
+                response.push_str(&format!(
+                    "Heap: {} bytes free (min {}, largest block {})\r\n",
+                    telemetry::free_heap(),
+                    telemetry::min_free_heap(),
+                    telemetry::largest_free_block()
+                ));
+                let tasks = telemetry::tasks();
+                response.push_str(&format!("Tasks ({}):\r\n", tasks.len()));
+                response.push_str("  Name             State      Prio  Stack free  Core");
+                for task in tasks {
+                    response.push_str(&format!(
+                        "\r\n  {:<16} {:<10} {:>4}  {:>10}  {}",
+                        task.name,
+                        task.state,
+                        task.priority,
+                        task.stack_free_min,
+                        task.core
+                            .map(|core| core.to_string())
+                            .unwrap_or_else(|| "-".to_string())
+                    ));
                 }
-                if minutes > 0 || hours > 0 {
-                    response.push_str(&format!("{}m ", minutes));
+            }
+            CliCommand::ChipInfo => response.push_str(&base::chip_info()),
+            CliCommand::Uptime => response.push_str(&base::uptime(self.start_time)),
+            CliCommand::Time => {
+                log::info!("CLI: Time requested");
+                match timekeeping::now_iso8601() {
+                    Some(now) => response.push_str(&format!("Time: {} (UTC)", now)),
+                    None => {
+                        response.push_str("Time: Not synchronized (syncs on next WiFi connect)")
+                    }
+                }
+                match timekeeping::last_sync() {
+                    Some(last) => response.push_str(&format!(
+                        "\r\nLast SNTP sync: {}",
+                        timekeeping::iso8601(last)
+                    )),
+                    None => response.push_str("\r\nLast SNTP sync: never"),
                 }
-                response.push_str(&format!("{}s", seconds));
             }
             CliCommand::Clear => {
                 // Clear is handled in terminal.rs
                 response.push_str("Screen cleared");
             }
-            CliCommand::Reset => {
-                log::info!("CLI: Reset requested");
-                response.push_str("Resetting system...");
-                // Perform system reset using ESP-IDF
-                unsafe {
-                    esp_idf_svc::sys::esp_restart();
+            CliCommand::Color(on) => {
+                // Applied by the terminal
+                log::info!("CLI: Color {}", if on { "on" } else { "off" });
+                response.push_str(if on { "✅ Color on" } else { "Color off" });
+            }
+            CliCommand::Pager(lines) => {
+                // Applied by the terminal
+                log::info!("CLI: Pager {:?}", lines);
+                match lines {
+                    Some(lines) => response.push_str(&format!("Pager: {} lines per page", lines)),
+                    None => response.push_str("Pager off"),
+                }
+            }
+            CliCommand::Reset => base::reset(),
+            CliCommand::Reboot => {
+                log::info!("CLI: Reboot requested");
+                response.push_str(&format!("Rebooting in {}s...", REBOOT_DELAY.as_secs()));
+                restart_after(REBOOT_DELAY);
+            }
+            CliCommand::FactoryReset => {
+                log::warn!("CLI: Factory reset requested");
+                let result = match self.config_store {
+                    Some(ref mut store) => store.factory_reset(),
+                    None => Err(anyhow::anyhow!("Configuration storage not available")),
+                };
+                match result {
+                    Ok(_) => {
+                        response
+                            .push_str("Configuration, WiFi networks and meter settings erased\r\n");
+                        response.push_str(&format!(
+                            "Restarting into provisioning mode in {}s...",
+                            REBOOT_DELAY.as_secs()
+                        ));
+                        restart_after(REBOOT_DELAY);
+                    }
+                    Err(e) => response.push_str(&format!("❌ Factory reset failed: {:?}", e)),
                 }
             }
             CliCommand::Echo(text) => {
@@ -136,6 +957,10 @@ impl CommandHandler {
                     response.push_str("MTU not configured");
                 }
             }
+            CliCommand::MtuRead(duration) => {
+                log::info!("CLI: MTU read requested");
+                response.push_str(&self.handle_mtu_read(duration.unwrap_or(30)));
+            }
             CliCommand::MtuStop => {
                 log::info!("CLI: MTU stop requested");
                 if let Some(ref sender) = self.mtu_cmd_sender {
@@ -178,6 +1003,10 @@ impl CommandHandler {
                         }
                     ));
                     response.push_str(&format!("  Baud rate: {} bps\r\n", baud_rate));
+                    response.push_str(&format!("  Framing: {}\r\n", mtu.get_framing().name()));
+                    if let Some(expected) = mtu.get_expected_message() {
+                        response.push_str(&format!("  Expected: {}\r\n", expected.trim_end()));
+                    }
                     response.push_str("  Pins: GPIO4 (clock), GPIO5 (data)\r\n");
                     response.push_str(&format!("  Total cycles: {}\r\n", cycles));
                     response.push_str("  Statistics:\r\n");
@@ -202,7 +1031,7 @@ impl CommandHandler {
                 log::info!("CLI: MTU baud rate set to {}", baud_rate);
                 if let Some(ref mtu) = self.mtu {
                     if mtu.is_running() {
-                        response.push_str("Cannot change baud rate while MTU is running.\r\n");
+                        response.push_str("❌ Cannot change baud rate while MTU is running.\r\n");
                         response.push_str("Use 'mtu_stop' first.");
                     } else {
                         mtu.set_baud_rate(baud_rate);
@@ -212,6 +1041,49 @@ impl CommandHandler {
                     response.push_str("MTU not configured");
                 }
             }
+            CliCommand::MtuExpected(expected) => {
+                log::info!("CLI: MTU expected message command");
+                if let Some(ref mtu) = self.mtu {
+                    if expected.is_empty() {
+                        match mtu.get_expected_message() {
+                            Some(message) => response
+                                .push_str(&format!("Expected message: {}", message.trim_end())),
+                            None => response.push_str("Expected message: off (not checked)"),
+                        }
+                    } else if mtu.is_running() {
+                        response.push_str(
+                            "❌ Cannot change expected message while MTU is running.\r\n",
+                        );
+                        response.push_str("Use 'mtu_stop' first.");
+                    } else if expected.eq_ignore_ascii_case("off") {
+                        mtu.set_expected_message(None);
+                        response.push_str("Expected message check disabled");
+                    } else {
+                        let mut message = heapless::String::<256>::new();
+                        let _ = message.push_str(&expected);
+                        let _ = message.push('\r');
+                        mtu.set_expected_message(Some(message));
+                        response.push_str(&format!("Expected message set to: {}\r\n", expected));
+                        response.push_str("Reads that differ now count as corrupted");
+                    }
+                } else {
+                    response.push_str("MTU not configured");
+                }
+            }
+            CliCommand::MtuFraming(framing) => {
+                log::info!("CLI: MTU framing set to {}", framing.name());
+                if let Some(ref mtu) = self.mtu {
+                    if mtu.is_running() {
+                        response.push_str("❌ Cannot change framing while MTU is running.\r\n");
+                        response.push_str("Use 'mtu_stop' first.");
+                    } else {
+                        mtu.set_framing(framing);
+                        response.push_str(&format!("MTU framing set to {}", framing.name()));
+                    }
+                } else {
+                    response.push_str("MTU not configured");
+                }
+            }
             CliCommand::MtuReset => {
                 log::info!("CLI: MTU statistics reset requested");
                 if let Some(ref mtu) = self.mtu {
@@ -224,25 +1096,31 @@ impl CommandHandler {
             CliCommand::WifiConnect(ssid, password) => {
                 log::info!("CLI: WiFi connect requested");
                 if let Some(ref wifi) = self.wifi {
-                    let ssid_ref = ssid.as_deref();
-                    let password_ref = password.as_deref();
-
                     match wifi.lock() {
-                        Ok(mut wifi_guard) => match wifi_guard.reconnect(ssid_ref, password_ref) {
-                            Ok(_) => {
-                                if ssid.is_none() {
-                                    response.push_str("✅ WiFi reconnected to default network");
-                                } else {
-                                    response.push_str(&format!(
-                                        "✅ WiFi connected to: {}",
-                                        ssid.as_ref().unwrap()
-                                    ));
+                        // Progress is reported from the main loop via connect_poll
+                        Ok(mut wifi_guard) => {
+                            match wifi_guard.connect_start(ssid.as_deref(), password.as_deref()) {
+                                Ok(_) => {
+                                    match ssid {
+                                        Some(ref ssid) => response.push_str(&format!(
+                                            "🌐 WiFi connecting to: {}...",
+                                            ssid
+                                        )),
+                                        None => response
+                                            .push_str("🌐 WiFi connecting to saved networks..."),
+                                    }
+                                    let command = match ssid {
+                                        Some(ref ssid) => format!("wifi_connect {}", ssid),
+                                        None => "wifi_connect".to_string(),
+                                    };
+                                    response.push_str(&self.start_wifi_job(&command));
+                                }
+                                Err(e) => {
+                                    response
+                                        .push_str(&format!("❌ WiFi connection failed: {:?}", e));
                                 }
                             }
-                            Err(e) => {
-                                response.push_str(&format!("❌ WiFi connection failed: {:?}", e));
-                            }
-                        },
+                        }
                         Err(_) => {
                             response.push_str("❌ WiFi manager lock error");
                         }
@@ -255,9 +1133,10 @@ impl CommandHandler {
                 log::info!("CLI: WiFi reconnect requested");
                 if let Some(ref wifi) = self.wifi {
                     match wifi.lock() {
-                        Ok(mut wifi_guard) => match wifi_guard.reconnect(None, None) {
+                        Ok(mut wifi_guard) => match wifi_guard.connect_start(None, None) {
                             Ok(_) => {
-                                response.push_str("✅ WiFi reconnected to default network");
+                                response.push_str("🌐 WiFi reconnecting to saved networks...");
+                                response.push_str(&self.start_wifi_job("wifi_reconnect"));
                             }
                             Err(e) => {
                                 response.push_str(&format!("❌ WiFi reconnect failed: {:?}", e));
@@ -271,6 +1150,106 @@ impl CommandHandler {
                     response.push_str("❌ WiFi not initialized");
                 }
             }
+            CliCommand::WifiSave(ssid, password, priority, auth) => {
+                log::info!("CLI: WiFi save requested for SSID: {}", ssid);
+                let result = self.update_wifi_networks(|networks| {
+                    networks.add(&ssid, &password, priority, auth)?;
+                    Ok(format!(
+                        "✅ WiFi network saved: {} (priority {}, {})",
+                        ssid,
+                        priority,
+                        auth.name()
+                    ))
+                });
+                response.push_str(&result);
+            }
+            CliCommand::WifiSaveEnterprise(ssid, identity, username, password, priority) => {
+                log::info!("CLI: WiFi enterprise save requested for SSID: {}", ssid);
+                let result = self.update_wifi_networks(|networks| {
+                    networks.add_enterprise(&ssid, &identity, &username, &password, priority)?;
+                    Ok(format!(
+                        "✅ WPA2-Enterprise network saved: {} (priority {}, user {})",
+                        ssid, priority, username
+                    ))
+                });
+                response.push_str(&result);
+            }
+            CliCommand::WifiCaCert(line) => {
+                log::info!("CLI: WiFi CA certificate command");
+                response.push_str(&self.handle_ca_cert(line));
+            }
+            CliCommand::WifiForget(ssid) => {
+                log::info!("CLI: WiFi forget requested for SSID: {}", ssid);
+                let result = self.update_wifi_networks(|networks| {
+                    if networks.remove(&ssid) {
+                        Ok(format!("✅ WiFi network removed: {}", ssid))
+                    } else {
+                        Err("Network not stored")
+                    }
+                });
+                response.push_str(&result);
+            }
+            CliCommand::WifiStatic(static_ip) => {
+                log::info!("CLI: WiFi static IP requested: {:?}", static_ip);
+                let result = self.update_wifi_networks(|networks| {
+                    let message = match static_ip {
+                        Some(ref config) => format!(
+                            "✅ Static IP saved: {}/{} gateway {} DNS {}",
+                            config.ip,
+                            config.prefix_len,
+                            config.gateway,
+                            config
+                                .dns
+                                .map(|dns| dns.to_string())
+                                .unwrap_or_else(|| "(none)".to_string())
+                        ),
+                        None => "✅ DHCP restored".to_string(),
+                    };
+                    networks.static_ip = static_ip;
+                    Ok(format!("{}\r\nTakes effect after 'reset'", message))
+                });
+                response.push_str(&result);
+            }
+            CliCommand::WifiScan => {
+                log::info!("CLI: WiFi scan requested");
+                match self.wifi {
+                    Some(ref wifi) => {
+                        let wifi = Arc::clone(wifi);
+                        let started = self.jobs.spawn("wifi_scan", "wifi_scan", move |_, _| {
+                            let networks = wifi
+                                .lock()
+                                .map_err(|_| "❌ WiFi manager lock error".to_string())?
+                                .scan()
+                                .map_err(|e| format!("❌ WiFi scan failed: {:?}", e))?;
+                            let mut out = format!("Found {} networks:\r\n", networks.len());
+                            out.push_str(&format!(
+                                "  {:<32} {:>5} {:>3}  {}",
+                                "SSID", "RSSI", "CH", "Auth"
+                            ));
+                            for network in networks.iter() {
+                                let ssid = if network.ssid.is_empty() {
+                                    "(hidden)"
+                                } else {
+                                    network.ssid.as_str()
+                                };
+                                out.push_str(&format!(
+                                    "\r\n  {:<32} {:>5} {:>3}  {}",
+                                    ssid,
+                                    network.rssi,
+                                    network.channel,
+                                    network.auth_name()
+                                ));
+                            }
+                            Ok(out)
+                        });
+                        match started {
+                            Ok(id) => response.push_str(&job_started(id, "wifi_scan")),
+                            Err(e) => response.push_str(&format!("❌ {}", e)),
+                        }
+                    }
+                    None => response.push_str("❌ WiFi not initialized"),
+                }
+            }
             CliCommand::WifiStatus => {
                 log::info!("CLI: WiFi status requested");
                 if let Some(ref wifi) = self.wifi {
@@ -287,13 +1266,79 @@ impl CommandHandler {
                                             response
                                                 .push_str(&format!("\r\nSSID: {}", ssid.as_str()));
                                         }
+                                        if let Ok(rssi) = wifi_guard.get_rssi() {
+                                            response.push_str(&format!(
+                                                "\r\nRSSI: {} dBm ({})",
+                                                rssi,
+                                                rssi_quality(rssi)
+                                            ));
+                                        }
+                                        if let Ok(channel) = wifi_guard.get_channel() {
+                                            response.push_str(&format!("\r\nChannel: {}", channel));
+                                        }
+                                        match wifi_guard.active_profile() {
+                                            Some(profile) => response.push_str(&format!(
+                                                "\r\nProfile: {} (priority {})",
+                                                profile.ssid, profile.priority
+                                            )),
+                                            None => response.push_str("\r\nProfile: (manual)"),
+                                        }
                                     } else {
                                         response
                                             .push_str("WiFi Status: Connected (IP unavailable)");
                                     }
+                                } else if wifi_guard.is_connecting() {
+                                    response.push_str("WiFi Status: Connecting...");
                                 } else {
                                     response.push_str("WiFi Status: Disconnected");
                                 }
+                                response.push_str(if wifi_guard.is_static_ip() {
+                                    "\r\nIP mode: Static"
+                                } else {
+                                    "\r\nIP mode: DHCP"
+                                });
+                                if let Ok(mac) = wifi_guard.get_mac() {
+                                    response.push_str(&format!("\r\nMAC: {}", mac));
+                                }
+                                if let Ok(hostname) = wifi_guard.get_hostname() {
+                                    response.push_str(&format!("\r\nHostname: {}", hostname));
+                                }
+                                let stats = wifi_guard.link_stats();
+                                response.push_str(&format!(
+                                    "\r\nConnect attempts: {} ({} failed)",
+                                    stats.connect_attempts, stats.connect_failures
+                                ));
+                                response.push_str(&format!(
+                                    "\r\nLink events: {} connects, {} disconnects, {} auto-reconnect attempts",
+                                    stats.connects, stats.disconnects, stats.reconnect_attempts
+                                ));
+                                response.push_str(&format!(
+                                    "\r\nOnline time: {}h {}m {}s",
+                                    stats.online_secs / 3600,
+                                    (stats.online_secs % 3600) / 60,
+                                    stats.online_secs % 60
+                                ));
+                                if let Some(reason) = stats.last_disconnect_reason {
+                                    response.push_str(&format!(
+                                        "\r\nLast disconnect: {} ({})",
+                                        reason,
+                                        disconnect_reason_name(reason)
+                                    ));
+                                }
+
+                                let networks = wifi_guard.networks().by_priority();
+                                response.push_str(&format!(
+                                    "\r\nStored networks ({}):",
+                                    networks.len()
+                                ));
+                                for network in networks {
+                                    response.push_str(&format!(
+                                        "\r\n  [{}] {} ({})",
+                                        network.priority,
+                                        network.ssid,
+                                        network.auth.name()
+                                    ));
+                                }
                             }
                             Err(_) => {
                                 response.push_str("WiFi Status: Error checking connection");
@@ -307,10 +1352,9 @@ impl CommandHandler {
                     response.push_str("WiFi Status: Not initialized");
                 }
             }
-            CliCommand::MqttConnect(_broker_url) => {
-                log::info!("CLI: MQTT connect requested");
-                response
-                    .push_str("MQTT connect not available - MQTT must be initialized at startup");
+            CliCommand::MqttConnect(broker_url) => {
+                log::info!("CLI: MQTT connect requested: {}", broker_url);
+                response.push_str(&self.handle_mqtt_connect(&broker_url));
             }
             CliCommand::MqttStatus => {
                 log::info!("CLI: MQTT status requested");
@@ -340,6 +1384,12 @@ impl CommandHandler {
                     let recv_count = *status.receive_count.lock().unwrap();
                     response.push_str(&format!("  Published: {} messages\r\n", pub_count));
                     response.push_str(&format!("  Received: {} messages\r\n", recv_count));
+                    let failed = *status.failed_publishes.lock().unwrap();
+                    let unacked = *status.unacked_publishes.lock().unwrap();
+                    response.push_str(&format!(
+                        "  Failed: {} publishes, unacknowledged: {}\r\n",
+                        failed, unacked
+                    ));
 
                     let last_pub = status.last_published_topic.lock().unwrap();
                     if !last_pub.is_empty() {
@@ -374,6 +1424,280 @@ impl CommandHandler {
                     response.push_str("MQTT not initialized");
                 }
             }
+            CliCommand::MqttCert(slot, line) => {
+                log::info!("CLI: MQTT {} certificate requested", slot);
+                response.push_str(&self.handle_mqtt_cert(&slot, line));
+            }
+            CliCommand::ConfigShow => {
+                log::info!("CLI: Config show requested");
+                if let Some(ref config) = self.config {
+                    response.push_str("Configuration:\r\n");
+                    response.push_str(&config.describe());
+                } else {
+                    response.push_str("Configuration not available");
+                }
+            }
+            CliCommand::ConfigSet(key, value) => {
+                log::info!("CLI: Config set requested: {}", key);
+                if let Some(ref mut config) = self.config {
+                    match config.set(&key, &value) {
+                        Ok(_) => {
+                            response.push_str(&format!("{} updated\r\n", key));
+                            response.push_str("Use 'config save' then 'reset' to apply");
+                        }
+                        Err(e) => {
+                            response.push_str(&format!("config set {}: {}\r\n", key, e));
+                            response.push_str("Keys: ");
+                            response.push_str(&CONFIG_KEYS.join(", "));
+                        }
+                    }
+                } else {
+                    response.push_str("Configuration not available");
+                }
+            }
+            CliCommand::ConfigSave => {
+                log::info!("CLI: Config save requested");
+                match (&self.config, &mut self.config_store) {
+                    (Some(config), Some(store)) => match store.save(config) {
+                        Ok(_) => response.push_str("Configuration saved to NVS"),
+                        Err(e) => {
+                            response.push_str(&format!("Failed to save configuration: {:?}", e))
+                        }
+                    },
+                    _ => response.push_str("Configuration storage not available"),
+                }
+            }
+            CliCommand::LogShow => {
+                log::info!("CLI: Log show requested");
+                let contents = logging::contents();
+                if contents.is_empty() {
+                    response.push_str("Log buffer empty");
+                } else {
+                    response.push_str(&contents.replace('\n', "\r\n"));
+                    response.push_str(&format!(
+                        "({} of {} bytes)",
+                        contents.len(),
+                        logging::LOG_BUFFER_SIZE
+                    ));
+                }
+            }
+            CliCommand::LogClear => {
+                log::info!("CLI: Log clear requested");
+                logging::clear();
+                response.push_str("Log buffer cleared");
+            }
+            CliCommand::LogUpload => {
+                log::info!("CLI: Log upload requested");
+                logging::request_upload();
+                response.push_str("Log buffer will be uploaded with the next MQTT session");
+            }
+            CliCommand::Export(count) => {
+                log::info!("CLI: Export of {} readings requested", count);
+                let result = match self.data_log {
+                    Some(ref data_log) => data_log
+                        .lock()
+                        .map_err(|_| anyhow::anyhow!("Data log unavailable"))
+                        .and_then(|data_log| {
+                            Ok((data_log.last_readings_csv(count)?, data_log.stored_bytes()))
+                        }),
+                    None => Err(anyhow::anyhow!(
+                        "Data log not enabled (storage.backend none)"
+                    )),
+                };
+                match result {
+                    Ok((lines, stored_bytes)) => {
+                        response.push_str(CSV_HEADER);
+                        response.push_str("\r\n");
+                        for line in &lines {
+                            response.push_str(line);
+                            response.push_str("\r\n");
+                        }
+                        response.push_str(&format!(
+                            "({} readings, {} KB stored)",
+                            lines.len(),
+                            stored_bytes / 1024
+                        ));
+                    }
+                    Err(e) => response.push_str(&format!("❌ Export failed: {}", e)),
+                }
+            }
+            CliCommand::ExportUpload(count) => {
+                log::info!("CLI: Export upload of {} readings requested", count);
+                if self.data_log.is_none() {
+                    response.push_str("❌ Data log not enabled (storage.backend none)");
+                } else {
+                    storage::request_export(count);
+                    response.push_str(&format!(
+                        "Last {} readings will be published to the export topic with the next MQTT session",
+                        count
+                    ));
+                }
+            }
+            CliCommand::MetersList => {
+                log::info!("CLI: Meters list requested");
+                match self.config {
+                    Some(ref config) => response.push_str(&config.meters.describe()),
+                    None => response.push_str("Configuration not available"),
+                }
+            }
+            CliCommand::MetersAdd(id, channel, interval, subtopic) => {
+                log::info!("CLI: Meter add requested: {}", id);
+                match self.config {
+                    Some(ref mut config) => {
+                        match config
+                            .meters
+                            .add(&id, channel, interval, subtopic.as_deref())
+                        {
+                            Ok(_) => {
+                                response.push_str(&format!(
+                                    "Meter {} on channel {} every {}s\r\n",
+                                    id, channel, interval
+                                ));
+                                response.push_str("Use 'config save' then 'reset' to apply");
+                            }
+                            Err(e) => response.push_str(&format!("meters add: {}", e)),
+                        }
+                    }
+                    None => response.push_str("Configuration not available"),
+                }
+            }
+            CliCommand::MetersSet(id, field, value) => {
+                log::info!("CLI: Meter {} {} set to {}", id, field, value);
+                match self
+                    .config
+                    .as_mut()
+                    .map(|config| config.meters.set(&id, &field, &value))
+                {
+                    Some(Ok(_)) => {
+                        response.push_str(&format!("Meter {} {} set to {}\r\n", id, field, value));
+                        response.push_str("Use 'config save' then 'reset' to apply");
+                    }
+                    Some(Err(e)) => response.push_str(&format!("meters set: {}", e)),
+                    None => response.push_str("Configuration not available"),
+                }
+            }
+            CliCommand::MetersRemove(id) => {
+                log::info!("CLI: Meter remove requested: {}", id);
+                match self.config.as_mut().map(|config| config.meters.remove(&id)) {
+                    Some(true) => {
+                        response.push_str(&format!("Meter {} removed\r\n", id));
+                        response.push_str("Use 'config save' then 'reset' to apply");
+                    }
+                    Some(false) => response.push_str(&format!("No meter '{}'", id)),
+                    None => response.push_str("Configuration not available"),
+                }
+            }
+            CliCommand::Jobs => {
+                log::info!("CLI: Jobs requested");
+                response.push_str(&self.jobs.describe());
+            }
+            CliCommand::JobStatus(id) => {
+                log::info!("CLI: Job {} status requested", id);
+                match self.jobs.status(id) {
+                    Some(status) => response.push_str(&status),
+                    None => response.push_str(&format!("❌ No job {}", id)),
+                }
+            }
+            CliCommand::Login(password) => {
+                log::info!("CLI: Login requested");
+                response.push_str(&self.handle_login(password));
+            }
+            CliCommand::Logout => {
+                log::info!("CLI: Logout requested");
+                self.session.end();
+                response.push_str("Logged out");
+            }
+            CliCommand::Passwd(password) => {
+                log::info!("CLI: Password change requested");
+                response.push_str(&self.handle_passwd(&password));
+            }
+            CliCommand::AliasList => {
+                log::info!("CLI: Alias list requested");
+                let aliases = CommandParser::aliases();
+                response.push_str("Built-in:");
+                for (name, command) in BUILTIN_ALIASES {
+                    if !aliases.contains_key(*name) {
+                        response.push_str(&format!("\r\n  {:<8} = {}", name, command));
+                    }
+                }
+                if aliases.is_empty() {
+                    response.push_str("\r\nNo aliases defined (use 'alias <name> <command>')");
+                } else {
+                    response.push_str(&format!("\r\nAliases ({}):", aliases.len()));
+                    for (name, command) in &aliases {
+                        response.push_str(&format!("\r\n  {:<8} = {}", name, command));
+                    }
+                }
+            }
+            CliCommand::AliasSet(name, command) => {
+                log::info!("CLI: Alias set requested: {}", name);
+                response.push_str(&self.handle_alias_set(&name, &command));
+            }
+            CliCommand::AliasRemove(name) => {
+                log::info!("CLI: Alias remove requested: {}", name);
+                let mut aliases = CommandParser::aliases();
+                if aliases.remove(&name).is_none() {
+                    response.push_str(&format!("❌ No alias '{}'", name));
+                } else if let Some(ref mut store) = self.config_store {
+                    match store.save_aliases(&aliases) {
+                        Ok(_) => {
+                            CommandParser::set_aliases(aliases);
+                            response.push_str(&format!("✅ Alias '{}' removed", name));
+                        }
+                        Err(e) => response.push_str(&format!("❌ Failed to remove alias: {:?}", e)),
+                    }
+                } else {
+                    response.push_str("❌ Alias storage not available");
+                }
+            }
+            CliCommand::Run(target) => {
+                log::info!("CLI: run requested: {}", target);
+                response.push_str(&self.handle_run(&target));
+            }
+            CliCommand::ScriptSave(name, commands, append) => {
+                log::info!("CLI: Script save requested: {}", name);
+                response.push_str(&self.handle_script_save(&name, &commands, append));
+            }
+            CliCommand::ScriptList => {
+                log::info!("CLI: Script list requested");
+                match self.config_store.as_ref().map(|store| store.load_scripts()) {
+                    Some(Ok(scripts)) if scripts.is_empty() => {
+                        response.push_str("No scripts saved (use 'script save')")
+                    }
+                    Some(Ok(scripts)) => {
+                        response.push_str(&format!("Scripts ({}):", scripts.len()));
+                        for (name, script) in &scripts {
+                            response.push_str(&format!("\r\n  {}: {}", name, script));
+                        }
+                    }
+                    Some(Err(e)) => {
+                        response.push_str(&format!("❌ Failed to load scripts: {:?}", e))
+                    }
+                    None => response.push_str("❌ Script storage not available"),
+                }
+            }
+            CliCommand::ScriptDelete(name) => {
+                log::info!("CLI: Script delete requested: {}", name);
+                match self.config_store {
+                    Some(ref mut store) => {
+                        let result = store.load_scripts().and_then(|mut scripts| {
+                            let found = scripts.remove(&name).is_some();
+                            if found {
+                                store.save_scripts(&scripts)?;
+                            }
+                            Ok(found)
+                        });
+                        match result {
+                            Ok(true) => response.push_str(&format!("✅ Script '{}' deleted", name)),
+                            Ok(false) => response.push_str(&format!("❌ No script '{}'", name)),
+                            Err(e) => {
+                                response.push_str(&format!("❌ Failed to delete script: {:?}", e))
+                            }
+                        }
+                    }
+                    None => response.push_str("❌ Script storage not available"),
+                }
+            }
             CliCommand::Unknown(cmd) => {
                 log::info!("CLI: Unknown command: {}", cmd);
                 response.push_str("Unknown command: ");
@@ -385,3 +1709,27 @@ impl CommandHandler {
         Ok(response)
     }
 }
+
+/// Add one pasted PEM line to `buffer`. Returns the complete PEM once the
+/// `-----END` line arrives, None while more lines are expected.
+fn paste_pem_line(
+    buffer: &mut String,
+    line: &str,
+    max_len: usize,
+) -> Result<Option<Vec<u8>>, String> {
+    if line.starts_with("-----BEGIN") {
+        buffer.clear();
+    } else if buffer.is_empty() {
+        return Err("❌ Start with the '-----BEGIN ...-----' line".to_string());
+    }
+    buffer.push_str(line);
+    buffer.push('\n');
+    if buffer.len() > max_len {
+        buffer.clear();
+        return Err(format!("❌ PEM too large (max {} bytes)", max_len));
+    }
+    if !line.starts_with("-----END") {
+        return Ok(None);
+    }
+    Ok(Some(core::mem::take(buffer).into_bytes()))
+}
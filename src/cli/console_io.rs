@@ -0,0 +1,81 @@
+//! Hardware ports the serial console's `Terminal` runs on
+//!
+//! Kept apart from the line editor in `terminal`, which only needs a
+//! `TerminalIo` and so also runs over telnet or in-memory buffers.
+
+use super::terminal::{Terminal, TerminalIo};
+use super::CliError;
+use esp_idf_hal::uart::{UartRxDriver, UartTxDriver};
+#[cfg(feature = "usb-console")]
+use esp_idf_hal::{delay::TickType, usb_serial::UsbSerialDriver};
+
+/// Longest wait for the USB host to take console output
+#[cfg(feature = "usb-console")]
+const USB_WRITE_TIMEOUT_MS: u64 = 50;
+
+/// The port the firmware was built to run its console on
+#[cfg(not(feature = "usb-console"))]
+pub type ConsoleIo<'d> = UartIo<'d>;
+#[cfg(feature = "usb-console")]
+pub type ConsoleIo<'d> = UsbSerialIo<'d>;
+
+pub struct UartIo<'d> {
+    pub tx: UartTxDriver<'d>,
+    pub rx: UartRxDriver<'d>,
+}
+
+impl TerminalIo for UartIo<'_> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), CliError> {
+        self.tx.write(bytes).map_err(|_| CliError::UartError)?;
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, CliError> {
+        let mut buf = [0u8; 1];
+        match self.rx.read(&mut buf, 0) {
+            Ok(1) => Ok(Some(buf[0])),
+            Ok(_) => Ok(None),
+            Err(_) => Err(CliError::UartError),
+        }
+    }
+}
+
+/// Built-in USB-Serial-JTAG port of the ESP32-S3/C3 (`usb-console` feature)
+#[cfg(feature = "usb-console")]
+pub struct UsbSerialIo<'d> {
+    pub usb: UsbSerialDriver<'d>,
+}
+
+#[cfg(feature = "usb-console")]
+impl TerminalIo for UsbSerialIo<'_> {
+    fn write_bytes(&mut self, mut bytes: &[u8]) -> Result<(), CliError> {
+        // Without a host reading the port the TX FIFO stays full; give up
+        // instead of stalling the console loop
+        let timeout = TickType::new_millis(USB_WRITE_TIMEOUT_MS).ticks();
+        while !bytes.is_empty() {
+            match self.usb.write(bytes, timeout) {
+                Ok(0) | Err(_) => return Err(CliError::UartError),
+                Ok(written) => bytes = &bytes[written..],
+            }
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, CliError> {
+        let mut buf = [0u8; 1];
+        match self.usb.read(&mut buf, 0) {
+            Ok(1) => Ok(Some(buf[0])),
+            Ok(_) => Ok(None),
+            Err(_) => Err(CliError::UartError),
+        }
+    }
+}
+
+impl<'d> Terminal<UartIo<'d>> {
+    pub fn new(uart_tx: UartTxDriver<'d>, uart_rx: UartRxDriver<'d>) -> Self {
+        Self::with_io(UartIo {
+            tx: uart_tx,
+            rx: uart_rx,
+        })
+    }
+}
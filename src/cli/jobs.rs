@@ -0,0 +1,254 @@
+//! Background jobs started from the CLI
+//!
+//! Commands that take a while (`wifi_connect`, `wifi_reconnect`, `wifi_scan`,
+//! `mqtt_connect`) answer at once with a job number and finish in the
+//! background, so the console (and a remote command) is not held up:
+//!
+//! ```text
+//! ESP32 CLI> wifi_scan
+//! Job 3 started: wifi_scan (see 'jobs', 'job_status 3')
+//! ...
+//! ✅ Job 3 (wifi_scan) done: Found 7 networks:
+//! ```
+//!
+//! `jobs` lists running and recent jobs with their progress, `job_status <id>`
+//! shows one job's full output. The last `MAX_FINISHED_JOBS` finished jobs
+//! are kept.
+//!
+//! The on-demand publish cycle is not a job: it is started by a reading, not
+//! a command, and runs on the main loop, which owns the publisher. There is
+//! no OTA download command to run as one (`ota.rs` only checks new images).
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+pub type JobId = u32;
+
+const MAX_FINISHED_JOBS: usize = 8;
+const JOB_STACK_SIZE: usize = 8192;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Done,
+    Failed,
+}
+
+impl JobState {
+    pub fn name(self) -> &'static str {
+        match self {
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+        }
+    }
+}
+
+struct Job {
+    id: JobId,
+    /// Groups jobs driving the same resource, e.g. "wifi"
+    kind: &'static str,
+    command: String,
+    started: Instant,
+    finished: Option<Instant>,
+    state: JobState,
+    progress: String,
+    output: String,
+    /// Completion not yet shown on the console
+    announce: bool,
+}
+
+#[derive(Default)]
+struct JobTable {
+    next_id: JobId,
+    jobs: Vec<Job>,
+}
+
+/// Shared job table; clones refer to the same jobs
+#[derive(Clone, Default)]
+pub struct Jobs {
+    table: Arc<Mutex<JobTable>>,
+}
+
+impl Jobs {
+    /// Register a job driven by someone else (see `progress` and `finish`)
+    pub fn start(&self, kind: &'static str, command: &str) -> JobId {
+        let mut table = self.table.lock().unwrap();
+        table.next_id += 1;
+        let id = table.next_id;
+        table.jobs.push(Job {
+            id,
+            kind,
+            command: command.to_string(),
+            started: Instant::now(),
+            finished: None,
+            state: JobState::Running,
+            progress: String::new(),
+            output: String::new(),
+            announce: false,
+        });
+        log::info!("CLI: Job {} started: {}", id, command);
+        id
+    }
+
+    /// Run `work` in its own task; its Ok/Err text becomes the job output
+    pub fn spawn<F>(&self, kind: &'static str, command: &str, work: F) -> Result<JobId, String>
+    where
+        F: FnOnce(&Jobs, JobId) -> Result<String, String> + Send + 'static,
+    {
+        let id = self.start(kind, command);
+        let jobs = self.clone();
+        let spawned = std::thread::Builder::new()
+            .name("cli_job".to_string())
+            .stack_size(JOB_STACK_SIZE)
+            .spawn(move || {
+                let result = work(&jobs, id);
+                jobs.finish(id, result);
+            });
+        match spawned {
+            Ok(_) => Ok(id),
+            Err(e) => {
+                let message = format!("Failed to start job: {:?}", e);
+                self.finish(id, Err(message.clone()));
+                Err(message)
+            }
+        }
+    }
+
+    /// Latest progress line of a running job
+    pub fn progress(&self, id: JobId, text: &str) {
+        if let Some(job) = self.table.lock().unwrap().find(id) {
+            job.progress = text.to_string();
+        }
+    }
+
+    pub fn finish(&self, id: JobId, result: Result<String, String>) {
+        let mut table = self.table.lock().unwrap();
+        if let Some(job) = table.find(id) {
+            let (state, output) = match result {
+                Ok(output) => (JobState::Done, output),
+                Err(output) => (JobState::Failed, output),
+            };
+            log::info!("CLI: Job {} {}", id, state.name());
+            job.state = state;
+            job.output = output;
+            job.progress.clear();
+            job.finished = Some(Instant::now());
+            job.announce = true;
+        }
+        table.prune();
+    }
+
+    /// The running job of a kind, if any
+    pub fn running(&self, kind: &str) -> Option<JobId> {
+        let table = self.table.lock().unwrap();
+        table
+            .jobs
+            .iter()
+            .find(|job| job.kind == kind && job.state == JobState::Running)
+            .map(|job| job.id)
+    }
+
+    /// One line per job that finished since the last call, for the console
+    pub fn take_announcements(&self) -> Vec<String> {
+        let mut table = self.table.lock().unwrap();
+        table
+            .jobs
+            .iter_mut()
+            .filter(|job| job.announce)
+            .map(|job| {
+                job.announce = false;
+                let icon = if job.state == JobState::Done {
+                    "✅"
+                } else {
+                    "❌"
+                };
+                format!(
+                    "{} Job {} ({}) {}: {}",
+                    icon,
+                    job.id,
+                    job.command,
+                    job.state.name(),
+                    job.output.lines().next().unwrap_or("")
+                )
+            })
+            .collect()
+    }
+
+    /// `jobs`
+    pub fn describe(&self) -> String {
+        let table = self.table.lock().unwrap();
+        if table.jobs.is_empty() {
+            return "No jobs".to_string();
+        }
+        let mut out = format!("Jobs ({}):", table.jobs.len());
+        for job in &table.jobs {
+            let elapsed = job
+                .finished
+                .unwrap_or_else(Instant::now)
+                .duration_since(job.started)
+                .as_secs();
+            out.push_str(&format!(
+                "\r\n  {:>3}  {:<7} {:>4}s  {}",
+                job.id,
+                job.state.name(),
+                elapsed,
+                job.command
+            ));
+            if !job.progress.is_empty() {
+                out.push_str(&format!(" - {}", job.progress));
+            }
+        }
+        out
+    }
+
+    /// `job_status <id>`
+    pub fn status(&self, id: JobId) -> Option<String> {
+        let table = self.table.lock().unwrap();
+        let job = table.jobs.iter().find(|job| job.id == id)?;
+        let mut out = format!(
+            "Job {}: {}\r\n  State: {}\r\n  Started: {}s ago",
+            job.id,
+            job.command,
+            job.state.name(),
+            job.started.elapsed().as_secs()
+        );
+        if let Some(finished) = job.finished {
+            out.push_str(&format!(
+                "\r\n  Took: {}s",
+                finished.duration_since(job.started).as_secs()
+            ));
+        }
+        if !job.progress.is_empty() {
+            out.push_str(&format!("\r\n  Progress: {}", job.progress));
+        }
+        if !job.output.is_empty() {
+            out.push_str("\r\n");
+            out.push_str(&job.output);
+        }
+        Some(out)
+    }
+}
+
+impl JobTable {
+    fn find(&mut self, id: JobId) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    /// Drop the oldest finished jobs beyond `MAX_FINISHED_JOBS`
+    fn prune(&mut self) {
+        let mut finished = self
+            .jobs
+            .iter()
+            .filter(|job| job.state != JobState::Running)
+            .count();
+        self.jobs.retain(|job| {
+            if finished > MAX_FINISHED_JOBS && job.state != JobState::Running && !job.announce {
+                finished -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
@@ -1,12 +1,14 @@
-use super::meter_parser::MeterCommand;
+use super::base::{self, restart_after, REBOOT_DELAY};
+use super::meter_parser::{MeterCommand, METER_COMMANDS};
 use super::CliError;
-use crate::meter::{MeterHandler, MeterType};
+use crate::meter::{MeterHandler, MeterStorage, MeterType, MAX_SCRIPT_MESSAGES};
 use std::sync::Arc;
 use std::time::Instant;
 
 pub struct MeterCommandHandler {
     start_time: Instant,
     meter: Option<Arc<MeterHandler>>,
+    storage: Option<MeterStorage>,
 }
 
 impl Default for MeterCommandHandler {
@@ -20,6 +22,7 @@ impl MeterCommandHandler {
         Self {
             start_time: Instant::now(),
             meter: None,
+            storage: None,
         }
     }
 
@@ -28,9 +31,38 @@ impl MeterCommandHandler {
         self
     }
 
+    /// Persist configuration changes to NVS
+    pub fn with_storage(mut self, storage: MeterStorage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Save the current meter configuration if storage is attached
+    fn save_config(&mut self) {
+        if let (Some(meter), Some(storage)) = (&self.meter, &mut self.storage) {
+            if let Err(e) = storage.save(&meter.get_config()) {
+                log::warn!("CLI: Failed to save meter config: {:?}", e);
+            }
+        }
+    }
+
     pub fn execute_command(&mut self, command: MeterCommand) -> Result<String, CliError> {
         let mut response = String::new();
 
+        let modifies_config = matches!(
+            command,
+            MeterCommand::Enable
+                | MeterCommand::Disable
+                | MeterCommand::SetType(_)
+                | MeterCommand::SetMessage(_)
+                | MeterCommand::SetDebounce(_)
+                | MeterCommand::SetThreshold(_)
+                | MeterCommand::ScriptAdd(_)
+                | MeterCommand::ScriptClear
+                | MeterCommand::SetFlag(_, _)
+                | MeterCommand::ClearFlags
+        );
+
         match command {
             MeterCommand::Empty => {
                 // Empty command - just return empty response (no error)
@@ -39,10 +71,12 @@ impl MeterCommandHandler {
                 // Help is handled in terminal.rs
                 response.push_str("Help displayed");
             }
+            MeterCommand::HelpCommand(name) => match METER_COMMANDS.describe(&name) {
+                Some(help) => response.push_str(&help),
+                None => response.push_str(&format!("❌ Unknown command '{}' (see 'help')", name)),
+            },
             MeterCommand::Version => {
-                log::info!("CLI: Version requested");
-                response.push_str("ESP32 Water Meter Simulator v1.0.0\r\n");
-                response.push_str("Built with ESP-IDF");
+                response.push_str(&base::version("ESP32 Water Meter Simulator v1.0.0"));
             }
             MeterCommand::Status => {
                 log::info!("CLI: Meter status requested");
@@ -66,8 +100,40 @@ impl MeterCommandHandler {
                         config.response_message.as_str(),
                         config.response_message.len()
                     ));
+                    response.push_str(&format!("  Status flags: {}\r\n", config.status_flags));
+                    if config.flags_dropped() {
+                        response.push_str(&format!(
+                            "  ⚠️  No {} field in the message, flags are not sent\r\n",
+                            config.meter_type.flags_field()
+                        ));
+                    }
+                    if !config.script.is_empty() {
+                        response.push_str(&format!(
+                            "  Script: {} messages (next: #{})\r\n",
+                            config.script.len(),
+                            meter.script_position() + 1
+                        ));
+                    }
+                    response.push_str(&format!(
+                        "  Wake-up threshold: {} pulses\r\n",
+                        config.wake_up_threshold
+                    ));
+                    response.push_str(&format!("  Debounce: {}us\r\n", config.debounce_us));
+                    match meter.clock_rate() {
+                        Some(rate) => response.push_str(&format!(
+                            "  Clock rate: {} Hz (~{} baud), last edge {}s ago\r\n",
+                            rate.hz,
+                            rate.baud,
+                            rate.idle_ms / 1000
+                        )),
+                        None => response.push_str("  Clock rate: no clock seen yet\r\n"),
+                    }
                     response.push_str("  Statistics:\r\n");
                     response.push_str(&format!("    Clock pulses: {}\r\n", pulses));
+                    response.push_str(&format!(
+                        "    Filtered edges: {}\r\n",
+                        meter.get_filtered_edges()
+                    ));
                     response.push_str(&format!("    Bits transmitted: {}\r\n", bits_tx));
                     response.push_str(&format!("    Messages sent: {}\r\n", messages));
                     response.push_str(&format!(
@@ -78,35 +144,13 @@ impl MeterCommandHandler {
                     response.push_str("Meter not configured");
                 }
             }
-            MeterCommand::Uptime => {
-                log::info!("CLI: Uptime requested");
-                let uptime = self.start_time.elapsed();
-                let uptime_secs = uptime.as_secs();
-                let hours = uptime_secs / 3600;
-                let minutes = (uptime_secs % 3600) / 60;
-                let seconds = uptime_secs % 60;
-
-                response.push_str("Uptime: ");
-                if hours > 0 {
-                    response.push_str(&format!("{}h ", hours));
-                }
-                if minutes > 0 || hours > 0 {
-                    response.push_str(&format!("{}m ", minutes));
-                }
-                response.push_str(&format!("{}s", seconds));
-            }
+            MeterCommand::Uptime => response.push_str(&base::uptime(self.start_time)),
+            MeterCommand::ChipInfo => response.push_str(&base::chip_info()),
             MeterCommand::Clear => {
                 // Clear is handled in terminal.rs
                 response.push_str("Screen cleared");
             }
-            MeterCommand::Reset => {
-                log::info!("CLI: Reset requested");
-                response.push_str("Resetting system...");
-                // Perform system reset using ESP-IDF
-                unsafe {
-                    esp_idf_svc::sys::esp_restart();
-                }
-            }
+            MeterCommand::Reset => base::reset(),
             MeterCommand::Enable => {
                 log::info!("CLI: Meter enable requested");
                 if let Some(ref meter) = self.meter {
@@ -157,12 +201,212 @@ impl MeterCommandHandler {
                     response.push_str("Meter not configured");
                 }
             }
+            MeterCommand::SetDebounce(debounce_us) => {
+                log::info!("CLI: Meter debounce set to {}us", debounce_us);
+                if let Some(ref meter) = self.meter {
+                    meter.set_debounce_us(debounce_us);
+                    if debounce_us == 0 {
+                        response.push_str("Clock debounce disabled");
+                    } else {
+                        response.push_str(&format!(
+                            "Clock debounce set to {}us (edges closer than this are ignored)",
+                            debounce_us
+                        ));
+                    }
+                } else {
+                    response.push_str("Meter not configured");
+                }
+            }
+            MeterCommand::SetThreshold(pulses) => {
+                log::info!("CLI: Meter wake-up threshold set to {}", pulses);
+                if let Some(ref meter) = self.meter {
+                    meter.set_wake_up_threshold(pulses);
+                    response.push_str(&format!("Wake-up threshold set to {} clock pulses", pulses));
+                } else {
+                    response.push_str("Meter not configured");
+                }
+            }
+            MeterCommand::ScriptAdd(text) => {
+                log::info!("CLI: Meter script add: {}", text);
+                if let Some(ref meter) = self.meter {
+                    let mut heapless_msg = heapless::String::<256>::new();
+                    if heapless_msg.push_str(&text).is_err() {
+                        response.push_str("Error: Message too long (max 256 characters)");
+                    } else {
+                        match meter.script_add(heapless_msg) {
+                            Some(count) => {
+                                response.push_str(&format!(
+                                    "Script entry #{} added ({} characters)",
+                                    count,
+                                    text.len()
+                                ));
+                            }
+                            None => {
+                                response.push_str(&format!(
+                                    "Error: Script full (max {} messages)",
+                                    MAX_SCRIPT_MESSAGES
+                                ));
+                            }
+                        }
+                    }
+                } else {
+                    response.push_str("Meter not configured");
+                }
+            }
+            MeterCommand::ScriptList => {
+                log::info!("CLI: Meter script list requested");
+                if let Some(ref meter) = self.meter {
+                    let config = meter.get_config();
+                    if config.script.is_empty() {
+                        response.push_str("Script empty - sending response message on every read");
+                    } else {
+                        let next = meter.script_position();
+                        response.push_str(&format!("Script ({} messages):", config.script.len()));
+                        for (i, msg) in config.script.iter().enumerate() {
+                            response.push_str(&format!(
+                                "\r\n  {}{}: '{}'",
+                                if i == next { "> " } else { "  " },
+                                i + 1,
+                                msg.trim_end_matches('\r')
+                            ));
+                        }
+                    }
+                } else {
+                    response.push_str("Meter not configured");
+                }
+            }
+            MeterCommand::ScriptClear => {
+                log::info!("CLI: Meter script clear requested");
+                if let Some(ref meter) = self.meter {
+                    meter.script_clear();
+                    response.push_str("Script cleared - sending response message on every read");
+                } else {
+                    response.push_str("Meter not configured");
+                }
+            }
+            MeterCommand::SetFlag(bit, on) => {
+                log::info!("CLI: Meter status flag {:#04x} set {}", bit, on);
+                if let Some(ref meter) = self.meter {
+                    meter.set_status_flag(bit, on);
+                    let config = meter.get_config();
+                    let field = config.meter_type.flags_field();
+                    response.push_str(&format!(
+                        "Status flags: {} (encoded in the {} field)",
+                        config.status_flags, field
+                    ));
+                    if config.flags_dropped() {
+                        response.push_str(&format!(
+                            "\r\n⚠️  The message has no {} field, flags are not sent with it",
+                            field
+                        ));
+                    }
+                } else {
+                    response.push_str("Meter not configured");
+                }
+            }
+            MeterCommand::ClearFlags => {
+                log::info!("CLI: Meter status flags clear requested");
+                if let Some(ref meter) = self.meter {
+                    meter.clear_status_flags();
+                    response.push_str("Status flags cleared - message sent unmodified");
+                } else {
+                    response.push_str("Meter not configured");
+                }
+            }
+            MeterCommand::ShowLog => {
+                log::info!("CLI: Meter event log requested");
+                if let Some(ref meter) = self.meter {
+                    let events = meter.get_events();
+                    if events.is_empty() {
+                        response.push_str("Event log empty");
+                    } else {
+                        response.push_str(&format!("Event log ({} events):", events.len()));
+                        for event in events.iter() {
+                            response.push_str(&format!("\r\n  {}", event));
+                        }
+                    }
+                } else {
+                    response.push_str("Meter not configured");
+                }
+            }
+            MeterCommand::ClearLog => {
+                log::info!("CLI: Meter event log clear requested");
+                if let Some(ref meter) = self.meter {
+                    meter.clear_events();
+                    response.push_str("Event log cleared");
+                } else {
+                    response.push_str("Meter not configured");
+                }
+            }
+            MeterCommand::ResetStats => {
+                log::info!("CLI: Meter statistics reset requested");
+                if let Some(ref meter) = self.meter {
+                    meter.reset_stats();
+                    response.push_str("Meter statistics reset");
+                } else {
+                    response.push_str("Meter not configured");
+                }
+            }
+            MeterCommand::Frames(count) => {
+                log::info!("CLI: Meter frame preview requested");
+                if let Some(ref meter) = self.meter {
+                    let config = meter.get_config();
+                    let (message, frames) = meter.preview_frames(count);
+                    response.push_str(&format!(
+                        "Frames ({}, start / data LSB first / parity / stop), first {} of {} chars:",
+                        config.meter_type.framing().name(),
+                        frames.len(),
+                        message.trim_end_matches('\r').len()
+                    ));
+                    for (i, (ch, frame)) in frames.iter().enumerate() {
+                        let bits: String = frame.iter().map(|bit| (b'0' + bit) as char).collect();
+                        let shown = if ch.is_ascii_graphic() || *ch == ' ' {
+                            format!("'{}'", ch)
+                        } else {
+                            format!("{:?}", ch)
+                        };
+                        response.push_str(&format!(
+                            "\r\n  #{:<2} {:<6} 0x{:02x}  {} {} {} {}",
+                            i + 1,
+                            shown,
+                            *ch as u32,
+                            &bits[..1],
+                            &bits[1..8],
+                            &bits[8..9],
+                            &bits[9..]
+                        ));
+                    }
+                } else {
+                    response.push_str("Meter not configured");
+                }
+            }
+            MeterCommand::FactoryReset => {
+                log::warn!("CLI: Meter factory reset requested");
+                match self.storage {
+                    Some(ref mut storage) => match storage.erase() {
+                        Ok(_) => {
+                            response.push_str("Meter settings erased\r\n");
+                            response.push_str(&format!(
+                                "Restarting with defaults in {}s...",
+                                REBOOT_DELAY.as_secs()
+                            ));
+                            restart_after(REBOOT_DELAY);
+                        }
+                        Err(e) => response.push_str(&format!("❌ Factory reset failed: {:?}", e)),
+                    },
+                    None => response.push_str("❌ Factory reset failed: NVS not available"),
+                }
+            }
             MeterCommand::Unknown(msg) => {
                 log::info!("CLI: Unknown command");
                 response.push_str(&msg);
             }
         }
 
+        if modifies_config {
+            self.save_config();
+        }
+
         Ok(response)
     }
 }
@@ -1,21 +1,278 @@
-use crate::meter::MeterType;
+use super::base::BaseCommand;
+use super::parser::split_args;
+use super::registry::{CommandSet, CommandSpec};
+use crate::meter::{MeterType, StatusFlags};
 
 #[derive(Debug, Clone)]
 pub enum MeterCommand {
     Help,
+    /// `help <command>`
+    HelpCommand(String),
     Clear,
     Version,
     Status,
     Uptime,
+    ChipInfo,
     Reset,
     SetType(MeterType),
     SetMessage(String),
+    SetDebounce(u32),
+    SetThreshold(u32),
+    ScriptAdd(String),
+    ScriptList,
+    ScriptClear,
+    SetFlag(u8, bool),
+    ClearFlags,
+    ShowLog,
+    ClearLog,
+    ResetStats,
+    /// Bit patterns of the first n characters of the message
+    Frames(usize),
+    FactoryReset,
     Enable,
     Disable,
     Empty,
     Unknown(String),
 }
 
+impl From<BaseCommand> for MeterCommand {
+    fn from(command: BaseCommand) -> Self {
+        match command {
+            BaseCommand::Help => MeterCommand::Help,
+            BaseCommand::HelpCommand(name) => MeterCommand::HelpCommand(name),
+            BaseCommand::Version => MeterCommand::Version,
+            BaseCommand::Uptime => MeterCommand::Uptime,
+            BaseCommand::ChipInfo => MeterCommand::ChipInfo,
+            BaseCommand::Clear => MeterCommand::Clear,
+            BaseCommand::Reset => MeterCommand::Reset,
+        }
+    }
+}
+
+/// Characters shown by `frames` without an argument, and at most
+const DEFAULT_PREVIEW_FRAMES: usize = 4;
+const MAX_PREVIEW_FRAMES: usize = 32;
+
+/// Short forms of meter commands
+const METER_ALIASES: &[(&str, &str)] = &[
+    ("h", "help"),
+    ("cls", "clear"),
+    ("ver", "version"),
+    ("stat", "status"),
+    ("msg", "message"),
+];
+
+/// Append the carriage return the MTU expects at the end of a message
+fn with_cr(mut message: String) -> String {
+    if !message.ends_with('\r') {
+        message.push('\r');
+    }
+    message
+}
+
+/// The meter app's commands
+pub static METER_COMMANDS: CommandSet<MeterCommand> = CommandSet { role: COMMANDS };
+
+/// Meter commands beyond the base set, in `help` order
+static COMMANDS: &[CommandSpec<MeterCommand>] = &[
+    CommandSpec {
+        name: "status",
+        help: &[("status", "Show meter status and statistics")],
+        details: "",
+        parse: |_| MeterCommand::Status,
+    },
+    CommandSpec {
+        name: "enable",
+        help: &[("enable", "Enable meter response to clock signals")],
+        details: "",
+        parse: |_| MeterCommand::Enable,
+    },
+    CommandSpec {
+        name: "disable",
+        help: &[("disable", "Disable meter response")],
+        details: "",
+        parse: |_| MeterCommand::Disable,
+    },
+    CommandSpec {
+        name: "type",
+        help: &[("type <sensus|neptune>", "Set meter type (7E1 or 7E2)")],
+        details: "sensus (s): 7 data bits, even parity, 1 stop bit\nneptune (n): 7 data bits, even parity, 2 stop bits\nExample: type neptune",
+        parse: |parts| match parts.next() {
+            Some("sensus" | "s") => MeterCommand::SetType(MeterType::Sensus),
+            Some("neptune" | "n") => MeterCommand::SetType(MeterType::Neptune),
+            Some(other) => MeterCommand::Unknown(format!(
+                "Invalid meter type: '{}'. Use 'sensus' or 'neptune'",
+                other
+            )),
+            None => MeterCommand::Unknown(
+                "Usage: type <sensus|neptune>. Type 'help' for more info.".to_string(),
+            ),
+        },
+    },
+    CommandSpec {
+        name: "message",
+        help: &[(
+            "message <text>",
+            "Set response message (\\r added automatically)",
+        )],
+        details: "Example: message V;RB00000200;IB61564400;A1000;Z3214",
+        parse: |parts| {
+            let words: Vec<&str> = parts.collect();
+            if words.is_empty() {
+                MeterCommand::Unknown(
+                    "Usage: message <text>. Carriage return (\\r) will be added automatically."
+                        .to_string(),
+                )
+            } else {
+                MeterCommand::SetMessage(with_cr(words.join(" ")))
+            }
+        },
+    },
+    CommandSpec {
+        name: "threshold",
+        help: &[(
+            "threshold <n>",
+            "Set wake-up threshold (1-1000 clock pulses)",
+        )],
+        details: "Clock pulses counted before the meter starts transmitting.\nExample: threshold 10",
+        parse: |parts| match parts.next() {
+            Some(arg) => match arg.parse::<u32>() {
+                Ok(pulses) if (1..=1000).contains(&pulses) => MeterCommand::SetThreshold(pulses),
+                _ => MeterCommand::Unknown(format!(
+                    "Invalid threshold: '{}'. Use 1-1000 pulses",
+                    arg
+                )),
+            },
+            None => MeterCommand::Unknown(
+                "Usage: threshold <pulses>. Clock pulses before transmitting.".to_string(),
+            ),
+        },
+    },
+    CommandSpec {
+        name: "debounce",
+        help: &[(
+            "debounce <us>",
+            "Set clock edge debounce (0-10000us, 0 = off)",
+        )],
+        details: "Clock edges closer together than this are ignored.\nExample: debounce 50",
+        parse: |parts| match parts.next() {
+            Some(arg) => match arg.parse::<u32>() {
+                Ok(us) if us <= 10_000 => MeterCommand::SetDebounce(us),
+                _ => MeterCommand::Unknown(format!(
+                    "Invalid debounce: '{}'. Use 0-10000 microseconds",
+                    arg
+                )),
+            },
+            None => {
+                MeterCommand::Unknown("Usage: debounce <us>. Use 0 to disable filtering.".to_string())
+            }
+        },
+    },
+    CommandSpec {
+        name: "script",
+        help: &[
+            ("script add <text>", "Append message to response script"),
+            ("script list", "Show response script"),
+            ("script clear", "Clear script (use single message)"),
+        ],
+        details: "Script messages are sent in turn, one per read.\nExample: script add V;RB00000201;IB61564400;A1000;Z3214",
+        parse: |parts| match parts.next() {
+            Some("add") => {
+                let words: Vec<&str> = parts.collect();
+                if words.is_empty() {
+                    MeterCommand::Unknown("Usage: script add <text>".to_string())
+                } else {
+                    MeterCommand::ScriptAdd(with_cr(words.join(" ")))
+                }
+            }
+            Some("list") => MeterCommand::ScriptList,
+            Some("clear") => MeterCommand::ScriptClear,
+            _ => MeterCommand::Unknown(
+                "Usage: script <add <text>|list|clear>. Messages are sent in turn on each read."
+                    .to_string(),
+            ),
+        },
+    },
+    CommandSpec {
+        name: "flag",
+        help: &[
+            (
+                "flag <name> <on|off>",
+                "Simulate alarm flag (tamper, battery, leak, reverse)",
+            ),
+            ("flag clear", "Clear all alarm flags"),
+        ],
+        details: "Flags replace the first digit of the XT field (Sensus) or the MT\nfield (Neptune); the other digits are kept.\nExample: flag leak on",
+        parse: |parts| match (parts.next(), parts.next()) {
+            (Some("clear"), None) => MeterCommand::ClearFlags,
+            (Some(name), Some(state)) => match (StatusFlags::from_name(name), state) {
+                (Some(bit), "on") => MeterCommand::SetFlag(bit, true),
+                (Some(bit), "off") => MeterCommand::SetFlag(bit, false),
+                (None, _) => MeterCommand::Unknown(format!(
+                    "Invalid flag: '{}'. Use tamper, battery, leak or reverse",
+                    name
+                )),
+                _ => MeterCommand::Unknown(format!(
+                    "Invalid state: '{}'. Use 'on' or 'off'",
+                    state
+                )),
+            },
+            _ => MeterCommand::Unknown(
+                "Usage: flag <tamper|battery|leak|reverse> <on|off> or flag clear".to_string(),
+            ),
+        },
+    },
+    CommandSpec {
+        name: "log",
+        help: &[(
+            "log [clear]",
+            "Show (or clear) wake-up/transmission event log",
+        )],
+        details: "",
+        parse: |parts| match parts.next() {
+            None | Some("show") => MeterCommand::ShowLog,
+            Some("clear") => MeterCommand::ClearLog,
+            Some(other) => MeterCommand::Unknown(format!(
+                "Invalid log option: '{}'. Use 'log' or 'log clear'",
+                other
+            )),
+        },
+    },
+    CommandSpec {
+        name: "reset_stats",
+        help: &[(
+            "reset_stats",
+            "Clear pulse, bit and message counters",
+        )],
+        details: "Clears the statistics and the measured clock rate shown by status\nwithout a reboot; the configuration and event log are kept.",
+        parse: |_| MeterCommand::ResetStats,
+    },
+    CommandSpec {
+        name: "frames",
+        help: &[(
+            "frames [n]",
+            "Show the bit pattern of the first n characters (1-32, default 4)",
+        )],
+        details: "Shows the frames the next read sends (script entry and status flags\napplied) as start, data bits LSB first, parity and stop bits.\nExample: frames 8",
+        parse: |parts| match parts.next().map(|arg| arg.parse::<usize>()) {
+            None => MeterCommand::Frames(DEFAULT_PREVIEW_FRAMES),
+            Some(Ok(count)) if (1..=MAX_PREVIEW_FRAMES).contains(&count) => {
+                MeterCommand::Frames(count)
+            }
+            Some(_) => MeterCommand::Unknown("frames: n must be 1-32".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "factory_reset",
+        help: &[(
+            "factory_reset",
+            "Erase saved meter settings, then restart with defaults",
+        )],
+        details: "Erases the meter namespace from NVS (type, message, script, flags,\nthreshold, debounce). Cannot be undone.",
+        parse: |_| MeterCommand::FactoryReset,
+    },
+];
+
 pub struct MeterCommandParser;
 
 impl MeterCommandParser {
@@ -26,67 +283,30 @@ impl MeterCommandParser {
             return MeterCommand::Empty;
         }
 
-        let parts: Vec<&str> = input.split_whitespace().collect();
-
-        if parts.is_empty() {
+        let args = split_args(input);
+        let words: Vec<&str> = args.iter().map(String::as_str).collect();
+        let mut parts = words.iter().copied();
+        let Some(cmd) = parts.next() else {
             return MeterCommand::Empty;
-        }
-
-        match parts[0] {
-            "help" | "h" => MeterCommand::Help,
-            "clear" | "cls" => MeterCommand::Clear,
-            "version" | "ver" => MeterCommand::Version,
-            "status" | "stat" => MeterCommand::Status,
-            "uptime" => MeterCommand::Uptime,
-            "reset" => MeterCommand::Reset,
-            "enable" => MeterCommand::Enable,
-            "disable" => MeterCommand::Disable,
-            "type" => {
-                if parts.len() >= 2 {
-                    match parts[1] {
-                        "sensus" | "s" => MeterCommand::SetType(MeterType::Sensus),
-                        "neptune" | "n" => MeterCommand::SetType(MeterType::Neptune),
-                        _ => MeterCommand::Unknown(format!(
-                            "Invalid meter type: '{}'. Use 'sensus' or 'neptune'",
-                            parts[1]
-                        )),
-                    }
-                } else {
-                    MeterCommand::Unknown(
-                        "Usage: type <sensus|neptune>. Type 'help' for more info.".to_string(),
-                    )
-                }
-            }
-            "message" | "msg" => {
-                if parts.len() >= 2 {
-                    // Join all parts after "message" as the message content
-                    let message_text = parts[1..].join(" ");
+        };
+        let cmd = METER_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == cmd)
+            .map_or(cmd, |(_, command)| command);
 
-                    // Add carriage return if not present
-                    let mut message = message_text;
-                    if !message.ends_with('\r') {
-                        message.push('\r');
-                    }
-
-                    MeterCommand::SetMessage(message)
-                } else {
-                    MeterCommand::Unknown(
-                        "Usage: message <text>. Carriage return (\\r) will be added automatically."
-                            .to_string(),
-                    )
-                }
-            }
-            _ => MeterCommand::Unknown(format!(
+        METER_COMMANDS.parse(cmd, &mut parts).unwrap_or_else(|| {
+            MeterCommand::Unknown(format!(
                 "Unknown command: '{}'. Type 'help' for available commands.",
-                parts[0]
-            )),
-        }
+                cmd
+            ))
+        })
+    }
+
+    pub fn available_commands() -> Vec<&'static str> {
+        METER_COMMANDS.names()
     }
 
-    pub fn available_commands() -> &'static [&'static str] {
-        &[
-            "help", "clear", "version", "status", "uptime", "reset", "type", "message", "enable",
-            "disable",
-        ]
+    pub fn autocomplete(partial: &str) -> Vec<&'static str> {
+        METER_COMMANDS.autocomplete(partial)
     }
 }
@@ -1,19 +1,34 @@
+pub mod auth;
+pub mod base;
 pub mod commands;
+pub mod console_io;
+pub mod jobs;
 pub mod parser;
+pub mod registry;
+pub mod remote;
+pub mod telnet;
 pub mod terminal;
+pub mod wizard;
 
 // Meter CLI modules
 pub mod meter_commands;
 pub mod meter_parser;
 
 pub use commands::CommandHandler;
+#[cfg(feature = "usb-console")]
+pub use console_io::UsbSerialIo;
+pub use console_io::{ConsoleIo, UartIo};
 pub use parser::CommandParser;
-pub use terminal::Terminal;
+pub use remote::cli_downlink_handler;
+pub use terminal::{StreamIo, Terminal, TerminalIo};
 
 // Meter CLI exports
 pub use meter_commands::MeterCommandHandler;
 pub use meter_parser::{MeterCommand, MeterCommandParser};
 
+use crate::mtu::UartFraming;
+use crate::network_config::{StaticIpConfig, WifiAuth};
+
 // CLI-related types and constants
 pub const CLI_BUFFER_SIZE: usize = 128;
 pub const MAX_HISTORY_SIZE: usize = 10;
@@ -21,23 +36,69 @@ pub const MAX_HISTORY_SIZE: usize = 10;
 #[derive(Debug, Clone)]
 pub enum CliCommand {
     Help,
+    /// `help <command>`
+    HelpCommand(String),
     Version,
     Status,
+    Sys,
+    ChipInfo,
     Uptime,
+    Time,
     Clear,
+    /// Lines per page of long output (None = no paging)
+    Pager(Option<usize>),
+    /// ANSI colors in command output
+    Color(bool),
     Reset,
     Echo(String),
     MtuStart(Option<u16>), // Optional duration in seconds
     MtuStop,
+    /// Start a read and wait for its result (duration in seconds)
+    MtuRead(Option<u16>),
     MtuStatus,
     MtuBaud(u32),                                // Set MTU baud rate
+    MtuExpected(String),                         // Expected message ("" = show, "off" = no check)
+    MtuFraming(UartFraming),                     // Set MTU UART framing
     MtuReset,                                    // Reset MTU statistics
     WifiConnect(Option<String>, Option<String>), // ssid, password (None = use default)
     WifiStatus,
-    WifiReconnect,       // Reconnect using stored credentials
-    MqttConnect(String), // broker_url
+    WifiReconnect,                          // Reconnect using stored credentials
+    WifiSave(String, String, u8, WifiAuth), // ssid, password, priority, auth (encrypted in NVS)
+    WifiSaveEnterprise(String, String, String, String, u8), // ssid, identity, username, password, priority
+    WifiCaCert(Option<String>),                             // PEM line or "clear"; None = status
+    WifiForget(String),                                     // ssid
+    WifiScan,
+    WifiStatic(Option<StaticIpConfig>), // None = back to DHCP
+    MqttConnect(String),                // broker_url
     MqttStatus,
-    MqttPublish(String, String), // topic, message
+    MqttPublish(String, String),      // topic, message
+    MqttCert(String, Option<String>), // "ca"/"cert"/"key", PEM line or "clear"; None = status
+    ConfigShow,
+    ConfigSet(String, String), // key, value
+    ConfigSave,
+    LogShow,
+    LogClear,
+    LogUpload,
+    Export(usize),       // readings
+    ExportUpload(usize), // readings
+    Reboot,
+    FactoryReset,
+    MetersList,
+    MetersAdd(String, u8, u32, Option<String>), // id, channel, interval (s), subtopic
+    MetersSet(String, String, String),          // id, field, value
+    Jobs,
+    JobStatus(u32),
+    Login(Option<String>), // None = show login state
+    Logout,
+    Passwd(String), // new password, "clear" = no password
+    AliasList,
+    AliasSet(String, String), // name, command line
+    AliasRemove(String),
+    Run(String),                      // script name or "cmd; cmd; ..."
+    ScriptSave(String, String, bool), // name, commands, append
+    ScriptList,
+    ScriptDelete(String),
+    MetersRemove(String), // id
     Empty,
     Unknown(String),
 }
@@ -48,6 +109,8 @@ pub enum CliError {
     InvalidArgument,
     UartError,
     BufferFull,
+    /// The other end of a stream (telnet client, closed input) went away
+    Disconnected,
 }
 
 impl std::fmt::Display for CliError {
@@ -57,6 +120,7 @@ impl std::fmt::Display for CliError {
             CliError::InvalidArgument => write!(f, "Invalid argument"),
             CliError::UartError => write!(f, "UART error"),
             CliError::BufferFull => write!(f, "Buffer full"),
+            CliError::Disconnected => write!(f, "Disconnected"),
         }
     }
 }
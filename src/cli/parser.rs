@@ -1,7 +1,80 @@
+use super::registry::MTU_COMMANDS;
 use super::CliCommand;
+use crate::config_store::Aliases;
+use std::sync::Mutex;
+
+/// Short forms of the most used MTU commands; a user alias of the same name
+/// takes precedence
+pub const BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("start", "mtu_start"),
+    ("stop", "mtu_stop"),
+    ("ms", "mtu_status"),
+    ("baud", "mtu_baud"),
+    ("mr", "mtu_reset"),
+];
+
+/// User aliases (`alias`), loaded from NVS at startup
+static ALIASES: Mutex<Aliases> = Mutex::new(Aliases::new());
 
 pub struct CommandParser;
 
+/// Split a command list at semicolons outside double quotes; empty entries
+/// are dropped
+pub fn split_commands(list: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (at, c) in list.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                commands.push(list[start..at].trim());
+                start = at + 1;
+            }
+            _ => {}
+        }
+    }
+    commands.push(list[start..].trim());
+    commands.retain(|command| !command.is_empty());
+    commands
+}
+
+/// Split a command line at whitespace. An argument that starts with a double
+/// quote runs to the closing quote and may contain spaces (`\"` and `\\`
+/// inside it are a literal quote and backslash); quotes elsewhere, as in
+/// JSON, are kept as typed.
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(first) = chars.next() else {
+            return args;
+        };
+        let mut arg = String::new();
+        if first == '"' {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' if matches!(chars.peek(), Some('"' | '\\')) => {
+                        arg.extend(chars.next());
+                    }
+                    _ => arg.push(c),
+                }
+            }
+        } else {
+            arg.push(first);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+}
+
 impl Default for CommandParser {
     fn default() -> Self {
         Self::new()
@@ -13,36 +86,37 @@ impl CommandParser {
         Self
     }
 
-    pub fn get_available_commands() -> &'static [&'static str] {
-        &[
-            "help",
-            "version",
-            "status",
-            "uptime",
-            "clear",
-            "reset",
-            "echo",
-            "mtu_start",
-            "mtu_stop",
-            "mtu_status",
-            "mtu_baud",
-            "mtu_reset",
-            "wifi_connect",
-            "wifi_reconnect",
-            "wifi_status",
-            "mqtt_connect",
-            "mqtt_status",
-            "mqtt_publish",
-        ]
+    pub fn get_available_commands() -> Vec<&'static str> {
+        MTU_COMMANDS.names()
     }
 
     pub fn autocomplete(partial: &str) -> Vec<&'static str> {
-        let commands = Self::get_available_commands();
-        commands
-            .iter()
-            .filter(|&&cmd| cmd.starts_with(partial))
-            .copied()
-            .collect()
+        MTU_COMMANDS.autocomplete(partial)
+    }
+
+    pub fn set_aliases(aliases: Aliases) {
+        *ALIASES.lock().unwrap() = aliases;
+    }
+
+    pub fn aliases() -> Aliases {
+        ALIASES.lock().unwrap().clone()
+    }
+
+    /// The line with a leading alias replaced by its command; commands are
+    /// never shadowed and expansions are not expanded again
+    pub fn expand_alias(line: &str) -> Option<String> {
+        let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
+        if MTU_COMMANDS.contains(word) {
+            return None;
+        }
+        let command = match ALIASES.lock().unwrap().get(word) {
+            Some(command) => command.clone(),
+            None => BUILTIN_ALIASES
+                .iter()
+                .find(|(name, _)| *name == word)
+                .map(|(_, command)| command.to_string())?,
+        };
+        Some(format!("{} {}", command, rest).trim_end().to_string())
     }
 
     pub fn parse_command(input: &str) -> CliCommand {
@@ -50,85 +124,16 @@ impl CommandParser {
         if trimmed.is_empty() {
             return CliCommand::Empty;
         }
+        let expanded = Self::expand_alias(trimmed);
+        let trimmed = expanded.as_deref().unwrap_or(trimmed);
 
-        let mut parts = trimmed.split_whitespace();
+        let args = split_args(trimmed);
+        let words: Vec<&str> = args.iter().map(String::as_str).collect();
+        let mut parts = words.iter().copied();
         let cmd = parts.next().unwrap_or("");
 
-        match cmd {
-            "help" => CliCommand::Help,
-            "version" => CliCommand::Version,
-            "status" => CliCommand::Status,
-            "uptime" => CliCommand::Uptime,
-            "clear" => CliCommand::Clear,
-            "reset" => CliCommand::Reset,
-            "mtu_start" => {
-                if let Some(arg) = parts.next() {
-                    if let Ok(duration) = arg.parse::<u16>() {
-                        if duration > 0 && duration <= 300 {
-                            CliCommand::MtuStart(Some(duration))
-                        } else {
-                            CliCommand::Unknown(
-                                "mtu_start: duration must be 1-300 seconds".to_string(),
-                            )
-                        }
-                    } else {
-                        CliCommand::Unknown("mtu_start: invalid duration".to_string())
-                    }
-                } else {
-                    CliCommand::MtuStart(None) // Default duration
-                }
-            }
-            "mtu_stop" => CliCommand::MtuStop,
-            "mtu_status" => CliCommand::MtuStatus,
-            "mtu_baud" => {
-                if let Some(baud_str) = parts.next() {
-                    if let Ok(baud_rate) = baud_str.parse::<u32>() {
-                        if (1..=115200).contains(&baud_rate) {
-                            CliCommand::MtuBaud(baud_rate)
-                        } else {
-                            CliCommand::Unknown("mtu_baud: rate must be 1-115200".to_string())
-                        }
-                    } else {
-                        CliCommand::Unknown("mtu_baud: invalid baud rate".to_string())
-                    }
-                } else {
-                    CliCommand::Unknown("mtu_baud: baud rate required".to_string())
-                }
-            }
-            "echo" => {
-                let args: Vec<&str> = parts.collect();
-                let echo_string = args.join(" ");
-                CliCommand::Echo(echo_string)
-            }
-            "mtu_reset" => CliCommand::MtuReset,
-            "wifi_connect" => {
-                let ssid = parts.next().map(|s| s.to_string());
-                let password = parts.next().map(|s| s.to_string());
-                CliCommand::WifiConnect(ssid, password)
-            }
-            "wifi_reconnect" => CliCommand::WifiReconnect,
-            "wifi_status" => CliCommand::WifiStatus,
-            "mqtt_connect" => {
-                if let Some(broker_url) = parts.next() {
-                    CliCommand::MqttConnect(broker_url.to_string())
-                } else {
-                    CliCommand::Unknown("mqtt_connect: broker URL required".to_string())
-                }
-            }
-            "mqtt_status" => CliCommand::MqttStatus,
-            "mqtt_publish" => {
-                let topic = parts.next().unwrap_or("").to_string();
-                let message_parts: Vec<&str> = parts.collect();
-                let message = message_parts.join(" ");
-                if topic.is_empty() {
-                    CliCommand::Unknown("mqtt_publish: topic required".to_string())
-                } else if message.is_empty() {
-                    CliCommand::Unknown("mqtt_publish: message required".to_string())
-                } else {
-                    CliCommand::MqttPublish(topic, message)
-                }
-            }
-            _ => CliCommand::Unknown(cmd.to_string()),
-        }
+        MTU_COMMANDS
+            .parse(cmd, &mut parts)
+            .unwrap_or_else(|| CliCommand::Unknown(cmd.to_string()))
     }
 }
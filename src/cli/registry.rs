@@ -0,0 +1,758 @@
+//! Command registry
+//!
+//! Every console command is declared once, with its help lines and argument
+//! parser; `help`, TAB completion and the parsers all read these tables, so
+//! a command can't be added to one and missed in the others. A console's
+//! `CommandSet` is the shared `base::BASE_COMMANDS` followed by its role's
+//! table: `COMMANDS` below for the MTU app, `meter_parser` for the meter.
+
+use super::base::{BaseCommand, BASE_COMMANDS};
+use super::terminal::{MAX_PAGE_LINES, MIN_PAGE_LINES};
+use super::CliCommand;
+use crate::mtu::UartFraming;
+use crate::network_config::{StaticIpConfig, WifiAuth};
+use crate::storage::MAX_EXPORT_READINGS;
+
+/// Arguments after the command name
+pub type Args<'a> = std::iter::Copied<std::slice::Iter<'a, &'a str>>;
+
+pub struct CommandSpec<C: 'static = CliCommand> {
+    pub name: &'static str,
+    /// `(usage, description)` per help line; subcommands get a line each
+    pub help: &'static [(&'static str, &'static str)],
+    /// Argument ranges and examples for `help <command>` (lines separated
+    /// by `\n`, may be empty)
+    pub details: &'static str,
+    /// Build the command from its arguments (the role's `Unknown` with a
+    /// message when they are invalid)
+    pub parse: fn(&mut Args) -> C,
+}
+
+impl<C> CommandSpec<C> {
+    fn find(table: &'static [CommandSpec<C>], name: &str) -> Option<&'static CommandSpec<C>> {
+        table.iter().find(|spec| spec.name == name)
+    }
+
+    /// `help <command>`: the help lines, then the details
+    pub fn describe(&self) -> String {
+        let mut out = String::from("Usage:");
+        for (usage, description) in self.help {
+            out.push_str(&format!("\r\n  {}\r\n      {}", usage, description));
+        }
+        if !self.details.is_empty() {
+            out.push_str("\r\n");
+            for line in self.details.lines() {
+                out.push_str("\r\n");
+                out.push_str(line);
+            }
+        }
+        out
+    }
+}
+
+/// A console's commands: the shared base set, then the role's own
+pub struct CommandSet<C: 'static> {
+    pub role: &'static [CommandSpec<C>],
+}
+
+impl<C: From<BaseCommand>> CommandSet<C> {
+    /// Command names in `help` order
+    pub fn names(&self) -> Vec<&'static str> {
+        BASE_COMMANDS
+            .iter()
+            .map(|spec| spec.name)
+            .chain(self.role.iter().map(|spec| spec.name))
+            .collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names().contains(&name)
+    }
+
+    pub fn autocomplete(&self, partial: &str) -> Vec<&'static str> {
+        self.names()
+            .into_iter()
+            .filter(|name| name.starts_with(partial))
+            .collect()
+    }
+
+    /// The command `name` built from its arguments, None if there is none
+    pub fn parse(&self, name: &str, parts: &mut Args) -> Option<C> {
+        if let Some(spec) = CommandSpec::find(BASE_COMMANDS, name) {
+            return Some((spec.parse)(parts).into());
+        }
+        CommandSpec::find(self.role, name).map(|spec| (spec.parse)(parts))
+    }
+
+    /// `help <command>`, None if there is no such command
+    pub fn describe(&self, name: &str) -> Option<String> {
+        match CommandSpec::find(BASE_COMMANDS, name) {
+            Some(spec) => Some(spec.describe()),
+            None => CommandSpec::find(self.role, name).map(CommandSpec::describe),
+        }
+    }
+
+    /// `help`: one line per usage, the base commands set apart from the role's
+    pub fn help_text(&self) -> String {
+        let mut help = String::from("Available commands:\r\n");
+        let base = BASE_COMMANDS.iter().flat_map(|spec| spec.help);
+        for (usage, description) in base {
+            help.push_str(&format!("  {:<11} - {}\r\n", usage, description));
+        }
+        help.push_str("\r\n");
+        for (usage, description) in self.role.iter().flat_map(|spec| spec.help) {
+            help.push_str(&format!("  {:<11} - {}\r\n", usage, description));
+        }
+        help
+    }
+}
+
+/// The MTU app's commands
+pub static MTU_COMMANDS: CommandSet<CliCommand> = CommandSet { role: COMMANDS };
+
+impl From<BaseCommand> for CliCommand {
+    fn from(command: BaseCommand) -> Self {
+        match command {
+            BaseCommand::Help => CliCommand::Help,
+            BaseCommand::HelpCommand(name) => CliCommand::HelpCommand(name),
+            BaseCommand::Version => CliCommand::Version,
+            BaseCommand::Uptime => CliCommand::Uptime,
+            BaseCommand::ChipInfo => CliCommand::ChipInfo,
+            BaseCommand::Clear => CliCommand::Clear,
+            BaseCommand::Reset => CliCommand::Reset,
+        }
+    }
+}
+
+/// MTU app commands beyond the base set, in `help` order
+pub static COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "status",
+        help: &[("status", "Show system status")],
+        details: "",
+        parse: |_| CliCommand::Status,
+    },
+    CommandSpec {
+        name: "sys",
+        help: &[("sys", "Show heap and task stack usage")],
+        details: "",
+        parse: |_| CliCommand::Sys,
+    },
+    CommandSpec {
+        name: "time",
+        help: &[("time", "Show UTC time (SNTP) and last sync")],
+        details: "The clock is set by SNTP once the link is up.",
+        parse: |_| CliCommand::Time,
+    },
+    CommandSpec {
+        name: "pager",
+        help: &[(
+            "pager <n|off>",
+            "Pause long output every n lines (5-200, default 22)",
+        )],
+        details: "Long output pauses at --More--: space shows the next page, Enter one\nmore line, q or Ctrl-C skips the rest. Turn it off when capturing\noutput from a script.\nExample: pager 40",
+        parse: |parts| match parts.next() {
+            Some("off") => CliCommand::Pager(None),
+            Some(lines) => match lines.parse::<usize>() {
+                Ok(lines) if (MIN_PAGE_LINES..=MAX_PAGE_LINES).contains(&lines) => {
+                    CliCommand::Pager(Some(lines))
+                }
+                _ => CliCommand::Unknown("pager: lines must be 5-200 or 'off'".to_string()),
+            },
+            None => CliCommand::Unknown("Usage: pager <lines|off>".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "color",
+        help: &[("color <on|off>", "Color errors, successes and headers")],
+        details: "Errors are shown red, successes green, warnings yellow and headers\nbold. Off by default; needs a terminal that understands ANSI colors.",
+        parse: |parts| match parts.next() {
+            Some("on") => CliCommand::Color(true),
+            Some("off") => CliCommand::Color(false),
+            _ => CliCommand::Unknown("Usage: color <on|off>".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "reboot",
+        help: &[(
+            "reboot",
+            "Restart in 3 seconds (lets a remote reply go out)",
+        )],
+        details: "Unlike reset, waits so the reply to an MQTT or HTTP command goes out.",
+        parse: |_| CliCommand::Reboot,
+    },
+    CommandSpec {
+        name: "factory_reset",
+        help: &[(
+            "factory_reset",
+            "Erase configuration, WiFi networks and meter settings, then provision",
+        )],
+        details: "Erases the config (MQTT, MTU and all other settings, certificates,\nscripts, aliases, console password), wifi_creds and meter namespaces\nfrom NVS and restarts into provisioning mode. The boot counter, logs\nand crash reports are kept. Cannot be undone.",
+        parse: |_| CliCommand::FactoryReset,
+    },
+    CommandSpec {
+        name: "echo",
+        help: &[("echo <text>", "Echo text back")],
+        details: "Example: echo hello",
+        parse: |parts| CliCommand::Echo(parts.collect::<Vec<&str>>().join(" ")),
+    },
+    CommandSpec {
+        name: "mtu_start",
+        help: &[("mtu_start [dur]", "Start MTU operation (default 30s)")],
+        details: "dur: read window in seconds, 1-300 (default 30). The read stops early\nonce a complete message has been received; Ctrl-C stops it.\nExample: mtu_start 60",
+        parse: |parts| {
+            if let Some(arg) = parts.next() {
+                if let Ok(duration) = arg.parse::<u16>() {
+                    if duration > 0 && duration <= 300 {
+                        CliCommand::MtuStart(Some(duration))
+                    } else {
+                        CliCommand::Unknown("mtu_start: duration must be 1-300 seconds".to_string())
+                    }
+                } else {
+                    CliCommand::Unknown("mtu_start: invalid duration".to_string())
+                }
+            } else {
+                CliCommand::MtuStart(None) // Default duration
+            }
+        },
+    },
+    CommandSpec {
+        name: "mtu_read",
+        help: &[("mtu_read [dur]", "Read the meter and show the result")],
+        details: "Like mtu_start, but waits for the read to finish and shows the\nmessage, register, serial and statistics. dur: 1-300 seconds\n(default 30).\nExample: mtu_read 10",
+        parse: |parts| match parts.next().map(|arg| arg.parse::<u16>()) {
+            None => CliCommand::MtuRead(None),
+            Some(Ok(duration)) if (1..=300).contains(&duration) => {
+                CliCommand::MtuRead(Some(duration))
+            }
+            Some(_) => CliCommand::Unknown("mtu_read: duration must be 1-300 seconds".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "mtu_stop",
+        help: &[("mtu_stop", "Stop MTU operation")],
+        details: "",
+        parse: |_| CliCommand::MtuStop,
+    },
+    CommandSpec {
+        name: "mtu_status",
+        help: &[("mtu_status", "Show MTU status")],
+        details: "",
+        parse: |_| CliCommand::MtuStatus,
+    },
+    CommandSpec {
+        name: "mtu_baud",
+        help: &[(
+            "mtu_baud <rate>",
+            "Set MTU baud rate (1-115200, default 1200)",
+        )],
+        details: "rate: 1-115200 bit/s; most meters answer at 1200. Refused while a read\nruns; persist with 'config set mtu.baud'.\nExample: mtu_baud 2400",
+        parse: |parts| {
+            if let Some(baud_str) = parts.next() {
+                if let Ok(baud_rate) = baud_str.parse::<u32>() {
+                    if (1..=115200).contains(&baud_rate) {
+                        CliCommand::MtuBaud(baud_rate)
+                    } else {
+                        CliCommand::Unknown("mtu_baud: rate must be 1-115200".to_string())
+                    }
+                } else {
+                    CliCommand::Unknown("mtu_baud: invalid baud rate".to_string())
+                }
+            } else {
+                CliCommand::Unknown("mtu_baud: baud rate required".to_string())
+            }
+        },
+    },
+    CommandSpec {
+        name: "mtu_framing",
+        help: &[(
+            "mtu_framing <7e1|7e2>",
+            "Set MTU UART framing (7e2 for Neptune)",
+        )],
+        details: "7e1: 7 data bits, even parity, 1 stop bit (Sensus)\n7e2: 7 data bits, even parity, 2 stop bits (Neptune)\nRefused while a read runs; persist with 'config set mtu.framing'.\nExample: mtu_framing 7e2",
+        parse: |parts| match parts.next() {
+            Some(name) => match UartFraming::from_name(&name.to_uppercase()) {
+                Some(framing) => CliCommand::MtuFraming(framing),
+                None => CliCommand::Unknown("mtu_framing: use 7e1 or 7e2".to_string()),
+            },
+            None => CliCommand::Unknown("mtu_framing: framing required (7e1|7e2)".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "mtu_expected",
+        help: &[(
+            "mtu_expected [text|off]",
+            "Count reads that differ from text as corrupted",
+        )],
+        details: "With text, a clean read that differs from it (ignoring the trailing CR)\ncounts as corrupted, for bench tests against a known meter. No\nargument shows the current text, 'off' turns the check off.\nExample: mtu_expected V;RB00000200;IB61564400;A1000;Z3214",
+        parse: |parts| {
+            let expected = parts.collect::<Vec<&str>>().join(" ");
+            // Room for the trailing \r
+            if expected.len() > 255 {
+                CliCommand::Unknown("mtu_expected: message too long (max 255)".to_string())
+            } else {
+                CliCommand::MtuExpected(expected)
+            }
+        },
+    },
+    CommandSpec {
+        name: "mtu_reset",
+        help: &[("mtu_reset", "Reset MTU statistics")],
+        details: "",
+        parse: |_| CliCommand::MtuReset,
+    },
+    CommandSpec {
+        name: "wifi_connect",
+        help: &[(
+            "wifi_connect [ssid] [password]",
+            "Connect to WiFi (no args = saved networks)",
+        )],
+        details: "Without arguments, tries the saved networks by priority. Runs as a\nbackground job (see 'jobs'). Quote an SSID with spaces.\nExample: wifi_connect \"My AP\" secret",
+        parse: |parts| {
+            let ssid = parts.next().map(|s| s.to_string());
+            let password = parts.next().map(|s| s.to_string());
+            CliCommand::WifiConnect(ssid, password)
+        },
+    },
+    CommandSpec {
+        name: "wifi_reconnect",
+        help: &[(
+            "wifi_reconnect",
+            "Reconnect, trying saved networks by priority",
+        )],
+        details: "Runs as a background job (see 'jobs').",
+        parse: |_| CliCommand::WifiReconnect,
+    },
+    CommandSpec {
+        name: "wifi_status",
+        help: &[("wifi_status", "Show WiFi connection status")],
+        details: "",
+        parse: |_| CliCommand::WifiStatus,
+    },
+    CommandSpec {
+        name: "wifi_save",
+        help: &[(
+            "wifi_save <ssid> <password> [prio] [auth]",
+            "Save WiFi network (encrypted)",
+        )],
+        details: "prio: 0-255, higher is tried first (default 0)\nauth: open, wpa2 (default), wpa3 or wpa2-ent\nExample: wifi_save \"My AP\" secret 10 wpa3",
+        parse: |parts| match (parts.next(), parts.next()) {
+            (Some(ssid), Some(password)) => {
+                let priority = match parts.next().map(|p| p.parse::<u8>()) {
+                    None => Ok(0),
+                    Some(Ok(priority)) => Ok(priority),
+                    Some(Err(_)) => Err("wifi_save: priority must be 0-255"),
+                };
+                let auth = match parts.next() {
+                    None => Ok(WifiAuth::Wpa2),
+                    Some(name) => WifiAuth::from_name(name)
+                        .ok_or("wifi_save: auth must be open, wpa2, wpa3 or wpa2-ent"),
+                };
+                match (priority, auth) {
+                    (Ok(priority), Ok(auth)) => {
+                        CliCommand::WifiSave(ssid.to_string(), password.to_string(), priority, auth)
+                    }
+                    (Err(e), _) | (_, Err(e)) => CliCommand::Unknown(e.to_string()),
+                }
+            }
+            _ => CliCommand::Unknown("wifi_save: ssid and password required".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "wifi_save_ent",
+        help: &[(
+            "wifi_save_ent <ssid> <identity> <user> <pw> [prio]",
+            "Save WPA2-Enterprise network",
+        )],
+        details: "identity: outer (anonymous) identity; user and pw: the inner login.\nprio: 0-255, higher is tried first (default 0)\nExample: wifi_save_ent corp anonymous alice secret 5",
+        parse: |parts| match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(ssid), Some(identity), Some(username), Some(password)) => {
+                match parts.next().map(|p| p.parse::<u8>()) {
+                    None => CliCommand::WifiSaveEnterprise(
+                        ssid.to_string(),
+                        identity.to_string(),
+                        username.to_string(),
+                        password.to_string(),
+                        0,
+                    ),
+                    Some(Ok(priority)) => CliCommand::WifiSaveEnterprise(
+                        ssid.to_string(),
+                        identity.to_string(),
+                        username.to_string(),
+                        password.to_string(),
+                        priority,
+                    ),
+                    Some(Err(_)) => {
+                        CliCommand::Unknown("wifi_save_ent: priority must be 0-255".to_string())
+                    }
+                }
+            }
+            _ => CliCommand::Unknown(
+                "wifi_save_ent: ssid, identity, username and password required".to_string(),
+            ),
+        },
+    },
+    CommandSpec {
+        name: "wifi_ca_cert",
+        help: &[(
+            "wifi_ca_cert [<pem line>|clear]",
+            "Paste/clear enterprise CA certificate",
+        )],
+        details: "Paste the PEM one line per command, from the BEGIN to the END line.\nNo argument shows whether a certificate is stored, 'clear' removes it.",
+        parse: |parts| {
+            let line = parts.collect::<Vec<&str>>().join(" ");
+            if line.is_empty() {
+                CliCommand::WifiCaCert(None)
+            } else {
+                CliCommand::WifiCaCert(Some(line))
+            }
+        },
+    },
+    CommandSpec {
+        name: "wifi_forget",
+        help: &[("wifi_forget <ssid>", "Remove a saved WiFi network")],
+        details: "Example: wifi_forget \"My AP\"",
+        parse: |parts| match parts.next() {
+            Some(ssid) => CliCommand::WifiForget(ssid.to_string()),
+            None => CliCommand::Unknown("wifi_forget: ssid required".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "wifi_scan",
+        help: &[("wifi_scan", "List visible networks (SSID/RSSI/auth)")],
+        details: "Runs as a background job (see 'jobs', 'job_status').",
+        parse: |_| CliCommand::WifiScan,
+    },
+    CommandSpec {
+        name: "wifi_static",
+        help: &[(
+            "wifi_static <ip>[/prefix] <gw> [dns] | dhcp",
+            "Static IPv4 (after reset)",
+        )],
+        details: "prefix: 1-32 (default 24); give dns to resolve broker\nhostnames. Applied after reset; 'wifi_static dhcp' goes back to DHCP.\nExample: wifi_static 192.168.1.50/24 192.168.1.1 1.1.1.1",
+        parse: |parts| match (parts.next(), parts.next()) {
+            (Some("dhcp"), None) => CliCommand::WifiStatic(None),
+            (Some(ip), Some(gateway)) => match StaticIpConfig::parse(ip, gateway, parts.next()) {
+                Ok(config) => CliCommand::WifiStatic(Some(config)),
+                Err(e) => CliCommand::Unknown(format!("wifi_static: {}", e)),
+            },
+            _ => CliCommand::Unknown(
+                "wifi_static: <ip>[/prefix] <gateway> [dns] or 'dhcp'".to_string(),
+            ),
+        },
+    },
+    CommandSpec {
+        name: "mqtt_connect",
+        help: &[(
+            "mqtt_connect <broker_url>",
+            "Connect to MQTT broker (stored login and TLS)",
+        )],
+        details: "Uses the stored login, TLS and session settings and replaces an\nearlier console session. Runs as a background job.\nExample: mqtt_connect mqtts://broker.example.com:8883",
+        parse: |parts| {
+            if let Some(broker_url) = parts.next() {
+                if ["mqtt://", "mqtts://", "ws://", "wss://"]
+                    .iter()
+                    .any(|scheme| broker_url.starts_with(scheme))
+                {
+                    CliCommand::MqttConnect(broker_url.to_string())
+                } else {
+                    CliCommand::Unknown(
+                        "mqtt_connect: URL must start with mqtt://, mqtts://, ws:// or wss://"
+                            .to_string(),
+                    )
+                }
+            } else {
+                CliCommand::Unknown("mqtt_connect: broker URL required".to_string())
+            }
+        },
+    },
+    CommandSpec {
+        name: "mqtt_status",
+        help: &[("mqtt_status", "Show MQTT connection status")],
+        details: "",
+        parse: |_| CliCommand::MqttStatus,
+    },
+    CommandSpec {
+        name: "mqtt_publish",
+        help: &[("mqtt_publish <topic> <message>", "Publish MQTT message")],
+        details: "Publishes on the session opened by mqtt_connect.\nExample: mqtt_publish test/topic hello world",
+        parse: |parts| {
+            let topic = parts.next().unwrap_or("").to_string();
+            let message = parts.collect::<Vec<&str>>().join(" ");
+            if topic.is_empty() {
+                CliCommand::Unknown("mqtt_publish: topic required".to_string())
+            } else if message.is_empty() {
+                CliCommand::Unknown("mqtt_publish: message required".to_string())
+            } else {
+                CliCommand::MqttPublish(topic, message)
+            }
+        },
+    },
+    CommandSpec {
+        name: "mqtt_cert",
+        help: &[(
+            "mqtt_cert <ca|cert|key> [<pem line>|clear]",
+            "Paste/clear MQTT TLS certificate or key",
+        )],
+        details: "Paste the PEM one line per command, from the BEGIN to the END line.\n'clear' removes the stored certificate or key.",
+        parse: |parts| match parts.next() {
+            Some(slot @ ("ca" | "cert" | "key")) => {
+                let line = parts.collect::<Vec<&str>>().join(" ");
+                if line.is_empty() {
+                    CliCommand::MqttCert(slot.to_string(), None)
+                } else {
+                    CliCommand::MqttCert(slot.to_string(), Some(line))
+                }
+            }
+            _ => CliCommand::Unknown("mqtt_cert: ca, cert or key required".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "config",
+        help: &[
+            ("config [show]", "Show stored configuration"),
+            ("config set <key> <value>", "Change a configuration value"),
+            (
+                "config save",
+                "Save configuration to NVS (applied on reset)",
+            ),
+        ],
+        details: "'config show' lists every key with its value (secrets masked).\nChanges take effect after 'config save' and a reset unless noted.\nExample: config set mqtt.keepalive 60",
+        parse: |parts| match parts.next() {
+            Some("show") | None => CliCommand::ConfigShow,
+            Some("set") => {
+                let key = parts.next().unwrap_or("").to_string();
+                let value = parts.collect::<Vec<&str>>().join(" ");
+                if key.is_empty() {
+                    CliCommand::Unknown("config set: key required".to_string())
+                } else {
+                    CliCommand::ConfigSet(key, value)
+                }
+            }
+            Some("save") => CliCommand::ConfigSave,
+            Some(_) => CliCommand::Unknown("config: use show, set or save".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "log",
+        help: &[
+            ("log [show]", "Show the persistent log buffer"),
+            ("log clear", "Clear the log buffer"),
+            (
+                "log upload",
+                "Publish the log buffer with the next MQTT session",
+            ),
+        ],
+        details: "The buffer survives resets.",
+        parse: |parts| match parts.next() {
+            Some("show") | None => CliCommand::LogShow,
+            Some("clear") => CliCommand::LogClear,
+            Some("upload") => CliCommand::LogUpload,
+            Some(_) => CliCommand::Unknown("log: use show, clear or upload".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "export",
+        help: &[
+            ("export <n>", "Print the last n stored readings as CSV"),
+            (
+                "export upload <n>",
+                "Publish the last n stored readings to the export topic",
+            ),
+        ],
+        details: "n: 1-500, most recent first.\nExample: export 20",
+        parse: |parts| {
+            let upload = parts.clone().next() == Some("upload");
+            if upload {
+                parts.next();
+            }
+            match parts.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(count)) if (1..=MAX_EXPORT_READINGS).contains(&count) => {
+                    if upload {
+                        CliCommand::ExportUpload(count)
+                    } else {
+                        CliCommand::Export(count)
+                    }
+                }
+                _ => CliCommand::Unknown(format!(
+                    "export: usage export [upload] <1-{}>",
+                    MAX_EXPORT_READINGS
+                )),
+            }
+        },
+    },
+    CommandSpec {
+        name: "jobs",
+        help: &[("jobs", "List background jobs and their progress")],
+        details: "Shows running jobs and the last 8 finished ones.",
+        parse: |_| CliCommand::Jobs,
+    },
+    CommandSpec {
+        name: "job_status",
+        help: &[("job_status <id>", "Show a background job's result")],
+        details: "Example: job_status 3",
+        parse: |parts| match parts.next().map(|id| id.parse::<u32>()) {
+            Some(Ok(id)) => CliCommand::JobStatus(id),
+            _ => CliCommand::Unknown("job_status: job number required".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "login",
+        help: &[(
+            "login [password]",
+            "Unlock privileged commands (no args = show state)",
+        )],
+        details: "Needed once a password is set with passwd; the session ends with\nlogout or after 15 minutes without a privileged command.",
+        parse: |parts| CliCommand::Login(parts.next().map(|s| s.to_string())),
+    },
+    CommandSpec {
+        name: "logout",
+        help: &[("logout", "Lock privileged commands again")],
+        details: "",
+        parse: |_| CliCommand::Logout,
+    },
+    CommandSpec {
+        name: "passwd",
+        help: &[("passwd <new|clear>", "Set or remove the console password")],
+        details: "new: at least 6 characters; 'clear' removes the password.\nOnly a salted hash is stored.",
+        parse: |parts| match parts.next() {
+            Some(password) => CliCommand::Passwd(password.to_string()),
+            None => CliCommand::Unknown("passwd: new password or 'clear' required".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "alias",
+        help: &[
+            ("alias", "Show aliases (built-in short forms and your own)"),
+            (
+                "alias <name> <command>",
+                "Define an alias, e.g. 'alias r mtu_start 10'",
+            ),
+        ],
+        details: "Arguments typed after an alias are appended. Built in: start, stop,\nms, baud and mr. An alias can't reuse a command name or stand for a\ncommand that needs a login (factory_reset, config set, ...).\nExample: alias r mtu_start 10",
+        parse: |parts| match parts.next() {
+            None => CliCommand::AliasList,
+            Some(name) => {
+                let command = parts.collect::<Vec<&str>>().join(" ");
+                if command.is_empty() {
+                    CliCommand::Unknown(
+                        "alias: <name> <command> (unalias <name> removes)".to_string(),
+                    )
+                } else {
+                    CliCommand::AliasSet(name.to_string(), command)
+                }
+            }
+        },
+    },
+    CommandSpec {
+        name: "unalias",
+        help: &[("unalias <name>", "Remove an alias")],
+        details: "",
+        parse: |parts| match parts.next() {
+            Some(name) => CliCommand::AliasRemove(name.to_string()),
+            None => CliCommand::Unknown("unalias: name required".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "run",
+        help: &[
+            ("run <name>", "Run a saved script"),
+            (
+                "run <cmd>; <cmd>; ...",
+                "Run commands in order, stopping at a failure",
+            ),
+        ],
+        details: "Stops at the first failing command; scripts can't run other scripts.\nExample: run mtu_baud 2400; mtu_start 20",
+        parse: |parts| {
+            let commands = parts.collect::<Vec<&str>>().join(" ");
+            if commands.is_empty() {
+                CliCommand::Unknown("run: script name or commands required".to_string())
+            } else {
+                CliCommand::Run(commands)
+            }
+        },
+    },
+    CommandSpec {
+        name: "script",
+        help: &[
+            ("script [list]", "Show the saved scripts"),
+            (
+                "script save <name> \"<cmd>; <cmd>\"",
+                "Save a script (replaces one with that name)",
+            ),
+            ("script add <name> <cmd>", "Append commands to a script"),
+            ("script delete <name>", "Delete a script"),
+        ],
+        details: "Up to 8 scripts of 512 characters, saved in NVS.\nExample: script save bench \"mtu_baud 1200; mtu_start 20\"",
+        parse: |parts| match parts.next() {
+            Some("list") | None => CliCommand::ScriptList,
+            Some(action @ ("save" | "add")) => match parts.next() {
+                Some(name) => {
+                    let commands = parts.collect::<Vec<&str>>().join(" ");
+                    if commands.is_empty() {
+                        CliCommand::Unknown(format!("script {}: commands required", action))
+                    } else {
+                        CliCommand::ScriptSave(name.to_string(), commands, action == "add")
+                    }
+                }
+                None => CliCommand::Unknown(format!("script {}: <name> <commands>", action)),
+            },
+            Some("delete") => match parts.next() {
+                Some(name) => CliCommand::ScriptDelete(name.to_string()),
+                None => CliCommand::Unknown("script delete: name required".to_string()),
+            },
+            Some(_) => CliCommand::Unknown("script: use list, save, add or delete".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "meters",
+        help: &[
+            (
+                "meters [list]",
+                "Show the meters read through the multiplexer",
+            ),
+            (
+                "meters add <id> <channel> <interval_secs> [subtopic]",
+                "Add or change a meter",
+            ),
+            (
+                "meters set <id> <type|serial> <value>",
+                "Set meter type (sensus/neptune) or expected serial",
+            ),
+            ("meters remove <id>", "Remove a meter"),
+        ],
+        details: "channel: multiplexer channel; interval_secs: read interval of the meter.\nExample: meters add kitchen 2 900\nExample: meters set kitchen type neptune",
+        parse: |parts| match parts.next() {
+            Some("list") | None => CliCommand::MetersList,
+            Some("add") => match (parts.next(), parts.next(), parts.next()) {
+                (Some(id), Some(channel), Some(interval)) => {
+                    match (channel.parse::<u8>(), interval.parse::<u32>()) {
+                        (Ok(channel), Ok(interval)) => CliCommand::MetersAdd(
+                            id.to_string(),
+                            channel,
+                            interval,
+                            parts.next().map(|s| s.to_string()),
+                        ),
+                        _ => CliCommand::Unknown(
+                            "meters add: channel and interval must be numbers".to_string(),
+                        ),
+                    }
+                }
+                _ => CliCommand::Unknown(
+                    "meters add: <id> <channel> <interval_secs> [subtopic]".to_string(),
+                ),
+            },
+            Some("set") => match (parts.next(), parts.next(), parts.next()) {
+                (Some(id), Some(field), Some(value)) => {
+                    CliCommand::MetersSet(id.to_string(), field.to_string(), value.to_string())
+                }
+                _ => CliCommand::Unknown("meters set: <id> <type|serial> <value>".to_string()),
+            },
+            Some("remove") => match parts.next() {
+                Some(id) => CliCommand::MetersRemove(id.to_string()),
+                None => CliCommand::Unknown("meters remove: id required".to_string()),
+            },
+            Some(_) => CliCommand::Unknown("meters: use list, add, set or remove".to_string()),
+        },
+    },
+];
@@ -0,0 +1,279 @@
+//! CLI over the MQTT control topics
+//!
+//! Control payloads (plain text, JSON with an optional `"id"`, or batches)
+//! are mapped to command lines and run through the same parser and handler
+//! as the serial console; replies go to the response topic. A factory reset
+//! needs a nonce round trip. The formats are listed in docs/mqtt-control.md.
+
+use super::{CliCommand, CommandHandler, CommandParser};
+use crate::connectivity::DownlinkHandler;
+use std::sync::{Arc, Mutex};
+
+/// Downlink handler executing control payloads as CLI commands
+pub fn cli_downlink_handler(handler: Arc<Mutex<CommandHandler>>) -> DownlinkHandler {
+    // Nonce handed out for the pending factory reset
+    let reset_nonce = Mutex::new(None::<String>);
+    Arc::new(move |topic, data| {
+        let msg = std::str::from_utf8(data).ok()?;
+        log::info!("📩 MQTT control message on {}: {}", topic, msg);
+
+        let id = request_id(msg);
+        if let Some(batch) = split_batch(msg) {
+            log::info!("MQTT: Running a batch of {} commands", batch.len());
+            let results: Vec<_> = batch
+                .iter()
+                .map(|item| run_control(&handler, &reset_nonce, item))
+                .collect();
+            return Some(batch_reply(id, &results));
+        }
+
+        let (ok, command_line, detail) = run_control(&handler, &reset_nonce, msg);
+        match id {
+            Some(id) => Some(
+                serde_json::json!({
+                    "id": id,
+                    "ok": ok,
+                    "command": command_line,
+                    "detail": detail,
+                })
+                .to_string(),
+            ),
+            None => Some(detail),
+        }
+    })
+}
+
+/// Run one control payload; returns whether it succeeded, the command line
+/// it mapped to and the output
+fn run_control(
+    handler: &Arc<Mutex<CommandHandler>>,
+    reset_nonce: &Mutex<Option<String>>,
+    msg: &str,
+) -> (bool, Option<String>, String) {
+    let Some(line) = to_command_line(msg) else {
+        log::warn!("MQTT: Unrecognized control message: {}", msg);
+        return (
+            false,
+            None,
+            format!("Unrecognized control message: {}", msg),
+        );
+    };
+    let (ok, detail) = match CommandParser::parse_command(&line) {
+        // The nonce only confirms the command sent out in full
+        CliCommand::FactoryReset if CommandParser::expand_alias(&line).is_some() => {
+            log::warn!("MQTT: Refused factory_reset through an alias");
+            (
+                false,
+                "❌ 'factory_reset' can't run through an alias remotely".to_string(),
+            )
+        }
+        CliCommand::FactoryReset => {
+            let refusal = match handler.lock() {
+                Ok(handler) => handler.remote_refusal(&CliCommand::FactoryReset),
+                Err(_) => Some("Command handler unavailable"),
+            };
+            match refusal {
+                Some(refusal) => {
+                    log::warn!("MQTT: Refused factory_reset: {}", refusal);
+                    (false, refusal.to_string())
+                }
+                None => {
+                    let nonce = line.split_once(' ').map_or("", |(_, nonce)| nonce.trim());
+                    confirm_factory_reset(handler, reset_nonce, nonce)
+                }
+            }
+        }
+        _ => {
+            log::info!("MQTT: Running '{}'", line);
+            execute(handler, &line)
+        }
+    };
+    (ok, Some(line), detail)
+}
+
+/// The commands of a batch payload: a JSON array, a `"commands"` array, or
+/// plain text of several lines. None for a single command.
+fn split_batch(msg: &str) -> Option<Vec<String>> {
+    // Entries may be plain command lines or control objects
+    let entry = |item: &serde_json::Value| match item.as_str() {
+        Some(line) => line.to_string(),
+        None => item.to_string(),
+    };
+    match serde_json::from_str::<serde_json::Value>(msg) {
+        Ok(serde_json::Value::Array(items)) => Some(items.iter().map(entry).collect()),
+        Ok(json) => json
+            .get("commands")
+            .and_then(|commands| commands.as_array())
+            .map(|items| items.iter().map(entry).collect()),
+        Err(_) => {
+            let lines: Vec<String> = msg
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect();
+            (lines.len() > 1).then_some(lines)
+        }
+    }
+}
+
+/// Reply to a batch: a JSON acknowledgement with one result per command when
+/// the payload carried an `"id"`, otherwise each command's output under its
+/// command line
+fn batch_reply(
+    id: Option<serde_json::Value>,
+    results: &[(bool, Option<String>, String)],
+) -> String {
+    match id {
+        Some(id) => serde_json::json!({
+            "id": id,
+            "ok": results.iter().all(|(ok, _, _)| *ok),
+            "results": results
+                .iter()
+                .map(|(ok, command, detail)| {
+                    serde_json::json!({ "command": command, "ok": ok, "detail": detail })
+                })
+                .collect::<Vec<_>>(),
+        })
+        .to_string(),
+        None => results
+            .iter()
+            .map(|(_, command, detail)| {
+                format!("> {}\r\n{}", command.as_deref().unwrap_or("?"), detail)
+            })
+            .collect::<Vec<_>>()
+            .join("\r\n"),
+    }
+}
+
+/// `"id"` of a JSON control payload, echoed in the acknowledgement
+fn request_id(msg: &str) -> Option<serde_json::Value> {
+    let json = serde_json::from_str::<serde_json::Value>(msg).ok()?;
+    json.get("id")
+        .filter(|id| id.is_string() || id.is_number())
+        .cloned()
+}
+
+/// Map a control payload to a CLI command line
+pub fn to_command_line(msg: &str) -> Option<String> {
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(msg) {
+        if let Some(baud_rate) = json.get("baud_rate").and_then(|v| v.as_u64()) {
+            return Some(format!("mtu_baud {}", baud_rate));
+        }
+        return match json.get("command").and_then(|v| v.as_str())? {
+            "start" => {
+                let duration = json.get("duration").and_then(|v| v.as_u64()).unwrap_or(30);
+                Some(format!("mtu_start {}", duration))
+            }
+            "stop" => Some("mtu_stop".to_string()),
+            "factory_reset" => match json.get("nonce").and_then(|v| v.as_str()) {
+                Some(nonce) => Some(format!("factory_reset {}", nonce)),
+                None => Some("factory_reset".to_string()),
+            },
+            "export" => {
+                let count = json.get("count").and_then(|v| v.as_u64()).unwrap_or(10);
+                Some(format!("export upload {}", count))
+            }
+            line => Some(remote_export(line.trim())),
+        };
+    }
+
+    let line = msg.trim();
+    let lower = line.to_lowercase();
+    if lower == "start" {
+        Some("mtu_start 30".to_string())
+    } else if let Some(duration) = lower.strip_prefix("start ") {
+        Some(format!("mtu_start {}", duration.trim()))
+    } else if lower == "stop" {
+        Some("mtu_stop".to_string())
+    } else if line.is_empty() {
+        None
+    } else {
+        Some(remote_export(line))
+    }
+}
+
+/// Run `factory_reset` if `nonce` is the one handed out by the previous
+/// request; otherwise hand out a new one. The nonce is published on the
+/// response topic, so it stops accidental resets (retained or misdirected
+/// messages), not a sender who can read that topic.
+fn confirm_factory_reset(
+    handler: &Arc<Mutex<CommandHandler>>,
+    pending: &Mutex<Option<String>>,
+    nonce: &str,
+) -> (bool, String) {
+    let Ok(mut pending) = pending.lock() else {
+        return (false, "Factory reset unavailable".to_string());
+    };
+    if pending.take().is_some_and(|expected| expected == nonce) {
+        log::warn!("MQTT: Factory reset confirmed");
+        return execute(handler, "factory_reset");
+    }
+    let nonce = format!("{:08x}", unsafe { esp_idf_svc::sys::esp_random() });
+    log::warn!("MQTT: Factory reset requested, waiting for confirmation");
+    let detail = format!(
+        "Factory reset not confirmed; send {{\"command\":\"factory_reset\",\"nonce\":\"{}\"}} to erase the configuration",
+        nonce
+    );
+    *pending = Some(nonce);
+    (false, detail)
+}
+
+/// `export <n>` → `export upload <n>`: the CSV goes to the export topic
+fn remote_export(line: &str) -> String {
+    match line.strip_prefix("export ") {
+        Some(count) if count.trim().parse::<usize>().is_ok() => {
+            format!("export upload {}", count.trim())
+        }
+        _ => line.to_string(),
+    }
+}
+
+/// Run a command line; returns whether it succeeded and its output. Handlers
+/// report failures in the response text, marked with ❌; unknown commands and
+/// usage errors also count as failures.
+pub(crate) fn execute(handler: &Arc<Mutex<CommandHandler>>, command_line: &str) -> (bool, String) {
+    run(handler, command_line, false)
+}
+
+/// `execute` for a caller that authenticated itself, so privileged commands
+/// run without `network.remote_privileged`
+pub(crate) fn execute_authorized(
+    handler: &Arc<Mutex<CommandHandler>>,
+    command_line: &str,
+) -> (bool, String) {
+    run(handler, command_line, true)
+}
+
+fn run(
+    handler: &Arc<Mutex<CommandHandler>>,
+    command_line: &str,
+    authorized: bool,
+) -> (bool, String) {
+    let command = CommandParser::parse_command(command_line);
+    // Help is rendered by the terminal on the console; list the commands instead
+    if let CliCommand::Help = command {
+        return (
+            true,
+            format!(
+                "Commands: {}",
+                CommandParser::get_available_commands().join(", ")
+            ),
+        );
+    }
+    let parsed = !matches!(command, CliCommand::Unknown(_));
+
+    let mut handler = match handler.lock() {
+        Ok(handler) => handler,
+        Err(_) => return (false, "Command handler unavailable".to_string()),
+    };
+    let result = if authorized {
+        handler.execute_authorized(command)
+    } else {
+        handler.execute_command(command)
+    };
+    match result {
+        Ok(response) => (parsed && !response.contains('❌'), response),
+        Err(e) => (false, format!("Error: {}", e)),
+    }
+}
@@ -0,0 +1,254 @@
+//! Telnet CLI server
+//!
+//! With `network.telnet = true` the serial console is also reachable over
+//! TCP while the link is up, for installers without a USB cable:
+//!
+//! ```text
+//! $ telnet esp32-water-meter.local
+//! ESP32 Water Meter MTU Interface (telnet)
+//! ESP32 CLI> status
+//! ```
+//!
+//! Lines are edited with the same `Terminal` (history, TAB completion,
+//! arrows, Ctrl-C) and run through the same handler as the serial console.
+//! Each client has its own login session: once a console password is set
+//! (`passwd`), privileged commands need `login` on the telnet session too.
+//! One client is served at a time; it is dropped after `IDLE_TIMEOUT`
+//! without input.
+
+use super::auth::Session;
+use super::terminal::{Terminal, TerminalIo};
+use super::{CliCommand, CliError, CommandHandler, CommandParser};
+use crate::mtu::{GpioMtuTimerV2, MtuCommand};
+use anyhow::Result;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const TELNET_PORT: u16 = 23;
+
+/// How long a read waits for input before the idle check
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// A client that stops reading is dropped after this long
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const SERVER_STACK_SIZE: usize = 8192;
+
+// Telnet commands and options (RFC 854, 857, 858)
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const IP: u8 = 244;
+const SE: u8 = 240;
+const OPT_ECHO: u8 = 1;
+const OPT_SGA: u8 = 3;
+
+/// Where the input filter is inside a telnet command
+#[derive(Clone, Copy, PartialEq)]
+enum IacState {
+    Data,
+    /// After IAC
+    Command,
+    /// After IAC WILL/WONT/DO/DONT, the option byte follows
+    Option,
+    /// Inside IAC SB ... IAC SE
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// A client socket with telnet negotiation stripped from the input
+pub struct TcpIo {
+    stream: TcpStream,
+    state: IacState,
+    /// Drop the LF or NUL a telnet client sends after CR
+    after_cr: bool,
+}
+
+impl TcpIo {
+    fn new(stream: TcpStream) -> std::io::Result<Self> {
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let _ = stream.set_nodelay(true);
+        let mut io = Self {
+            stream,
+            state: IacState::Data,
+            after_cr: false,
+        };
+        // The device echoes and edits the line itself (character mode)
+        io.stream
+            .write_all(&[IAC, WILL, OPT_ECHO, IAC, WILL, OPT_SGA])?;
+        Ok(io)
+    }
+
+    /// Input byte for the terminal, None for telnet protocol bytes
+    fn filter(&mut self, byte: u8) -> Option<u8> {
+        match self.state {
+            IacState::Data => {
+                if byte == IAC {
+                    self.state = IacState::Command;
+                    return None;
+                }
+                let after_cr = std::mem::replace(&mut self.after_cr, byte == b'\r');
+                if after_cr && matches!(byte, b'\n' | 0) {
+                    return None;
+                }
+                Some(byte)
+            }
+            IacState::Command => {
+                self.state = match byte {
+                    WILL | WONT | DO | DONT => IacState::Option,
+                    SB => IacState::Subnegotiation,
+                    _ => IacState::Data,
+                };
+                match byte {
+                    // Escaped 0xFF data byte
+                    IAC => Some(IAC),
+                    // Interrupt Process, sent by some clients for Ctrl-C
+                    IP => Some(b'\x03'),
+                    _ => None,
+                }
+            }
+            IacState::Option => {
+                self.state = IacState::Data;
+                None
+            }
+            IacState::Subnegotiation => {
+                if byte == IAC {
+                    self.state = IacState::SubnegotiationIac;
+                }
+                None
+            }
+            IacState::SubnegotiationIac => {
+                self.state = if byte == SE {
+                    IacState::Data
+                } else {
+                    IacState::Subnegotiation
+                };
+                None
+            }
+        }
+    }
+}
+
+impl TerminalIo for TcpIo {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), CliError> {
+        self.stream
+            .write_all(bytes)
+            .map_err(|_| CliError::Disconnected)
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, CliError> {
+        let mut buf = [0u8; 1];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(CliError::Disconnected),
+                Ok(_) => {
+                    if let Some(byte) = self.filter(buf[0]) {
+                        return Ok(Some(byte));
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None)
+                }
+                Err(_) => return Err(CliError::Disconnected),
+            }
+        }
+    }
+}
+
+/// Listen on `port` and serve telnet clients from a background thread
+pub fn spawn_telnet_server(
+    port: u16,
+    handler: Arc<Mutex<CommandHandler>>,
+    mtu: Arc<GpioMtuTimerV2>,
+    mtu_cmd_sender: Sender<MtuCommand>,
+) -> Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    std::thread::Builder::new()
+        .name("telnet_cli".into())
+        .stack_size(SERVER_STACK_SIZE)
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::warn!("⚠️  Telnet: Accept failed: {:?}", e);
+                        continue;
+                    }
+                };
+                let peer = stream
+                    .peer_addr()
+                    .map(|addr| addr.to_string())
+                    .unwrap_or_default();
+                log::info!("🔌 Telnet: Client {} connected", peer);
+                let io = match TcpIo::new(stream) {
+                    Ok(io) => io,
+                    Err(e) => {
+                        log::warn!("⚠️  Telnet: Failed to set up client: {:?}", e);
+                        continue;
+                    }
+                };
+                let mut terminal =
+                    Terminal::with_io(io).with_mtu(Arc::clone(&mtu), mtu_cmd_sender.clone());
+                let _ = serve(&mut terminal, &handler);
+                log::info!("🔌 Telnet: Client {} disconnected", peer);
+            }
+        })?;
+    log::info!("✅ Telnet CLI listening on port {}", port);
+    Ok(())
+}
+
+/// Run one client's session until it disconnects or goes idle
+fn serve(terminal: &mut Terminal<TcpIo>, handler: &Mutex<CommandHandler>) -> Result<(), CliError> {
+    let mut session = Session::default();
+    terminal.write_line("")?;
+    terminal.write_line("ESP32 Water Meter MTU Interface (telnet)")?;
+    terminal.write_line("Type 'help' for available commands")?;
+    terminal.print_prompt()?;
+
+    let mut last_input = Instant::now();
+    loop {
+        let Some(ch) = terminal.read_char()? else {
+            if last_input.elapsed() > IDLE_TIMEOUT {
+                terminal.write_line("")?;
+                terminal.write_line("Idle timeout, closing connection")?;
+                return Ok(());
+            }
+            continue;
+        };
+        last_input = Instant::now();
+        let Some(command_line) = terminal.handle_char(ch)? else {
+            continue;
+        };
+
+        let command = CommandParser::parse_command(&command_line);
+        let command_clone = command.clone();
+        let result = match handler.lock() {
+            Ok(mut handler) => handler.execute_session(command, &mut session),
+            Err(_) => Err(CliError::InvalidCommand),
+        };
+        match result {
+            Ok(response) => {
+                if !response.is_empty() {
+                    terminal.write_paged(&response)?;
+                }
+            }
+            Err(_) => terminal.write_line("Command execution error.")?,
+        }
+
+        // Commands that need terminal interaction
+        match command_clone {
+            CliCommand::Help => terminal.show_help()?,
+            CliCommand::Clear => terminal.clear_screen()?,
+            CliCommand::Pager(lines) => terminal.set_page_lines(lines),
+            CliCommand::Color(on) => terminal.set_color(on),
+            _ => {}
+        }
+        terminal.print_prompt()?;
+    }
+}
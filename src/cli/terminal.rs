@@ -1,16 +1,84 @@
+use super::meter_parser::METER_COMMANDS;
+use super::registry::MTU_COMMANDS;
 use super::{parser::CommandParser, CliError, CLI_BUFFER_SIZE};
-use esp_idf_hal::uart::{UartRxDriver, UartTxDriver};
+use crate::mtu::{GpioMtuTimerV2, MtuCommand};
+use std::io::{ErrorKind, Read, Write};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
 
 const HISTORY_SIZE: usize = 10;
+/// Longest CSI parameter string kept (e.g. "1;5" of Ctrl+Right)
+const CSI_PARAMS_MAX: usize = 8;
+/// Lines of long output shown before `--More--` (a 24-line terminal less
+/// the prompt and the `--More--` line)
+pub const DEFAULT_PAGE_LINES: usize = 22;
+pub const MIN_PAGE_LINES: usize = 5;
+pub const MAX_PAGE_LINES: usize = 200;
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+const MORE_PROMPT: &str = "--More-- (space: page, enter: line, q: quit)";
+const PAGER_POLL_MS: u64 = 10;
+
+/// Byte stream a `Terminal` edits lines over (the UART console, a telnet client)
+pub trait TerminalIo {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), CliError>;
+    /// Next input byte, None when nothing is waiting
+    fn read_byte(&mut self) -> Result<Option<u8>, CliError>;
+}
+
+/// Any `std::io` reader/writer pair (USB-serial-JTAG through VFS, a host-side
+/// harness with in-memory buffers); reads that time out count as no input
+pub struct StreamIo<R, W> {
+    pub reader: R,
+    pub writer: W,
+}
+
+impl<R: Read, W: Write> TerminalIo for StreamIo<R, W> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), CliError> {
+        self.writer
+            .write_all(bytes)
+            .and_then(|_| self.writer.flush())
+            .map_err(|_| CliError::UartError)
+    }
 
-pub struct Terminal<'d> {
-    pub uart_tx: UartTxDriver<'d>,
-    pub uart_rx: UartRxDriver<'d>,
+    fn read_byte(&mut self) -> Result<Option<u8>, CliError> {
+        let mut buf = [0u8; 1];
+        match self.reader.read(&mut buf) {
+            Ok(0) => Err(CliError::Disconnected),
+            Ok(_) => Ok(Some(buf[0])),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(_) => Err(CliError::UartError),
+        }
+    }
+}
+
+pub struct Terminal<IO> {
+    pub io: IO,
     line_buffer: String,
     cursor_pos: usize,
     command_history: Vec<String>,
     history_index: Option<usize>,
     escape_state: EscapeState,
+    /// Parameter bytes of the CSI sequence being received
+    csi_params: String,
+    /// Stopped by Ctrl-C while a read is running
+    mtu: Option<(Arc<GpioMtuTimerV2>, Sender<MtuCommand>)>,
+    /// Lines per page in `write_paged` (None = no paging)
+    page_lines: Option<usize>,
+    /// Style command output with ANSI colors (`color on`)
+    color: bool,
+    /// Command names for TAB completion (the MTU set unless changed)
+    completion: fn(&str) -> Vec<&'static str>,
+}
+
+/// Answer to the `--More--` prompt
+enum More {
+    Page,
+    Line,
+    Quit,
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -18,26 +86,40 @@ enum EscapeState {
     Normal,
     Escape,
     Csi,
+    /// ESC O (application cursor keys, Home/End on some terminals)
+    Ss3,
 }
 
-impl<'d> Terminal<'d> {
-    pub fn new(uart_tx: UartTxDriver<'d>, uart_rx: UartRxDriver<'d>) -> Self {
+impl<IO: TerminalIo> Terminal<IO> {
+    pub fn with_io(io: IO) -> Self {
         Self {
-            uart_tx,
-            uart_rx,
+            io,
             line_buffer: String::new(),
             cursor_pos: 0,
             command_history: Vec::new(),
             history_index: None,
             escape_state: EscapeState::Normal,
+            csi_params: String::new(),
+            mtu: None,
+            page_lines: Some(DEFAULT_PAGE_LINES),
+            color: false,
+            completion: CommandParser::autocomplete,
         }
     }
 
+    /// Complete command names from another console's set (the meter's)
+    pub fn with_completion(mut self, completion: fn(&str) -> Vec<&'static str>) -> Self {
+        self.completion = completion;
+        self
+    }
+
+    pub fn with_mtu(mut self, mtu: Arc<GpioMtuTimerV2>, cmd_sender: Sender<MtuCommand>) -> Self {
+        self.mtu = Some((mtu, cmd_sender));
+        self
+    }
+
     pub fn write_str(&mut self, s: &str) -> Result<(), CliError> {
-        self.uart_tx
-            .write(s.as_bytes())
-            .map_err(|_| CliError::UartError)?;
-        Ok(())
+        self.io.write_bytes(s.as_bytes())
     }
 
     pub fn write_line(&mut self, s: &str) -> Result<(), CliError> {
@@ -45,18 +127,78 @@ impl<'d> Terminal<'d> {
         self.write_str("\r\n")
     }
 
+    pub fn set_page_lines(&mut self, lines: Option<usize>) {
+        self.page_lines = lines;
+    }
+
+    pub fn set_color(&mut self, on: bool) {
+        self.color = on;
+    }
+
+    /// `write_line` for command output: styled (see `style_line`), pausing
+    /// at `--More--` every page, where space shows the next page, Enter one
+    /// more line and q (or Ctrl-C) skips the rest
+    pub fn write_paged(&mut self, text: &str) -> Result<(), CliError> {
+        let page = self.page_lines.unwrap_or(usize::MAX);
+        let mut remaining = page;
+        for line in text.lines() {
+            if remaining == 0 {
+                remaining = match self.wait_more()? {
+                    More::Page => page,
+                    More::Line => 1,
+                    More::Quit => return Ok(()),
+                };
+            }
+            let line = self.style_line(line);
+            self.write_line(&line)?;
+            remaining -= 1;
+        }
+        Ok(())
+    }
+
+    /// With color on, errors red, successes green, warnings yellow and
+    /// headers ("MTU Status:") bold; with color off, any ANSI styling a
+    /// handler added is stripped
+    fn style_line(&self, line: &str) -> String {
+        if !self.color {
+            return strip_styles(line);
+        }
+        let style = if line.contains('❌') || line.starts_with("Error") {
+            RED
+        } else if line.contains('✅') {
+            GREEN
+        } else if line.contains('⚠') {
+            YELLOW
+        } else if !line.starts_with(' ') && line.ends_with(':') {
+            BOLD
+        } else {
+            return line.to_string();
+        };
+        format!("{}{}{}", style, line, RESET)
+    }
+
+    fn wait_more(&mut self) -> Result<More, CliError> {
+        self.write_str(MORE_PROMPT)?;
+        let more = loop {
+            match self.io.read_byte()? {
+                Some(b' ') => break More::Page,
+                Some(b'\r' | b'\n') => break More::Line,
+                Some(b'q' | b'Q' | b'\x03') => break More::Quit,
+                Some(_) => {}
+                None => std::thread::sleep(std::time::Duration::from_millis(PAGER_POLL_MS)),
+            }
+        };
+        // Erase the prompt line
+        self.write_str("\r\x1b[K")?;
+        Ok(more)
+    }
+
     pub fn print_prompt(&mut self) -> Result<(), CliError> {
         self.write_str("ESP32 CLI> ")
     }
 
     pub fn read_char(&mut self) -> Result<Option<u8>, CliError> {
-        let mut buf = [0u8; 1];
-        match self.uart_rx.read(&mut buf, 0) {
-            Ok(1) => Ok(Some(buf[0])),
-            Ok(0) => Ok(None),
-            Ok(_) => Ok(None),
-            Err(_) => Err(CliError::UartError),
-        }
+        self.io.read_byte()
     }
 
     pub fn handle_char(&mut self, ch: u8) -> Result<Option<String>, CliError> {
@@ -68,7 +210,9 @@ impl<'d> Terminal<'d> {
                     let command = self.line_buffer.clone();
 
                     // Add to history if non-empty and different from last entry
-                    if !command.is_empty() {
+                    // (never with a password on it)
+                    let secret = command.starts_with("login ") || command.starts_with("passwd ");
+                    if !command.is_empty() && !secret {
                         let should_add = self.command_history.is_empty()
                             || self.command_history.last() != Some(&command);
 
@@ -85,6 +229,11 @@ impl<'d> Terminal<'d> {
                     self.history_index = None;
                     Ok(Some(command))
                 }
+                b'\x03' => {
+                    // Ctrl-C - abort a running MTU read, otherwise drop the line
+                    self.handle_interrupt()?;
+                    Ok(None)
+                }
                 b'\x1b' => {
                     // ESC - start escape sequence
                     self.escape_state = EscapeState::Escape;
@@ -119,6 +268,11 @@ impl<'d> Terminal<'d> {
                     b'[' => {
                         // ESC[ - Control Sequence Introducer
                         self.escape_state = EscapeState::Csi;
+                        self.csi_params.clear();
+                        Ok(None)
+                    }
+                    b'O' => {
+                        self.escape_state = EscapeState::Ss3;
                         Ok(None)
                     }
                     _ => {
@@ -129,37 +283,49 @@ impl<'d> Terminal<'d> {
                 }
             }
             EscapeState::Csi => {
-                match ch {
-                    b'A' => {
-                        // Up arrow - previous command in history
-                        self.handle_history_up()?;
-                        self.escape_state = EscapeState::Normal;
-                        Ok(None)
-                    }
-                    b'B' => {
-                        // Down arrow - next command in history
-                        self.handle_history_down()?;
-                        self.escape_state = EscapeState::Normal;
-                        Ok(None)
-                    }
-                    b'C' => {
-                        // Right arrow - move cursor right
-                        self.handle_cursor_right()?;
-                        self.escape_state = EscapeState::Normal;
-                        Ok(None)
-                    }
-                    b'D' => {
-                        // Left arrow - move cursor left
-                        self.handle_cursor_left()?;
-                        self.escape_state = EscapeState::Normal;
-                        Ok(None)
-                    }
-                    _ => {
-                        // Other CSI sequences, ignore for now
-                        self.escape_state = EscapeState::Normal;
-                        Ok(None)
+                if (0x30..=0x3F).contains(&ch) {
+                    // Parameter byte, e.g. the "3" of ESC[3~
+                    if self.csi_params.len() < CSI_PARAMS_MAX {
+                        self.csi_params.push(ch as char);
                     }
+                    return Ok(None);
+                }
+                self.escape_state = EscapeState::Normal;
+                let params = std::mem::take(&mut self.csi_params);
+                match (ch, params.as_str()) {
+                    // Up arrow - previous command in history
+                    (b'A', _) => self.handle_history_up()?,
+                    // Down arrow - next command in history
+                    (b'B', _) => self.handle_history_down()?,
+                    // Ctrl+Right / Ctrl+Left - move by word
+                    (b'C', "1;5") => self.handle_word_right()?,
+                    (b'D', "1;5") => self.handle_word_left()?,
+                    // Right arrow - move cursor right
+                    (b'C', _) => self.handle_cursor_right()?,
+                    // Left arrow - move cursor left
+                    (b'D', _) => self.handle_cursor_left()?,
+                    // Home / End (ESC[H, ESC[F and the VT220 ESC[1~ .. ESC[8~ forms)
+                    (b'H', _) | (b'~', "1" | "7") => self.move_cursor_to(0)?,
+                    (b'F', _) | (b'~', "4" | "8") => self.move_cursor_to(self.line_buffer.len())?,
+                    // Delete - remove the character under the cursor
+                    (b'~', "3") => self.delete_char_at_cursor()?,
+                    // Other CSI sequences, ignore for now
+                    _ => {}
+                }
+                Ok(None)
+            }
+            EscapeState::Ss3 => {
+                self.escape_state = EscapeState::Normal;
+                match ch {
+                    b'A' => self.handle_history_up()?,
+                    b'B' => self.handle_history_down()?,
+                    b'C' => self.handle_cursor_right()?,
+                    b'D' => self.handle_cursor_left()?,
+                    b'H' => self.move_cursor_to(0)?,
+                    b'F' => self.move_cursor_to(self.line_buffer.len())?,
+                    _ => {}
                 }
+                Ok(None)
             }
         }
     }
@@ -176,7 +342,7 @@ impl<'d> Terminal<'d> {
         // Only autocomplete the first word (command)
         if words.is_empty() || (!current_line.ends_with(' ') && words.len() == 1) {
             let partial = if words.is_empty() { "" } else { words[0] };
-            let matches = CommandParser::autocomplete(partial);
+            let matches = (self.completion)(partial);
 
             match matches.len() {
                 0 => {
@@ -200,16 +366,14 @@ impl<'d> Terminal<'d> {
                         if self.line_buffer.len() < CLI_BUFFER_SIZE - 1 {
                             self.line_buffer.push(ch);
                             self.cursor_pos += 1;
-                            self.uart_tx
-                                .write(&[ch as u8])
-                                .map_err(|_| CliError::UartError)?;
+                            self.io.write_bytes(&[ch as u8])?;
                         }
                     }
                     // Add a space after completion
                     if self.line_buffer.len() < CLI_BUFFER_SIZE - 1 {
                         self.line_buffer.push(' ');
                         self.cursor_pos += 1;
-                        self.uart_tx.write(b" ").map_err(|_| CliError::UartError)?;
+                        self.io.write_bytes(b" ")?;
                     }
                 }
                 _ => {
@@ -232,49 +396,44 @@ impl<'d> Terminal<'d> {
     }
 
     pub fn show_help(&mut self) -> Result<(), CliError> {
-        self.write_line("Available commands:")?;
-        self.write_line("  help        - Show this help")?;
-        self.write_line("  version     - Show firmware version")?;
-        self.write_line("  status      - Show system status")?;
-        self.write_line("  uptime      - Show system uptime")?;
-        self.write_line("  clear       - Clear terminal")?;
-        self.write_line("  reset       - Reset system")?;
-        self.write_line("  echo <text> - Echo text back")?;
-        self.write_line("  mtu_start [dur] - Start MTU operation (default 30s)")?;
-        self.write_line("  mtu_stop    - Stop MTU operation")?;
-        self.write_line("  mtu_status  - Show MTU status")?;
-        self.write_line("  mtu_baud <rate> - Set MTU baud rate (1-115200, default 1200)")?;
-        self.write_line("  mtu_reset   - Reset MTU statistics")?;
-        self.write_line("  wifi_connect [ssid] [password] - Connect to WiFi (no args = default)")?;
-        self.write_line("  wifi_reconnect - Quick reconnect to default WiFi")?;
-        self.write_line("  wifi_status - Show WiFi connection status")?;
-        self.write_line("  mqtt_connect <broker_url> - Connect to MQTT broker")?;
-        self.write_line("  mqtt_status - Show MQTT connection status")?;
-        self.write_line("  mqtt_publish <topic> <message> - Publish MQTT message")?;
-        self.write_line("")?;
-        self.write_line("Use TAB to autocomplete commands")?;
-        self.write_line("Use UP/DOWN arrows to navigate command history")?;
-        self.write_line("Use LEFT/RIGHT arrows to move cursor and edit")?;
-        Ok(())
+        let mut help = MTU_COMMANDS.help_text();
+        help.push_str("\r\n");
+        help.push_str("Use 'help <command>' for a command's arguments and examples\r\n");
+        help.push_str("Use TAB to autocomplete commands\r\n");
+        help.push_str("Use UP/DOWN arrows to navigate command history\r\n");
+        help.push_str("Use LEFT/RIGHT arrows to move cursor and edit\r\n");
+        help.push_str("Use HOME/END, DELETE and Ctrl+LEFT/RIGHT (by word) while editing\r\n");
+        help.push_str("Use Ctrl-C to stop a running MTU read or discard the line\r\n");
+        help.push_str("Long output pauses at --More-- (see 'pager')");
+        self.write_paged(&help)
     }
 
     pub fn show_meter_help(&mut self) -> Result<(), CliError> {
-        self.write_line("Available commands:")?;
-        self.write_line("  help        - Show this help")?;
-        self.write_line("  version     - Show firmware version")?;
-        self.write_line("  status      - Show meter status and statistics")?;
-        self.write_line("  uptime      - Show system uptime")?;
-        self.write_line("  clear       - Clear terminal")?;
-        self.write_line("  reset       - Reset system")?;
-        self.write_line("  enable      - Enable meter response to clock signals")?;
-        self.write_line("  disable     - Disable meter response")?;
-        self.write_line("  type <sensus|neptune> - Set meter type (7E1 or 7E2)")?;
-        self.write_line("  message <text> - Set response message (\\r added automatically)")?;
-        self.write_line("")?;
-        self.write_line("Use TAB to autocomplete commands")?;
-        self.write_line("Use UP/DOWN arrows to navigate command history")?;
-        self.write_line("Use LEFT/RIGHT arrows to move cursor and edit")?;
-        Ok(())
+        let mut help = METER_COMMANDS.help_text();
+        help.push_str("\r\n");
+        help.push_str("Use 'help <command>' for a command's arguments and examples\r\n");
+        help.push_str("Use TAB to autocomplete commands\r\n");
+        help.push_str("Use UP/DOWN arrows to navigate command history\r\n");
+        help.push_str("Use LEFT/RIGHT arrows to move cursor and edit");
+        self.write_paged(&help)
+    }
+
+    fn handle_interrupt(&mut self) -> Result<(), CliError> {
+        self.write_str("^C\r\n")?;
+        if let Some((ref mtu, ref sender)) = self.mtu {
+            if mtu.is_running() {
+                let message = match sender.send(MtuCommand::Stop) {
+                    Ok(_) => "MTU stop signal sent",
+                    Err(_) => "Error: Failed to send command to MTU thread",
+                };
+                log::info!("CLI: Ctrl-C, {}", message);
+                self.write_line(message)?;
+            }
+        }
+        self.line_buffer.clear();
+        self.cursor_pos = 0;
+        self.history_index = None;
+        self.print_prompt()
     }
 
     fn handle_history_up(&mut self) -> Result<(), CliError> {
@@ -354,15 +513,49 @@ impl<'d> Terminal<'d> {
         Ok(())
     }
 
+    fn move_cursor_to(&mut self, pos: usize) -> Result<(), CliError> {
+        if pos < self.cursor_pos {
+            self.write_str(&format!("\x1b[{}D", self.cursor_pos - pos))?;
+        } else if pos > self.cursor_pos {
+            self.write_str(&format!("\x1b[{}C", pos - self.cursor_pos))?;
+        }
+        self.cursor_pos = pos;
+        Ok(())
+    }
+
+    /// To the start of the current or previous word
+    fn handle_word_left(&mut self) -> Result<(), CliError> {
+        let bytes = self.line_buffer.as_bytes();
+        let mut pos = self.cursor_pos;
+        while pos > 0 && bytes[pos - 1] == b' ' {
+            pos -= 1;
+        }
+        while pos > 0 && bytes[pos - 1] != b' ' {
+            pos -= 1;
+        }
+        self.move_cursor_to(pos)
+    }
+
+    /// To the end of the current or next word
+    fn handle_word_right(&mut self) -> Result<(), CliError> {
+        let bytes = self.line_buffer.as_bytes();
+        let mut pos = self.cursor_pos;
+        while pos < bytes.len() && bytes[pos] == b' ' {
+            pos += 1;
+        }
+        while pos < bytes.len() && bytes[pos] != b' ' {
+            pos += 1;
+        }
+        self.move_cursor_to(pos)
+    }
+
     fn insert_char_at_cursor(&mut self, ch: char) -> Result<(), CliError> {
         if self.cursor_pos == self.line_buffer.len() {
             // Simple case: inserting at end
             self.line_buffer.push(ch);
             self.cursor_pos += 1;
             // Echo the character
-            self.uart_tx
-                .write(&[ch as u8])
-                .map_err(|_| CliError::UartError)?;
+            self.io.write_bytes(&[ch as u8])?;
         } else {
             // Complex case: inserting in middle - need to rebuild string
             self.line_buffer.insert(self.cursor_pos, ch);
@@ -415,6 +608,14 @@ impl<'d> Terminal<'d> {
         Ok(())
     }
 
+    fn delete_char_at_cursor(&mut self) -> Result<(), CliError> {
+        if self.cursor_pos < self.line_buffer.len() {
+            self.line_buffer.remove(self.cursor_pos);
+            self.redraw_line_from_cursor_with_clear()?;
+        }
+        Ok(())
+    }
+
     fn redraw_line_from_cursor_with_clear(&mut self) -> Result<(), CliError> {
         // Save current cursor position
         let saved_cursor = self.cursor_pos;
@@ -437,3 +638,99 @@ impl<'d> Terminal<'d> {
         Ok(())
     }
 }
+
+/// `line` without ANSI SGR sequences (`ESC [ ... m`)
+fn strip_styles(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find("\x1b[") {
+        out.push_str(&rest[..start]);
+        let sequence = &rest[start + 2..];
+        match sequence.find(|c: char| !c.is_ascii_digit() && c != ';') {
+            Some(end) if sequence.as_bytes()[end] == b'm' => rest = &sequence[end + 1..],
+            _ => {
+                // Not a style sequence, keep it
+                out.push_str("\x1b[");
+                rest = sequence;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    type MemoryTerminal = Terminal<StreamIo<Cursor<Vec<u8>>, Vec<u8>>>;
+
+    fn terminal(input: &[u8]) -> MemoryTerminal {
+        Terminal::with_io(StreamIo {
+            reader: Cursor::new(input.to_vec()),
+            writer: Vec::new(),
+        })
+    }
+
+    /// Feed the terminal all of its input; returns the lines entered
+    fn entered_lines(terminal: &mut MemoryTerminal) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            match terminal.read_char() {
+                Ok(Some(ch)) => lines.extend(terminal.handle_char(ch).unwrap()),
+                Ok(None) => {}
+                Err(CliError::Disconnected) => return lines,
+                Err(e) => panic!("unexpected error: {:?}", e),
+            }
+        }
+    }
+
+    fn output(terminal: &MemoryTerminal) -> String {
+        String::from_utf8_lossy(&terminal.io.writer).into_owned()
+    }
+
+    #[test]
+    fn enter_returns_the_edited_line() {
+        // Backspace, then Left and an insert in the middle
+        let mut terminal = terminal(b"statx\x7fs\x1b[Du\r");
+        assert_eq!(entered_lines(&mut terminal), ["status"]);
+        assert!(output(&terminal).ends_with("\r\n"));
+    }
+
+    #[test]
+    fn up_arrow_recalls_history_but_not_passwords() {
+        let mut terminal = terminal(b"status\rlogin secret\r\x1b[A\r");
+        assert_eq!(
+            entered_lines(&mut terminal),
+            ["status", "login secret", "status"]
+        );
+    }
+
+    #[test]
+    fn ctrl_c_drops_the_line() {
+        let mut terminal = terminal(b"reboot\x03version\r");
+        assert_eq!(entered_lines(&mut terminal), ["version"]);
+    }
+
+    #[test]
+    fn pager_stops_at_q() {
+        let mut terminal = terminal(b"q");
+        terminal.set_page_lines(Some(MIN_PAGE_LINES));
+        let text: Vec<String> = (1..=12).map(|n| format!("line {}", n)).collect();
+        terminal.write_paged(&text.join("\n")).unwrap();
+        let output = output(&terminal);
+        assert!(output.contains("line 5\r\n"));
+        assert!(output.contains(MORE_PROMPT));
+        assert!(!output.contains("line 6"));
+    }
+
+    #[test]
+    fn color_off_strips_styles() {
+        let mut terminal = terminal(b"");
+        terminal
+            .write_paged(&format!("{}❌ Failed{}", RED, RESET))
+            .unwrap();
+        assert_eq!(output(&terminal), "❌ Failed\r\n");
+    }
+}
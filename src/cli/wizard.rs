@@ -0,0 +1,214 @@
+//! Guided first-boot setup on the console
+//!
+//! A device that boots with nothing in NVS (no configuration, no WiFi
+//! networks) asks for the essentials on the serial console before falling
+//! back to the provisioning portal:
+//!
+//! ```text
+//! === First-time setup ===
+//! WiFi SSID: HomeNetwork
+//! WiFi password (empty for an open network): ********
+//! MQTT broker [mqtt://test.mosquitto.org:1883]: broker.example.com
+//! Save and reboot? [Y/n]
+//! ```
+//!
+//! The answers are saved the same way as the portal's form
+//! (`ProvisioningSettings`) and the device reboots into station mode. An
+//! empty SSID, Ctrl-C or no input for `IDLE_TIMEOUT` skips the setup, so a
+//! board without a console attached still reaches the portal.
+
+use super::terminal::TerminalIo;
+use super::CliError;
+use crate::config_store::DeviceConfig;
+use crate::connectivity::preflight::broker_address;
+use crate::wifi::ProvisioningSettings;
+use anyhow::Result;
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use std::time::{Duration, Instant};
+
+/// Wait for each answer before giving up on the setup
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_MS: u64 = 10;
+const MAX_SSID_LEN: usize = 32;
+const MAX_PASSWORD_LEN: usize = 64;
+const MAX_BROKER_LEN: usize = 128;
+
+/// How the setup ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetupOutcome {
+    /// Settings saved; the caller reboots to apply them
+    Saved,
+    /// Nothing saved (skipped, cancelled or timed out)
+    Skipped,
+}
+
+/// Ask for the WiFi network and MQTT broker and save them to NVS
+pub fn run(io: &mut impl TerminalIo, nvs: &EspDefaultNvsPartition) -> Result<SetupOutcome> {
+    let mut io = Console {
+        io,
+        after_cr: false,
+    };
+    let default_broker = DeviceConfig::default().mqtt.broker_url;
+    io.write_line("")?;
+    io.write_line("=== First-time setup ===")?;
+    io.write_line("No configuration found. Enter the WiFi network and MQTT broker,")?;
+    io.write_line("or leave the SSID empty to use the provisioning portal instead.")?;
+
+    loop {
+        io.write_line("")?;
+        let Some(ssid) = io.prompt("WiFi SSID: ", MAX_SSID_LEN, false)? else {
+            return io.skipped();
+        };
+        if ssid.is_empty() {
+            return io.skipped();
+        }
+        let Some(password) = io.prompt(
+            "WiFi password (empty for an open network): ",
+            MAX_PASSWORD_LEN,
+            true,
+        )?
+        else {
+            return io.skipped();
+        };
+        if !password.is_empty() && password.len() < 8 {
+            io.write_line("❌ WPA2 passwords are at least 8 characters")?;
+            continue;
+        }
+
+        let broker = loop {
+            let label = format!("MQTT broker [{}]: ", default_broker);
+            let Some(answer) = io.prompt(&label, MAX_BROKER_LEN, false)? else {
+                return io.skipped();
+            };
+            let url = broker_url(&answer, &default_broker);
+            if broker_address(&url).is_some() {
+                break url;
+            }
+            io.write_line("❌ Use a host name or a URL such as mqtt://broker.example.com:1883")?;
+        };
+
+        io.write_line("")?;
+        io.write_line(&format!("  SSID:     {}", ssid))?;
+        io.write_line(&format!(
+            "  Password: {}",
+            if password.is_empty() {
+                "(open network)"
+            } else {
+                "********"
+            }
+        ))?;
+        io.write_line(&format!("  Broker:   {}", broker))?;
+        let Some(confirm) = io.prompt("Save and reboot? [Y/n] ", 1, false)? else {
+            return io.skipped();
+        };
+        if confirm.eq_ignore_ascii_case("n") {
+            io.write_line("Starting over")?;
+            continue;
+        }
+
+        let settings = ProvisioningSettings {
+            ssid,
+            password,
+            mqtt_broker: broker,
+            mqtt_username: String::new(),
+            mqtt_password: String::new(),
+        };
+        match settings.save(nvs) {
+            Ok(()) => {
+                log::info!(
+                    "✅ Setup: Saved settings for '{}', rebooting",
+                    settings.ssid
+                );
+                io.write_line("✅ Settings saved, rebooting...")?;
+                return Ok(SetupOutcome::Saved);
+            }
+            Err(e) => {
+                log::error!("❌ Setup: Failed to save settings: {:?}", e);
+                io.write_line(&format!("❌ Failed to save settings: {}", e))?;
+            }
+        }
+    }
+}
+
+/// The broker answer as a URL: empty keeps `default`, a bare host gets `mqtt://`
+fn broker_url(answer: &str, default: &str) -> String {
+    if answer.is_empty() {
+        default.to_string()
+    } else if answer.contains("://") {
+        answer.to_string()
+    } else {
+        format!("mqtt://{}", answer)
+    }
+}
+
+/// Console the questions are asked on
+struct Console<'a, IO> {
+    io: &'a mut IO,
+    /// Last byte was a CR: a following LF ends the same line (CRLF terminals)
+    after_cr: bool,
+}
+
+impl<IO: TerminalIo> Console<'_, IO> {
+    fn skipped(&mut self) -> Result<SetupOutcome> {
+        self.write_line("")?;
+        self.write_line("Setup skipped")?;
+        log::info!("Setup: Skipped on the console");
+        Ok(SetupOutcome::Skipped)
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), CliError> {
+        self.io.write_bytes(line.as_bytes())?;
+        self.io.write_bytes(b"\r\n")
+    }
+
+    /// Read one line after `label`, echoing `*` for `secret` input (kept
+    /// untrimmed). None on Ctrl-C or when nothing was typed for `IDLE_TIMEOUT`.
+    fn prompt(
+        &mut self,
+        label: &str,
+        max_len: usize,
+        secret: bool,
+    ) -> Result<Option<String>, CliError> {
+        self.io.write_bytes(label.as_bytes())?;
+        let mut line = String::new();
+        let mut last_input = Instant::now();
+        loop {
+            let Some(byte) = self.io.read_byte()? else {
+                if last_input.elapsed() >= IDLE_TIMEOUT {
+                    self.io.write_bytes(b"\r\n")?;
+                    return Ok(None);
+                }
+                std::thread::sleep(Duration::from_millis(POLL_MS));
+                continue;
+            };
+            last_input = Instant::now();
+            let after_cr = std::mem::replace(&mut self.after_cr, byte == b'\r');
+            match byte {
+                b'\n' if after_cr => {}
+                b'\r' | b'\n' => {
+                    self.io.write_bytes(b"\r\n")?;
+                    // Passphrases may start or end with spaces
+                    if !secret {
+                        line = line.trim().to_string();
+                    }
+                    return Ok(Some(line));
+                }
+                b'\x03' => {
+                    self.io.write_bytes(b"^C\r\n")?;
+                    return Ok(None);
+                }
+                b'\x08' | b'\x7f' => {
+                    if line.pop().is_some() {
+                        self.io.write_bytes(b"\x08 \x08")?;
+                    }
+                }
+                0x20..=0x7e if line.len() < max_len => {
+                    line.push(byte as char);
+                    let echo = if secret { b'*' } else { byte };
+                    self.io.write_bytes(&[echo])?;
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! CoAP uplink (RFC 7252 over UDP)
+//!
+//! For constrained backhaul (NB-IoT, metered links) where a TCP connection
+//! plus MQTT keepalives costs more than the reading itself: with
+//! `network.uplink = coap` each reading is sent as one confirmable POST to
+//! `coap.url`, carrying the same payload as the MQTT data topic (JSON or CBOR
+//! per `mqtt.format`, with the matching Content-Format option). A cycle is
+//! usually two datagrams: the request and the piggybacked response.
+//!
+//! ```text
+//! coap://collector.example.com/readings        default port 5683
+//! coap://192.168.1.10:5683/meters/water
+//! ```
+//!
+//! DTLS (`coaps://`) is not supported.
+
+use crate::payloads::PayloadFormat;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_PORT: u16 = 5683;
+
+/// Initial retransmission timeout, doubled after each attempt (RFC 7252 ACK_TIMEOUT)
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+/// Retransmissions after the first attempt (RFC 7252 MAX_RETRANSMIT)
+const MAX_RETRANSMIT: u32 = 4;
+/// How long to wait for a separate response after an empty ACK
+const SEPARATE_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const VERSION: u8 = 1;
+const TYPE_CON: u8 = 0;
+const TYPE_NON: u8 = 1;
+const TYPE_ACK: u8 = 2;
+const TYPE_RST: u8 = 3;
+const CODE_EMPTY: u8 = 0x00;
+const CODE_POST: u8 = 0x02;
+const OPTION_URI_PATH: u16 = 11;
+const OPTION_CONTENT_FORMAT: u16 = 12;
+const PAYLOAD_MARKER: u8 = 0xFF;
+const TOKEN_LEN: usize = 4;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoapSettings {
+    /// `coap://host[:port]/path` (empty = not set)
+    pub url: heapless::String<128>,
+}
+
+/// Host, port and Uri-Path segments of a `coap://` URL
+pub fn parse_url(url: &str) -> Option<(String, u16, Vec<String>)> {
+    let rest = url.strip_prefix("coap://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(at) => (&rest[..at], &rest[at + 1..]),
+        None => (rest, ""),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, DEFAULT_PORT),
+    };
+    if host.is_empty() || host.contains(' ') || path.contains(' ') {
+        return None;
+    }
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string)
+        .collect();
+    Some((host.to_string(), port, segments))
+}
+
+/// Content-Format option value for a payload format
+fn content_format(format: PayloadFormat) -> u16 {
+    match format {
+        PayloadFormat::Json => 50,
+        PayloadFormat::Cbor => 60,
+    }
+}
+
+/// Response code as `class.detail`, e.g. 2.04 Changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseCode(u8);
+
+impl ResponseCode {
+    pub fn is_success(&self) -> bool {
+        self.0 >> 5 == 2
+    }
+}
+
+impl fmt::Display for ResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:02}", self.0 >> 5, self.0 & 0x1F)
+    }
+}
+
+/// Option header: delta and length nibbles plus their extended bytes
+fn push_option(message: &mut Vec<u8>, last_number: &mut u16, number: u16, value: &[u8]) {
+    fn nibble(value: usize) -> (u8, Vec<u8>) {
+        match value {
+            0..=12 => (value as u8, Vec::new()),
+            13..=268 => (13, vec![(value - 13) as u8]),
+            _ => (14, ((value - 269) as u16).to_be_bytes().to_vec()),
+        }
+    }
+    let (delta, delta_ext) = nibble((number - *last_number) as usize);
+    let (length, length_ext) = nibble(value.len());
+    message.push(delta << 4 | length);
+    message.extend_from_slice(&delta_ext);
+    message.extend_from_slice(&length_ext);
+    message.extend_from_slice(value);
+    *last_number = number;
+}
+
+/// Shortest big-endian encoding of an unsigned option value
+fn uint_option(value: u16) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    bytes[skip..].to_vec()
+}
+
+/// Header fields of a received message
+struct Received {
+    kind: u8,
+    code: u8,
+    message_id: u16,
+    token: Vec<u8>,
+}
+
+fn parse_header(datagram: &[u8]) -> Option<Received> {
+    if datagram.len() < 4 || datagram[0] >> 6 != VERSION {
+        return None;
+    }
+    let token_len = (datagram[0] & 0x0F) as usize;
+    let token = datagram.get(4..4 + token_len)?.to_vec();
+    Some(Received {
+        kind: (datagram[0] >> 4) & 0x03,
+        code: datagram[1],
+        message_id: u16::from_be_bytes([datagram[2], datagram[3]]),
+        token,
+    })
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    unsafe {
+        esp_idf_svc::sys::esp_fill_random(bytes.as_mut_ptr() as *mut core::ffi::c_void, N);
+    }
+    bytes
+}
+
+fn empty_message(kind: u8, message_id: u16) -> [u8; 4] {
+    let id = message_id.to_be_bytes();
+    [VERSION << 6 | kind << 4, CODE_EMPTY, id[0], id[1]]
+}
+
+enum Reply {
+    /// Request received, response to follow separately
+    EmptyAck,
+    Response(ResponseCode),
+}
+
+/// Wait until `deadline` for the ACK or response matching the request;
+/// confirmable separate responses are acknowledged
+fn receive(
+    socket: &UdpSocket,
+    deadline: Instant,
+    message_id: u16,
+    token: &[u8],
+) -> Result<Option<Reply>> {
+    let mut buf = [0u8; 256];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        socket.set_read_timeout(Some(remaining.max(Duration::from_millis(1))))?;
+        let Ok(n) = socket.recv(&mut buf) else {
+            break;
+        };
+        let Some(received) = parse_header(&buf[..n]) else {
+            continue;
+        };
+        match received.kind {
+            TYPE_RST if received.message_id == message_id => {
+                return Err(anyhow::anyhow!("CoAP: Request reset by server"));
+            }
+            TYPE_ACK if received.message_id == message_id => {
+                if received.code == CODE_EMPTY {
+                    return Ok(Some(Reply::EmptyAck));
+                }
+                if received.token == token {
+                    return Ok(Some(Reply::Response(ResponseCode(received.code))));
+                }
+            }
+            TYPE_CON | TYPE_NON if received.token == token => {
+                if received.kind == TYPE_CON {
+                    socket.send(&empty_message(TYPE_ACK, received.message_id))?;
+                }
+                return Ok(Some(Reply::Response(ResponseCode(received.code))));
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+pub struct CoapClient {
+    url: String,
+    host: String,
+    port: u16,
+    path: Vec<String>,
+    next_message_id: u16,
+}
+
+impl CoapClient {
+    /// None when no (valid) URL is configured
+    pub fn from_settings(settings: &CoapSettings) -> Option<Self> {
+        let (host, port, path) = parse_url(&settings.url)?;
+        Some(Self {
+            url: settings.url.to_string(),
+            host,
+            port,
+            path,
+            next_message_id: u16::from_be_bytes(random_bytes()),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Confirmable POST of `body`, retransmitted until acknowledged; any
+    /// response class other than 2.xx is an error
+    pub fn post(&mut self, format: PayloadFormat, body: &[u8]) -> Result<ResponseCode> {
+        let address = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("CoAP: {} did not resolve", self.host))?;
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(address)?;
+
+        let message_id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+        let token: [u8; TOKEN_LEN] = random_bytes();
+
+        let mut request = vec![VERSION << 6 | TYPE_CON << 4 | TOKEN_LEN as u8, CODE_POST];
+        request.extend_from_slice(&message_id.to_be_bytes());
+        request.extend_from_slice(&token);
+        let mut last_option = 0;
+        for segment in &self.path {
+            push_option(
+                &mut request,
+                &mut last_option,
+                OPTION_URI_PATH,
+                segment.as_bytes(),
+            );
+        }
+        push_option(
+            &mut request,
+            &mut last_option,
+            OPTION_CONTENT_FORMAT,
+            &uint_option(content_format(format)),
+        );
+        if !body.is_empty() {
+            request.push(PAYLOAD_MARKER);
+            request.extend_from_slice(body);
+        }
+
+        let mut timeout = ACK_TIMEOUT;
+        let mut acknowledged = false;
+        for attempt in 0..=MAX_RETRANSMIT {
+            if attempt > 0 {
+                log::info!("🔄 CoAP: Retransmitting (attempt {})", attempt + 1);
+            }
+            socket.send(&request)?;
+            match receive(&socket, Instant::now() + timeout, message_id, &token)? {
+                Some(Reply::Response(code)) => return Self::check(code),
+                Some(Reply::EmptyAck) => {
+                    acknowledged = true;
+                    break;
+                }
+                None => timeout *= 2,
+            }
+        }
+        if !acknowledged {
+            return Err(anyhow::anyhow!(
+                "CoAP: No acknowledgement after {} attempts",
+                MAX_RETRANSMIT + 1
+            ));
+        }
+
+        // Separate response follows in its own message
+        let deadline = Instant::now() + SEPARATE_RESPONSE_TIMEOUT;
+        loop {
+            match receive(&socket, deadline, message_id, &token)? {
+                Some(Reply::Response(code)) => return Self::check(code),
+                Some(Reply::EmptyAck) => continue,
+                None => {
+                    return Err(anyhow::anyhow!(
+                        "CoAP: No response {}s after acknowledgement",
+                        SEPARATE_RESPONSE_TIMEOUT.as_secs()
+                    ))
+                }
+            }
+        }
+    }
+
+    fn check(code: ResponseCode) -> Result<ResponseCode> {
+        if code.is_success() {
+            Ok(code)
+        } else {
+            Err(anyhow::anyhow!("CoAP {}", code))
+        }
+    }
+}
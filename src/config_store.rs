@@ -0,0 +1,1341 @@
+//! NVS-backed device configuration
+//!
+//! Each section is stored as a JSON string under its own key, alongside a
+//! version number so stored data from an incompatible layout is ignored
+//! rather than half-applied. WiFi networks are kept separately, encrypted,
+//! by `wifi::WifiCredentialStore`.
+
+use crate::ble_readout::BleSettings;
+use crate::button::{ButtonSettings, BUTTON_GPIOS};
+use crate::cellular::CellularSettings;
+use crate::coap::{self, CoapSettings};
+use crate::connectivity::preflight;
+use crate::display::{DisplaySettings, DisplayType};
+use crate::espnow::{format_mac, parse_mac, EspNowRole, EspNowSettings, MAX_ALLOWED_NODES};
+use crate::influxdb::InfluxSettings;
+use crate::integrations::{AwsIotSettings, AzureSettings};
+use crate::meter::storage::METER_NVS_NAMESPACE;
+use crate::meter_manager::{MetersSettings, SerialMismatch, MAX_SELECT_GPIOS, SELECT_GPIOS};
+use crate::modbus::{ModbusParity, ModbusSettings};
+use crate::mtu::{MtuConfig, UartFraming};
+use crate::network_config::{
+    is_valid_topic_template, ConnectivityMode, MqttConfig, MtuMqttTopics, NetworkTransport, Uplink,
+};
+use crate::payloads::PayloadFormat;
+use crate::power::{PowerMode, PowerSettings};
+use crate::status_led::{LedSettings, LedType, LED_GPIOS};
+use crate::storage::{LogFormat, StorageBackend, StorageSettings};
+use crate::watchdog::{WatchdogAction, WatchdogSettings};
+use crate::webhook::{is_valid_url, WebhookSettings};
+use crate::wifi::credentials::{self, WIFI_CREDS_NVS_NAMESPACE};
+use anyhow::Result;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// NVS namespace holding the device configuration
+pub const CONFIG_NVS_NAMESPACE: &str = "config";
+
+/// Other namespaces holding settings, erased along with the configuration by
+/// `factory_reset` (logs, crash reports and the dual-role app's role are kept)
+pub const FACTORY_RESET_NAMESPACES: &[&str] = &[WIFI_CREDS_NVS_NAMESPACE, METER_NVS_NAMESPACE];
+
+/// Bump when a section's layout changes incompatibly
+pub const CONFIG_VERSION: u8 = 1;
+
+// NVS keys (max 15 chars)
+const KEY_VERSION: &str = "version";
+/// Plaintext WiFi section written by earlier firmware, purged on save
+const KEY_LEGACY_WIFI: &str = "wifi";
+const KEY_MQTT: &str = "mqtt";
+const KEY_TOPICS: &str = "topics";
+const KEY_MTU: &str = "mtu";
+const KEY_DEVICE: &str = "device";
+const KEY_NETWORK: &str = "network";
+const KEY_AWS: &str = "aws";
+const KEY_AZURE: &str = "azure";
+const KEY_POWER: &str = "power";
+const KEY_BUTTON: &str = "button";
+const KEY_LED: &str = "led";
+const KEY_WATCHDOG: &str = "watchdog";
+const KEY_STORAGE: &str = "storage";
+const KEY_DISPLAY: &str = "display";
+const KEY_MODBUS: &str = "modbus";
+const KEY_BLE: &str = "ble";
+const KEY_ESPNOW: &str = "espnow";
+const KEY_WEBHOOK: &str = "webhook";
+const KEY_INFLUX: &str = "influx";
+const KEY_COAP: &str = "coap";
+const KEY_CELLULAR: &str = "cellular";
+const KEY_METERS: &str = "meters";
+// MQTT TLS material (PEM blobs)
+const KEY_MQTT_CA: &str = "mqtt_ca";
+const KEY_MQTT_CERT: &str = "mqtt_cert";
+/// Client private key, sealed with `credentials::seal`
+const KEY_MQTT_KEY: &str = "mqtt_key";
+const MQTT_KEY_CONTEXT: &[u8] = b"esp32-water-meter/mqtt-key/v1";
+/// Console password hash (`passwd`), saved at once
+const KEY_CLI_PASSWORD: &str = "cli_pw";
+/// Boot counter (u32), outside the versioned sections
+const KEY_BOOT_COUNT: &str = "boot_count";
+/// Set by a long button press: start provisioning on the next boot (u8)
+const KEY_PROVISION: &str = "provision";
+/// Named command scripts (`script save`) and command aliases (`alias`),
+/// saved at once rather than by `config save`
+const KEY_SCRIPTS: &str = "scripts";
+const KEY_ALIASES: &str = "aliases";
+
+/// Command scripts by name (`script save`, `run`)
+pub type Scripts = BTreeMap<String, String>;
+/// Command lines by alias name (`alias`)
+pub type Aliases = BTreeMap<String, String>;
+
+/// Largest PEM certificate or key accepted for MQTT TLS
+pub const MAX_MQTT_CERT_LEN: usize = 4096;
+
+/// Keys accepted by `config set`
+pub const CONFIG_KEYS: &[&str] = &[
+    "device.name",
+    "device.hostname",
+    "network.transport",
+    "network.mode",
+    "network.uplink",
+    "network.telnet",
+    "network.remote_privileged",
+    "mqtt.broker",
+    "mqtt.client_id",
+    "mqtt.username",
+    "mqtt.password",
+    "mqtt.alpn",
+    "mqtt.clean_session",
+    "mqtt.keepalive",
+    "mqtt.reconnect_timeout",
+    "mqtt.min_interval",
+    "mqtt.dedup",
+    "mqtt.downlink_wait",
+    "mqtt.format",
+    "topics.readings",
+    "topics.status",
+    "topics.availability",
+    "topics.telemetry",
+    "topics.logs",
+    "topics.crash",
+    "topics.export",
+    "topics.relay",
+    "topics.control",
+    "topics.control_device",
+    "topics.response",
+    "topics.config_desired",
+    "topics.config_reported",
+    "mtu.baud",
+    "mtu.power_up_delay",
+    "mtu.framing",
+    "power.mode",
+    "power.read_interval",
+    "button.gpio",
+    "led.gpio",
+    "led.type",
+    "watchdog.timeout",
+    "watchdog.action",
+    "storage.backend",
+    "storage.format",
+    "storage.max_file_kb",
+    "storage.max_files",
+    "display.type",
+    "display.address",
+    "modbus.address",
+    "modbus.baud",
+    "modbus.parity",
+    "ble.readout",
+    "ble.passkey",
+    "espnow.role",
+    "espnow.gateway",
+    "espnow.channel",
+    "espnow.nodes",
+    "webhook.url",
+    "webhook.auth",
+    "influx.url",
+    "influx.org",
+    "influx.bucket",
+    "influx.token",
+    "coap.url",
+    "cellular.apn",
+    "cellular.pin",
+    "cellular.baud",
+    "meters.select",
+    "meters.mismatch",
+    "aws.endpoint",
+    "aws.thing_name",
+    "azure.hub",
+    "azure.device_id",
+    "azure.key",
+];
+
+/// Persisted MTU settings (runtime statistics are not stored)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MtuSettings {
+    pub baud_rate: u32,
+    pub power_up_delay_ms: u64,
+    #[serde(default)]
+    pub framing: UartFraming,
+}
+
+impl Default for MtuSettings {
+    fn default() -> Self {
+        let defaults = MtuConfig::default();
+        Self {
+            baud_rate: defaults.baud_rate,
+            power_up_delay_ms: defaults.power_up_delay_ms,
+            framing: defaults.framing,
+        }
+    }
+}
+
+impl MtuSettings {
+    /// Apply the stored settings on top of an MTU configuration
+    pub fn apply(&self, config: &mut MtuConfig) {
+        config.baud_rate = self.baud_rate;
+        config.power_up_delay_ms = self.power_up_delay_ms;
+        config.framing = self.framing;
+    }
+}
+
+/// Identity of this unit within a fleet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceSettings {
+    /// Human-friendly name included in MQTT payloads (empty = not set)
+    pub name: heapless::String<32>,
+    /// DHCP hostname of the WiFi station interface
+    pub hostname: heapless::String<32>,
+}
+
+impl Default for DeviceSettings {
+    fn default() -> Self {
+        let mut hostname = heapless::String::new();
+        let _ = hostname.push_str("esp32-water-meter");
+        Self {
+            name: heapless::String::new(),
+            hostname,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkSettings {
+    /// Applied at boot
+    pub transport: NetworkTransport,
+    /// Applied at boot
+    #[serde(default)]
+    pub mode: ConnectivityMode,
+    /// Applied at boot
+    #[serde(default)]
+    pub uplink: Uplink,
+    /// CLI on TCP port 23 (applied at boot)
+    #[serde(default)]
+    pub telnet: bool,
+    /// Run privileged commands (`auth::is_privileged`) sent over MQTT
+    #[serde(default)]
+    pub remote_privileged: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    pub device: DeviceSettings,
+    pub network: NetworkSettings,
+    pub mqtt: MqttConfig,
+    pub topics: MtuMqttTopics,
+    pub mtu: MtuSettings,
+    #[serde(default)]
+    pub aws: AwsIotSettings,
+    #[serde(default)]
+    pub azure: AzureSettings,
+    #[serde(default)]
+    pub power: PowerSettings,
+    #[serde(default)]
+    pub button: ButtonSettings,
+    #[serde(default)]
+    pub led: LedSettings,
+    #[serde(default)]
+    pub watchdog: WatchdogSettings,
+    #[serde(default)]
+    pub storage: StorageSettings,
+    #[serde(default)]
+    pub display: DisplaySettings,
+    #[serde(default)]
+    pub modbus: ModbusSettings,
+    #[serde(default)]
+    pub ble: BleSettings,
+    #[serde(default)]
+    pub espnow: EspNowSettings,
+    #[serde(default)]
+    pub webhook: WebhookSettings,
+    #[serde(default)]
+    pub influx: InfluxSettings,
+    #[serde(default)]
+    pub coap: CoapSettings,
+    #[serde(default)]
+    pub cellular: CellularSettings,
+    /// Edited with the `meters` command, except `meters.select`
+    #[serde(default)]
+    pub meters: MetersSettings,
+}
+
+impl DeviceConfig {
+    /// Set a single value by dotted key (see `CONFIG_KEYS`)
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), &'static str> {
+        match key {
+            "device.name" => self.device.name = to_heapless(value, "Name too long (max 32 chars)")?,
+            "device.hostname" => {
+                if !is_valid_hostname(value) {
+                    return Err("Hostname must be 1-32 letters, digits or '-' (not at either end)");
+                }
+                self.device.hostname = to_heapless(value, "Hostname too long (max 32 chars)")?
+            }
+            "network.transport" => {
+                self.network.transport = NetworkTransport::from_name(value)
+                    .ok_or("Transport must be 'wifi' or 'ethernet'")?
+            }
+            "network.mode" => {
+                self.network.mode = ConnectivityMode::from_name(value)
+                    .ok_or("Mode must be 'on_demand' or 'persistent'")?
+            }
+            "network.uplink" => {
+                self.network.uplink =
+                    Uplink::from_name(value).ok_or("Uplink must be 'mqtt', 'webhook', 'influxdb' or 'coap'")?
+            }
+            "network.telnet" => {
+                self.network.telnet = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err("Telnet must be 'true' or 'false'"),
+                }
+            }
+            "network.remote_privileged" => {
+                self.network.remote_privileged = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err("Remote privileged must be 'true' or 'false'"),
+                }
+            }
+            "mqtt.broker" => {
+                if preflight::broker_address(value).is_none() {
+                    return Err(
+                        "Broker must be mqtt://, mqtts://, ws:// or wss:// followed by a host",
+                    );
+                }
+                self.mqtt.broker_url = to_heapless(value, "Broker URL too long (max 128 chars)")?
+            }
+            "mqtt.client_id" => {
+                if value.len() > 32 {
+                    return Err("Client ID too long (max 32 chars)");
+                }
+                self.mqtt.client_id = to_heapless(value, "Client ID too long (max 32 chars)")?
+            }
+            "mqtt.username" => {
+                self.mqtt.username = if value.is_empty() {
+                    None
+                } else {
+                    Some(to_heapless(value, "Username too long (max 32 chars)")?)
+                }
+            }
+            "mqtt.password" => {
+                self.mqtt.password = if value.is_empty() {
+                    None
+                } else {
+                    Some(to_heapless(value, "Password too long (max 64 chars)")?)
+                }
+            }
+            "mqtt.alpn" => {
+                self.mqtt.tls.alpn = if value.is_empty() {
+                    None
+                } else {
+                    Some(to_heapless(value, "ALPN protocol too long (max 32 chars)")?)
+                }
+            }
+            "mqtt.clean_session" => {
+                self.mqtt.clean_session = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err("Clean session must be 'true' or 'false'"),
+                }
+            }
+            "mqtt.keepalive" => match value.parse::<u16>() {
+                Ok(secs) if (5..=3600).contains(&secs) => self.mqtt.keep_alive_secs = secs,
+                _ => return Err("Keepalive must be 5-3600 seconds"),
+            },
+            "mqtt.reconnect_timeout" => match value.parse::<u16>() {
+                Ok(secs) if (1..=300).contains(&secs) => self.mqtt.reconnect_timeout_secs = secs,
+                _ => return Err("Reconnect timeout must be 1-300 seconds"),
+            },
+            "mqtt.min_interval" => match value.parse::<u16>() {
+                Ok(secs) if secs <= 3600 => self.mqtt.min_publish_interval_secs = secs,
+                _ => return Err("Minimum interval must be 0-3600 seconds (0 = off)"),
+            },
+            "mqtt.dedup" => {
+                self.mqtt.dedup_readings = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err("Dedup must be 'true' or 'false'"),
+                }
+            }
+            "mqtt.downlink_wait" => match value.parse::<u16>() {
+                Ok(secs) if secs <= 60 => self.mqtt.downlink_wait_secs = secs,
+                _ => return Err("Downlink wait must be 0-60 seconds"),
+            },
+            "mqtt.format" => {
+                self.mqtt.format =
+                    PayloadFormat::from_name(value).ok_or("Format must be 'json' or 'cbor'")?
+            }
+            "topics.readings"
+            | "topics.status"
+            | "topics.availability"
+            | "topics.telemetry"
+            | "topics.logs"
+            | "topics.crash"
+            | "topics.export"
+            | "topics.relay"
+            | "topics.control"
+            | "topics.control_device"
+            | "topics.response"
+            | "topics.config_desired"
+            | "topics.config_reported" => {
+                if !is_valid_topic_template(value) {
+                    return Err("Topic must be non-empty, without wildcards; placeholders: {chip_id}, {hostname}");
+                }
+                let topic = to_heapless(value, "Topic too long (max 64 chars)")?;
+                match key {
+                    "topics.readings" => self.topics.readings = topic,
+                    "topics.status" => self.topics.status = topic,
+                    "topics.availability" => self.topics.availability = topic,
+                    "topics.telemetry" => self.topics.telemetry = topic,
+                    "topics.logs" => self.topics.logs = topic,
+                    "topics.crash" => self.topics.crash = topic,
+                    "topics.export" => self.topics.export = topic,
+                    "topics.relay" => self.topics.relay = topic,
+                    "topics.control" => self.topics.control = topic,
+                    "topics.control_device" => self.topics.control_device = topic,
+                    "topics.config_desired" => self.topics.config_desired = topic,
+                    "topics.config_reported" => self.topics.config_reported = topic,
+                    _ => self.topics.response = topic,
+                }
+            }
+            "mtu.baud" => match value.parse::<u32>() {
+                Ok(baud_rate) if (1..=115200).contains(&baud_rate) => {
+                    self.mtu.baud_rate = baud_rate
+                }
+                _ => return Err("Baud rate must be 1-115200"),
+            },
+            "mtu.power_up_delay" => match value.parse::<u64>() {
+                Ok(delay_ms) if delay_ms <= 10_000 => self.mtu.power_up_delay_ms = delay_ms,
+                _ => return Err("Power-up delay must be 0-10000 ms"),
+            },
+            "mtu.framing" => {
+                self.mtu.framing = UartFraming::from_name(value).ok_or("Framing must be 7E1 or 7E2")?
+            }
+            "power.mode" => {
+                self.power.mode =
+                    PowerMode::from_name(value).ok_or("Mode must be 'always_on' or 'deep_sleep'")?
+            }
+            "power.read_interval" => match value.parse::<u32>() {
+                Ok(secs) if (60..=86_400).contains(&secs) => self.power.read_interval_secs = secs,
+                _ => return Err("Read interval must be 60-86400 seconds"),
+            },
+            "button.gpio" => {
+                self.button.gpio = parse_gpio(
+                    value,
+                    BUTTON_GPIOS,
+                    "Button GPIO must be 0, 2, 12-15, 25-27 or 32-39 ('none' disables)",
+                )?
+            }
+            "led.gpio" => {
+                self.led.gpio = parse_gpio(
+                    value,
+                    LED_GPIOS,
+                    "LED GPIO must be 0, 2, 12-19, 21-23, 25-27, 32 or 33 ('none' disables)",
+                )?
+            }
+            "led.type" => {
+                self.led.led_type =
+                    LedType::from_name(value).ok_or("LED type must be 'gpio' or 'ws2812'")?
+            }
+            "watchdog.timeout" => match value.parse::<u32>() {
+                Ok(secs) if secs == 0 || (10..=3600).contains(&secs) => {
+                    self.watchdog.timeout_secs = secs
+                }
+                _ => return Err("Watchdog timeout must be 10-3600 seconds (0 disables)"),
+            },
+            "watchdog.action" => {
+                self.watchdog.action = WatchdogAction::from_name(value)
+                    .ok_or("Watchdog action must be 'reset' or 'log'")?
+            }
+            "storage.backend" => {
+                self.storage.backend = StorageBackend::from_name(value)
+                    .ok_or("Storage backend must be 'none', 'flash' or 'sd'")?
+            }
+            "storage.format" => {
+                self.storage.format =
+                    LogFormat::from_name(value).ok_or("Storage format must be 'csv' or 'jsonl'")?
+            }
+            "storage.max_file_kb" => match value.parse::<u32>() {
+                Ok(kb) if (4..=1024).contains(&kb) => self.storage.max_file_kb = kb,
+                _ => return Err("Max file size must be 4-1024 KB"),
+            },
+            "storage.max_files" => match value.parse::<u8>() {
+                Ok(files) if (1..=16).contains(&files) => self.storage.max_files = files,
+                _ => return Err("Max files must be 1-16"),
+            },
+            "display.type" => {
+                self.display.display_type = DisplayType::from_name(value)
+                    .ok_or("Display type must be 'none' or 'ssd1306'")?
+            }
+            "display.address" => {
+                self.display.address = match value.to_ascii_lowercase().as_str() {
+                    "0x3c" | "60" => 0x3C,
+                    "0x3d" | "61" => 0x3D,
+                    _ => return Err("Display address must be 0x3C or 0x3D"),
+                }
+            }
+            "modbus.address" => match value.parse::<u8>() {
+                Ok(address) if address <= 247 => self.modbus.address = address,
+                _ => return Err("Modbus address must be 1-247 (0 disables)"),
+            },
+            "modbus.baud" => match value.parse::<u32>() {
+                Ok(baud) if [1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200].contains(&baud) => {
+                    self.modbus.baud_rate = baud
+                }
+                _ => return Err("Modbus baud rate must be 1200, 2400, 4800, 9600, 19200, 38400, 57600 or 115200"),
+            },
+            "modbus.parity" => {
+                self.modbus.parity = ModbusParity::from_name(value)
+                    .ok_or("Modbus parity must be 'none', 'even' or 'odd'")?
+            }
+            "ble.readout" => {
+                self.ble.readout = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err("BLE readout must be 'true' or 'false'"),
+                }
+            }
+            "ble.passkey" => {
+                self.ble.passkey = match value {
+                    "random" => None,
+                    digits if digits.len() == 6 && digits.bytes().all(|b| b.is_ascii_digit()) => {
+                        digits.parse().ok()
+                    }
+                    _ => return Err("BLE passkey must be 6 digits or 'random'"),
+                }
+            }
+            "espnow.role" => {
+                self.espnow.role = EspNowRole::from_name(value)
+                    .ok_or("ESP-NOW role must be 'off', 'node' or 'gateway'")?
+            }
+            "espnow.gateway" => {
+                self.espnow.gateway = match value {
+                    "broadcast" => None,
+                    mac => Some(parse_mac(mac).ok_or(
+                        "Gateway must be a MAC address (aa:bb:cc:dd:ee:ff) or 'broadcast'",
+                    )?),
+                }
+            }
+            "espnow.channel" => match value.parse::<u8>() {
+                Ok(channel) if (1..=13).contains(&channel) => self.espnow.channel = channel,
+                _ => return Err("ESP-NOW channel must be 1-13"),
+            },
+            "espnow.nodes" => {
+                let nodes = match value {
+                    "any" | "" => Vec::new(),
+                    list => list
+                        .split(',')
+                        .map(|mac| parse_mac(mac.trim()))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or("Nodes must be MAC addresses (aa:bb:cc:dd:ee:ff,...) or 'any'")?,
+                };
+                if nodes.len() > MAX_ALLOWED_NODES {
+                    return Err("Too many nodes (max 16)");
+                }
+                self.espnow.nodes = nodes;
+            }
+            "webhook.url" => {
+                if !value.is_empty() && !is_valid_url(value) {
+                    return Err("URL must start with http:// or https://; empty clears");
+                }
+                self.webhook.url = to_heapless(value, "URL too long (max 128 chars)")?
+            }
+            "webhook.auth" => {
+                self.webhook.auth = to_heapless(value, "Auth header too long (max 128 chars)")?
+            }
+            "influx.url" => {
+                if !value.is_empty() && !is_valid_url(value) {
+                    return Err("URL must start with http:// or https://; empty clears");
+                }
+                self.influx.url = to_heapless(value, "URL too long (max 128 chars)")?
+            }
+            "influx.org" => {
+                self.influx.org = to_heapless(value, "Organization too long (max 64 chars)")?
+            }
+            "influx.bucket" => {
+                self.influx.bucket = to_heapless(value, "Bucket too long (max 64 chars)")?
+            }
+            "influx.token" => {
+                self.influx.token = to_heapless(value, "Token too long (max 128 chars)")?
+            }
+            "coap.url" => {
+                if !value.is_empty() && coap::parse_url(value).is_none() {
+                    return Err("URL must be coap://host[:port]/path; empty clears");
+                }
+                self.coap.url = to_heapless(value, "URL too long (max 128 chars)")?
+            }
+            "cellular.apn" => {
+                if value.contains(['"', ' ']) {
+                    return Err("APN must not contain quotes or spaces; empty disables");
+                }
+                self.cellular.apn = to_heapless(value, "APN too long (max 64 chars)")?
+            }
+            "cellular.pin" => {
+                if !value.is_empty()
+                    && (!(4..=8).contains(&value.len())
+                        || !value.chars().all(|c| c.is_ascii_digit()))
+                {
+                    return Err("SIM PIN must be 4-8 digits; empty clears");
+                }
+                self.cellular.pin = to_heapless(value, "SIM PIN too long (max 8 digits)")?
+            }
+            "cellular.baud" => match value.parse::<u32>() {
+                Ok(baud) if [9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600].contains(&baud) => {
+                    self.cellular.baud_rate = baud
+                }
+                _ => return Err("Cellular baud rate must be 9600, 19200, 38400, 57600, 115200, 230400, 460800 or 921600"),
+            },
+            "meters.select" => {
+                let mut gpios = heapless::Vec::<u8, MAX_SELECT_GPIOS>::new();
+                if !matches!(value, "" | "none") {
+                    for gpio in value.split(',') {
+                        match gpio.trim().parse::<u8>() {
+                            Ok(gpio) if SELECT_GPIOS.contains(&gpio) && !gpios.contains(&gpio) => {
+                                gpios
+                                    .push(gpio)
+                                    .map_err(|_| "At most 3 select GPIOs (8 channels)")?
+                            }
+                            _ => return Err("Select GPIOs must be distinct, from 2, 12-15, 25-27, 32, 33, comma-separated ('none' clears)"),
+                        }
+                    }
+                }
+                self.meters.select_gpios = gpios;
+            }
+            "meters.mismatch" => match SerialMismatch::from_name(value) {
+                Some(mismatch) => self.meters.mismatch = mismatch,
+                None => return Err("Mismatch must be 'reject' or 'flag'"),
+            },
+            "aws.endpoint" => {
+                if value.contains(['/', ':', ' ']) {
+                    return Err("Endpoint must be a host name (no scheme or port); empty disables");
+                }
+                self.aws.endpoint = to_heapless(value, "Endpoint too long (max 96 chars)")?
+            }
+            "aws.thing_name" => {
+                self.aws.thing_name = to_heapless(value, "Thing name too long (max 48 chars)")?
+            }
+            "azure.hub" => {
+                if value.contains(['/', ':', ' ']) {
+                    return Err("Hub must be a host name (no scheme or port); empty disables");
+                }
+                self.azure.hub = to_heapless(value, "Hub host name too long (max 64 chars)")?
+            }
+            "azure.device_id" => {
+                self.azure.device_id = to_heapless(value, "Device ID too long (max 48 chars)")?
+            }
+            "azure.key" => self.azure.key = to_heapless(value, "Key too long (max 64 chars)")?,
+            _ => return Err("Unknown key"),
+        }
+        Ok(())
+    }
+
+    /// Human-readable listing with secrets masked
+    pub fn describe(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!(
+            "  device.name        = {}\r\n",
+            if self.device.name.is_empty() {
+                "(none)"
+            } else {
+                self.device.name.as_str()
+            }
+        ));
+        out.push_str(&format!(
+            "  device.hostname    = {}\r\n",
+            self.device.hostname
+        ));
+        out.push_str(&format!(
+            "  network.transport  = {}\r\n",
+            self.network.transport.name()
+        ));
+        out.push_str(&format!(
+            "  network.mode       = {}\r\n",
+            self.network.mode.name()
+        ));
+        out.push_str(&format!(
+            "  network.uplink     = {}\r\n",
+            self.network.uplink.name()
+        ));
+        out.push_str(&format!(
+            "  network.telnet     = {}\r\n",
+            self.network.telnet
+        ));
+        out.push_str(&format!(
+            "  network.remote_privileged = {}\r\n",
+            self.network.remote_privileged
+        ));
+        out.push_str(&format!(
+            "  mqtt.broker        = {}\r\n",
+            self.mqtt.broker_url
+        ));
+        out.push_str(&format!(
+            "  mqtt.client_id     = {}\r\n",
+            self.mqtt.client_id
+        ));
+        out.push_str(&format!(
+            "  mqtt.username      = {}\r\n",
+            self.mqtt.username.as_deref().unwrap_or("(none)")
+        ));
+        out.push_str(&format!(
+            "  mqtt.password      = {}\r\n",
+            mask(self.mqtt.password.as_deref())
+        ));
+        out.push_str(&format!(
+            "  mqtt.alpn          = {}\r\n",
+            self.mqtt.tls.alpn.as_deref().unwrap_or("(none)")
+        ));
+        out.push_str(&format!(
+            "  mqtt.clean_session = {}\r\n",
+            self.mqtt.clean_session
+        ));
+        out.push_str(&format!(
+            "  mqtt.keepalive     = {} s\r\n",
+            self.mqtt.keep_alive_secs
+        ));
+        out.push_str(&format!(
+            "  mqtt.reconnect_timeout = {} s\r\n",
+            self.mqtt.reconnect_timeout_secs
+        ));
+        out.push_str(&format!(
+            "  mqtt.min_interval  = {}\r\n",
+            match self.mqtt.min_publish_interval_secs {
+                0 => "off".to_string(),
+                secs => format!("{} s", secs),
+            }
+        ));
+        out.push_str(&format!(
+            "  mqtt.dedup         = {}\r\n",
+            self.mqtt.dedup_readings
+        ));
+        out.push_str(&format!(
+            "  mqtt.downlink_wait = {} s\r\n",
+            self.mqtt.downlink_wait_secs
+        ));
+        out.push_str(&format!(
+            "  mqtt.format        = {}\r\n",
+            self.mqtt.format.name()
+        ));
+        out.push_str(&format!(
+            "  mqtt TLS           = CA {}, client cert {}, client key {}\r\n",
+            pem_status(&self.mqtt.tls.ca_cert),
+            pem_status(&self.mqtt.tls.client_cert),
+            pem_status(&self.mqtt.tls.client_key)
+        ));
+        out.push_str(&format!(
+            "  topics.readings    = {}\r\n",
+            self.topics.readings
+        ));
+        out.push_str(&format!(
+            "  topics.status      = {}\r\n",
+            self.topics.status
+        ));
+        out.push_str(&format!(
+            "  topics.availability = {}\r\n",
+            self.topics.availability
+        ));
+        out.push_str(&format!(
+            "  topics.telemetry   = {}\r\n",
+            self.topics.telemetry
+        ));
+        out.push_str(&format!("  topics.logs        = {}\r\n", self.topics.logs));
+        out.push_str(&format!("  topics.crash       = {}\r\n", self.topics.crash));
+        out.push_str(&format!(
+            "  topics.export      = {}\r\n",
+            self.topics.export
+        ));
+        out.push_str(&format!("  topics.relay       = {}\r\n", self.topics.relay));
+        out.push_str(&format!(
+            "  topics.control     = {}\r\n",
+            self.topics.control
+        ));
+        out.push_str(&format!(
+            "  topics.control_device = {}\r\n",
+            self.topics.control_device
+        ));
+        out.push_str(&format!(
+            "  topics.response    = {}\r\n",
+            self.topics.response
+        ));
+        out.push_str(&format!(
+            "  topics.config_desired = {}\r\n",
+            self.topics.config_desired
+        ));
+        out.push_str(&format!(
+            "  topics.config_reported = {}\r\n",
+            self.topics.config_reported
+        ));
+        out.push_str(&format!(
+            "  mtu.baud           = {}\r\n",
+            self.mtu.baud_rate
+        ));
+        out.push_str(&format!(
+            "  mtu.power_up_delay = {} ms\r\n",
+            self.mtu.power_up_delay_ms
+        ));
+        out.push_str(&format!(
+            "  mtu.framing        = {}\r\n",
+            self.mtu.framing.name()
+        ));
+        out.push_str(&format!(
+            "  power.mode         = {}\r\n",
+            self.power.mode.name()
+        ));
+        out.push_str(&format!(
+            "  power.read_interval = {} s\r\n",
+            self.power.read_interval_secs
+        ));
+        out.push_str(&format!(
+            "  button.gpio        = {}\r\n",
+            match self.button.gpio {
+                Some(gpio) => format!("GPIO{}", gpio),
+                None => "(none)".to_string(),
+            }
+        ));
+        out.push_str(&format!(
+            "  led.gpio           = {}\r\n",
+            match self.led.gpio {
+                Some(gpio) => format!("GPIO{}", gpio),
+                None => "(none)".to_string(),
+            }
+        ));
+        out.push_str(&format!(
+            "  led.type           = {}\r\n",
+            self.led.led_type.name()
+        ));
+        out.push_str(&format!(
+            "  watchdog.timeout   = {}\r\n",
+            match self.watchdog.timeout_secs {
+                0 => "off".to_string(),
+                secs => format!("{} s", secs),
+            }
+        ));
+        out.push_str(&format!(
+            "  watchdog.action    = {}\r\n",
+            self.watchdog.action.name()
+        ));
+        out.push_str(&format!(
+            "  storage.backend    = {}\r\n",
+            self.storage.backend.name()
+        ));
+        out.push_str(&format!(
+            "  storage.format     = {}\r\n",
+            self.storage.format.name()
+        ));
+        out.push_str(&format!(
+            "  storage.max_file_kb = {} KB\r\n",
+            self.storage.max_file_kb
+        ));
+        out.push_str(&format!(
+            "  storage.max_files  = {}\r\n",
+            self.storage.max_files
+        ));
+        out.push_str(&format!(
+            "  display.type       = {}\r\n",
+            self.display.display_type.name()
+        ));
+        out.push_str(&format!(
+            "  display.address    = 0x{:02X}\r\n",
+            self.display.address
+        ));
+        out.push_str(&format!(
+            "  modbus.address     = {}\r\n",
+            match self.modbus.address {
+                0 => "off".to_string(),
+                address => address.to_string(),
+            }
+        ));
+        out.push_str(&format!(
+            "  modbus.baud        = {}\r\n",
+            self.modbus.baud_rate
+        ));
+        out.push_str(&format!(
+            "  modbus.parity      = {}\r\n",
+            self.modbus.parity.name()
+        ));
+        out.push_str(&format!("  ble.readout        = {}\r\n", self.ble.readout));
+        out.push_str(&format!(
+            "  ble.passkey        = {}\r\n",
+            match self.ble.passkey {
+                Some(_) => "******",
+                None => "random",
+            }
+        ));
+        out.push_str(&format!(
+            "  espnow.role        = {}\r\n",
+            self.espnow.role.name()
+        ));
+        out.push_str(&format!(
+            "  espnow.gateway     = {}\r\n",
+            match self.espnow.gateway {
+                Some(ref mac) => format_mac(mac),
+                None => "broadcast".to_string(),
+            }
+        ));
+        out.push_str(&format!(
+            "  espnow.channel     = {}\r\n",
+            self.espnow.channel
+        ));
+        out.push_str(&format!(
+            "  espnow.nodes       = {}\r\n",
+            if self.espnow.nodes.is_empty() {
+                "any".to_string()
+            } else {
+                self.espnow
+                    .nodes
+                    .iter()
+                    .map(format_mac)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }
+        ));
+        out.push_str(&format!(
+            "  webhook.url        = {}\r\n",
+            if self.webhook.url.is_empty() {
+                "(none)"
+            } else {
+                self.webhook.url.as_str()
+            }
+        ));
+        out.push_str(&format!(
+            "  webhook.auth       = {}\r\n",
+            mask(Some(self.webhook.auth.as_str()))
+        ));
+        for (key, value) in [
+            ("influx.url   ", self.influx.url.as_str()),
+            ("influx.org   ", self.influx.org.as_str()),
+            ("influx.bucket", self.influx.bucket.as_str()),
+        ] {
+            out.push_str(&format!(
+                "  {}      = {}\r\n",
+                key,
+                if value.is_empty() { "(none)" } else { value }
+            ));
+        }
+        out.push_str(&format!(
+            "  influx.token       = {}\r\n",
+            mask(Some(self.influx.token.as_str()))
+        ));
+        out.push_str(&format!(
+            "  coap.url           = {}\r\n",
+            if self.coap.url.is_empty() {
+                "(none)"
+            } else {
+                self.coap.url.as_str()
+            }
+        ));
+        out.push_str(&format!(
+            "  cellular.apn       = {}\r\n",
+            if self.cellular.apn.is_empty() {
+                "(disabled)"
+            } else {
+                self.cellular.apn.as_str()
+            }
+        ));
+        out.push_str(&format!(
+            "  cellular.pin       = {}\r\n",
+            mask(Some(self.cellular.pin.as_str()))
+        ));
+        out.push_str(&format!(
+            "  cellular.baud      = {}\r\n",
+            self.cellular.baud_rate
+        ));
+        out.push_str(&format!(
+            "  meters.select      = {}\r\n",
+            if self.meters.select_gpios.is_empty() {
+                "(none)".to_string()
+            } else {
+                self.meters
+                    .select_gpios
+                    .iter()
+                    .map(|gpio| gpio.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }
+        ));
+        out.push_str(&format!(
+            "  meters.mismatch    = {}\r\n",
+            self.meters.mismatch.name()
+        ));
+        out.push_str(&format!(
+            "  meters             = {} (see 'meters list')\r\n",
+            self.meters.meters.len()
+        ));
+        out.push_str(&format!(
+            "  aws.endpoint       = {}\r\n",
+            if self.aws.endpoint.is_empty() {
+                "(disabled)"
+            } else {
+                self.aws.endpoint.as_str()
+            }
+        ));
+        out.push_str(&format!(
+            "  aws.thing_name     = {}\r\n",
+            if self.aws.thing_name.is_empty() {
+                "(hostname)"
+            } else {
+                self.aws.thing_name.as_str()
+            }
+        ));
+        out.push_str(&format!(
+            "  azure.hub          = {}\r\n",
+            if self.azure.hub.is_empty() {
+                "(disabled)"
+            } else {
+                self.azure.hub.as_str()
+            }
+        ));
+        out.push_str(&format!(
+            "  azure.device_id    = {}\r\n",
+            if self.azure.device_id.is_empty() {
+                "(hostname)"
+            } else {
+                self.azure.device_id.as_str()
+            }
+        ));
+        out.push_str(&format!(
+            "  azure.key          = {}",
+            mask(Some(self.azure.key.as_str()))
+        ));
+        out
+    }
+}
+
+/// Persists `DeviceConfig` to NVS
+pub struct ConfigStore {
+    nvs: EspNvs<NvsDefault>,
+    /// For erasing the other namespaces on a factory reset
+    partition: EspDefaultNvsPartition,
+}
+
+/// Remove every key of a namespace
+pub fn erase_namespace(nvs: &EspNvs<NvsDefault>) -> Result<()> {
+    sys::esp!(unsafe { sys::nvs_erase_all(nvs.handle()) })?;
+    sys::esp!(unsafe { sys::nvs_commit(nvs.handle()) })?;
+    Ok(())
+}
+
+impl ConfigStore {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+        let nvs = EspNvs::new(partition.clone(), CONFIG_NVS_NAMESPACE, true)?;
+        Ok(Self { nvs, partition })
+    }
+
+    /// Load the saved configuration, or None if nothing (compatible) has been saved yet.
+    /// Sections that are missing or fail to parse fall back to their defaults.
+    pub fn load(&self) -> Result<Option<DeviceConfig>> {
+        match self.nvs.get_u8(KEY_VERSION)? {
+            Some(CONFIG_VERSION) => {}
+            Some(version) => {
+                log::warn!(
+                    "Config: Stored version {} != {}, using defaults",
+                    version,
+                    CONFIG_VERSION
+                );
+                return Ok(None);
+            }
+            None => return Ok(None),
+        }
+
+        let mut mqtt: MqttConfig = self.load_section(KEY_MQTT)?.unwrap_or_default();
+        mqtt.tls.ca_cert = self.load_blob(KEY_MQTT_CA)?;
+        mqtt.tls.client_cert = self.load_blob(KEY_MQTT_CERT)?;
+        mqtt.tls.client_key = self.load_mqtt_key()?;
+
+        let mut topics: MtuMqttTopics = self.load_section(KEY_TOPICS)?.unwrap_or_default();
+        if topics.is_legacy_default() {
+            topics = MtuMqttTopics::default();
+        }
+
+        Ok(Some(DeviceConfig {
+            device: self.load_section(KEY_DEVICE)?.unwrap_or_default(),
+            network: self.load_section(KEY_NETWORK)?.unwrap_or_default(),
+            mqtt,
+            topics,
+            mtu: self.load_section(KEY_MTU)?.unwrap_or_default(),
+            aws: self.load_section(KEY_AWS)?.unwrap_or_default(),
+            azure: self.load_section(KEY_AZURE)?.unwrap_or_default(),
+            power: self.load_section(KEY_POWER)?.unwrap_or_default(),
+            button: self.load_section(KEY_BUTTON)?.unwrap_or_default(),
+            led: self.load_section(KEY_LED)?.unwrap_or_default(),
+            watchdog: self.load_section(KEY_WATCHDOG)?.unwrap_or_default(),
+            storage: self.load_section(KEY_STORAGE)?.unwrap_or_default(),
+            display: self.load_section(KEY_DISPLAY)?.unwrap_or_default(),
+            modbus: self.load_section(KEY_MODBUS)?.unwrap_or_default(),
+            ble: self.load_section(KEY_BLE)?.unwrap_or_default(),
+            espnow: self.load_section(KEY_ESPNOW)?.unwrap_or_default(),
+            webhook: self.load_section(KEY_WEBHOOK)?.unwrap_or_default(),
+            influx: self.load_section(KEY_INFLUX)?.unwrap_or_default(),
+            coap: self.load_section(KEY_COAP)?.unwrap_or_default(),
+            cellular: self.load_section(KEY_CELLULAR)?.unwrap_or_default(),
+            meters: self.load_section(KEY_METERS)?.unwrap_or_default(),
+        }))
+    }
+
+    pub fn save(&mut self, config: &DeviceConfig) -> Result<()> {
+        self.nvs.remove(KEY_LEGACY_WIFI)?;
+        self.save_section(KEY_DEVICE, &config.device)?;
+        self.save_section(KEY_NETWORK, &config.network)?;
+        self.save_section(KEY_AWS, &config.aws)?;
+        self.save_section(KEY_AZURE, &config.azure)?;
+        self.save_section(KEY_POWER, &config.power)?;
+        self.save_section(KEY_BUTTON, &config.button)?;
+        self.save_section(KEY_LED, &config.led)?;
+        self.save_section(KEY_WATCHDOG, &config.watchdog)?;
+        self.save_section(KEY_STORAGE, &config.storage)?;
+        self.save_section(KEY_DISPLAY, &config.display)?;
+        self.save_section(KEY_MODBUS, &config.modbus)?;
+        self.save_section(KEY_BLE, &config.ble)?;
+        self.save_section(KEY_ESPNOW, &config.espnow)?;
+        self.save_section(KEY_WEBHOOK, &config.webhook)?;
+        self.save_section(KEY_INFLUX, &config.influx)?;
+        self.save_section(KEY_COAP, &config.coap)?;
+        self.save_section(KEY_CELLULAR, &config.cellular)?;
+        self.save_section(KEY_METERS, &config.meters)?;
+        self.save_section(KEY_MQTT, &config.mqtt)?;
+        self.save_blob(KEY_MQTT_CA, config.mqtt.tls.ca_cert.as_deref())?;
+        self.save_blob(KEY_MQTT_CERT, config.mqtt.tls.client_cert.as_deref())?;
+        let sealed_key = match config.mqtt.tls.client_key {
+            Some(ref key) => Some(credentials::seal(MQTT_KEY_CONTEXT, key)?),
+            None => None,
+        };
+        self.save_blob(KEY_MQTT_KEY, sealed_key.as_deref())?;
+        self.save_section(KEY_TOPICS, &config.topics)?;
+        self.save_section(KEY_MTU, &config.mtu)?;
+        self.nvs.set_u8(KEY_VERSION, CONFIG_VERSION)?;
+
+        log::info!("Config: Configuration saved to NVS");
+        Ok(())
+    }
+
+    /// Erase every stored section and MQTT certificate (factory reset); the
+    /// boot counter is kept
+    pub fn erase(&mut self) -> Result<()> {
+        let boot_count = self.nvs.get_u32(KEY_BOOT_COUNT)?;
+        erase_namespace(&self.nvs)?;
+        if let Some(count) = boot_count {
+            self.nvs.set_u32(KEY_BOOT_COUNT, count)?;
+        }
+        log::info!("Config: Configuration erased from NVS");
+        Ok(())
+    }
+
+    /// Erase the configuration (see `erase`) and `FACTORY_RESET_NAMESPACES`,
+    /// and start provisioning on the next boot
+    pub fn factory_reset(&mut self) -> Result<()> {
+        self.erase()?;
+        for namespace in FACTORY_RESET_NAMESPACES {
+            let nvs = EspNvs::new(self.partition.clone(), namespace, true)?;
+            erase_namespace(&nvs)?;
+            log::info!("Config: NVS namespace '{}' erased", namespace);
+        }
+        self.request_provisioning()
+    }
+
+    /// Increment the persistent boot counter; returns this boot's number
+    pub fn record_boot(&mut self) -> Result<u32> {
+        let count = self
+            .nvs
+            .get_u32(KEY_BOOT_COUNT)?
+            .unwrap_or(0)
+            .wrapping_add(1);
+        self.nvs.set_u32(KEY_BOOT_COUNT, count)?;
+        Ok(count)
+    }
+
+    /// Start provisioning on the next boot, even with saved networks
+    pub fn request_provisioning(&mut self) -> Result<()> {
+        self.nvs.set_u8(KEY_PROVISION, 1)?;
+        Ok(())
+    }
+
+    /// Whether provisioning was requested; clears the request
+    pub fn take_provisioning_request(&mut self) -> Result<bool> {
+        let requested = self.nvs.get_u8(KEY_PROVISION)?.unwrap_or(0) != 0;
+        if requested {
+            self.nvs.remove(KEY_PROVISION)?;
+        }
+        Ok(requested)
+    }
+
+    pub fn load_cli_password(&self) -> Result<Option<Vec<u8>>> {
+        self.load_blob(KEY_CLI_PASSWORD)
+    }
+
+    /// Store the console password hash, or remove it when `None`
+    pub fn save_cli_password(&mut self, hash: Option<&[u8]>) -> Result<()> {
+        self.save_blob(KEY_CLI_PASSWORD, hash)
+    }
+
+    pub fn load_scripts(&self) -> Result<Scripts> {
+        self.load_map(KEY_SCRIPTS)
+    }
+
+    pub fn save_scripts(&mut self, scripts: &Scripts) -> Result<()> {
+        self.save_map(KEY_SCRIPTS, scripts)
+    }
+
+    pub fn load_aliases(&self) -> Result<Aliases> {
+        self.load_map(KEY_ALIASES)
+    }
+
+    pub fn save_aliases(&mut self, aliases: &Aliases) -> Result<()> {
+        self.save_map(KEY_ALIASES, aliases)
+    }
+
+    /// Name-to-text map of any size (empty when missing or unreadable)
+    fn load_map(&self, key: &str) -> Result<BTreeMap<String, String>> {
+        let len = match self.nvs.str_len(key)? {
+            Some(len) => len,
+            None => return Ok(BTreeMap::new()),
+        };
+        let mut buf = vec![0u8; len + 1];
+        match self.nvs.get_str(key, &mut buf)? {
+            Some(json) => match serde_json::from_str(json) {
+                Ok(map) => Ok(map),
+                Err(e) => {
+                    log::warn!("Config: Failed to parse '{}': {:?}", key, e);
+                    Ok(BTreeMap::new())
+                }
+            },
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Store a map, or remove it when empty
+    fn save_map(&mut self, key: &str, map: &BTreeMap<String, String>) -> Result<()> {
+        if map.is_empty() {
+            self.nvs.remove(key)?;
+            return Ok(());
+        }
+        self.save_section(key, map)
+    }
+
+    fn load_section<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Result<Option<T>> {
+        // Fits the meters section with every meter at its maximum length
+        let mut buf = vec![0u8; 2048];
+        match self.nvs.get_str(key, &mut buf)? {
+            Some(json) => match serde_json::from_str(json) {
+                Ok(section) => Ok(Some(section)),
+                Err(e) => {
+                    log::warn!("Config: Failed to parse '{}' section: {:?}", key, e);
+                    Ok(None)
+                }
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn save_section<T: Serialize>(&mut self, key: &str, section: &T) -> Result<()> {
+        let json = serde_json::to_string(section)?;
+        self.nvs.set_str(key, &json)?;
+        Ok(())
+    }
+
+    fn load_blob(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let len = match self.nvs.blob_len(key)? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        let mut buf = vec![0u8; len];
+        Ok(self.nvs.get_blob(key, &mut buf)?.map(|data| data.to_vec()))
+    }
+
+    /// MQTT client private key. Keys stored in clear text by earlier firmware
+    /// are still read; `config save` encrypts them.
+    fn load_mqtt_key(&self) -> Result<Option<Vec<u8>>> {
+        let Some(blob) = self.load_blob(KEY_MQTT_KEY)? else {
+            return Ok(None);
+        };
+        if let Some(key) = credentials::unseal(MQTT_KEY_CONTEXT, &blob) {
+            return Ok(Some(key));
+        }
+        if blob.starts_with(b"-----BEGIN") {
+            log::warn!(
+                "Config: MQTT client key stored unencrypted, run 'config save' to encrypt it"
+            );
+            return Ok(Some(blob));
+        }
+        log::warn!("Config: MQTT client key failed to decrypt (different chip?)");
+        Ok(None)
+    }
+
+    /// Store a blob, or remove it when `None`
+    fn save_blob(&mut self, key: &str, data: Option<&[u8]>) -> Result<()> {
+        match data {
+            Some(data) => self.nvs.set_blob(key, data)?,
+            None => {
+                self.nvs.remove(key)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Optional GPIO from a `config set` value ("none" or empty = not used)
+fn parse_gpio(
+    value: &str,
+    allowed: &[u8],
+    error: &'static str,
+) -> Result<Option<u8>, &'static str> {
+    match value {
+        "none" | "" => Ok(None),
+        _ => match value.parse::<u8>() {
+            Ok(gpio) if allowed.contains(&gpio) => Ok(Some(gpio)),
+            _ => Err(error),
+        },
+    }
+}
+
+fn to_heapless<const N: usize>(
+    value: &str,
+    err: &'static str,
+) -> Result<heapless::String<N>, &'static str> {
+    let mut s = heapless::String::new();
+    s.push_str(value).map_err(|_| err)?;
+    Ok(s)
+}
+
+/// RFC 1123 label: letters, digits and hyphens, no leading/trailing hyphen
+fn is_valid_hostname(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn pem_status(pem: &Option<Vec<u8>>) -> String {
+    match pem {
+        Some(pem) => format!("{} bytes", pem.len()),
+        None => "(none)".to_string(),
+    }
+}
+
+fn mask(secret: Option<&str>) -> &'static str {
+    match secret {
+        Some(s) if !s.is_empty() => "********",
+        _ => "(none)",
+    }
+}
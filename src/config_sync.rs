@@ -0,0 +1,138 @@
+//! Remote configuration sync (desired / reported)
+//!
+//! Lets a fleet be reconfigured from the broker side without a cloud IoT
+//! platform: the device subscribes to the retained `topics.config_desired`
+//! document, applies the settings it carries, saves them to NVS and answers
+//! on `topics.config_reported` with the settings now in effect:
+//!
+//! ```text
+//! istorrs/mtu/{chip_id}/config/desired   -> {"version":7,"baud_rate":2400,"framing":"7E2","read_interval":900}
+//! istorrs/mtu/{chip_id}/config/reported  <- {"schema":1,"timestamp":"...","version":7,"baud_rate":2400,
+//!                                            "framing":"7E2","read_interval":900,"errors":{}}
+//! ```
+//!
+//! `baud_rate` (`mtu.baud`) and `framing` (`mtu.framing`) apply from the next
+//! MTU read, `read_interval` (`power.read_interval`) from the next deep-sleep
+//! cycle. Missing settings are left as they are; rejected ones are listed in
+//! `errors`. The retained document is delivered again with every session, so
+//! NVS is only written when a value actually changes.
+
+use crate::cli::CommandHandler;
+use crate::config_store::DeviceConfig;
+use crate::connectivity::DownlinkHandler;
+use crate::payloads::{ConfigReportedPayload, PAYLOAD_SCHEMA_VERSION};
+use crate::timekeeping;
+use serde_json::{Map, Value};
+use std::sync::{Arc, Mutex};
+
+/// Desired document fields and the config keys they set
+const SYNCED_KEYS: &[(&str, &str)] = &[
+    ("baud_rate", "mtu.baud"),
+    ("framing", "mtu.framing"),
+    ("read_interval", "power.read_interval"),
+];
+
+/// Downlink handler for the desired topic; the reply goes to the reported topic
+pub fn config_sync_handler(handler: Arc<Mutex<CommandHandler>>) -> DownlinkHandler {
+    Arc::new(move |_topic, data| {
+        // An empty retained message clears the desired document
+        if data.is_empty() {
+            return None;
+        }
+        let desired = match serde_json::from_slice::<Value>(data) {
+            Ok(Value::Object(desired)) => desired,
+            Ok(_) => {
+                log::warn!("⚠️  Config sync: Desired document is not a JSON object");
+                return None;
+            }
+            Err(e) => {
+                log::warn!("⚠️  Config sync: Invalid desired document: {:?}", e);
+                return None;
+            }
+        };
+        log::info!("📩 Config sync: Desired {}", Value::from(desired.clone()));
+
+        let mut handler = handler.lock().ok()?;
+        let payload = apply(&mut handler, &desired)?;
+        serde_json::to_string(&payload).ok()
+    })
+}
+
+/// Apply and save the desired settings; None without a configuration
+fn apply(
+    handler: &mut CommandHandler,
+    desired: &Map<String, Value>,
+) -> Option<ConfigReportedPayload> {
+    let mut errors = Map::new();
+    for key in desired.keys() {
+        if key != "version" && !SYNCED_KEYS.iter().any(|(field, _)| field == key) {
+            errors.insert(key.clone(), Value::from("unsupported setting"));
+        }
+    }
+
+    let before = reported(handler.config()?, None, Map::new());
+    let config = handler.config_mut()?;
+    for (field, key) in SYNCED_KEYS {
+        let value = match desired.get(*field) {
+            None => continue,
+            Some(Value::String(value)) => value.clone(),
+            Some(Value::Number(value)) => value.to_string(),
+            Some(_) => {
+                errors.insert(field.to_string(), Value::from("must be a string or number"));
+                continue;
+            }
+        };
+        if let Err(e) = config.set(key, &value) {
+            errors.insert(field.to_string(), Value::from(e));
+        }
+    }
+
+    let version = desired.get("version").cloned();
+    let after = reported(handler.config()?, version, errors);
+    let changed = before.baud_rate != after.baud_rate
+        || before.framing != after.framing
+        || before.read_interval != after.read_interval;
+    if changed {
+        match handler.save_config() {
+            Ok(_) => log::info!(
+                "✅ Config sync: Applied baud rate {}, framing {}, read interval {}s",
+                after.baud_rate,
+                after.framing,
+                after.read_interval
+            ),
+            Err(e) => log::error!("❌ Config sync: Failed to save configuration: {:?}", e),
+        }
+        apply_to_mtu(handler);
+    }
+    Some(after)
+}
+
+/// Baud rate and framing for the next read (the MTU is idle while the
+/// downlink messages after a reading are handled)
+fn apply_to_mtu(handler: &CommandHandler) {
+    let (Some(mtu), Some(config)) = (handler.mtu(), handler.config()) else {
+        return;
+    };
+    if mtu.is_running() {
+        log::warn!("⚠️  Config sync: MTU busy, baud rate and framing apply after reset");
+        return;
+    }
+    mtu.set_baud_rate(config.mtu.baud_rate);
+    mtu.set_framing(config.mtu.framing);
+}
+
+fn reported(
+    config: &DeviceConfig,
+    version: Option<Value>,
+    errors: Map<String, Value>,
+) -> ConfigReportedPayload {
+    ConfigReportedPayload {
+        schema: PAYLOAD_SCHEMA_VERSION,
+        timestamp: timekeeping::now_iso8601(),
+        version,
+        baud_rate: config.mtu.baud_rate,
+        framing: config.mtu.framing.name(),
+        read_interval: config.power.read_interval_secs,
+        errors,
+    }
+}
@@ -0,0 +1,1500 @@
+//! Publish pipeline
+//!
+//! `Publisher` sends readings over MQTT, or a webhook, InfluxDB or CoAP. In
+//! on-demand mode (default) each `publish_reading` runs one cycle driven by a
+//! `Supervisor`; in persistent mode the session is kept and `poll` handles
+//! control messages. The sequence is described in docs/mqtt-control.md
+//! (On-Demand Mode Behavior).
+
+pub mod preflight;
+pub mod supervisor;
+
+pub use preflight::PreflightError;
+pub use supervisor::{Backoff, CycleFailure, CycleState, CycleTimeouts, RetryPolicy, Supervisor};
+
+use crate::coap::CoapClient;
+use crate::crash::CrashStore;
+use crate::device;
+use crate::espnow::RelayedReading;
+use crate::events::{DeviceEvent, EventBus};
+use crate::influxdb::{self, InfluxDb};
+use crate::logging;
+use crate::meter_manager::MeterRef;
+use crate::mqtt::{
+    topic_matches, DeliveryStatus, MqttClient, DEFAULT_CHUNK_SIZE, MAX_RECEIVED_LEN,
+};
+use crate::network::NetworkLink;
+use crate::network_config::{ConnectivityMode, DeviceTopics, MqttConfig};
+use crate::payloads::{
+    BatchInfo, CrashPayload, DeviceInfo, ReadingPayload, RelayedReadingPayload, StatusPayload,
+    TelemetryPayload, PAYLOAD_SCHEMA_VERSION,
+};
+use crate::storage::{self, DataLog};
+use crate::telemetry;
+use crate::timekeeping;
+use crate::webhook::Webhook;
+use crate::wifi::LinkStats;
+use anyhow::Result;
+use esp_idf_svc::mqtt::client::QoS;
+use std::collections::VecDeque;
+use std::net::Ipv4Addr;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Control messages held until the publishing thread handles them; more are dropped
+const DOWNLINK_QUEUE_CAPACITY: usize = 16;
+
+/// Handles a message received on a control topic (called on the publishing
+/// thread, in arrival order). A returned reply is published to the response
+/// topic.
+pub type DownlinkHandler = Arc<dyn Fn(&str, &[u8]) -> Option<String> + Send + Sync>;
+
+/// Retry interval for opening the persistent session
+const SESSION_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// First retry of a failed publish; doubled after each further failure
+const RETRY_BACKOFF_INITIAL: Duration = Duration::from_secs(30);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(15 * 60);
+/// Publish attempts for one reading before it is given up
+const MAX_PUBLISH_ATTEMPTS: u8 = 6;
+/// Readings kept for a retry (in RAM); the oldest is dropped when full
+const OFFLINE_QUEUE_CAPACITY: usize = 16;
+
+/// How long a reading publish waits for the broker's PUBACK
+const PUBLISH_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Computes the MQTT username and password for each new session
+pub type LoginProvider = Arc<dyn Fn() -> Result<(String, String)> + Send + Sync>;
+
+/// Formats an extra document published after each reading (see `Publisher::with_reading_report`)
+pub type ReadingReport = Arc<dyn Fn(&MeterReading) -> String + Send + Sync>;
+
+/// Control topics served by one handler; replies go to `response_topic`, or
+/// to the publisher's response topic when None
+struct Downlink {
+    topics: Vec<String>,
+    handler: DownlinkHandler,
+    response_topic: Option<String>,
+}
+
+/// A control message as received, waiting to be handled; `route` is the
+/// index of its `Downlink`
+struct QueuedDownlink {
+    route: usize,
+    topic: String,
+    data: Vec<u8>,
+    /// Announced size of a message too large to receive (`data` is empty)
+    oversized: Option<usize>,
+}
+
+/// MQTT client of an open session, with the control messages received on it
+struct Session {
+    client: MqttClient,
+    downlinks: mpsc::Receiver<QueuedDownlink>,
+}
+
+/// A reading waiting in the offline queue
+#[derive(Clone)]
+struct PendingReading {
+    reading: MeterReading,
+    /// When it was queued, i.e. read (ISO 8601, None if the clock is not set)
+    read_at: Option<String>,
+    /// Publish attempts so far
+    attempts: u8,
+}
+
+/// One MTU read cycle, as published
+#[derive(Debug, Clone)]
+pub struct MeterReading {
+    /// Raw meter response string
+    pub message: String,
+    pub baud_rate: u32,
+    /// UART framing, e.g. "7E1"
+    pub framing: &'static str,
+    pub cycles: usize,
+    pub successful: u32,
+    pub corrupted: u32,
+    /// Meter read through the multiplexer (None with a single meter)
+    pub meter: Option<MeterRef>,
+}
+
+pub struct Publisher {
+    network: Arc<Mutex<dyn NetworkLink + Send>>,
+    mqtt_config: MqttConfig,
+    chip_id: String,
+    data_topic: Option<String>,
+    status_topic: Option<String>,
+    device_name: Option<String>,
+    /// Shared and per-device control topics, without duplicates
+    control_topics: Vec<String>,
+    /// Desired and reported settings documents (`config_sync`)
+    config_topics: Option<(String, String)>,
+    downlinks: Vec<Downlink>,
+    response_topic: Option<String>,
+    reading_reports: Vec<(String, ReadingReport)>,
+    login: Option<LoginProvider>,
+    timeouts: CycleTimeouts,
+    retry: RetryPolicy,
+    mode: ConnectivityMode,
+    /// Persistent mode only
+    session: Option<Session>,
+    next_session_attempt: Option<Instant>,
+    publish_count: u32,
+    /// Last published reading and when, for the status document
+    last_reading: Option<(MeterReading, Option<String>)>,
+    last_publish_at: Option<Instant>,
+    suppressed_count: u32,
+    telemetry_topic: Option<String>,
+    /// None = no telemetry
+    telemetry_interval: Option<Duration>,
+    boot_count: u32,
+    next_telemetry: Option<Instant>,
+    log_topic: Option<String>,
+    crash_topic: Option<String>,
+    crash_reports: Option<CrashStore>,
+    export_topic: Option<String>,
+    export: Option<Arc<Mutex<DataLog>>>,
+    relay_topic: Option<String>,
+    /// Replace MQTT as the uplink when set
+    webhook: Option<Webhook>,
+    influxdb: Option<InfluxDb>,
+    coap: Option<CoapClient>,
+    events: Option<EventBus>,
+    /// Where the last publish failed
+    failure: Option<CycleFailure>,
+    /// Readings whose publish failed, oldest first, retried from `poll`
+    pending: VecDeque<PendingReading>,
+    backoff: Backoff,
+    next_retry: Option<Instant>,
+}
+
+impl Publisher {
+    /// Payloads identify the device by its chip ID. Every document goes to
+    /// its topic in `topics`: readings, the retained status document (each
+    /// connect cycle, in persistent mode on each session open and after each
+    /// reading), control replies, log uploads (after `logging::request_upload`)
+    /// and relayed ESP-NOW readings here; telemetry, crash reports, exports
+    /// and the control and config handlers once their builder is called.
+    /// An empty topic turns its document off.
+    pub fn new(
+        network: Arc<Mutex<dyn NetworkLink + Send>>,
+        mqtt_config: MqttConfig,
+        topics: &DeviceTopics,
+    ) -> Self {
+        let topic = |topic: &str| (!topic.is_empty()).then(|| topic.to_string());
+        // The same topic twice would run each command twice
+        let mut control_topics: Vec<String> = Vec::new();
+        for control in [&topics.control, &topics.control_device] {
+            if !control.is_empty() && !control_topics.contains(control) {
+                control_topics.push(control.clone());
+            }
+        }
+        let timeouts = CycleTimeouts {
+            downlink: Duration::from_secs(mqtt_config.downlink_wait_secs.into()),
+            ..CycleTimeouts::default()
+        };
+        Self {
+            network,
+            mqtt_config,
+            chip_id: device::chip_id(),
+            data_topic: topic(&topics.readings),
+            status_topic: topic(&topics.status),
+            device_name: None,
+            control_topics,
+            config_topics: topic(&topics.config_desired)
+                .map(|desired| (desired, topics.config_reported.clone())),
+            downlinks: Vec::new(),
+            response_topic: topic(&topics.response),
+            reading_reports: Vec::new(),
+            login: None,
+            timeouts,
+            retry: RetryPolicy::default(),
+            mode: ConnectivityMode::default(),
+            session: None,
+            next_session_attempt: None,
+            publish_count: 0,
+            last_reading: None,
+            last_publish_at: None,
+            suppressed_count: 0,
+            telemetry_topic: topic(&topics.telemetry),
+            telemetry_interval: None,
+            boot_count: 0,
+            next_telemetry: None,
+            log_topic: topic(&topics.logs),
+            crash_topic: topic(&topics.crash),
+            crash_reports: None,
+            export_topic: topic(&topics.export),
+            export: None,
+            relay_topic: topic(&topics.relay),
+            webhook: None,
+            influxdb: None,
+            coap: None,
+            events: None,
+            failure: None,
+            pending: VecDeque::new(),
+            backoff: Backoff::new(RETRY_BACKOFF_INITIAL, RETRY_BACKOFF_MAX),
+            next_retry: None,
+        }
+    }
+
+    /// Human-friendly name included in payloads (empty = not set)
+    pub fn with_device_name(mut self, name: &str) -> Self {
+        self.device_name = if name.is_empty() {
+            None
+        } else {
+            Some(name.to_string())
+        };
+        self
+    }
+
+    /// Subscribe to the control topics during each session and pass their
+    /// messages to `handler`; replies go to the response topic
+    pub fn with_control(mut self, handler: DownlinkHandler) -> Self {
+        if !self.control_topics.is_empty() {
+            self.downlinks.push(Downlink {
+                topics: self.control_topics.clone(),
+                handler,
+                response_topic: None,
+            });
+        }
+        self
+    }
+
+    /// Pass desired settings documents to `handler`, its replies going to the
+    /// reported topic (nothing when the desired topic is empty)
+    pub fn with_config_sync(mut self, handler: DownlinkHandler) -> Self {
+        if let Some((desired, reported)) = self.config_topics.clone() {
+            self.downlinks.push(Downlink {
+                topics: vec![desired],
+                handler,
+                response_topic: Some(reported),
+            });
+        }
+        self
+    }
+
+    /// Serve a cloud service's own topics (AWS IoT shadow, Azure IoT twin),
+    /// which are not device topics: messages on `topics` go to `handler`,
+    /// its replies to `response_topic`
+    pub fn with_downlink_to(
+        mut self,
+        topics: &[&str],
+        handler: DownlinkHandler,
+        response_topic: &str,
+    ) -> Self {
+        self.downlinks.push(Downlink {
+            topics: topics.iter().map(|topic| topic.to_string()).collect(),
+            handler,
+            response_topic: Some(response_topic.to_string()),
+        });
+        self
+    }
+
+    /// Also publish `report(reading)` to `topic` after each published reading
+    pub fn with_reading_report(mut self, topic: &str, report: ReadingReport) -> Self {
+        self.reading_reports.push((topic.to_string(), report));
+        self
+    }
+
+    /// Log in with `login()` instead of the configured username and password
+    pub fn with_login(mut self, login: LoginProvider) -> Self {
+        self.login = Some(login);
+        self
+    }
+
+    /// Publish a `TelemetryPayload` at most every `interval`, whenever a
+    /// session is open (on-demand: with the next reading)
+    pub fn with_telemetry(mut self, interval: Duration) -> Self {
+        self.telemetry_interval = Some(interval);
+        self
+    }
+
+    /// Boot counter reported in the status and telemetry payloads
+    pub fn with_boot_count(mut self, boot_count: u32) -> Self {
+        self.boot_count = boot_count;
+        self
+    }
+
+    /// Publish the crash saved in `store` on the next connection, then clear it
+    pub fn with_crash_reports(mut self, store: CrashStore) -> Self {
+        self.crash_reports = Some(store);
+        self
+    }
+
+    /// Publish the readings stored in `data_log` (chunked CSV) with the first
+    /// session after `storage::request_export`
+    pub fn with_export(mut self, data_log: Arc<Mutex<DataLog>>) -> Self {
+        self.export = Some(data_log);
+        self
+    }
+
+    /// POST readings to `webhook` instead of publishing them over MQTT
+    pub fn with_webhook(mut self, webhook: Webhook) -> Self {
+        self.webhook = Some(webhook);
+        self
+    }
+
+    /// Write readings (and telemetry, when due) to InfluxDB instead of
+    /// publishing them over MQTT
+    pub fn with_influxdb(mut self, influxdb: InfluxDb) -> Self {
+        self.influxdb = Some(influxdb);
+        self
+    }
+
+    /// Send readings as CoAP POSTs instead of publishing them over MQTT
+    pub fn with_coap(mut self, coap: CoapClient) -> Self {
+        self.coap = Some(coap);
+        self
+    }
+
+    /// Report publish outcomes (`PublishSucceeded`, `LinkFailed`, `MqttFailed`)
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Overrides `MqttConfig::downlink_wait_secs`
+    pub fn with_downlink_wait(mut self, wait: Duration) -> Self {
+        self.timeouts.downlink = wait;
+        self
+    }
+
+    /// Retries of the link and session steps of an on-demand cycle
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: ConnectivityMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Continue the publish counter from an earlier wake cycle (deep sleep)
+    pub fn with_publish_count(mut self, count: u32) -> Self {
+        self.publish_count = count;
+        self
+    }
+
+    /// Readings published since boot
+    pub fn publish_count(&self) -> u32 {
+        self.publish_count
+    }
+
+    /// Readings dropped by rate limiting or deduplication since boot
+    pub fn suppressed_count(&self) -> u32 {
+        self.suppressed_count
+    }
+
+    /// Readings in the offline queue
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Publish one reading. On-demand: a complete connect → publish →
+    /// downlink → disconnect cycle. Persistent: over the open session,
+    /// opening it first if needed.
+    ///
+    /// A reading that fails goes to the offline queue and is retried by
+    /// `poll` with exponential backoff (30s doubling up to 15 min), at most
+    /// `MAX_PUBLISH_ATTEMPTS` times. While a retry is pending, new readings
+    /// join the queue instead of starting a cycle of their own; when it is
+    /// due, the queue is published oldest first. Each cycle reports its
+    /// result on the event bus; a reading given up is reported with
+    /// `PublishAbandoned`.
+    pub fn publish_reading(&mut self, reading: &MeterReading) -> Result<()> {
+        if let Some(reason) = self.suppress_reason(reading) {
+            self.suppressed_count += 1;
+            log::info!("⏳ Reading not published ({})", reason);
+            return Ok(());
+        }
+
+        self.enqueue(reading);
+        match self.next_retry {
+            Some(at) if Instant::now() < at => Err(anyhow::anyhow!(
+                "Uplink down, reading queued ({} waiting, retry in {}s)",
+                self.pending.len(),
+                at.saturating_duration_since(Instant::now()).as_secs()
+            )),
+            _ => self.flush_pending(),
+        }
+    }
+
+    /// Add a reading to the offline queue, dropping the oldest when full
+    fn enqueue(&mut self, reading: &MeterReading) {
+        if self.pending.len() >= OFFLINE_QUEUE_CAPACITY {
+            if let Some(dropped) = self.pending.pop_front() {
+                log::warn!(
+                    "⚠️  Offline queue full, dropping reading: {}",
+                    dropped.reading.message
+                );
+                self.emit(DeviceEvent::PublishAbandoned);
+            }
+        }
+        self.pending.push_back(PendingReading {
+            reading: reading.clone(),
+            read_at: timekeeping::now_iso8601(),
+            attempts: 0,
+        });
+    }
+
+    /// Publish the offline queue, oldest first, until a reading fails; that
+    /// one and those after it stay queued (unless out of attempts) and a
+    /// retry is scheduled. Over MQTT the whole queue goes out in one session
+    /// (see `publish_payloads`); the other uplinks send one reading per cycle.
+    fn flush_pending(&mut self) -> Result<()> {
+        while !self.pending.is_empty() {
+            let included = if self.mqtt_uplink() {
+                self.pending.len()
+            } else {
+                1
+            };
+            for pending in self.pending.iter_mut().take(included) {
+                pending.attempts += 1;
+            }
+            if let Err(e) = self.attempt() {
+                let before = self.pending.len();
+                self.pending.retain(|pending| {
+                    let keep = pending.attempts < MAX_PUBLISH_ATTEMPTS;
+                    if !keep {
+                        log::error!(
+                            "❌ Reading given up after {} attempts: {}",
+                            pending.attempts,
+                            pending.reading.message
+                        );
+                    }
+                    keep
+                });
+                for _ in self.pending.len()..before {
+                    self.emit(DeviceEvent::PublishAbandoned);
+                }
+                if self.pending.is_empty() {
+                    self.backoff.reset();
+                    self.next_retry = None;
+                } else {
+                    let delay = self.backoff.fail();
+                    self.next_retry = Some(Instant::now() + delay);
+                    log::warn!(
+                        "⏳ Publish failed, retrying in {}s ({} reading(s) queued)",
+                        delay.as_secs(),
+                        self.pending.len()
+                    );
+                }
+                return Err(e);
+            }
+            self.backoff.reset();
+            self.next_retry = None;
+        }
+        Ok(())
+    }
+
+    /// One publish cycle for the front of the offline queue, reported on the
+    /// event bus. Readings are removed from the queue as they go out.
+    fn attempt(&mut self) -> Result<()> {
+        self.failure = None;
+        let result = self.publish_cycle();
+        self.emit(match result {
+            Ok(_) => DeviceEvent::PublishSucceeded,
+            Err(_) => self.failure.unwrap_or(CycleFailure::Publish).event(),
+        });
+        result
+    }
+
+    fn emit(&self, event: DeviceEvent) {
+        if let Some(events) = &self.events {
+            events.emit(event);
+        }
+    }
+
+    fn publish_cycle(&mut self) -> Result<()> {
+        if !self.mqtt_uplink() {
+            return self.post_cycle();
+        }
+        if self.mode == ConnectivityMode::Persistent {
+            self.ensure_session()?;
+            let session = self.session.take().expect("session opened above");
+            let result = self.publish_payloads(&session.client);
+            self.session = Some(session);
+            return result;
+        }
+
+        self.on_demand_cycle()
+    }
+
+    /// Run the steps of one on-demand cycle in the order the supervisor gives
+    fn on_demand_cycle(&mut self) -> Result<()> {
+        let mut supervisor =
+            Supervisor::new(self.timeouts, self.retry).with_downlink(!self.downlinks.is_empty());
+        let mut session: Option<Session> = None;
+        let mut error = None;
+
+        while !supervisor.is_done() {
+            let result = match supervisor.state() {
+                CycleState::LinkUp => {
+                    log::info!("📡 On-demand publish: Connecting network...");
+                    self.connect_link()
+                }
+                CycleState::Preflight => {
+                    self.check_broker(supervisor.timeout().unwrap_or_default())
+                }
+                // MQTT client (kept between cycles), connection, control subscriptions
+                CycleState::SessionUp => self.resume_session().map(|opened| {
+                    session = Some(opened);
+                }),
+                // The queued readings with device identification
+                CycleState::Publish => match session.as_ref() {
+                    Some(session) => self.publish_payloads(&session.client),
+                    None => Err(anyhow::anyhow!("No MQTT session")),
+                },
+                // Wait for queued downlink messages, then handle all of them
+                // (including those received while connecting and publishing)
+                CycleState::Downlink => {
+                    if let Some(session) = session.as_ref() {
+                        let wait = supervisor.timeout().unwrap_or_default();
+                        log::info!(
+                            "⏳ Waiting {}s for queued downlink messages...",
+                            wait.as_secs()
+                        );
+                        let handled = self.process_downlinks(session, Some(Instant::now() + wait));
+                        if handled > 0 {
+                            log::info!("📩 Handled {} control message(s)", handled);
+                        }
+                    }
+                    Ok(())
+                }
+                // Disconnect MQTT cleanly; the client is reused next cycle
+                CycleState::SessionDown => {
+                    if let Some(session) = session.take() {
+                        if let Err(e) = session.client.disconnect() {
+                            log::warn!("⚠️  MQTT disconnect failed: {:?}", e);
+                        }
+                        self.session = Some(session);
+                    }
+                    Ok(())
+                }
+                // Disconnect the network, whatever happened on MQTT
+                CycleState::LinkDown => {
+                    log::info!("🔌 Disconnecting network...");
+                    if let Ok(mut link) = self.network.lock() {
+                        if let Err(e) = link.disconnect() {
+                            log::warn!("⚠️  Network disconnect failed: {:?}", e);
+                        }
+                    }
+                    Ok(())
+                }
+                CycleState::Done => Ok(()),
+            };
+            let succeeded = match result {
+                Ok(()) => true,
+                Err(e) => {
+                    log::warn!(
+                        "⚠️  Publish cycle: {} failed: {:?}",
+                        supervisor.state().name(),
+                        e
+                    );
+                    error = Some(e);
+                    false
+                }
+            };
+            let pause = supervisor.advance(succeeded);
+            if !pause.is_zero() {
+                std::thread::sleep(pause);
+            }
+        }
+
+        self.failure = supervisor.failure();
+        match self.failure {
+            None => {
+                log::info!("✅ On-demand publish cycle complete");
+                Ok(())
+            }
+            Some(_) => Err(error.unwrap_or_else(|| anyhow::anyhow!("Publish cycle failed"))),
+        }
+    }
+
+    /// No webhook, InfluxDB or CoAP replacing MQTT
+    fn mqtt_uplink(&self) -> bool {
+        self.webhook.is_none() && self.influxdb.is_none() && self.coap.is_none()
+    }
+
+    /// POST the oldest queued reading to the webhook, InfluxDB or CoAP
+    /// server, bringing the link up first if needed
+    fn post_cycle(&mut self) -> Result<()> {
+        let Some(pending) = self.pending.front().cloned() else {
+            return Ok(());
+        };
+        let reading = &pending.reading;
+        let link_up = self.mode == ConnectivityMode::Persistent
+            && self
+                .network
+                .lock()
+                .map(|link| link.is_connected().unwrap_or(false))
+                .unwrap_or(false);
+        if !link_up {
+            log::info!("📡 Uplink: Connecting network...");
+            self.connect_link()?;
+        }
+
+        let payload = self.reading_payload(&pending);
+        let result = if let Some(ref webhook) = self.webhook {
+            serde_json::to_vec(&payload)
+                .map_err(anyhow::Error::from)
+                .and_then(|body| webhook.post_json(&body))
+                .map(|status| format!("webhook (HTTP {})", status))
+        } else if let Some(ref mut coap) = self.coap {
+            let format = self.mqtt_config.format;
+            format
+                .encode(&payload)
+                .and_then(|body| coap.post(format, &body))
+                .map(|code| format!("CoAP server ({})", code))
+        } else {
+            self.write_influx(&payload)
+                .map(|status| format!("InfluxDB (HTTP {})", status))
+        };
+        if let Ok(ref target) = result {
+            self.pending.pop_front();
+            self.publish_count += 1;
+            self.last_reading = Some((reading.clone(), payload.timestamp));
+            self.last_publish_at = Some(Instant::now());
+            log::info!(
+                "📤 Posted #{} to {}: {}",
+                self.publish_count,
+                target,
+                reading.message
+            );
+        }
+
+        if self.mode == ConnectivityMode::OnDemand {
+            log::info!("🔌 Disconnecting network...");
+            if let Ok(mut link) = self.network.lock() {
+                if let Err(e) = link.disconnect() {
+                    log::warn!("⚠️  Network disconnect failed: {:?}", e);
+                }
+            }
+        }
+        result.map(|_| ())
+    }
+
+    /// One line-protocol write: the reading, plus telemetry when due
+    fn write_influx(&mut self, payload: &ReadingPayload) -> Result<u16> {
+        let unix_secs = timekeeping::now_unix_secs();
+        let mut lines = influxdb::reading_line(payload, unix_secs);
+        if self.telemetry_due() {
+            lines.push('\n');
+            lines.push_str(&influxdb::telemetry_line(
+                &self.telemetry_payload(),
+                unix_secs,
+            ));
+        }
+        match self.influxdb {
+            Some(ref influxdb) => influxdb.write(&lines),
+            None => Err(anyhow::anyhow!("No InfluxDB")),
+        }
+    }
+
+    /// Publish a reading received from an ESP-NOW node to the relay topic.
+    /// Persistent mode only: the gateway has to stay on its access point's
+    /// channel to hear the nodes.
+    pub fn publish_relayed(&mut self, relayed: &RelayedReading) -> Result<()> {
+        let Some(topic) = self.relay_topic.clone() else {
+            return Err(anyhow::anyhow!("No relay topic"));
+        };
+        if self.mode != ConnectivityMode::Persistent {
+            return Err(anyhow::anyhow!("Relaying needs network.mode persistent"));
+        }
+        if !self.mqtt_uplink() {
+            return Err(anyhow::anyhow!("Relaying needs network.uplink mqtt"));
+        }
+        self.ensure_session()?;
+        let payload = self.mqtt_config.format.encode(&RelayedReadingPayload {
+            schema: PAYLOAD_SCHEMA_VERSION,
+            timestamp: timekeeping::now_iso8601(),
+            node: relayed.node.clone(),
+            seq: relayed.seq,
+            gateway: self.chip_id.clone(),
+            message: relayed.reading.message.clone(),
+            baud_rate: relayed.reading.baud_rate,
+            framing: relayed.reading.framing,
+            cycles: relayed.reading.cycles,
+            successful: relayed.reading.successful,
+            corrupted: relayed.reading.corrupted,
+        })?;
+        let session = self.session.as_ref().expect("session opened above");
+        let delivery = session.client.publish_confirmed(
+            &topic,
+            &payload,
+            QoS::AtLeastOnce,
+            false,
+            PUBLISH_ACK_TIMEOUT,
+        )?;
+        if !delivery.is_delivered() {
+            return Err(anyhow::anyhow!(
+                "Relayed reading not acknowledged: {:?}",
+                delivery
+            ));
+        }
+        log::info!(
+            "📤 Relayed reading from {} to {}: {}",
+            relayed.node,
+            topic,
+            relayed.reading.message
+        );
+        Ok(())
+    }
+
+    /// Why `reading` should not be published, if the opt-in rate limit
+    /// (`mqtt.min_interval`) or deduplication (`mqtt.dedup`) applies
+    fn suppress_reason(&self, reading: &MeterReading) -> Option<String> {
+        let min_interval = Duration::from_secs(self.mqtt_config.min_publish_interval_secs.into());
+        // Several meters are read back to back; each has its own interval
+        if let (Some(at), None) = (self.last_publish_at, &reading.meter) {
+            if !min_interval.is_zero() && at.elapsed() < min_interval {
+                return Some(format!(
+                    "{}s since last publish, minimum {}s",
+                    at.elapsed().as_secs(),
+                    min_interval.as_secs()
+                ));
+            }
+        }
+        if self.mqtt_config.dedup_readings {
+            if let Some((last, _)) = self.last_reading.as_ref() {
+                if last.message == reading.message && last.meter == reading.meter {
+                    return Some("same message as last publish".to_string());
+                }
+            }
+        }
+        None
+    }
+
+    /// Call regularly from the main loop. Retries the offline queue when
+    /// due. Persistent mode: opens the session (retrying with a delay) and
+    /// handles the queued control commands.
+    pub fn poll(&mut self) {
+        if self.next_retry.is_some_and(|at| Instant::now() >= at) {
+            log::info!(
+                "🔄 Retrying publish of {} queued reading(s) ({} failure(s) in a row)",
+                self.pending.len(),
+                self.backoff.failures()
+            );
+            if let Err(e) = self.flush_pending() {
+                log::warn!("⚠️  Publish retry failed: {:?}", e);
+            }
+        }
+        if self.mode != ConnectivityMode::Persistent || !self.mqtt_uplink() {
+            return;
+        }
+        if self.session.is_none() {
+            let due = match self.next_session_attempt {
+                Some(at) => Instant::now() >= at,
+                None => true,
+            };
+            if due {
+                if let Err(e) = self.ensure_session() {
+                    log::warn!(
+                        "⚠️  MQTT session failed, retrying in {}s: {:?}",
+                        SESSION_RETRY_DELAY.as_secs(),
+                        e
+                    );
+                }
+            }
+            return;
+        }
+        if let Some(session) = self.session.take() {
+            self.process_downlinks(&session, None);
+            if session.client.is_connected() {
+                self.publish_telemetry_if_due(&session.client);
+                self.upload_logs_if_requested(&session.client);
+                self.publish_export_if_requested(&session.client);
+            }
+            self.session = Some(session);
+        }
+    }
+
+    /// Whether an MQTT session is currently open and connected
+    pub fn is_connected(&self) -> bool {
+        self.session
+            .as_ref()
+            .is_some_and(|session| session.client.is_connected())
+    }
+
+    fn connect_link(&mut self) -> Result<()> {
+        let connected = self
+            .network
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed to lock network link"))
+            .and_then(|mut link| link.connect());
+        if connected.is_err() {
+            self.failure = Some(CycleFailure::Link);
+        }
+        connected?;
+
+        log::info!("✅ Network connected");
+
+        // Set the clock for reading timestamps (first publish after boot, then daily)
+        if timekeeping::needs_sync() {
+            if let Err(e) = timekeeping::sync(Duration::from_secs(5)) {
+                log::warn!("⚠️  SNTP sync failed: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve the broker and open a TCP connection to it; skipped when the
+    /// broker URL has no host the check understands
+    fn check_broker(&self, timeout: Duration) -> Result<()> {
+        let Some((host, port)) = preflight::broker_address(&self.mqtt_config.broker_url) else {
+            return Ok(());
+        };
+        log::info!("🌐 Checking broker {}:{}...", host, port);
+        let address = preflight::check_broker(&host, port, timeout)?;
+        log::info!("✅ Broker reachable at {}", address);
+        Ok(())
+    }
+
+    /// Persistent mode: bring up the link and the MQTT session if not open.
+    /// After that, esp-mqtt reconnects by itself and the WiFi manager brings
+    /// back a dropped link.
+    fn ensure_session(&mut self) -> Result<()> {
+        if self.session.is_some() {
+            return Ok(());
+        }
+        self.next_session_attempt = Some(Instant::now() + SESSION_RETRY_DELAY);
+
+        let link_up = self
+            .network
+            .lock()
+            .map(|link| link.is_connected().unwrap_or(false))
+            .unwrap_or(false);
+        if !link_up {
+            log::info!("📡 Persistent mode: Connecting network...");
+            self.connect_link()?;
+        }
+
+        self.check_broker(self.timeouts.preflight)?;
+        let session = self.open_session()?;
+        log::info!("✅ Persistent MQTT session open");
+        self.publish_status(&session.client);
+        self.publish_crash_report(&session.client);
+        self.session = Some(session);
+        Ok(())
+    }
+
+    /// On-demand mode: reconnect the client kept from the previous cycle, or
+    /// create one on the first cycle
+    fn resume_session(&mut self) -> Result<Session> {
+        match self.session.take() {
+            // A computed login (e.g. a SAS token) is only used for one connection
+            Some(session) if self.login.is_some() => {
+                session.client.shutdown();
+                self.open_session()
+            }
+            Some(session) => {
+                let result = session
+                    .client
+                    .reconnect()
+                    .and_then(|_| self.await_session(&session.client));
+                match result {
+                    Ok(()) => Ok(session),
+                    Err(e) => {
+                        let _ = session.client.disconnect();
+                        self.session = Some(session);
+                        Err(e)
+                    }
+                }
+            }
+            None => self.open_session(),
+        }
+    }
+
+    fn open_session(&self) -> Result<Session> {
+        // Step 2: Create MQTT client, forwarding control topic messages to the handler
+        log::info!("📡 Creating MQTT client...");
+        let client = match self.login {
+            Some(ref login) => {
+                let (username, password) = login()?;
+                MqttClient::from_config_with_login(
+                    &self.mqtt_config,
+                    Some(&username),
+                    Some(&password),
+                )?
+            }
+            None => MqttClient::from_config(&self.mqtt_config)?,
+        };
+
+        // Control messages are only queued on the connection thread and
+        // handled on the publishing thread (see `process_downlinks`)
+        let (queue_tx, downlinks) = mpsc::sync_channel::<QueuedDownlink>(DOWNLINK_QUEUE_CAPACITY);
+        for (route, downlink) in self.downlinks.iter().enumerate() {
+            for topic in &downlink.topics {
+                let queue_tx = queue_tx.clone();
+                client.on(
+                    topic,
+                    Arc::new(move |topic, data| {
+                        let queued = QueuedDownlink {
+                            route,
+                            topic: topic.to_string(),
+                            data: data.to_vec(),
+                            oversized: None,
+                        };
+                        if queue_tx.try_send(queued).is_err() {
+                            log::warn!("⚠️  Downlink queue full, message on {} dropped", topic);
+                        }
+                    }),
+                );
+            }
+        }
+        // Too large to receive: still queued so the sender gets an error reply
+        let filters: Vec<(usize, String)> = self
+            .downlinks
+            .iter()
+            .enumerate()
+            .flat_map(|(route, downlink)| {
+                downlink
+                    .topics
+                    .iter()
+                    .map(move |topic| (route, topic.clone()))
+            })
+            .collect();
+        client.on_oversized(Arc::new(move |topic, size| {
+            let Some(&(route, _)) = filters
+                .iter()
+                .find(|(_, filter)| topic_matches(filter, topic))
+            else {
+                return;
+            };
+            let queued = QueuedDownlink {
+                route,
+                topic: topic.to_string(),
+                data: Vec::new(),
+                oversized: Some(size),
+            };
+            if queue_tx.try_send(queued).is_err() {
+                log::warn!("⚠️  Downlink queue full, message on {} dropped", topic);
+            }
+        }));
+
+        if let Err(e) = self.await_session(&client) {
+            client.shutdown();
+            return Err(e);
+        }
+        Ok(Session { client, downlinks })
+    }
+
+    /// Steps 3-4: wait for the connection, then subscribe to the control topics
+    fn await_session(&self, client: &MqttClient) -> Result<()> {
+        log::info!("⏳ Waiting for MQTT connection...");
+        if !client.wait_connected(self.timeouts.session) {
+            return Err(anyhow::anyhow!("MQTT connection timeout"));
+        }
+        log::info!("✅ MQTT connected");
+
+        // Step 4: Subscribe to control topics
+        for topic in self.downlinks.iter().flat_map(|downlink| &downlink.topics) {
+            log::info!("📥 Subscribing to control topic: {}", topic);
+            if let Err(e) = client.subscribe(topic, QoS::AtLeastOnce) {
+                log::warn!("⚠️  Failed to subscribe to {}: {:?}", topic, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Publish the offline queue, oldest first, removing each reading once
+    /// the broker has acknowledged it and stopping at the first failure;
+    /// then the status and the other documents that are due. Several queued
+    /// readings go out as one batch in this session, each message carrying
+    /// the batch ID and its position.
+    fn publish_payloads(&mut self, client: &MqttClient) -> Result<()> {
+        let queued: Vec<PendingReading> = self.pending.iter().cloned().collect();
+        let batch_id = (queued.len() > 1)
+            .then(|| format!("{:08x}", unsafe { esp_idf_svc::sys::esp_random() }));
+        if let Some(ref id) = batch_id {
+            log::info!(
+                "📦 Publishing {} queued readings as batch {}",
+                queued.len(),
+                id
+            );
+        }
+
+        let mut result = Ok(());
+        for (index, pending) in queued.iter().enumerate() {
+            let batch = batch_id.as_ref().map(|id| BatchInfo {
+                id: id.clone(),
+                index: index + 1,
+                size: queued.len(),
+            });
+            result = self.publish_reading_payload(client, pending, batch);
+            if result.is_err() {
+                break;
+            }
+            self.pending.pop_front();
+        }
+
+        self.publish_status(client);
+        self.publish_crash_report(client);
+        self.publish_telemetry_if_due(client);
+        self.upload_logs_if_requested(client);
+        self.publish_export_if_requested(client);
+        result
+    }
+
+    /// One reading to the data topic (or its meter's subtopic), then the
+    /// reading reports
+    fn publish_reading_payload(
+        &mut self,
+        client: &MqttClient,
+        pending: &PendingReading,
+        batch: Option<BatchInfo>,
+    ) -> Result<()> {
+        let reading = &pending.reading;
+        let Some(ref data_topic) = self.data_topic else {
+            log::info!("⏳ Reading not published (no readings topic)");
+            return Ok(());
+        };
+        let mut reading_payload = self.reading_payload(pending);
+        reading_payload.batch = batch;
+        let payload = self.mqtt_config.format.encode(&reading_payload)?;
+        let topic = match reading.meter {
+            Some(ref meter) => format!("{}/{}", data_topic, meter.subtopic),
+            None => data_topic.clone(),
+        };
+        let result = client
+            .publish_confirmed(
+                &topic,
+                &payload,
+                QoS::AtLeastOnce,
+                false,
+                PUBLISH_ACK_TIMEOUT,
+            )
+            .and_then(|delivery| {
+                if delivery.is_delivered() {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!("Reading not acknowledged: {:?}", delivery))
+                }
+            });
+        if result.is_ok() {
+            self.publish_count += 1;
+            self.last_reading = Some((reading.clone(), reading_payload.timestamp));
+            self.last_publish_at = Some(Instant::now());
+            log::info!(
+                "📤 Published #{} to {}: {}",
+                self.publish_count,
+                topic,
+                reading.message
+            );
+        }
+
+        if result.is_ok() {
+            for (topic, report) in &self.reading_reports {
+                if let Err(e) =
+                    client.publish(topic, report(reading).as_bytes(), QoS::AtLeastOnce, false)
+                {
+                    log::warn!("⚠️  Report publish to {} failed: {:?}", topic, e);
+                }
+            }
+        }
+        result
+    }
+
+    fn publish_crash_report(&mut self, client: &MqttClient) {
+        if self.crash_topic.is_none() {
+            return;
+        }
+        let crash = match self.crash_reports.as_ref().map(|store| store.pending()) {
+            Some(Ok(Some(crash))) => crash,
+            Some(Err(e)) => {
+                log::warn!("⚠️  Failed to read crash report: {:?}", e);
+                return;
+            }
+            _ => return,
+        };
+        let payload = CrashPayload {
+            schema: PAYLOAD_SCHEMA_VERSION,
+            timestamp: timekeeping::now_iso8601(),
+            device: self.device_info(),
+            firmware: device::firmware_version(),
+            crash,
+        };
+        let (Some(topic), Some(store)) = (&self.crash_topic, self.crash_reports.as_mut()) else {
+            return;
+        };
+        let delivered = self
+            .mqtt_config
+            .format
+            .encode(&payload)
+            .and_then(|data| {
+                client.publish_confirmed(topic, &data, QoS::AtLeastOnce, false, PUBLISH_ACK_TIMEOUT)
+            })
+            .map(|delivery| delivery.is_delivered());
+        match delivered {
+            Ok(true) => {
+                log::info!("📤 Crash report published to {}", topic);
+                if let Err(e) = store.clear() {
+                    log::warn!("⚠️  Failed to clear crash report: {:?}", e);
+                }
+            }
+            Ok(false) => log::warn!("⚠️  Crash report not acknowledged, retrying next session"),
+            Err(e) => log::warn!("⚠️  Crash report publish failed: {:?}", e),
+        }
+    }
+
+    fn upload_logs_if_requested(&self, client: &MqttClient) {
+        let topic = match self.log_topic {
+            Some(ref topic) => topic,
+            None => return,
+        };
+        if !logging::take_upload_request() {
+            return;
+        }
+        let contents = logging::contents();
+        match client.publish_chunked(topic, contents.as_bytes(), DEFAULT_CHUNK_SIZE) {
+            Ok(transfer) => log::info!(
+                "📤 Log buffer ({} bytes) uploaded to {} (transfer {})",
+                contents.len(),
+                topic,
+                transfer
+            ),
+            Err(e) => {
+                log::warn!("⚠️  Log upload failed, retrying next session: {:?}", e);
+                logging::request_upload();
+            }
+        }
+    }
+
+    fn publish_export_if_requested(&self, client: &MqttClient) {
+        let (Some(topic), Some(data_log)) = (&self.export_topic, &self.export) else {
+            return;
+        };
+        let Some(count) = storage::take_export_request() else {
+            return;
+        };
+        let csv = match data_log.lock() {
+            Ok(data_log) => data_log.export_csv(count),
+            Err(_) => Err(anyhow::anyhow!("Data log unavailable")),
+        };
+        let result = csv.and_then(|csv| {
+            client
+                .publish_chunked(topic, csv.as_bytes(), DEFAULT_CHUNK_SIZE)
+                .map(|transfer| (csv.lines().count() - 1, transfer))
+        });
+        match result {
+            Ok((readings, transfer)) => log::info!(
+                "📤 Exported {} stored readings to {} (transfer {})",
+                readings,
+                topic,
+                transfer
+            ),
+            Err(e) => {
+                log::warn!("⚠️  Export failed, retrying next session: {:?}", e);
+                storage::request_export(count);
+            }
+        }
+    }
+
+    /// Whether a telemetry snapshot should go out now; schedules the next one
+    fn telemetry_due(&mut self) -> bool {
+        let Some(interval) = self.telemetry_interval else {
+            return false;
+        };
+        if self.telemetry_topic.is_none() {
+            return false;
+        }
+        if let Some(at) = self.next_telemetry {
+            if Instant::now() < at {
+                return false;
+            }
+        }
+        self.next_telemetry = Some(Instant::now() + interval);
+        true
+    }
+
+    fn publish_telemetry_if_due(&mut self, client: &MqttClient) {
+        let topic = match self.telemetry_topic.clone() {
+            Some(topic) => topic,
+            None => return,
+        };
+        if !self.telemetry_due() {
+            return;
+        }
+
+        let result = self
+            .mqtt_config
+            .format
+            .encode(&self.telemetry_payload())
+            .and_then(|payload| client.publish(&topic, &payload, QoS::AtLeastOnce, false));
+        match result {
+            Ok(()) => log::info!("📤 Telemetry published to {}", topic),
+            Err(e) => log::warn!("⚠️  Telemetry publish failed: {:?}", e),
+        }
+    }
+
+    /// Retained status document, if a status topic is set
+    fn publish_status(&self, client: &MqttClient) {
+        if let Some(ref topic) = self.status_topic {
+            let result = self
+                .mqtt_config
+                .format
+                .encode(&self.status_payload())
+                .and_then(|status| client.publish(topic, &status, QoS::AtLeastOnce, true));
+            if let Err(e) = result {
+                log::warn!("⚠️  Status publish failed: {:?}", e);
+            }
+        }
+    }
+
+    /// Run the queued control messages through their handlers, in arrival
+    /// order, and publish the replies to the response topic. Waits for more
+    /// until `deadline`, or until a message has been handled and its reply
+    /// acknowledged by the broker; after that (or with None) only what is
+    /// already queued is handled. Returns the number of messages handled.
+    fn process_downlinks(&self, session: &Session, mut deadline: Option<Instant>) -> usize {
+        let mut handled = 0;
+        loop {
+            let remaining =
+                deadline.and_then(|deadline| deadline.checked_duration_since(Instant::now()));
+            let queued = match remaining {
+                Some(remaining) => match session.downlinks.recv_timeout(remaining) {
+                    Ok(queued) => queued,
+                    // Deadline reached: drain what arrived meanwhile
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                },
+                None => match session.downlinks.try_recv() {
+                    Ok(queued) => queued,
+                    Err(_) => break,
+                },
+            };
+            handled += 1;
+
+            let Some(downlink) = self.downlinks.get(queued.route) else {
+                continue;
+            };
+            let reply = match queued.oversized {
+                Some(size) => Some(format!(
+                    "❌ Message too large ({} bytes, max {})",
+                    size, MAX_RECEIVED_LEN
+                )),
+                None => (downlink.handler)(&queued.topic, &queued.data),
+            };
+            let topic = downlink
+                .response_topic
+                .as_ref()
+                .or(self.response_topic.as_ref());
+            let acknowledged = match (reply, topic) {
+                // Waiting for more: confirm the reply so the wait can end early
+                (Some(reply), Some(topic)) if deadline.is_some() => {
+                    match session.client.publish_confirmed(
+                        topic,
+                        reply.as_bytes(),
+                        QoS::AtLeastOnce,
+                        false,
+                        PUBLISH_ACK_TIMEOUT,
+                    ) {
+                        Ok(status) => status == DeliveryStatus::Delivered,
+                        Err(e) => {
+                            log::warn!("⚠️  Response publish failed: {:?}", e);
+                            false
+                        }
+                    }
+                }
+                (Some(reply), Some(topic)) => {
+                    if let Err(e) =
+                        session
+                            .client
+                            .publish(topic, reply.as_bytes(), QoS::AtLeastOnce, false)
+                    {
+                        log::warn!("⚠️  Response publish failed: {:?}", e);
+                    }
+                    false
+                }
+                // Nothing to acknowledge
+                _ => true,
+            };
+            if acknowledged && deadline.take().is_some() {
+                log::info!("✅ Control message answered, ending the downlink wait");
+            }
+        }
+        handled
+    }
+
+    fn device_info(&self) -> DeviceInfo {
+        let link = match self.network.lock() {
+            Ok(link) => link,
+            Err(_) => {
+                return DeviceInfo {
+                    device_name: self.device_name.clone(),
+                    chip_id: self.chip_id.clone(),
+                    transport: "unknown",
+                    wifi_mac: "unknown".to_string(),
+                    wifi_ip: "unknown".to_string(),
+                    wifi_rssi: None,
+                    wifi_channel: None,
+                    link_stats: None,
+                }
+            }
+        };
+
+        DeviceInfo {
+            device_name: self.device_name.clone(),
+            chip_id: self.chip_id.clone(),
+            transport: link.name(),
+            wifi_mac: link.get_mac().unwrap_or_else(|_| "unknown".to_string()),
+            wifi_ip: link
+                .get_ip()
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|_| "unknown".to_string()),
+            wifi_rssi: link.get_rssi(),
+            wifi_channel: link.get_channel(),
+            link_stats: link.link_stats(),
+        }
+    }
+
+    fn reading_payload(&self, pending: &PendingReading) -> ReadingPayload {
+        let reading = &pending.reading;
+        ReadingPayload {
+            schema: PAYLOAD_SCHEMA_VERSION,
+            timestamp: timekeeping::now_iso8601(),
+            read_at: pending.read_at.clone(),
+            device: self.device_info(),
+            message: reading.message.clone(),
+            baud_rate: reading.baud_rate,
+            cycles: reading.cycles,
+            successful: reading.successful,
+            corrupted: reading.corrupted,
+            count: self.publish_count,
+            meter_id: reading.meter.as_ref().map(|meter| meter.id.clone()),
+            serial_mismatch: reading
+                .meter
+                .as_ref()
+                .is_some_and(|meter| meter.serial_mismatch),
+            batch: None,
+        }
+    }
+
+    fn status_payload(&self) -> StatusPayload {
+        let last_reading = self.last_reading.as_ref();
+        let success_rate = last_reading.and_then(|(reading, _)| {
+            let total = reading.successful + reading.corrupted;
+            (total > 0).then(|| {
+                let rate = reading.successful as f32 / total as f32 * 100.0;
+                (rate * 10.0).round() / 10.0
+            })
+        });
+        StatusPayload {
+            schema: PAYLOAD_SCHEMA_VERSION,
+            timestamp: timekeeping::now_iso8601(),
+            device: self.device_info(),
+            firmware: device::firmware_version(),
+            uptime_secs: telemetry::uptime_secs(),
+            boot_count: self.boot_count,
+            reset_reason: telemetry::reset_reason(),
+            publish_count: self.publish_count,
+            suppressed_count: self.suppressed_count,
+            baud_rate: last_reading.map(|(reading, _)| reading.baud_rate),
+            framing: last_reading.map(|(reading, _)| reading.framing),
+            success_rate,
+            last_reading_at: last_reading.and_then(|(_, at)| at.clone()),
+        }
+    }
+
+    fn telemetry_payload(&self) -> TelemetryPayload {
+        TelemetryPayload {
+            schema: PAYLOAD_SCHEMA_VERSION,
+            timestamp: timekeeping::now_iso8601(),
+            device: self.device_info(),
+            free_heap: telemetry::free_heap(),
+            min_free_heap: telemetry::min_free_heap(),
+            uptime_secs: telemetry::uptime_secs(),
+            boot_count: self.boot_count,
+            reset_reason: telemetry::reset_reason(),
+        }
+    }
+}
+
+/// Link supervisor for a cellular fallback: `connect` tries the primary link
+/// (WiFi) and dials the fallback (cellular modem) only when that fails. Each
+/// connect starts over with the primary, so WiFi is used again as soon as it
+/// is back; the other methods report on whichever link connected last.
+pub struct FailoverLink {
+    primary: Arc<Mutex<dyn NetworkLink + Send>>,
+    fallback: Arc<Mutex<dyn NetworkLink + Send>>,
+    on_fallback: bool,
+}
+
+impl FailoverLink {
+    pub fn new(
+        primary: Arc<Mutex<dyn NetworkLink + Send>>,
+        fallback: Arc<Mutex<dyn NetworkLink + Send>>,
+    ) -> Self {
+        Self {
+            primary,
+            fallback,
+            on_fallback: false,
+        }
+    }
+
+    fn with_active<R>(&self, f: impl FnOnce(&mut dyn NetworkLink) -> Result<R>) -> Result<R> {
+        let link = if self.on_fallback {
+            &self.fallback
+        } else {
+            &self.primary
+        };
+        let mut link = link
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed to lock network link"))?;
+        f(&mut *link)
+    }
+}
+
+impl NetworkLink for FailoverLink {
+    fn name(&self) -> &'static str {
+        self.with_active(|link| Ok(link.name()))
+            .unwrap_or("unknown")
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        if self.on_fallback {
+            self.with_active(|link| link.disconnect())?;
+            self.on_fallback = false;
+        }
+        let primary = self.with_active(|link| link.connect());
+        let Err(e) = primary else {
+            return Ok(());
+        };
+        log::warn!("⚠️  Primary link failed, trying fallback: {:?}", e);
+        self.on_fallback = true;
+        self.with_active(|link| link.connect())
+    }
+
+    fn disconnect(&mut self) -> Result<()> {
+        self.with_active(|link| link.disconnect())
+    }
+
+    fn is_connected(&self) -> Result<bool> {
+        self.with_active(|link| link.is_connected())
+    }
+
+    fn get_ip(&self) -> Result<Ipv4Addr> {
+        self.with_active(|link| link.get_ip())
+    }
+
+    fn get_mac(&self) -> Result<String> {
+        self.with_active(|link| link.get_mac())
+    }
+
+    fn get_rssi(&self) -> Option<i8> {
+        self.with_active(|link| Ok(link.get_rssi())).ok().flatten()
+    }
+
+    fn get_channel(&self) -> Option<u8> {
+        self.with_active(|link| Ok(link.get_channel()))
+            .ok()
+            .flatten()
+    }
+
+    fn link_stats(&self) -> Option<LinkStats> {
+        self.with_active(|link| Ok(link.link_stats()))
+            .ok()
+            .flatten()
+    }
+}
@@ -0,0 +1,400 @@
+//! On-demand publish cycle as a state machine
+//!
+//! `Supervisor` decides the order of the steps of one cycle (`CycleState`),
+//! their timeouts and when a failed one is tried again. It does no I/O:
+//! `Publisher` performs each step and reports back with `advance`. A failed
+//! publish still handles the downlink queue and disconnects; `Backoff` spaces
+//! out retries of cycles that failed as a whole.
+
+use crate::events::DeviceEvent;
+use std::time::Duration;
+
+/// TCP connect limit of the broker reachability check
+pub const DEFAULT_PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for the broker to accept the session
+pub const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(10);
+/// Default time to stay connected after publishing, for queued downlink messages
+pub const DEFAULT_DOWNLINK_WAIT: Duration = Duration::from_secs(5);
+
+/// Step of an on-demand cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CycleState {
+    /// Network link (WiFi or Ethernet) up, SNTP when due
+    LinkUp,
+    /// Broker host resolved and accepting TCP connections
+    Preflight,
+    /// MQTT connection and control topic subscriptions
+    SessionUp,
+    /// Reading and the documents published with it
+    Publish,
+    /// Wait for control messages, then handle the downlink queue
+    Downlink,
+    /// MQTT disconnect; the client is kept for the next cycle
+    SessionDown,
+    /// Network link down
+    LinkDown,
+    /// Cycle over, see `Supervisor::failure`
+    Done,
+}
+
+impl CycleState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CycleState::LinkUp => "link up",
+            CycleState::Preflight => "broker preflight",
+            CycleState::SessionUp => "session up",
+            CycleState::Publish => "publish",
+            CycleState::Downlink => "downlink",
+            CycleState::SessionDown => "session down",
+            CycleState::LinkDown => "link down",
+            CycleState::Done => "done",
+        }
+    }
+}
+
+/// Step at which a cycle failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CycleFailure {
+    /// The network link could not be brought up
+    Link,
+    /// The link was up but the broker did not resolve or accept a TCP connection
+    BrokerUnreachable,
+    /// The link was up but the MQTT session could not be opened
+    Session,
+    /// The session was open but the reading was not published
+    Publish,
+}
+
+impl CycleFailure {
+    /// Event reported on the event bus for a cycle failing here
+    pub fn event(self) -> DeviceEvent {
+        match self {
+            CycleFailure::Link => DeviceEvent::LinkFailed,
+            CycleFailure::BrokerUnreachable | CycleFailure::Session | CycleFailure::Publish => {
+                DeviceEvent::MqttFailed
+            }
+        }
+    }
+}
+
+/// Time limits of the timed steps
+#[derive(Debug, Clone, Copy)]
+pub struct CycleTimeouts {
+    /// `Preflight`: TCP connection to the broker
+    pub preflight: Duration,
+    /// `SessionUp`: broker accepting the connection
+    pub session: Duration,
+    /// `Downlink`: waiting for control messages (0 = only those already received)
+    pub downlink: Duration,
+}
+
+impl Default for CycleTimeouts {
+    fn default() -> Self {
+        Self {
+            preflight: DEFAULT_PREFLIGHT_TIMEOUT,
+            session: DEFAULT_SESSION_TIMEOUT,
+            downlink: DEFAULT_DOWNLINK_WAIT,
+        }
+    }
+}
+
+/// How often `LinkUp`, `Preflight` and `SessionUp` are tried before the cycle gives up
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Tries per step (1 = no retry)
+    pub attempts: u8,
+    /// Pause before each retry
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 2,
+            delay: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Delay before retrying a failed cycle: `initial`, doubled after each
+/// further failure up to `max`
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    /// Failures in a row
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            failures: 0,
+        }
+    }
+
+    /// Record a failed cycle; returns how long to wait before the next one
+    pub fn fail(&mut self) -> Duration {
+        let delay = self
+            .initial
+            .saturating_mul(1 << self.failures.min(16))
+            .min(self.max);
+        self.failures = self.failures.saturating_add(1);
+        delay
+    }
+
+    /// A cycle succeeded: the next failure waits `initial` again
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
+
+/// Drives one on-demand cycle; create one per cycle
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    state: CycleState,
+    timeouts: CycleTimeouts,
+    retry: RetryPolicy,
+    /// Tries of the current state so far
+    attempt: u8,
+    /// Go through `Downlink` (control topics are subscribed)
+    downlink: bool,
+    failure: Option<CycleFailure>,
+}
+
+impl Supervisor {
+    pub fn new(timeouts: CycleTimeouts, retry: RetryPolicy) -> Self {
+        Self {
+            state: CycleState::LinkUp,
+            timeouts,
+            retry,
+            attempt: 1,
+            downlink: true,
+            failure: None,
+        }
+    }
+
+    /// Skip `Downlink` when there are no control topics
+    pub fn with_downlink(mut self, downlink: bool) -> Self {
+        self.downlink = downlink;
+        self
+    }
+
+    /// Step to perform next
+    pub fn state(&self) -> CycleState {
+        self.state
+    }
+
+    /// Limit of the current step, None for the untimed ones
+    pub fn timeout(&self) -> Option<Duration> {
+        match self.state {
+            CycleState::Preflight => Some(self.timeouts.preflight),
+            CycleState::SessionUp => Some(self.timeouts.session),
+            CycleState::Downlink => Some(self.timeouts.downlink),
+            _ => None,
+        }
+    }
+
+    /// Where the cycle failed, None while running or after a success
+    pub fn failure(&self) -> Option<CycleFailure> {
+        self.failure
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == CycleState::Done
+    }
+
+    /// Record the result of the current step and move on. Returns the pause
+    /// before the next step: the retry delay when the same step is tried
+    /// again, otherwise zero.
+    pub fn advance(&mut self, succeeded: bool) -> Duration {
+        let next = match (self.state, succeeded) {
+            (CycleState::LinkUp | CycleState::Preflight | CycleState::SessionUp, false)
+                if self.attempt < self.retry.attempts =>
+            {
+                self.attempt += 1;
+                log::warn!(
+                    "⚠️  Publish cycle: {} failed, retrying in {}s (attempt {}/{})",
+                    self.state.name(),
+                    self.retry.delay.as_secs(),
+                    self.attempt,
+                    self.retry.attempts
+                );
+                return self.retry.delay;
+            }
+            (CycleState::LinkUp, true) => CycleState::Preflight,
+            // Nothing to take down
+            (CycleState::LinkUp, false) => self.fail(CycleFailure::Link, CycleState::Done),
+            (CycleState::Preflight, true) => CycleState::SessionUp,
+            (CycleState::Preflight, false) => {
+                self.fail(CycleFailure::BrokerUnreachable, CycleState::LinkDown)
+            }
+            (CycleState::SessionUp, true) => CycleState::Publish,
+            (CycleState::SessionUp, false) => {
+                self.fail(CycleFailure::Session, CycleState::LinkDown)
+            }
+            (CycleState::Publish, succeeded) => {
+                if !succeeded {
+                    self.failure = Some(CycleFailure::Publish);
+                }
+                if self.downlink {
+                    CycleState::Downlink
+                } else {
+                    CycleState::SessionDown
+                }
+            }
+            // Teardown goes on whatever happens
+            (CycleState::Downlink, _) => CycleState::SessionDown,
+            (CycleState::SessionDown, _) => CycleState::LinkDown,
+            (CycleState::LinkDown, _) | (CycleState::Done, _) => CycleState::Done,
+        };
+        self.state = next;
+        self.attempt = 1;
+        Duration::ZERO
+    }
+
+    fn fail(&mut self, failure: CycleFailure, next: CycleState) -> CycleState {
+        self.failure = Some(failure);
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RETRY: RetryPolicy = RetryPolicy {
+        attempts: 2,
+        delay: Duration::from_secs(2),
+    };
+
+    fn supervisor() -> Supervisor {
+        Supervisor::new(CycleTimeouts::default(), RETRY)
+    }
+
+    /// Feed `results` to the supervisor, returning the states it went through
+    fn drive(supervisor: &mut Supervisor, results: &[bool]) -> Vec<CycleState> {
+        results
+            .iter()
+            .map(|&succeeded| {
+                supervisor.advance(succeeded);
+                supervisor.state()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn successful_cycle_goes_through_every_step() {
+        let mut supervisor = supervisor();
+        let states = drive(&mut supervisor, &[true; 7]);
+        assert_eq!(
+            states,
+            [
+                CycleState::Preflight,
+                CycleState::SessionUp,
+                CycleState::Publish,
+                CycleState::Downlink,
+                CycleState::SessionDown,
+                CycleState::LinkDown,
+                CycleState::Done,
+            ]
+        );
+        assert_eq!(supervisor.failure(), None);
+    }
+
+    #[test]
+    fn link_up_retries_then_gives_up() {
+        let mut supervisor = supervisor();
+        assert_eq!(supervisor.advance(false), RETRY.delay);
+        assert_eq!(supervisor.state(), CycleState::LinkUp);
+        assert_eq!(supervisor.advance(false), Duration::ZERO);
+        assert!(supervisor.is_done());
+        assert_eq!(supervisor.failure(), Some(CycleFailure::Link));
+    }
+
+    #[test]
+    fn preflight_retries_then_takes_the_link_down() {
+        let mut supervisor = supervisor();
+        supervisor.advance(true);
+        assert_eq!(supervisor.timeout(), Some(DEFAULT_PREFLIGHT_TIMEOUT));
+        assert_eq!(supervisor.advance(false), RETRY.delay);
+        assert_eq!(supervisor.state(), CycleState::Preflight);
+        supervisor.advance(false);
+        assert_eq!(supervisor.state(), CycleState::LinkDown);
+        assert_eq!(supervisor.failure(), Some(CycleFailure::BrokerUnreachable));
+    }
+
+    #[test]
+    fn session_up_retries_then_takes_the_link_down() {
+        let mut supervisor = supervisor();
+        drive(&mut supervisor, &[true, true]);
+        assert_eq!(supervisor.timeout(), Some(DEFAULT_SESSION_TIMEOUT));
+        assert_eq!(supervisor.advance(false), RETRY.delay);
+        assert_eq!(supervisor.state(), CycleState::SessionUp);
+        supervisor.advance(false);
+        assert_eq!(supervisor.state(), CycleState::LinkDown);
+        assert_eq!(supervisor.failure(), Some(CycleFailure::Session));
+    }
+
+    #[test]
+    fn retry_succeeding_resets_the_attempts() {
+        let mut supervisor = supervisor();
+        drive(&mut supervisor, &[false, true]);
+        assert_eq!(supervisor.state(), CycleState::Preflight);
+        // The next step gets its own retry
+        assert_eq!(supervisor.advance(false), RETRY.delay);
+        assert_eq!(supervisor.state(), CycleState::Preflight);
+    }
+
+    #[test]
+    fn failed_publish_still_tears_down() {
+        let mut supervisor = supervisor();
+        let states = drive(
+            &mut supervisor,
+            &[true, true, true, false, true, true, true],
+        );
+        assert_eq!(
+            &states[3..],
+            [
+                CycleState::Downlink,
+                CycleState::SessionDown,
+                CycleState::LinkDown,
+                CycleState::Done,
+            ]
+        );
+        assert_eq!(supervisor.failure(), Some(CycleFailure::Publish));
+    }
+
+    #[test]
+    fn without_downlink_publish_goes_to_session_down() {
+        let mut supervisor = supervisor().with_downlink(false);
+        let states = drive(&mut supervisor, &[true, true, true, true]);
+        assert_eq!(states[3], CycleState::SessionDown);
+        assert!(!states.contains(&CycleState::Downlink));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let mut backoff = Backoff::new(Duration::from_secs(30), Duration::from_secs(200));
+        let delays: Vec<u64> = (0..5).map(|_| backoff.fail().as_secs()).collect();
+        assert_eq!(delays, [30, 60, 120, 200, 200]);
+        assert_eq!(backoff.failures(), 5);
+        backoff.reset();
+        assert_eq!(backoff.fail(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn backoff_survives_many_failures() {
+        let mut backoff = Backoff::new(Duration::from_secs(30), Duration::from_secs(3600));
+        for _ in 0..100 {
+            backoff.fail();
+        }
+        assert_eq!(backoff.fail(), Duration::from_secs(3600));
+    }
+}
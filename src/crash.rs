@@ -0,0 +1,209 @@
+//! Crash reports
+//!
+//! The panic hook writes the panic message and a backtrace (program
+//! counters) to RTC memory, which keeps its contents across the reboot that
+//! follows. At the next boot `CrashStore::record_boot` moves it to NVS, so
+//! it also survives a later power cycle, together with resets the hook
+//! never sees (watchdogs, ESP-IDF aborts, brownouts). The publisher sends the
+//! report to the crash topic on the next successful MQTT connection and
+//! clears it once the broker acknowledged it.
+//!
+//! Resolve the addresses with
+//! `xtensa-esp32-elf-addr2line -pfiaC -e <mtu_app ELF> 0x400d1234 ...`.
+
+use crate::telemetry;
+use anyhow::Result;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys;
+use serde::{Deserialize, Serialize};
+
+pub const CRASH_NVS_NAMESPACE: &str = "crash";
+const KEY_REPORT: &str = "report";
+
+const MAX_MESSAGE_LEN: usize = 192;
+const MAX_FRAMES: usize = 16;
+
+/// Marks `RTC_PANIC` as written by the panic hook
+const PANIC_MAGIC: u32 = 0x5041_4e31;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct PanicRecord {
+    magic: u32,
+    uptime_secs: u32,
+    message_len: u32,
+    message: [u8; MAX_MESSAGE_LEN],
+    frame_count: u32,
+    frames: [u32; MAX_FRAMES],
+}
+
+// Not initialized at boot, so the record written before the panic reboot is
+// still there (random after power-on, hence the magic)
+#[link_section = ".rtc_noinit"]
+static mut RTC_PANIC: PanicRecord = PanicRecord {
+    magic: 0,
+    uptime_secs: 0,
+    message_len: 0,
+    message: [0; MAX_MESSAGE_LEN],
+    frame_count: 0,
+    frames: [0; MAX_FRAMES],
+};
+
+/// What is known about the last abnormal reset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    /// `telemetry::reset_reason()` of the boot after the crash
+    pub reset_reason: String,
+    /// Boot number that crashed (0 if the boot counter is unavailable)
+    pub boot: u32,
+    /// Uptime at the panic (None if the hook did not run)
+    pub uptime_secs: Option<u32>,
+    /// Panic message with its source location (None if the hook did not run)
+    pub message: Option<String>,
+    /// Program counters, innermost first, as "0x400d1234"
+    pub backtrace: Vec<String>,
+}
+
+/// Resets that count as crashes even without a Rust panic
+fn is_crash_reset(reason: &str) -> bool {
+    matches!(
+        reason,
+        "panic" | "interrupt_watchdog" | "task_watchdog" | "watchdog" | "brownout"
+    )
+}
+
+/// Record panics in RTC memory, then run the default hook (console output,
+/// abort and reboot)
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let text = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("(no message)");
+        let message = match info.location() {
+            Some(location) => format!("{} at {}:{}", text, location.file(), location.line()),
+            None => text.to_string(),
+        };
+        record_panic(&message);
+        default_hook(info);
+    }));
+}
+
+fn record_panic(message: &str) {
+    let mut record = PanicRecord {
+        magic: PANIC_MAGIC,
+        uptime_secs: telemetry::uptime_secs() as u32,
+        message_len: 0,
+        message: [0; MAX_MESSAGE_LEN],
+        frame_count: 0,
+        frames: [0; MAX_FRAMES],
+    };
+    let len = message.len().min(MAX_MESSAGE_LEN);
+    record.message[..len].copy_from_slice(&message.as_bytes()[..len]);
+    record.message_len = len as u32;
+
+    let mut frame = sys::esp_backtrace_frame_t {
+        pc: 0,
+        sp: 0,
+        next_pc: 0,
+        exc_frame: core::ptr::null(),
+    };
+    unsafe {
+        sys::esp_backtrace_get_start(&mut frame.pc, &mut frame.sp, &mut frame.next_pc);
+    }
+    let mut count = 0;
+    while count < MAX_FRAMES {
+        record.frames[count] = call_site(frame.pc);
+        count += 1;
+        if frame.next_pc == 0 || !unsafe { sys::esp_backtrace_get_next_frame(&mut frame) } {
+            break;
+        }
+    }
+    record.frame_count = count as u32;
+
+    // Safety: written once, by the panicking thread, right before the reboot
+    unsafe { core::ptr::addr_of_mut!(RTC_PANIC).write_volatile(record) };
+}
+
+/// Return address → address of the call instruction (`esp_cpu_process_stack_pc`)
+fn call_site(pc: u32) -> u32 {
+    let pc = if pc & 0x8000_0000 != 0 {
+        (pc & 0x3fff_ffff) | 0x4000_0000
+    } else {
+        pc
+    };
+    pc.saturating_sub(3)
+}
+
+/// Panic record left by the previous boot, if it ended in a panic
+fn take_panic_record(reset_reason: &str) -> Option<PanicRecord> {
+    // Safety: only read at boot by the main task, before any panic can write it
+    let record = unsafe { core::ptr::addr_of!(RTC_PANIC).read_volatile() };
+    unsafe { core::ptr::addr_of_mut!(RTC_PANIC.magic).write_volatile(0) };
+    (record.magic == PANIC_MAGIC && reset_reason == "panic").then_some(record)
+}
+
+/// NVS copy of the last unreported crash
+pub struct CrashStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl CrashStore {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+        let nvs = EspNvs::new(partition, CRASH_NVS_NAMESPACE, true)?;
+        Ok(Self { nvs })
+    }
+
+    /// Call once at boot with this boot's number: saves a report if the
+    /// previous boot crashed. A newer crash replaces one never published.
+    pub fn record_boot(&mut self, boot_count: u32) -> Result<()> {
+        let reset_reason = telemetry::reset_reason();
+        let record = take_panic_record(reset_reason);
+        if record.is_none() && !is_crash_reset(reset_reason) {
+            return Ok(());
+        }
+
+        let report = CrashReport {
+            reset_reason: reset_reason.to_string(),
+            boot: boot_count.saturating_sub(1),
+            uptime_secs: record.map(|r| r.uptime_secs),
+            message: record.map(|r| {
+                let len = (r.message_len as usize).min(MAX_MESSAGE_LEN);
+                String::from_utf8_lossy(&r.message[..len]).into_owned()
+            }),
+            backtrace: record
+                .map(|r| {
+                    r.frames[..(r.frame_count as usize).min(MAX_FRAMES)]
+                        .iter()
+                        .map(|pc| format!("0x{:08x}", pc))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+        log::error!(
+            "❌ Crash: Previous boot ended with {}: {}",
+            report.reset_reason,
+            report.message.as_deref().unwrap_or("no panic message")
+        );
+        self.nvs
+            .set_str(KEY_REPORT, &serde_json::to_string(&report)?)?;
+        Ok(())
+    }
+
+    /// The crash not yet published, if any
+    pub fn pending(&self) -> Result<Option<CrashReport>> {
+        let mut buf = [0u8; 1024];
+        Ok(self
+            .nvs
+            .get_str(KEY_REPORT, &mut buf)?
+            .and_then(|json| serde_json::from_str(json).ok()))
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        self.nvs.remove(KEY_REPORT)?;
+        Ok(())
+    }
+}
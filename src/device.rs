@@ -0,0 +1,57 @@
+//! Device identity
+//!
+//! The identifiers every part of the firmware describes the device with:
+//! the chip ID used in payloads and topic templates, the WiFi station MAC,
+//! the firmware version and a name that stays the same across reboots and
+//! resets (the default `device.name` shown to users, and the BLE name).
+
+use crate::chip;
+
+/// Crate version the firmware was built from
+pub const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Base MAC from eFuse, e.g. "24:0a:c4:12:34:56"; `{chip_id}` in topic templates
+pub fn chip_id() -> String {
+    chip::format_mac(&chip::base_mac())
+}
+
+/// MAC of the WiFi station interface (differs from the chip ID)
+pub fn station_mac() -> String {
+    chip::format_mac(&chip::wifi_sta_mac())
+}
+
+/// Firmware version, as published in the status and crash documents
+pub fn firmware_version() -> &'static str {
+    FIRMWARE_VERSION
+}
+
+/// e.g. "esp32-water-meter 0.1.0 (ESP-IDF v5.2.2)"
+pub fn build_info() -> String {
+    format!(
+        "{} {} (ESP-IDF {})",
+        env!("CARGO_PKG_NAME"),
+        FIRMWARE_VERSION,
+        chip::idf_version()
+    )
+}
+
+/// Last three bytes of the chip ID, e.g. "123456" (client ID suffixes)
+pub fn short_id() -> String {
+    let mac = chip::base_mac();
+    format!("{:02x}{:02x}{:02x}", mac[3], mac[4], mac[5])
+}
+
+/// Name derived from the last two bytes of the chip ID, e.g. "WaterMeter-3456"
+pub fn default_name() -> String {
+    let mac = chip::base_mac();
+    format!("WaterMeter-{:02X}{:02X}", mac[4], mac[5])
+}
+
+/// `configured` (`device.name`), or the default name when it is empty
+pub fn name(configured: &str) -> String {
+    if configured.is_empty() {
+        default_name()
+    } else {
+        configured.to_string()
+    }
+}
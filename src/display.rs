@@ -0,0 +1,252 @@
+//! OLED display driven by the event bus
+//!
+//! An SSD1306 128x64 module on I2C (SDA GPIO21, SCL GPIO22) showing the last
+//! register value, how long ago the meter was read and the network and MQTT
+//! state, for bench setups and demo units:
+//!
+//! ```text
+//! Last read 5m ago
+//! 200                     (large font)
+//! Net ok    MQTT ok
+//! ```
+
+use crate::events::DeviceEvent;
+use anyhow::{anyhow, Result};
+use embedded_graphics::mono_font::ascii::{FONT_10X20, FONT_6X10};
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Baseline, Text};
+use esp_idf_hal::gpio::{Gpio21, Gpio22};
+use esp_idf_hal::i2c::{I2cConfig, I2cDriver, I2C0};
+use esp_idf_hal::units::FromValueType;
+use serde::{Deserialize, Serialize};
+use ssd1306::mode::BufferedGraphicsMode;
+use ssd1306::prelude::*;
+use ssd1306::{I2CDisplayInterface, Ssd1306};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// GPIOs taken by the display (SDA, SCL)
+pub const DISPLAY_GPIOS: &[u8] = &[21, 22];
+
+/// Redraw rate for the "time since last read" line
+const REFRESH: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayType {
+    /// No display fitted
+    #[default]
+    None,
+    /// SSD1306 128x64 OLED on I2C
+    Ssd1306,
+}
+
+impl DisplayType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(DisplayType::None),
+            "ssd1306" => Some(DisplayType::Ssd1306),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisplayType::None => "none",
+            DisplayType::Ssd1306 => "ssd1306",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisplaySettings {
+    /// Applied at boot
+    pub display_type: DisplayType,
+    /// 7-bit I2C address (0x3C on most modules, 0x3D with the address jumper)
+    pub address: u8,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            display_type: DisplayType::None,
+            address: 0x3C,
+        }
+    }
+}
+
+/// State of the network link or the MQTT session, as far as events tell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkState {
+    Unknown,
+    Ok,
+    Error,
+}
+
+impl LinkState {
+    fn label(self) -> &'static str {
+        match self {
+            LinkState::Unknown => "--",
+            LinkState::Ok => "ok",
+            LinkState::Error => "err",
+        }
+    }
+}
+
+/// What the display shows
+struct Screen {
+    register: Option<u32>,
+    reading: bool,
+    /// Time and outcome of the last finished read
+    last_read: Option<(Instant, bool)>,
+    network: LinkState,
+    mqtt: LinkState,
+}
+
+impl Screen {
+    fn apply(&mut self, event: DeviceEvent) {
+        match event {
+            DeviceEvent::ReadStarted => self.reading = true,
+            DeviceEvent::ReadFinished { success, register } => {
+                self.reading = false;
+                self.last_read = Some((Instant::now(), success));
+                if register.is_some() {
+                    self.register = register;
+                }
+            }
+            DeviceEvent::PublishSucceeded => {
+                self.network = LinkState::Ok;
+                self.mqtt = LinkState::Ok;
+            }
+            DeviceEvent::LinkFailed => self.network = LinkState::Error,
+            DeviceEvent::MqttFailed => {
+                self.network = LinkState::Ok;
+                self.mqtt = LinkState::Error;
+            }
+            // The failed attempt already set the link line
+            DeviceEvent::PublishAbandoned => {}
+        }
+    }
+
+    /// Status line, register value, link line
+    fn lines(&self) -> [String; 3] {
+        let status = match (self.reading, self.last_read) {
+            (true, _) => "Reading meter...".to_string(),
+            (false, None) => "No read yet".to_string(),
+            (false, Some((at, true))) => format!("Last read {} ago", age(at.elapsed())),
+            (false, Some((at, false))) => format!("Read failed {} ago", age(at.elapsed())),
+        };
+        let register = match self.register {
+            Some(value) => value.to_string(),
+            None => "--------".to_string(),
+        };
+        let links = format!("Net {:<5} MQTT {}", self.network.label(), self.mqtt.label());
+        [status, register, links]
+    }
+}
+
+/// "45s", "12m", "3h", "2d"
+fn age(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+type Oled = Ssd1306<
+    I2CInterface<I2cDriver<'static>>,
+    DisplaySize128x64,
+    BufferedGraphicsMode<DisplaySize128x64>,
+>;
+
+pub struct Display;
+
+impl Display {
+    /// Take I2C0 with SDA/SCL, initialize the display and update it from
+    /// the events on a background thread
+    pub fn spawn(
+        settings: &DisplaySettings,
+        i2c: I2C0,
+        sda: Gpio21,
+        scl: Gpio22,
+        events: Receiver<DeviceEvent>,
+    ) -> Result<()> {
+        if settings.display_type == DisplayType::None {
+            return Ok(());
+        }
+        let driver = I2cDriver::new(i2c, sda, scl, &I2cConfig::new().baudrate(400.kHz().into()))?;
+        let interface = I2CDisplayInterface::new_custom_address(driver, settings.address);
+        let mut oled = Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
+            .into_buffered_graphics_mode();
+        oled.init().map_err(|e| {
+            anyhow!(
+                "SSD1306 at 0x{:02X} not responding: {:?}",
+                settings.address,
+                e
+            )
+        })?;
+
+        log::info!(
+            "🖥️  Display: {} at 0x{:02X} (SDA GPIO21, SCL GPIO22)",
+            settings.display_type.name(),
+            settings.address
+        );
+        std::thread::Builder::new()
+            .stack_size(4096)
+            .name("display".to_string())
+            .spawn(move || Self::run(oled, events))?;
+        Ok(())
+    }
+
+    fn run(mut oled: Oled, events: Receiver<DeviceEvent>) {
+        let mut screen = Screen {
+            register: None,
+            reading: false,
+            last_read: None,
+            network: LinkState::Unknown,
+            mqtt: LinkState::Unknown,
+        };
+        let mut shown: Option<[String; 3]> = None;
+
+        loop {
+            match events.recv_timeout(REFRESH) {
+                Ok(event) => screen.apply(event),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            let lines = screen.lines();
+            if shown.as_ref() != Some(&lines) {
+                if let Err(e) = Self::draw(&mut oled, &lines) {
+                    log::warn!("⚠️  Display update failed: {:?}", e);
+                }
+                shown = Some(lines);
+            }
+        }
+        oled.clear_buffer();
+        let _ = oled.flush();
+    }
+
+    fn draw(oled: &mut Oled, [status, register, links]: &[String; 3]) -> Result<()> {
+        let small = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+        let large = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+        let error = |e| anyhow!("{:?}", e);
+
+        oled.clear_buffer();
+        Text::with_baseline(status, Point::new(0, 0), small, Baseline::Top)
+            .draw(oled)
+            .map_err(error)?;
+        Text::with_baseline(register, Point::new(0, 20), large, Baseline::Top)
+            .draw(oled)
+            .map_err(error)?;
+        Text::with_baseline(links, Point::new(0, 52), small, Baseline::Top)
+            .draw(oled)
+            .map_err(error)?;
+        oled.flush().map_err(error)
+    }
+}
@@ -0,0 +1,488 @@
+//! ESP-NOW relay for meters out of WiFi range
+//!
+//! A node (`espnow.role = node`) never joins a network: each reading is sent
+//! over ESP-NOW to a gateway (`espnow.role = gateway`, a device running this
+//! firmware with WiFi and a persistent MQTT session), which acknowledges it and
+//! publishes it to `topics.relay` with the node's chip ID. The gateway listens
+//! on its access point's channel, so `espnow.channel` on the nodes must match it.
+//!
+//! Frames (integers little-endian):
+//!
+//! ```text
+//! reading  'W' 1 seq:u32 baud:u32 successful:u32 corrupted:u32 cycles:u32 framing:[u8;3] message
+//! ack      'W' 2 seq:u32 status:u8      (0 = accepted, 1 = gateway queue full)
+//! ```
+//!
+//! A node sends a reading up to `SEND_ATTEMPTS` times until it is accepted. The
+//! gateway acknowledges repeats of the last sequence number it accepted from a
+//! node (its ack was lost) without publishing them again.
+//!
+//! Frames are not encrypted. With `espnow.nodes` set, the gateway ignores
+//! readings from any other MAC address; this keeps out neighbouring devices,
+//! not a sender spoofing an allowed address.
+
+use crate::connectivity::{MeterReading, Publisher};
+use crate::mtu::UartFraming;
+use anyhow::Result;
+use esp_idf_hal::modem::Modem;
+use esp_idf_svc::espnow::{EspNow, PeerInfo, BROADCAST};
+use esp_idf_svc::eventloop::EspSystemEventLoop;
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_svc::sys;
+use esp_idf_svc::wifi::{BlockingWifi, ClientConfiguration, Configuration, EspWifi};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::time::{Duration, Instant};
+
+const MAGIC: u8 = b'W';
+const FRAME_READING: u8 = 1;
+const FRAME_ACK: u8 = 2;
+
+const ACK_ACCEPTED: u8 = 0;
+const ACK_BUSY: u8 = 1;
+
+/// ESP-NOW payload limit
+const MAX_FRAME_LEN: usize = 250;
+/// Magic, type, sequence number, four counters and the framing
+const READING_HEADER_LEN: usize = 25;
+
+/// How long a node waits for the gateway's ack before sending again
+const ACK_TIMEOUT: Duration = Duration::from_millis(300);
+const SEND_ATTEMPTS: u32 = 5;
+/// Wait before sending again after a "queue full" ack (doubles each attempt)
+const BUSY_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Accepted readings waiting for the MQTT publish on the gateway
+const GATEWAY_QUEUE_LEN: usize = 16;
+/// Wait after a failed publish before the gateway tries again
+const PUBLISH_RETRY_DELAY: Duration = Duration::from_secs(10);
+/// Nodes whose last sequence number the gateway remembers; the one heard
+/// from least recently is forgotten first
+const MAX_TRACKED_NODES: usize = 32;
+/// Length of the `espnow.nodes` allowlist
+pub const MAX_ALLOWED_NODES: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EspNowRole {
+    #[default]
+    Off,
+    /// Send readings to a gateway instead of publishing them
+    Node,
+    /// Publish the readings received from nodes
+    Gateway,
+}
+
+impl EspNowRole {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(EspNowRole::Off),
+            "node" => Some(EspNowRole::Node),
+            "gateway" => Some(EspNowRole::Gateway),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EspNowRole::Off => "off",
+            EspNowRole::Node => "node",
+            EspNowRole::Gateway => "gateway",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EspNowSettings {
+    /// Applied at boot
+    pub role: EspNowRole,
+    /// Node only: gateway station MAC, None = broadcast to any gateway
+    pub gateway: Option<[u8; 6]>,
+    /// Node only: radio channel (1-13), the channel of the gateway's access point
+    pub channel: u8,
+    /// Gateway only: nodes whose readings are accepted, empty = any node
+    #[serde(default)]
+    pub nodes: Vec<[u8; 6]>,
+}
+
+impl Default for EspNowSettings {
+    fn default() -> Self {
+        Self {
+            role: EspNowRole::Off,
+            gateway: None,
+            channel: 1,
+            nodes: Vec::new(),
+        }
+    }
+}
+
+/// `aa:bb:cc:dd:ee:ff`, the chip ID format
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    format!(
+        "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+    )
+}
+
+/// Parse `aa:bb:cc:dd:ee:ff` (or with `-` separators)
+pub fn parse_mac(text: &str) -> Option<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    let mut parts = text.split([':', '-']);
+    for byte in mac.iter_mut() {
+        let part = parts.next()?;
+        if part.len() != 2 {
+            return None;
+        }
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    parts.next().is_none().then_some(mac)
+}
+
+/// A reading received from a node and accepted by the gateway
+#[derive(Debug, Clone)]
+pub struct RelayedReading {
+    /// Chip ID of the node (its station MAC)
+    pub node: String,
+    /// Node's sequence number for this reading
+    pub seq: u32,
+    pub reading: MeterReading,
+}
+
+fn encode_reading(seq: u32, reading: &MeterReading) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(MAX_FRAME_LEN);
+    frame.extend([MAGIC, FRAME_READING]);
+    frame.extend(seq.to_le_bytes());
+    frame.extend(reading.baud_rate.to_le_bytes());
+    frame.extend(reading.successful.to_le_bytes());
+    frame.extend(reading.corrupted.to_le_bytes());
+    frame.extend((reading.cycles as u32).to_le_bytes());
+    let mut framing = [b' '; 3];
+    for (slot, byte) in framing.iter_mut().zip(reading.framing.bytes()) {
+        *slot = byte;
+    }
+    frame.extend(framing);
+    let message = reading.message.trim_end().as_bytes();
+    frame.extend(&message[..message.len().min(MAX_FRAME_LEN - READING_HEADER_LEN)]);
+    frame
+}
+
+fn decode_reading(frame: &[u8]) -> Option<(u32, MeterReading)> {
+    if frame.len() < READING_HEADER_LEN || frame[..2] != [MAGIC, FRAME_READING] {
+        return None;
+    }
+    let word =
+        |at: usize| u32::from_le_bytes([frame[at], frame[at + 1], frame[at + 2], frame[at + 3]]);
+    let framing = std::str::from_utf8(&frame[22..25])
+        .ok()
+        .and_then(UartFraming::from_name)?;
+    Some((
+        word(2),
+        MeterReading {
+            message: String::from_utf8_lossy(&frame[READING_HEADER_LEN..]).into_owned(),
+            baud_rate: word(6),
+            framing: framing.name(),
+            cycles: word(18) as usize,
+            successful: word(10),
+            corrupted: word(14),
+            meter: None,
+        },
+    ))
+}
+
+fn encode_ack(seq: u32, status: u8) -> [u8; 7] {
+    let seq = seq.to_le_bytes();
+    [MAGIC, FRAME_ACK, seq[0], seq[1], seq[2], seq[3], status]
+}
+
+fn decode_ack(frame: &[u8]) -> Option<(u32, u8)> {
+    match frame {
+        [MAGIC, FRAME_ACK, a, b, c, d, status] => {
+            Some((u32::from_le_bytes([*a, *b, *c, *d]), *status))
+        }
+        _ => None,
+    }
+}
+
+fn peer(mac: [u8; 6]) -> PeerInfo {
+    PeerInfo {
+        peer_addr: mac,
+        // 0 = the channel the radio is on
+        channel: 0,
+        ifidx: sys::wifi_interface_t_WIFI_IF_STA,
+        encrypt: false,
+        ..Default::default()
+    }
+}
+
+/// Sends this device's readings to the gateway
+pub struct EspNowNode {
+    espnow: EspNow<'static>,
+    /// Started but never connected; ESP-NOW needs the radio running
+    _wifi: BlockingWifi<EspWifi<'static>>,
+    gateway: [u8; 6],
+    acks: Receiver<(u32, u8)>,
+    seq: u32,
+}
+
+impl EspNowNode {
+    /// Start the WiFi radio on `settings.channel` (without joining a network)
+    /// and ESP-NOW
+    pub fn start(
+        settings: &EspNowSettings,
+        modem: Modem,
+        sysloop: EspSystemEventLoop,
+        nvs: EspDefaultNvsPartition,
+    ) -> Result<Self> {
+        let esp_wifi = EspWifi::new(modem, sysloop.clone(), Some(nvs))?;
+        let mut wifi = BlockingWifi::wrap(esp_wifi, sysloop)?;
+        wifi.set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
+        wifi.start()?;
+        sys::esp!(unsafe {
+            sys::esp_wifi_set_channel(
+                settings.channel,
+                sys::wifi_second_chan_t_WIFI_SECOND_CHAN_NONE,
+            )
+        })?;
+
+        let espnow = EspNow::take()?;
+        let gateway = settings.gateway.unwrap_or(BROADCAST);
+        espnow.add_peer(peer(gateway))?;
+        let (ack_tx, acks) = mpsc::channel();
+        espnow.register_recv_cb(move |_info, data| {
+            if let Some(ack) = decode_ack(data) {
+                let _ = ack_tx.send(ack);
+            }
+        })?;
+
+        // Random start so the gateway does not take the first reading after
+        // a reset for a repeat of the last one before it
+        let mut seq = [0u8; 4];
+        unsafe {
+            sys::esp_fill_random(seq.as_mut_ptr() as *mut core::ffi::c_void, seq.len());
+        }
+
+        log::info!(
+            "📡 ESP-NOW: Node on channel {}, gateway {}",
+            settings.channel,
+            match settings.gateway {
+                Some(ref mac) => format_mac(mac),
+                None => "broadcast".to_string(),
+            }
+        );
+        Ok(Self {
+            espnow,
+            _wifi: wifi,
+            gateway,
+            acks,
+            seq: u32::from_le_bytes(seq),
+        })
+    }
+
+    /// Send `reading` until the gateway accepts it (blocking, at most a few seconds)
+    pub fn send_reading(&mut self, reading: &MeterReading) -> Result<()> {
+        self.seq = self.seq.wrapping_add(1);
+        let frame = encode_reading(self.seq, reading);
+        // Acks of earlier readings that arrived late
+        while self.acks.try_recv().is_ok() {}
+
+        let mut backoff = BUSY_BACKOFF;
+        for attempt in 1..=SEND_ATTEMPTS {
+            self.espnow.send(self.gateway, &frame)?;
+            match self.wait_ack() {
+                Some(ACK_ACCEPTED) => {
+                    log::info!(
+                        "📤 ESP-NOW: Reading #{} accepted by the gateway (attempt {})",
+                        self.seq,
+                        attempt
+                    );
+                    return Ok(());
+                }
+                Some(_) => {
+                    log::warn!(
+                        "⚠️  ESP-NOW: Gateway queue full, retrying in {}ms",
+                        backoff.as_millis()
+                    );
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                None => log::warn!(
+                    "⚠️  ESP-NOW: No ack for reading #{} (attempt {})",
+                    self.seq,
+                    attempt
+                ),
+            }
+        }
+        Err(anyhow::anyhow!(
+            "Reading not acknowledged after {} attempts",
+            SEND_ATTEMPTS
+        ))
+    }
+
+    /// Status of the ack for the current sequence number, None on timeout
+    fn wait_ack(&self) -> Option<u8> {
+        let deadline = Instant::now() + ACK_TIMEOUT;
+        loop {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            match self.acks.recv_timeout(remaining) {
+                Ok((seq, status)) if seq == self.seq => return Some(status),
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+}
+
+/// Last sequence number accepted from each node, bounded to `MAX_TRACKED_NODES`
+#[derive(Default)]
+struct SeenNodes {
+    nodes: HashMap<[u8; 6], (u32, Instant)>,
+}
+
+impl SeenNodes {
+    fn is_repeat(&self, mac: &[u8; 6], seq: u32) -> bool {
+        matches!(self.nodes.get(mac), Some(&(last, _)) if last == seq)
+    }
+
+    fn accept(&mut self, mac: [u8; 6], seq: u32) {
+        if self.nodes.len() >= MAX_TRACKED_NODES && !self.nodes.contains_key(&mac) {
+            let oldest = self
+                .nodes
+                .iter()
+                .min_by_key(|(_, &(_, at))| at)
+                .map(|(mac, _)| *mac);
+            if let Some(oldest) = oldest {
+                self.nodes.remove(&oldest);
+            }
+        }
+        self.nodes.insert(mac, (seq, Instant::now()));
+    }
+}
+
+/// Receives readings from nodes and hands them to the publisher
+pub struct EspNowGateway {
+    readings: Receiver<RelayedReading>,
+    /// Reading whose publish failed, and when to try again
+    retry: Option<(RelayedReading, Instant)>,
+}
+
+impl EspNowGateway {
+    /// Start ESP-NOW on the running WiFi radio and acknowledge readings on a
+    /// background thread
+    pub fn start(settings: &EspNowSettings) -> Result<Self> {
+        // Modem sleep makes the radio miss frames between beacons
+        sys::esp!(unsafe { sys::esp_wifi_set_ps(sys::wifi_ps_type_t_WIFI_PS_NONE) })?;
+
+        let espnow = EspNow::take()?;
+        let (frame_tx, frames) = mpsc::channel::<([u8; 6], Vec<u8>)>();
+        espnow.register_recv_cb(move |info, data| {
+            let _ = frame_tx.send((*info.src_addr, data.to_vec()));
+        })?;
+        let (reading_tx, readings) = mpsc::sync_channel(GATEWAY_QUEUE_LEN);
+        let allowed = settings.nodes.clone();
+
+        if allowed.is_empty() {
+            log::info!("📡 ESP-NOW: Gateway listening for readings from any node");
+        } else {
+            log::info!(
+                "📡 ESP-NOW: Gateway listening for readings from {} node(s)",
+                allowed.len()
+            );
+        }
+        std::thread::Builder::new()
+            .stack_size(4096)
+            .name("espnow_gw".to_string())
+            .spawn(move || Self::run(espnow, frames, reading_tx, allowed))?;
+
+        Ok(Self {
+            readings,
+            retry: None,
+        })
+    }
+
+    fn run(
+        espnow: EspNow<'static>,
+        frames: Receiver<([u8; 6], Vec<u8>)>,
+        readings: SyncSender<RelayedReading>,
+        allowed: Vec<[u8; 6]>,
+    ) {
+        let mut seen = SeenNodes::default();
+        for (mac, frame) in frames {
+            let Some((seq, reading)) = decode_reading(&frame) else {
+                continue;
+            };
+            let node = format_mac(&mac);
+            if !allowed.is_empty() && !allowed.contains(&mac) {
+                log::warn!("⚠️  ESP-NOW: Reading from unknown node {} ignored", node);
+                continue;
+            }
+            let status = if seen.is_repeat(&mac, seq) {
+                log::info!("📥 ESP-NOW: Repeat of reading #{} from {}", seq, node);
+                ACK_ACCEPTED
+            } else {
+                match readings.try_send(RelayedReading {
+                    node: node.clone(),
+                    seq,
+                    reading,
+                }) {
+                    Ok(()) => {
+                        log::info!("📥 ESP-NOW: Reading #{} from {}", seq, node);
+                        seen.accept(mac, seq);
+                        ACK_ACCEPTED
+                    }
+                    Err(TrySendError::Full(_)) => ACK_BUSY,
+                    Err(TrySendError::Disconnected(_)) => break,
+                }
+            };
+
+            // Peer only for the ack: the peer table holds 20 entries
+            let added = match espnow.peer_exists(mac) {
+                Ok(true) => false,
+                _ => match espnow.add_peer(peer(mac)) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        log::warn!("⚠️  ESP-NOW: Ack to {} failed: {:?}", node, e);
+                        continue;
+                    }
+                },
+            };
+            if let Err(e) = espnow.send(mac, &encode_ack(seq, status)) {
+                log::warn!("⚠️  ESP-NOW: Ack to {} failed: {:?}", node, e);
+            }
+            if added {
+                if let Err(e) = espnow.del_peer(mac) {
+                    log::warn!("⚠️  ESP-NOW: Removing peer {} failed: {:?}", node, e);
+                }
+            }
+        }
+    }
+
+    /// Publish the readings received since the last call. Call regularly
+    /// from the main loop; a failed publish is retried after a delay, with
+    /// the nodes held off by "queue full" acks meanwhile.
+    pub fn forward(&mut self, publisher: &mut Publisher) {
+        loop {
+            let relayed = match self.retry.take() {
+                Some((relayed, at)) if Instant::now() < at => {
+                    self.retry = Some((relayed, at));
+                    return;
+                }
+                Some((relayed, _)) => relayed,
+                None => match self.readings.try_recv() {
+                    Ok(relayed) => relayed,
+                    Err(_) => return,
+                },
+            };
+            if let Err(e) = publisher.publish_relayed(&relayed) {
+                log::warn!(
+                    "⚠️  ESP-NOW: Publishing reading from {} failed, retrying in {}s: {:?}",
+                    relayed.node,
+                    PUBLISH_RETRY_DELAY.as_secs(),
+                    e
+                );
+                self.retry = Some((relayed, Instant::now() + PUBLISH_RETRY_DELAY));
+                return;
+            }
+        }
+    }
+}
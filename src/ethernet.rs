@@ -0,0 +1,115 @@
+//! Wired transport: W5500 SPI Ethernet module
+//!
+//! Wiring (VSPI host): SCLK GPIO18, MOSI GPIO23, MISO GPIO19, CS GPIO27,
+//! INT GPIO26, RST GPIO25. The MTU keeps GPIO4/GPIO5.
+
+use crate::network::NetworkLink;
+use anyhow::Result;
+use esp_idf_hal::gpio::{Gpio18, Gpio19, Gpio23, Gpio25, Gpio26, Gpio27};
+use esp_idf_hal::spi::{Dma, SpiDriver, SpiDriverConfig, SPI2};
+use esp_idf_hal::units::FromValueType;
+use esp_idf_svc::eth::{BlockingEth, EspEth, EthDriver, SpiEth, SpiEthChipset};
+use esp_idf_svc::eventloop::EspSystemEventLoop;
+use esp_idf_svc::sys;
+use log::info;
+use std::net::Ipv4Addr;
+
+/// SPI clock for the W5500 (rated up to 80 MHz, 20 MHz is safe on long wires)
+const W5500_SPI_MHZ: u32 = 20;
+
+/// GPIOs taken by the W5500 module (SCLK, MISO, MOSI, RST, INT, CS)
+pub const ETHERNET_GPIOS: &[u8] = &[18, 19, 23, 25, 26, 27];
+
+/// Pins used by the W5500 module
+pub struct EthernetPins {
+    pub sclk: Gpio18,
+    pub mosi: Gpio23,
+    pub miso: Gpio19,
+    pub cs: Gpio27,
+    pub int: Gpio26,
+    pub rst: Gpio25,
+}
+
+type SpiEthernet = BlockingEth<EspEth<'static, SpiEth<SpiDriver<'static>>>>;
+
+// SAFETY: EthernetManager wraps ESP-IDF Ethernet which is thread-safe
+unsafe impl Send for EthernetManager {}
+unsafe impl Sync for EthernetManager {}
+
+pub struct EthernetManager {
+    eth: Box<SpiEthernet>,
+}
+
+impl EthernetManager {
+    /// Initialize the W5500 without bringing the link up (on-demand, like WiFi)
+    pub fn new(spi: SPI2, pins: EthernetPins, sysloop: EspSystemEventLoop) -> Result<Self> {
+        info!("🔌 Ethernet: Initializing W5500 on SPI2...");
+        let spi_driver = SpiDriver::new(
+            spi,
+            pins.sclk,
+            pins.mosi,
+            Some(pins.miso),
+            &SpiDriverConfig::new().dma(Dma::Auto(4096)),
+        )?;
+
+        // The W5500 has no MAC of its own; use the chip's Ethernet MAC from eFuse
+        let mut mac = [0u8; 6];
+        sys::esp!(unsafe { sys::esp_read_mac(mac.as_mut_ptr(), sys::esp_mac_type_t_ESP_MAC_ETH) })?;
+
+        let driver = EthDriver::new_spi(
+            spi_driver,
+            pins.int,
+            Some(pins.cs),
+            Some(pins.rst),
+            SpiEthChipset::W5500,
+            W5500_SPI_MHZ.MHz().into(),
+            Some(&mac),
+            None,
+            sysloop.clone(),
+        )?;
+        let eth = BlockingEth::wrap(EspEth::wrap(driver)?, sysloop)?;
+        info!("✅ Ethernet: W5500 ready");
+
+        Ok(Self { eth: Box::new(eth) })
+    }
+}
+
+impl NetworkLink for EthernetManager {
+    fn name(&self) -> &'static str {
+        "ethernet"
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        if !self.eth.is_started()? {
+            info!("🔌 Ethernet: Starting...");
+            self.eth.start()?;
+        }
+        self.eth.wait_netif_up()?;
+        info!("✅ Ethernet: IP address: {}", self.get_ip()?);
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> Result<()> {
+        if self.eth.is_started()? {
+            info!("🔌 Ethernet: Stopping...");
+            self.eth.stop()?;
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> Result<bool> {
+        Ok(self.eth.is_connected()?)
+    }
+
+    fn get_ip(&self) -> Result<Ipv4Addr> {
+        Ok(self.eth.eth().netif().get_ip_info()?.ip)
+    }
+
+    fn get_mac(&self) -> Result<String> {
+        let mac = self.eth.eth().netif().get_mac()?;
+        Ok(format!(
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+        ))
+    }
+}
@@ -0,0 +1,52 @@
+//! Device event bus
+//!
+//! Lightweight fan-out of what the device is doing (MTU reads, publish
+//! outcomes, link errors) to consumers such as the status LED or the display, without the
+//! producers knowing who listens. Each subscriber gets its own channel;
+//! subscribers that went away are dropped on the next emit.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceEvent {
+    ReadStarted,
+    ReadFinished {
+        success: bool,
+        /// Register value (`RB` field) of the message received, if any
+        register: Option<u32>,
+    },
+    PublishSucceeded,
+    /// The network link (WiFi or Ethernet) could not be brought up
+    LinkFailed,
+    /// The link was up but the MQTT session or publish failed
+    MqttFailed,
+    /// A reading was given up after its last retry, or pushed out of a full
+    /// offline queue
+    PublishAbandoned,
+}
+
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<DeviceEvent>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> Receiver<DeviceEvent> {
+        let (tx, rx) = channel();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(tx);
+        }
+        rx
+    }
+
+    pub fn emit(&self, event: DeviceEvent) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|tx| tx.send(event).is_ok());
+        }
+    }
+}
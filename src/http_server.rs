@@ -0,0 +1,383 @@
+//! Local HTTP REST API
+//!
+//! Mirrors the CLI for integrations on the LAN: command endpoints run through
+//! the same handler as the console and the MQTT control topics and answer
+//! with the same JSON acknowledgement, and `/live` streams reads over a
+//! WebSocket. The endpoints are listed in the README (HTTP REST API).
+
+use crate::cli::{remote, CommandHandler};
+use crate::mtu::{GpioMtuTimerV2, LiveEvent};
+use crate::timekeeping;
+use anyhow::Result;
+use esp_idf_svc::http::server::{
+    Configuration as HttpConfig, EspHttpConnection, EspHttpServer, EspHttpWsConnection,
+    EspHttpWsDetachedSender, Request,
+};
+use esp_idf_svc::http::Method;
+use esp_idf_svc::io::{Read, Write};
+use esp_idf_svc::ws::FrameType;
+use log::info;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Largest request body accepted (config updates are small JSON objects)
+const MAX_BODY_LEN: usize = 1024;
+
+/// Live events are sent to WebSocket clients in batches this often
+const LIVE_BATCH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Wrong `/config` passwords in a row before it is locked for `AUTH_LOCKOUT`
+const AUTH_MAX_FAILURES: u32 = 5;
+const AUTH_LOCKOUT: Duration = Duration::from_secs(60);
+
+/// WebSocket clients streaming at once (each holds one of the server's sockets)
+const MAX_LIVE_CLIENTS: usize = 2;
+
+/// Live stream subscribers by session, fed by the forwarder thread
+type LiveClients = Arc<Mutex<Vec<(i32, EspHttpWsDetachedSender)>>>;
+
+/// Keeps the HTTP server alive while held
+pub struct HttpApi {
+    _server: EspHttpServer<'static>,
+    port: u16,
+}
+
+impl HttpApi {
+    pub fn start(
+        port: u16,
+        handler: Arc<Mutex<CommandHandler>>,
+        mtu: Arc<GpioMtuTimerV2>,
+    ) -> Result<Self> {
+        let mut server = EspHttpServer::new(&HttpConfig {
+            http_port: port,
+            ..Default::default()
+        })?;
+
+        let status_handler = Arc::clone(&handler);
+        server.fn_handler::<anyhow::Error, _>("/status", Method::Get, move |req| {
+            info!("🌐 HTTP: GET /status");
+            respond_command(req, &status_handler, "status")
+        })?;
+
+        let sys_handler = Arc::clone(&handler);
+        server.fn_handler::<anyhow::Error, _>("/sys", Method::Get, move |req| {
+            info!("🌐 HTTP: GET /sys");
+            respond_command(req, &sys_handler, "sys")
+        })?;
+
+        let clients: LiveClients = Arc::new(Mutex::new(Vec::new()));
+        spawn_live_forwarder(mtu.subscribe_live(), Arc::clone(&clients))?;
+        let live_mtu = Arc::clone(&mtu);
+        server.ws_handler("/live", move |ws: &mut EspHttpWsConnection| {
+            handle_live_socket(ws, &live_mtu, &clients)
+        })?;
+
+        server.fn_handler::<anyhow::Error, _>("/readings", Method::Get, move |req| {
+            info!("🌐 HTTP: GET /readings");
+            let (successful, corrupted, cycles) = mtu.get_stats();
+            let body = serde_json::json!({
+                "timestamp": timekeeping::now_iso8601(),
+                "message": mtu.get_last_message().map(|m| m.to_string()),
+                "baud_rate": mtu.get_baud_rate(),
+                "framing": mtu.get_framing().name(),
+                "cycles": cycles,
+                "successful": successful,
+                "corrupted": corrupted,
+            });
+            respond_json(req, 200, &body)
+        })?;
+
+        let start_handler = Arc::clone(&handler);
+        server.fn_handler::<anyhow::Error, _>("/mtu/start", Method::Post, move |mut req| {
+            info!("🌐 HTTP: POST /mtu/start");
+            let body = read_body(&mut req)?;
+            let command_line = if body.trim().is_empty() {
+                "mtu_start".to_string()
+            } else {
+                match serde_json::from_str::<serde_json::Value>(&body) {
+                    Ok(json) => match json.get("duration").and_then(|v| v.as_u64()) {
+                        Some(duration) => format!("mtu_start {}", duration),
+                        None => "mtu_start".to_string(),
+                    },
+                    Err(e) => {
+                        return respond_error(req, &format!("Invalid JSON body: {}", e));
+                    }
+                }
+            };
+            respond_command(req, &start_handler, &command_line)
+        })?;
+
+        let config_handler = handler;
+        let auth_guard = Mutex::new(AuthGuard::default());
+        server.fn_handler::<anyhow::Error, _>("/config", Method::Post, move |mut req| {
+            info!("🌐 HTTP: POST /config");
+            let refusal = match authorize(&req, &config_handler, &auth_guard) {
+                Auth::Granted => None,
+                Auth::Denied => Some((
+                    401,
+                    "❌ Console password required (Authorization: Bearer <password>)",
+                )),
+                Auth::LockedOut => Some((429, "❌ Too many wrong passwords, try again later")),
+            };
+            if let Some((status, detail)) = refusal {
+                log::warn!("⚠️  HTTP: POST /config refused ({})", status);
+                let body = serde_json::json!({
+                    "ok": false,
+                    "command": "config save",
+                    "detail": detail,
+                });
+                return respond_json(req, status, &body);
+            }
+            let body = read_body(&mut req)?;
+            let settings = match serde_json::from_str::<serde_json::Value>(&body) {
+                Ok(serde_json::Value::Object(settings)) if !settings.is_empty() => settings,
+                Ok(_) => {
+                    return respond_error(req, "Body must be a JSON object of config keys");
+                }
+                Err(e) => {
+                    return respond_error(req, &format!("Invalid JSON body: {}", e));
+                }
+            };
+
+            let settings: Vec<(String, String)> = settings
+                .into_iter()
+                .map(|(key, value)| match value {
+                    serde_json::Value::String(s) => (key, s),
+                    other => (key, other.to_string()),
+                })
+                .collect();
+            // Nothing is applied unless every key is accepted
+            let checked = match config_handler.lock() {
+                Ok(handler) => handler.check_config(&settings),
+                Err(_) => Err("Command handler unavailable".to_string()),
+            };
+            if let Err(e) = checked {
+                return respond_ack(req, false, "config set", &format!("❌ {}", e));
+            }
+
+            let mut detail = String::new();
+            for (key, value) in &settings {
+                let command_line = format!("config set {} {}", key, value);
+                let (_, output) = remote::execute_authorized(&config_handler, &command_line);
+                detail.push_str(&output);
+                detail.push_str("\r\n");
+            }
+            let (ok, output) = remote::execute_authorized(&config_handler, "config save");
+            detail.push_str(&output);
+            respond_ack(req, ok, "config save", &detail)
+        })?;
+
+        info!("✅ HTTP: REST API listening on port {}", port);
+
+        Ok(Self {
+            _server: server,
+            port,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+/// Register new live stream clients and handle their `bits` / `chars` requests
+fn handle_live_socket(
+    ws: &mut EspHttpWsConnection,
+    mtu: &GpioMtuTimerV2,
+    clients: &LiveClients,
+) -> Result<()> {
+    let mut clients = clients
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+    if ws.is_new() {
+        if clients.len() >= MAX_LIVE_CLIENTS {
+            log::warn!("⚠️  HTTP: Live stream client limit reached");
+            ws.send(FrameType::Close, &[])?;
+            return Ok(());
+        }
+        clients.push((ws.session(), ws.create_detached_sender()?));
+        info!("🌐 HTTP: Live stream client {} connected", ws.session());
+        return Ok(());
+    }
+    if ws.is_closed() {
+        clients.retain(|(session, _)| *session != ws.session());
+        info!("🌐 HTTP: Live stream client {} disconnected", ws.session());
+        return Ok(());
+    }
+
+    drop(clients);
+
+    let (_, len) = ws.recv(&mut [])?;
+    if len > MAX_BODY_LEN {
+        anyhow::bail!("WebSocket frame too large ({} bytes)", len);
+    }
+    let mut buf = vec![0u8; len];
+    ws.recv(&mut buf)?;
+    match std::str::from_utf8(&buf).map(str::trim) {
+        Ok("bits") => {
+            info!("🌐 HTTP: Live stream bits enabled");
+            mtu.set_live_bits(true);
+        }
+        Ok("chars") => {
+            info!("🌐 HTTP: Live stream bits disabled");
+            mtu.set_live_bits(false);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Batch live MTU events and send them to every connected client; clients
+/// whose socket is gone are dropped on the first failed send
+fn spawn_live_forwarder(events: Receiver<LiveEvent>, clients: LiveClients) -> Result<()> {
+    std::thread::Builder::new()
+        .stack_size(4096)
+        .name("http_live".to_string())
+        .spawn(move || {
+            let mut chars = String::new();
+            let mut bits = String::new();
+            let mut frame_errors = 0u32;
+            let mut last_flush = Instant::now();
+
+            loop {
+                match events.recv_timeout(LIVE_BATCH_INTERVAL) {
+                    Ok(LiveEvent::Bit(bit)) => bits.push(if bit == 1 { '1' } else { '0' }),
+                    Ok(LiveEvent::Char(ch)) => chars.push(ch),
+                    Ok(LiveEvent::FrameError) => frame_errors += 1,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if last_flush.elapsed() < LIVE_BATCH_INTERVAL {
+                    continue;
+                }
+                last_flush = Instant::now();
+                if chars.is_empty() && bits.is_empty() && frame_errors == 0 {
+                    continue;
+                }
+
+                let mut batch = serde_json::json!({
+                    "chars": chars,
+                    "frame_errors": frame_errors,
+                });
+                if !bits.is_empty() {
+                    batch["bits"] = serde_json::Value::from(bits.as_str());
+                }
+                let frame = batch.to_string();
+                if let Ok(mut clients) = clients.lock() {
+                    clients.retain_mut(|(_, client)| {
+                        client
+                            .send(FrameType::Text(false), frame.as_bytes())
+                            .is_ok()
+                    });
+                }
+                chars.clear();
+                bits.clear();
+                frame_errors = 0;
+            }
+            log::info!("HTTP: Live stream forwarder stopped");
+        })?;
+    Ok(())
+}
+
+/// Read the request body, capped at `MAX_BODY_LEN`
+fn read_body(req: &mut Request<&mut EspHttpConnection<'_>>) -> Result<String> {
+    let len = (req.content_len().unwrap_or(0) as usize).min(MAX_BODY_LEN);
+    let mut body = vec![0u8; len];
+    req.read_exact(&mut body)
+        .map_err(|e| anyhow::anyhow!("Failed to read body: {:?}", e))?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Outcome of the `/config` password check
+enum Auth {
+    Granted,
+    Denied,
+    /// Too many wrong passwords in a row; refused without checking
+    LockedOut,
+}
+
+/// Wrong `/config` passwords in a row, counted rather than answered slowly so
+/// a guess doesn't hold up the other endpoints
+#[derive(Default)]
+struct AuthGuard {
+    failures: u32,
+    locked_until: Option<Instant>,
+}
+
+/// Whether the request may change the configuration: always until a console
+/// password is set (`passwd`), then only with `Authorization: Bearer <password>`
+fn authorize(
+    req: &Request<&mut EspHttpConnection<'_>>,
+    handler: &Arc<Mutex<CommandHandler>>,
+    guard: &Mutex<AuthGuard>,
+) -> Auth {
+    let Ok(mut guard) = guard.lock() else {
+        return Auth::Denied;
+    };
+    if guard
+        .locked_until
+        .is_some_and(|until| Instant::now() < until)
+    {
+        return Auth::LockedOut;
+    }
+    let password = req
+        .header("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if handler
+        .lock()
+        .is_ok_and(|handler| handler.accepts_password(password))
+    {
+        guard.failures = 0;
+        return Auth::Granted;
+    }
+    guard.failures += 1;
+    if guard.failures >= AUTH_MAX_FAILURES {
+        guard.failures = 0;
+        guard.locked_until = Some(Instant::now() + AUTH_LOCKOUT);
+    }
+    Auth::Denied
+}
+
+/// Run a CLI command line and answer with its acknowledgement
+fn respond_command(
+    req: Request<&mut EspHttpConnection<'_>>,
+    handler: &Arc<Mutex<CommandHandler>>,
+    command_line: &str,
+) -> Result<()> {
+    let (ok, detail) = remote::execute(handler, command_line);
+    respond_ack(req, ok, command_line, &detail)
+}
+
+fn respond_ack(
+    req: Request<&mut EspHttpConnection<'_>>,
+    ok: bool,
+    command_line: &str,
+    detail: &str,
+) -> Result<()> {
+    let body = serde_json::json!({
+        "ok": ok,
+        "command": command_line,
+        "detail": detail,
+    });
+    respond_json(req, if ok { 200 } else { 400 }, &body)
+}
+
+fn respond_error(req: Request<&mut EspHttpConnection<'_>>, detail: &str) -> Result<()> {
+    log::warn!("⚠️  HTTP: {}", detail);
+    respond_json(
+        req,
+        400,
+        &serde_json::json!({ "ok": false, "detail": detail }),
+    )
+}
+
+fn respond_json(
+    req: Request<&mut EspHttpConnection<'_>>,
+    status: u16,
+    body: &serde_json::Value,
+) -> Result<()> {
+    req.into_response(status, None, &[("Content-Type", "application/json")])?
+        .write_all(body.to_string().as_bytes())?;
+    Ok(())
+}
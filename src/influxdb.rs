@@ -0,0 +1,178 @@
+//! InfluxDB v2 uplink (line protocol)
+//!
+//! With `network.uplink = influxdb` readings (and telemetry, when due) are
+//! written straight to `<influx.url>/api/v2/write` with token auth, so no
+//! MQTT-to-Influx bridge is needed:
+//!
+//! ```text
+//! water_meter,chip_id=24:0a:c4:12:34:56,transport=wifi register=200i,message="V;RB00000200;...",baud_rate=1200i,cycles=1i,successful=5i,corrupted=0i,count=3i,rssi=-61i 1717250607
+//! water_meter_telemetry,chip_id=24:0a:c4:12:34:56,transport=wifi free_heap=142312i,min_free_heap=118744i,uptime_secs=86412i,boot_count=7i,reset_reason="power_on",rssi=-61i 1717250607
+//! ```
+//!
+//! `device_name` and `meter_id` (several meters, see `meters add`) are added
+//! as tags when set. The timestamp (seconds) is left out while the clock is
+//! not set, so the server stamps the point instead.
+
+use crate::mtu::register_value;
+use crate::payloads::{DeviceInfo, ReadingPayload, TelemetryPayload};
+use crate::webhook::{self, is_valid_url};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+const READING_MEASUREMENT: &str = "water_meter";
+const TELEMETRY_MEASUREMENT: &str = "water_meter_telemetry";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InfluxSettings {
+    /// Server base URL, e.g. `https://eu-central-1-1.aws.cloud2.influxdata.com` (empty = not set)
+    pub url: heapless::String<128>,
+    pub org: heapless::String<64>,
+    pub bucket: heapless::String<64>,
+    /// API token with write access to the bucket
+    pub token: heapless::String<128>,
+}
+
+/// Escape commas, equals signs and spaces in a tag value
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Double-quoted string field value
+fn quote_field(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Percent-encode a query parameter value
+fn encode_query(value: &str) -> String {
+    value.bytes().fold(String::new(), |mut encoded, byte| {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+        encoded
+    })
+}
+
+/// Measurement and tags identifying the device
+fn series(measurement: &str, device: &DeviceInfo, meter_id: Option<&str>) -> String {
+    let mut line = format!("{},chip_id={}", measurement, escape_tag(&device.chip_id));
+    if let Some(ref name) = device.device_name {
+        let _ = write!(line, ",device_name={}", escape_tag(name));
+    }
+    if let Some(id) = meter_id {
+        let _ = write!(line, ",meter_id={}", escape_tag(id));
+    }
+    let _ = write!(line, ",transport={}", escape_tag(device.transport));
+    line
+}
+
+/// Fields, then the timestamp if known
+fn finish(mut line: String, fields: &[String], unix_secs: Option<u64>) -> String {
+    line.push(' ');
+    line.push_str(&fields.join(","));
+    if let Some(secs) = unix_secs {
+        let _ = write!(line, " {}", secs);
+    }
+    line
+}
+
+/// One point for a published reading
+pub fn reading_line(payload: &ReadingPayload, unix_secs: Option<u64>) -> String {
+    let mut fields = Vec::new();
+    if let Some(register) = register_value(&payload.message) {
+        fields.push(format!("register={}i", register));
+    }
+    fields.push(format!(
+        "message={}",
+        quote_field(payload.message.trim_end())
+    ));
+    fields.push(format!("baud_rate={}i", payload.baud_rate));
+    fields.push(format!("cycles={}i", payload.cycles));
+    fields.push(format!("successful={}i", payload.successful));
+    fields.push(format!("corrupted={}i", payload.corrupted));
+    fields.push(format!("count={}i", payload.count));
+    if payload.meter_id.is_some() {
+        fields.push(format!("serial_mismatch={}", payload.serial_mismatch));
+    }
+    if let Some(rssi) = payload.device.wifi_rssi {
+        fields.push(format!("rssi={}i", rssi));
+    }
+    finish(
+        series(
+            READING_MEASUREMENT,
+            &payload.device,
+            payload.meter_id.as_deref(),
+        ),
+        &fields,
+        unix_secs,
+    )
+}
+
+/// One point for a telemetry snapshot
+pub fn telemetry_line(payload: &TelemetryPayload, unix_secs: Option<u64>) -> String {
+    let mut fields = vec![
+        format!("free_heap={}i", payload.free_heap),
+        format!("min_free_heap={}i", payload.min_free_heap),
+        format!("uptime_secs={}i", payload.uptime_secs),
+        format!("boot_count={}i", payload.boot_count),
+        format!("reset_reason={}", quote_field(payload.reset_reason)),
+    ];
+    if let Some(rssi) = payload.device.wifi_rssi {
+        fields.push(format!("rssi={}i", rssi));
+    }
+    finish(
+        series(TELEMETRY_MEASUREMENT, &payload.device, None),
+        &fields,
+        unix_secs,
+    )
+}
+
+pub struct InfluxDb {
+    write_url: String,
+    auth: String,
+}
+
+impl InfluxDb {
+    /// None when the URL, org, bucket or token is missing
+    pub fn from_settings(settings: &InfluxSettings) -> Option<Self> {
+        if !is_valid_url(&settings.url)
+            || settings.org.is_empty()
+            || settings.bucket.is_empty()
+            || settings.token.is_empty()
+        {
+            return None;
+        }
+        Some(Self {
+            write_url: format!(
+                "{}/api/v2/write?org={}&bucket={}&precision=s",
+                settings.url.trim_end_matches('/'),
+                encode_query(&settings.org),
+                encode_query(&settings.bucket)
+            ),
+            auth: format!("Token {}", settings.token),
+        })
+    }
+
+    pub fn write_url(&self) -> &str {
+        &self.write_url
+    }
+
+    /// Write newline-separated points (the server answers 204 No Content)
+    pub fn write(&self, lines: &str) -> Result<u16> {
+        webhook::post(
+            &self.write_url,
+            "text/plain; charset=utf-8",
+            Some(&self.auth),
+            lines.as_bytes(),
+        )
+    }
+}
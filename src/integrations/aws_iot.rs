@@ -0,0 +1,129 @@
+//! AWS IoT Core
+//!
+//! Connects to the account's ATS endpoint with the X.509 client certificate
+//! (`mqtt_cert cert`/`key`), reports each reading in the thing's classic
+//! device shadow and applies shadow deltas:
+//!
+//! ```text
+//! $aws/things/<thing>/shadow/update        <- {"state":{"reported":{...}}}
+//! $aws/things/<thing>/shadow/update/delta  -> {"state":{"baud_rate":2400}}
+//! ```
+//!
+//! Supported desired keys: `baud_rate` (applied at once, like `mtu_baud`) and
+//! `min_interval` (`mqtt.min_interval`, saved and applied after reset).
+
+use super::apply_desired;
+use crate::cli::CommandHandler;
+use crate::connectivity::{DownlinkHandler, MeterReading, ReadingReport};
+use crate::network_config::MqttConfig;
+use crate::timekeeping;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+/// Stored under the `aws` config section; disabled while `endpoint` is empty
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AwsIotSettings {
+    /// Account endpoint, e.g. `a1b2c3d4e5f6g7-ats.iot.eu-west-1.amazonaws.com`
+    pub endpoint: heapless::String<96>,
+    /// Thing name, also used as the MQTT client ID (empty = device hostname)
+    pub thing_name: heapless::String<48>,
+}
+
+impl AwsIotSettings {
+    pub fn is_enabled(&self) -> bool {
+        !self.endpoint.is_empty()
+    }
+}
+
+pub struct AwsIot {
+    endpoint: String,
+    thing_name: String,
+}
+
+impl AwsIot {
+    /// None if the integration is not configured
+    pub fn from_settings(settings: &AwsIotSettings, hostname: &str) -> Option<Self> {
+        if !settings.is_enabled() {
+            return None;
+        }
+        let thing_name = if settings.thing_name.is_empty() {
+            hostname
+        } else {
+            settings.thing_name.as_str()
+        };
+        Some(Self {
+            endpoint: settings.endpoint.to_string(),
+            thing_name: thing_name.to_string(),
+        })
+    }
+
+    pub fn thing_name(&self) -> &str {
+        &self.thing_name
+    }
+
+    /// Point the session at the endpoint (MQTT over TLS, port 8883) with the
+    /// thing name as client ID, as AWS IoT policies usually require
+    pub fn apply(&self, config: &mut MqttConfig) {
+        config.broker_url.clear();
+        let _ = config
+            .broker_url
+            .push_str(&format!("mqtts://{}:8883", self.endpoint));
+        config.client_id.clear();
+        let _ = config.client_id.push_str(&self.thing_name);
+        // Authentication is by client certificate only
+        config.username = None;
+        config.password = None;
+    }
+
+    pub fn update_topic(&self) -> String {
+        format!("$aws/things/{}/shadow/update", self.thing_name)
+    }
+
+    pub fn delta_topic(&self) -> String {
+        format!("$aws/things/{}/shadow/update/delta", self.thing_name)
+    }
+
+    /// Shadow update reporting the reading (for `Publisher::with_reading_report`)
+    pub fn reading_report() -> ReadingReport {
+        Arc::new(|reading: &MeterReading| {
+            serde_json::json!({
+                "state": {
+                    "reported": {
+                        "message": reading.message,
+                        "baud_rate": reading.baud_rate,
+                        "framing": reading.framing,
+                        "successful": reading.successful,
+                        "corrupted": reading.corrupted,
+                        "last_reading_at": timekeeping::now_iso8601(),
+                    }
+                }
+            })
+            .to_string()
+        })
+    }
+
+    /// Applies shadow deltas through the CLI; the reply reports the values
+    /// that were applied (publish it to `update_topic`)
+    pub fn delta_handler(&self, handler: Arc<Mutex<CommandHandler>>) -> DownlinkHandler {
+        Arc::new(move |_topic, data| {
+            let delta: serde_json::Value = match serde_json::from_slice(data) {
+                Ok(delta) => delta,
+                Err(e) => {
+                    log::warn!("⚠️  AWS IoT: Invalid shadow delta: {:?}", e);
+                    return None;
+                }
+            };
+            let state = delta.get("state")?.as_object()?;
+            log::info!(
+                "📩 AWS IoT: Shadow delta {}",
+                serde_json::Value::from(state.clone())
+            );
+
+            let reported = apply_desired(&handler, state);
+            if reported.is_empty() {
+                return None;
+            }
+            Some(serde_json::json!({ "state": { "reported": reported } }).to_string())
+        })
+    }
+}
@@ -0,0 +1,210 @@
+//! Azure IoT Hub
+//!
+//! Authenticates with a SAS token derived from the device's symmetric key
+//! (generated for each connection, so the clock must be set via SNTP) and
+//! uses IoT Hub's fixed topic layout:
+//!
+//! ```text
+//! devices/<id>/messages/events/                  <- readings
+//! devices/<id>/messages/events/type=<kind>       <- status, telemetry, availability, responses
+//! devices/<id>/messages/devicebound/#            -> CLI commands (cloud-to-device)
+//! $iothub/twin/PATCH/properties/desired/#        -> desired properties
+//! $iothub/twin/PATCH/properties/reported/?$rid=  <- reported properties
+//! ```
+//!
+//! Supported desired properties are the same as for AWS IoT shadows
+//! (`baud_rate`, `min_interval`).
+
+use super::apply_desired;
+use crate::cli::CommandHandler;
+use crate::connectivity::{DownlinkHandler, LoginProvider, MeterReading, ReadingReport};
+use crate::network_config::{DeviceTopics, MqttConfig};
+use crate::timekeeping;
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// IoT Hub MQTT API version sent in the username
+const API_VERSION: &str = "2021-04-12";
+
+/// Lifetime of each SAS token. On-demand sessions get a new token on every
+/// connect; a persistent session must be reopened within this time.
+const TOKEN_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// Stored under the `azure` config section; disabled while `hub` is empty
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AzureSettings {
+    /// IoT Hub host name, e.g. `water-meters.azure-devices.net`
+    pub hub: heapless::String<64>,
+    /// Device ID registered in the hub (empty = device hostname)
+    pub device_id: heapless::String<48>,
+    /// Device primary key (base64) for SAS tokens
+    pub key: heapless::String<64>,
+}
+
+impl AzureSettings {
+    pub fn is_enabled(&self) -> bool {
+        !self.hub.is_empty()
+    }
+}
+
+pub struct AzureIot {
+    hub: String,
+    device_id: String,
+    key: Vec<u8>,
+}
+
+impl AzureIot {
+    /// None if the integration is not configured or the key is not valid base64
+    pub fn from_settings(settings: &AzureSettings, hostname: &str) -> Option<Self> {
+        if !settings.is_enabled() {
+            return None;
+        }
+        let key = match BASE64.decode(settings.key.as_bytes()) {
+            Ok(key) if !key.is_empty() => key,
+            _ => {
+                log::warn!(
+                    "⚠️  Azure IoT: azure.key is missing or not base64, integration disabled"
+                );
+                return None;
+            }
+        };
+        let device_id = if settings.device_id.is_empty() {
+            hostname
+        } else {
+            settings.device_id.as_str()
+        };
+        Some(Self {
+            hub: settings.hub.to_string(),
+            device_id: device_id.to_string(),
+            key,
+        })
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    /// Point the session at the hub (MQTT over TLS, port 8883) with the device
+    /// ID as client ID; the login comes from `login`
+    pub fn apply(&self, config: &mut MqttConfig) {
+        config.broker_url.clear();
+        let _ = config
+            .broker_url
+            .push_str(&format!("mqtts://{}:8883", self.hub));
+        config.client_id.clear();
+        let _ = config.client_id.push_str(&self.device_id);
+        config.username = None;
+        config.password = None;
+    }
+
+    /// The only topics IoT Hub accepts for this device
+    pub fn topics(&self) -> DeviceTopics {
+        let events = format!("devices/{}/messages/events/", self.device_id);
+        let c2d = format!("devices/{}/messages/devicebound/#", self.device_id);
+        DeviceTopics {
+            readings: events.clone(),
+            status: format!("{}type=status", events),
+            availability: format!("{}type=availability", events),
+            telemetry: format!("{}type=telemetry", events),
+            logs: format!("{}type=logs", events),
+            crash: format!("{}type=crash", events),
+            export: format!("{}type=export", events),
+            relay: format!("{}type=relay", events),
+            control: c2d.clone(),
+            control_device: c2d,
+            response: format!("{}type=response", events),
+            // Settings are synced through the twin's desired properties instead
+            config_desired: String::new(),
+            config_reported: String::new(),
+        }
+    }
+
+    pub fn desired_topic(&self) -> String {
+        "$iothub/twin/PATCH/properties/desired/#".to_string()
+    }
+
+    pub fn reported_topic(&self) -> String {
+        "$iothub/twin/PATCH/properties/reported/?$rid=1".to_string()
+    }
+
+    /// Username and a fresh SAS token for each connection (`Publisher::with_login`)
+    pub fn login(&self) -> LoginProvider {
+        let hub = self.hub.clone();
+        let device_id = self.device_id.clone();
+        let key = self.key.clone();
+        Arc::new(move || {
+            let now = timekeeping::now()
+                .ok_or_else(|| anyhow::anyhow!("Clock not synchronized, cannot sign SAS token"))?;
+            let expiry = (now + TOKEN_TTL).duration_since(UNIX_EPOCH)?.as_secs();
+            let username = format!("{}/{}/?api-version={}", hub, device_id, API_VERSION);
+            let password = sas_token(&format!("{}/devices/{}", hub, device_id), &key, expiry)?;
+            Ok((username, password))
+        })
+    }
+
+    /// Reported properties after each reading (`Publisher::with_reading_report`)
+    pub fn reading_report() -> ReadingReport {
+        Arc::new(|reading: &MeterReading| {
+            serde_json::json!({
+                "baud_rate": reading.baud_rate,
+                "framing": reading.framing,
+                "last_reading_at": timekeeping::now_iso8601(),
+            })
+            .to_string()
+        })
+    }
+
+    /// Applies desired-property patches through the CLI; the reply is the
+    /// reported-properties patch for the applied values
+    pub fn desired_handler(&self, handler: Arc<Mutex<CommandHandler>>) -> DownlinkHandler {
+        Arc::new(move |_topic, data| {
+            let desired: serde_json::Value = match serde_json::from_slice(data) {
+                Ok(desired) => desired,
+                Err(e) => {
+                    log::warn!("⚠️  Azure IoT: Invalid desired properties: {:?}", e);
+                    return None;
+                }
+            };
+            log::info!("📩 Azure IoT: Desired properties {}", desired);
+            let reported = apply_desired(&handler, desired.as_object()?);
+            if reported.is_empty() {
+                return None;
+            }
+            Some(serde_json::Value::from(reported).to_string())
+        })
+    }
+}
+
+/// `SharedAccessSignature sr=<resource>&sig=<HMAC-SHA256>&se=<expiry>`
+fn sas_token(resource: &str, key: &[u8], expiry: u64) -> Result<String> {
+    let resource = url_encode(resource);
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .map_err(|_| anyhow::anyhow!("Invalid SAS key length"))?;
+    mac.update(format!("{}\n{}", resource, expiry).as_bytes());
+    let signature = BASE64.encode(mac.finalize().into_bytes());
+    Ok(format!(
+        "SharedAccessSignature sr={}&sig={}&se={}",
+        resource,
+        url_encode(&signature),
+        expiry
+    ))
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
@@ -0,0 +1,52 @@
+//! Cloud IoT platform integrations
+//!
+//! Each helper adapts the generic MQTT publish path (`Publisher`) to a
+//! platform's endpoint, authentication and topic conventions.
+
+pub mod aws_iot;
+pub mod azure;
+
+pub use aws_iot::{AwsIot, AwsIotSettings};
+pub use azure::{AzureIot, AzureSettings};
+
+use crate::cli::remote::execute_authorized;
+use crate::cli::CommandHandler;
+use std::sync::{Arc, Mutex};
+
+/// Apply desired settings from a device shadow / twin through the CLI:
+/// `baud_rate` at once (`mtu_baud`), `min_interval` saved for the next boot
+/// (`mqtt.min_interval`). Returns the keys that were applied, to be reported.
+/// The platform decides who may write the desired state, so these run as an
+/// authorized caller.
+pub(crate) fn apply_desired(
+    handler: &Arc<Mutex<CommandHandler>>,
+    desired: &serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut applied = serde_json::Map::new();
+    for (key, value) in desired {
+        let commands: &[String] = match (key.as_str(), value.as_u64()) {
+            ("baud_rate", Some(baud_rate)) => &[format!("mtu_baud {}", baud_rate)],
+            ("min_interval", Some(secs)) => &[
+                format!("config set mqtt.min_interval {}", secs),
+                "config save".to_string(),
+            ],
+            // Metadata such as Azure's "$version"
+            _ if key.starts_with('$') => continue,
+            _ => {
+                log::warn!("⚠️  Unsupported desired setting '{}'", key);
+                continue;
+            }
+        };
+        let ok = commands.iter().all(|command| {
+            let (ok, detail) = execute_authorized(handler, command);
+            if !ok {
+                log::warn!("⚠️  '{}' failed: {}", command, detail);
+            }
+            ok
+        });
+        if ok {
+            applied.insert(key.clone(), value.clone());
+        }
+    }
+    applied
+}
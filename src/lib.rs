@@ -2,21 +2,67 @@
 //!
 //! This library provides modules for ESP32-based water meter MTU communication.
 
+pub mod ble_readout;
+pub mod button;
+pub mod cellular;
+pub mod chip;
 pub mod cli;
+pub mod coap;
+pub mod config_store;
+pub mod config_sync;
+pub mod connectivity;
+pub mod crash;
+pub mod device;
+pub mod display;
+pub mod espnow;
+pub mod ethernet;
+pub mod events;
+pub mod http_server;
+pub mod influxdb;
+pub mod integrations;
+pub mod logging;
 pub mod meter;
+pub mod meter_manager;
+pub mod modbus;
 pub mod mqtt;
 pub mod mtu;
+pub mod network;
 pub mod network_config;
+pub mod ota;
+pub mod payloads;
+pub mod power;
+pub mod role;
+pub mod status_led;
+pub mod storage;
+pub mod telemetry;
+pub mod timekeeping;
+pub mod watchdog;
+pub mod webhook;
 pub mod wifi;
 
+#[cfg(all(feature = "usb-console", not(any(esp32s3, esp32c3))))]
+compile_error!("The usb-console feature needs an ESP32-S3 or ESP32-C3 target (USB-Serial-JTAG)");
+
 pub use cli::{
     CliCommand, CliError, CommandHandler, CommandParser, MeterCommand, MeterCommandHandler,
     MeterCommandParser, Terminal,
 };
-pub use meter::{MeterConfig, MeterHandler, MeterType};
-pub use mqtt::{MqttClient, MqttStatus};
+pub use config_store::{ConfigStore, DeviceConfig, DeviceSettings, MtuSettings, NetworkSettings};
+pub use connectivity::{LoginProvider, MeterReading, Publisher, ReadingReport};
+pub use ethernet::EthernetManager;
+pub use events::{DeviceEvent, EventBus};
+pub use meter::{MeterConfig, MeterHandler, MeterStorage, MeterType};
+pub use mqtt::{DeliveryStatus, MqttClient, MqttSessionOptions, MqttStatus, TopicRouter};
 pub use mtu::{
     GpioMtu, GpioMtuTimer, GpioMtuTimerV2, MtuCommand, MtuConfig, MtuError, MtuResult, UartFraming,
 };
-pub use network_config::{MqttConfig, MtuMqttTopics, WifiConfig};
-pub use wifi::WifiManager;
+pub use network::NetworkLink;
+pub use network_config::{
+    DeviceTopics, MqttConfig, MqttTlsConfig, MtuMqttTopics, NetworkTransport, Uplink, WifiConfig,
+};
+pub use payloads::{
+    CrashPayload, PayloadFormat, ReadingPayload, StatusPayload, TelemetryPayload,
+    PAYLOAD_SCHEMA_VERSION,
+};
+pub use role::{DeviceRole, RoleStore};
+pub use wifi::{WifiCredentialStore, WifiManager};
@@ -0,0 +1,2 @@
+// Bindings for the LittleFS component (esp_idf_sys::littlefs)
+#include "esp_littlefs.h"
@@ -0,0 +1,201 @@
+//! Persistent log ring buffer
+//!
+//! Everything logged at info level and above is printed by the ESP-IDF
+//! logger as before and also kept in a size-bounded RAM ring (oldest lines
+//! dropped first). `LogStore` saves the ring to NVS at most every 15 minutes
+//! when it changed and restores it at boot, so the lines leading up to a
+//! reset survive it. Shown by `log show`; `log upload` publishes it to the logs
+//! topic with the next MQTT session.
+
+use anyhow::Result;
+use esp_idf_svc::log::EspLogger;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Bytes of log text kept (RAM and NVS)
+pub const LOG_BUFFER_SIZE: usize = 4096;
+
+pub const LOG_NVS_NAMESPACE: &str = "logs";
+const LOG_NVS_KEY: &str = "ring";
+
+/// Minimum time between NVS writes; bounds flash wear from chatty logging
+const FLUSH_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Log lines are truncated to this length in the ring
+const MAX_LINE_LEN: usize = 160;
+
+struct Ring {
+    text: String,
+    /// Changed since the last NVS write
+    dirty: bool,
+}
+
+static RING: Mutex<Ring> = Mutex::new(Ring {
+    text: String::new(),
+    dirty: false,
+});
+
+static UPLOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+static ESP_LOGGER: EspLogger = EspLogger::new();
+static LOGGER: RingLogger = RingLogger;
+
+/// Forwards to the ESP-IDF logger and records info and above in the ring
+struct RingLogger;
+
+impl log::Log for RingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        log::Log::enabled(&ESP_LOGGER, metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        log::Log::log(&ESP_LOGGER, record);
+        if record.level() > log::Level::Info || !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut line = format!(
+            "[{}] {} {}",
+            crate::telemetry::uptime_secs(),
+            level_letter(record.level()),
+            record.args()
+        );
+        if line.len() > MAX_LINE_LEN {
+            let mut end = MAX_LINE_LEN;
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            line.truncate(end);
+        }
+        line.push('\n');
+        // Never block a logging thread behind one that is reading the ring
+        if let Ok(mut ring) = RING.try_lock() {
+            append(&mut ring.text, &line);
+            ring.dirty = true;
+        }
+    }
+
+    fn flush(&self) {
+        log::Log::flush(&ESP_LOGGER);
+    }
+}
+
+fn level_letter(level: log::Level) -> char {
+    match level {
+        log::Level::Error => 'E',
+        log::Level::Warn => 'W',
+        log::Level::Info => 'I',
+        log::Level::Debug => 'D',
+        log::Level::Trace => 'V',
+    }
+}
+
+/// Append `text`, then drop whole lines from the front until it fits
+fn append(ring: &mut String, text: &str) {
+    ring.push_str(text);
+    if ring.len() <= LOG_BUFFER_SIZE {
+        return;
+    }
+    let excess = ring.len() - LOG_BUFFER_SIZE;
+    let cut = match ring[excess..].find('\n') {
+        Some(pos) => excess + pos + 1,
+        None => ring.len(),
+    };
+    ring.drain(..cut);
+}
+
+/// Install the logger; replaces `EspLogger::initialize_default()`
+pub fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        ESP_LOGGER.initialize();
+    }
+}
+
+/// Contents of the ring, oldest line first
+pub fn contents() -> String {
+    RING.lock()
+        .map(|ring| ring.text.clone())
+        .unwrap_or_default()
+}
+
+/// Empty the ring (the NVS copy is cleared with the next flush)
+pub fn clear() {
+    if let Ok(mut ring) = RING.lock() {
+        ring.text.clear();
+        ring.dirty = true;
+    }
+}
+
+/// Ask the publisher to upload the ring with the next MQTT session
+pub fn request_upload() {
+    UPLOAD_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// True once per `request_upload`
+pub fn take_upload_request() -> bool {
+    UPLOAD_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// NVS copy of the ring
+pub struct LogStore {
+    nvs: EspNvs<NvsDefault>,
+    next_flush: Instant,
+}
+
+impl LogStore {
+    /// Open the store and put the lines saved before this boot in front of
+    /// those logged since
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+        let nvs = EspNvs::new(partition, LOG_NVS_NAMESPACE, true)?;
+        let mut buf = vec![0u8; LOG_BUFFER_SIZE];
+        let saved = nvs
+            .get_blob(LOG_NVS_KEY, &mut buf)?
+            .map(|data| String::from_utf8_lossy(data).into_owned())
+            .unwrap_or_default();
+
+        if let Ok(mut ring) = RING.lock() {
+            let mut text = saved;
+            if !text.is_empty() {
+                text.push_str("--- reboot ---\n");
+            }
+            let since_boot = core::mem::take(&mut ring.text);
+            append(&mut text, &since_boot);
+            ring.text = text;
+        }
+        Ok(Self {
+            nvs,
+            next_flush: Instant::now() + FLUSH_INTERVAL,
+        })
+    }
+
+    /// Call regularly from the main loop; writes the ring to NVS at most
+    /// every `FLUSH_INTERVAL`
+    pub fn poll(&mut self) {
+        if Instant::now() >= self.next_flush {
+            self.next_flush = Instant::now() + FLUSH_INTERVAL;
+            if let Err(e) = self.flush() {
+                log::warn!("⚠️  Log buffer save failed: {:?}", e);
+            }
+        }
+    }
+
+    /// Write the ring to NVS now if it changed (before a restart or deep sleep)
+    pub fn flush(&mut self) -> Result<()> {
+        // Copy out first: logging while holding the lock would drop the line
+        let text = match RING.lock() {
+            Ok(mut ring) if ring.dirty => {
+                ring.dirty = false;
+                ring.text.clone()
+            }
+            _ => return Ok(()),
+        };
+        if text.is_empty() {
+            self.nvs.remove(LOG_NVS_KEY)?;
+        } else {
+            self.nvs.set_blob(LOG_NVS_KEY, text.as_bytes())?;
+        }
+        Ok(())
+    }
+}
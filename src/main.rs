@@ -1,35 +1,81 @@
-use esp32_water_meter::cli::{CommandHandler, CommandParser, Terminal};
-use esp32_water_meter::mqtt::MqttClient;
-use esp32_water_meter::mtu::{GpioMtuTimerV2, MtuCommand, MtuConfig};
-use esp32_water_meter::wifi::WifiManager;
+use esp32_water_meter::ble_readout::BleReadout;
+use esp32_water_meter::button::{Button, ButtonEvent};
+use esp32_water_meter::cellular::{CellularModem, CellularPins, CELLULAR_GPIOS};
+use esp32_water_meter::cli::telnet::{spawn_telnet_server, TELNET_PORT};
+use esp32_water_meter::cli::wizard::{self, SetupOutcome};
+#[cfg(not(feature = "usb-console"))]
+use esp32_water_meter::cli::UartIo;
+#[cfg(feature = "usb-console")]
+use esp32_water_meter::cli::UsbSerialIo;
+use esp32_water_meter::cli::{cli_downlink_handler, CommandHandler, CommandParser, Terminal};
+use esp32_water_meter::coap::CoapClient;
+use esp32_water_meter::config_store::{ConfigStore, DeviceConfig};
+use esp32_water_meter::config_sync::config_sync_handler;
+use esp32_water_meter::connectivity::{FailoverLink, MeterReading, Publisher};
+use esp32_water_meter::crash::{self, CrashStore};
+use esp32_water_meter::device;
+use esp32_water_meter::display::{Display, DisplayType, DISPLAY_GPIOS};
+use esp32_water_meter::espnow::{EspNowGateway, EspNowNode, EspNowRole};
+#[cfg(not(feature = "usb-console"))]
+use esp32_water_meter::ethernet::EthernetPins;
+use esp32_water_meter::ethernet::{EthernetManager, ETHERNET_GPIOS};
+use esp32_water_meter::events::{DeviceEvent, EventBus};
+use esp32_water_meter::http_server::HttpApi;
+use esp32_water_meter::influxdb::InfluxDb;
+use esp32_water_meter::integrations::{AwsIot, AzureIot};
+use esp32_water_meter::logging::{self, LogStore};
+use esp32_water_meter::meter_manager::MeterManager;
+use esp32_water_meter::modbus::{ModbusSlave, MODBUS_GPIOS};
+use esp32_water_meter::mtu::{register_value, GpioMtuTimerV2, MtuCommand, MtuConfig};
+use esp32_water_meter::network::NetworkLink;
+use esp32_water_meter::network_config::{ConnectivityMode, NetworkTransport, Uplink, WifiConfig};
+use esp32_water_meter::ota::HealthCheck;
+use esp32_water_meter::power::{self, PowerMode, SleepState};
+use esp32_water_meter::status_led::StatusLed;
+use esp32_water_meter::storage::{DataLog, StorageBackend, SD_GPIOS};
+use esp32_water_meter::telemetry;
+use esp32_water_meter::watchdog;
+use esp32_water_meter::webhook::Webhook;
+use esp32_water_meter::wifi::{
+    ble_device_name, BleProvisioning, ConnectProgress, ProvisioningPortal, WifiCredentialStore,
+    WifiManager,
+};
 use esp_idf_hal::delay::FreeRtos;
 use esp_idf_hal::gpio::{Input, Output, PinDriver};
 use esp_idf_hal::peripherals::Peripherals;
+#[cfg(not(feature = "usb-console"))]
 use esp_idf_hal::uart::{config::Config as UartConfig, UartDriver};
+#[cfg(feature = "usb-console")]
+use esp_idf_hal::usb_serial::{UsbSerialConfig, UsbSerialDriver};
 use esp_idf_svc::eventloop::EspSystemEventLoop;
-use esp_idf_svc::mqtt::client::QoS;
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
 use esp_idf_svc::sys;
 use std::sync::{Arc, Mutex};
-
-/// Get ESP32 base MAC address (chip ID) as a hex string
-fn get_chip_id() -> String {
-    let mut mac = [0u8; 6];
-    unsafe {
-        sys::esp_efuse_mac_get_default(mac.as_mut_ptr());
-    }
-    format!(
-        "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
-        mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
-    )
-}
+use std::time::{Duration, Instant};
+
+/// Minimum time between telemetry publishes (sent with the next session after that)
+const TELEMETRY_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Port of the local REST API (the provisioning portal uses it when no network is configured)
+const HTTP_API_PORT: u16 = 80;
+/// New firmware must reach the network and read the meter within this time or it is rolled back
+const OTA_HEALTH_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Self-test MTU read while an OTA health check is pending (retried until it succeeds)
+const OTA_SELF_TEST_SECS: u64 = 30;
+const OTA_SELF_TEST_INTERVAL: Duration = Duration::from_secs(2 * 60);
+/// Scheduled MTU read after each wake in deep-sleep mode
+const SCHEDULED_READ_SECS: u64 = 30;
+/// Go back to sleep even if the scheduled read never completed
+const MAX_AWAKE_TIME: Duration = Duration::from_secs(3 * 60);
+/// Console input keeps a deep-sleeping device awake this long after the last key
+const CONSOLE_AWAKE_TIME: Duration = Duration::from_secs(2 * 60);
 
 fn main() -> anyhow::Result<()> {
     // Initialize ESP-IDF system services
     sys::link_patches();
 
-    // Initialize logging
-    esp_idf_svc::log::EspLogger::initialize_default();
+    // Initialize logging (ESP-IDF console plus the persistent ring buffer)
+    logging::init();
+    crash::install_panic_hook();
 
     log::info!("ESP32 Water Meter MTU Interface with CLI");
     log::info!("Initializing...");
@@ -39,53 +85,317 @@ fn main() -> anyhow::Result<()> {
     log::info!("✅ ESP32 initialized with ESP-IDF");
 
     // Get unique chip ID for device-specific MQTT topics
-    let chip_id = get_chip_id();
+    let chip_id = device::chip_id();
     log::info!("📟 Chip ID: {}", chip_id);
+    log::info!("📟 Firmware: {}", device::build_info());
 
     // Initialize system event loop and NVS for WiFi
     let sysloop = EspSystemEventLoop::take()?;
     let nvs = EspDefaultNvsPartition::take()?;
 
-    // WiFi Configuration
-    const WIFI_SSID: &str = "Ian Storrs 1";
-    const WIFI_PASSWORD: &str = "abbaabba";
+    // Log lines saved before the last reset ('log show')
+    let mut log_store = match LogStore::new(nvs.clone()) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            log::warn!("⚠️  Log buffer not persistent: {:?}", e);
+            None
+        }
+    };
 
-    // MQTT Configuration - Mosquitto public test broker
-    const MQTT_BROKER: &str = "mqtt://test.mosquitto.org:1883";
-    const MQTT_PUBLISH_TOPIC: &str = "istorrs/mtu/data";
-    const MQTT_CONTROL_TOPIC_SHARED: &str = "istorrs/mtu/control"; // Shared topic for broadcast commands
+    // Load WiFi/MQTT/MTU configuration from NVS (defaults if never saved)
+    let mut config_store = match ConfigStore::new(nvs.clone()) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            log::warn!("⚠️  Config store unavailable, using defaults: {:?}", e);
+            None
+        }
+    };
+    let boot_count = match config_store.as_mut().map(|s| s.record_boot()) {
+        Some(Ok(count)) => count,
+        Some(Err(e)) => {
+            log::warn!("⚠️  Failed to update boot counter: {:?}", e);
+            0
+        }
+        None => 0,
+    };
+    log::info!(
+        "🔄 Boot #{} (reset reason: {})",
+        boot_count,
+        telemetry::reset_reason()
+    );
 
-    // Device-specific MQTT topics based on chip ID
-    let mqtt_client_id = format!("esp32-mtu-{}", chip_id.replace(":", ""));
-    let mqtt_control_topic_device = format!("istorrs/mtu/{}/control", chip_id);
+    // Panic or watchdog reset on the previous boot: keep the report until it is published
+    let crash_store = match CrashStore::new(nvs.clone()) {
+        Ok(mut store) => {
+            if let Err(e) = store.record_boot(boot_count) {
+                log::warn!("⚠️  Failed to save crash report: {:?}", e);
+            }
+            Some(store)
+        }
+        Err(e) => {
+            log::warn!("⚠️  Crash store unavailable: {:?}", e);
+            None
+        }
+    };
+
+    let loaded_config = config_store.as_ref().map(|s| s.load());
+    let first_boot = matches!(loaded_config, Some(Ok(None)));
+    let device_config = match loaded_config {
+        Some(Ok(Some(config))) => {
+            log::info!("✅ Configuration loaded from NVS");
+            config
+        }
+        Some(Err(e)) => {
+            log::warn!("⚠️  Failed to load configuration, using defaults: {:?}", e);
+            DeviceConfig::default()
+        }
+        _ => {
+            log::info!("Using default configuration (use 'config set'/'config save' to change)");
+            DeviceConfig::default()
+        }
+    };
+    log::info!(
+        "📟 Device name: {}",
+        device::name(&device_config.device.name)
+    );
+    if device_config.mqtt.is_tls() {
+        log::info!("🔐 MQTT over TLS");
+    }
+
+    // Task watchdog for the main loop and the MTU thread (both subscribe later)
+    if let Err(e) = watchdog::configure(&device_config.watchdog) {
+        log::warn!("⚠️  Watchdog configuration failed: {:?}", e);
+    }
+
+    // Long button press on the previous boot: provision even with saved networks
+    let force_provisioning = match config_store.as_mut().map(|s| s.take_provisioning_request()) {
+        Some(Ok(requested)) => requested,
+        Some(Err(e)) => {
+            log::warn!("⚠️  Failed to read provisioning request: {:?}", e);
+            false
+        }
+        None => false,
+    };
+
+    // Azure IoT Hub only accepts its own topic layout
+    let azure = AzureIot::from_settings(&device_config.azure, &device_config.device.hostname);
+
+    // MQTT topics ({chip_id}/{hostname} placeholders filled in)
+    let topics = match azure {
+        Some(ref azure) => azure.topics(),
+        None => device_config
+            .topics
+            .expand(&chip_id, &device_config.device.hostname),
+    };
+
+    // Per-device client ID based on chip ID
+    let mqtt_client_id = format!(
+        "{}-{}",
+        device_config.mqtt.client_id,
+        chip_id.replace(":", "")
+    );
 
     log::info!("📡 MQTT Client ID: {}", mqtt_client_id);
+
+    // Session settings: stored MQTT config with the per-device client ID and availability topic
+    let mut mqtt_config = device_config.mqtt.clone();
+    mqtt_config.client_id.clear();
+    let _ = mqtt_config.client_id.push_str(&mqtt_client_id);
+    mqtt_config.availability_topic = Some(topics.availability.clone());
+
+    // AWS IoT Core / Azure IoT Hub: endpoint and device identity replace the broker and client ID
+    let aws_iot = match azure {
+        Some(_) => None,
+        None => AwsIot::from_settings(&device_config.aws, &device_config.device.hostname),
+    };
+    if let Some(ref azure) = azure {
+        azure.apply(&mut mqtt_config);
+        log::info!(
+            "☁️  Azure IoT Hub: device '{}' at {}",
+            azure.device_id(),
+            mqtt_config.broker_url
+        );
+    }
+    if let Some(ref aws) = aws_iot {
+        aws.apply(&mut mqtt_config);
+        log::info!(
+            "☁️  AWS IoT: thing '{}' at {}",
+            aws.thing_name(),
+            mqtt_config.broker_url
+        );
+    }
+    log::info!("📡 MQTT Readings Topic: {}", topics.readings);
     log::info!("📡 MQTT Control Topics:");
-    log::info!("   Shared:  {}", MQTT_CONTROL_TOPIC_SHARED);
-    log::info!("   Device:  {}", mqtt_control_topic_device);
+    log::info!("   Shared:  {}", topics.control);
+    log::info!("   Device:  {}", topics.control_device);
+    log::info!("📡 MQTT Response Topic: {}", topics.response);
+    log::info!("📡 MQTT Status Topic: {}", topics.status);
+    log::info!("📡 MQTT Availability Topic: {}", topics.availability);
+    log::info!("📡 MQTT Telemetry Topic: {}", topics.telemetry);
+    log::info!("📡 MQTT Logs Topic: {}", topics.logs);
+    log::info!("📡 MQTT Crash Topic: {}", topics.crash);
+    log::info!("📡 MQTT Export Topic: {}", topics.export);
+    log::info!("📡 MQTT Relay Topic: {}", topics.relay);
+    log::info!("📡 MQTT Config Desired Topic: {}", topics.config_desired);
+    log::info!("📡 MQTT Config Reported Topic: {}", topics.config_reported);
+
+    // HTTP webhook, InfluxDB or CoAP instead of MQTT for readings
+    let webhook = match device_config.network.uplink {
+        Uplink::Webhook => {
+            let webhook = Webhook::from_settings(&device_config.webhook);
+            match webhook {
+                Some(ref webhook) => log::info!("🌐 Uplink: Webhook POST to {}", webhook.url()),
+                None => log::warn!(
+                    "⚠️  network.uplink is webhook but webhook.url is not set, using MQTT"
+                ),
+            }
+            webhook
+        }
+        _ => None,
+    };
+    let influxdb = match device_config.network.uplink {
+        Uplink::Influxdb => {
+            let influxdb = InfluxDb::from_settings(&device_config.influx);
+            match influxdb {
+                Some(ref influxdb) => {
+                    log::info!("🌐 Uplink: InfluxDB write to {}", influxdb.write_url())
+                }
+                None => log::warn!(
+                    "⚠️  network.uplink is influxdb but influx.url/org/bucket/token are not all set, using MQTT"
+                ),
+            }
+            influxdb
+        }
+        _ => None,
+    };
+    let coap = match device_config.network.uplink {
+        Uplink::Coap => {
+            let coap = CoapClient::from_settings(&device_config.coap);
+            match coap {
+                Some(ref coap) => log::info!("🌐 Uplink: CoAP POST to {}", coap.url()),
+                None => {
+                    log::warn!("⚠️  network.uplink is coap but coap.url is not set, using MQTT")
+                }
+            }
+            coap
+        }
+        _ => None,
+    };
+    let mqtt_uplink = webhook.is_none() && influxdb.is_none() && coap.is_none();
+
+    // WiFi networks saved with 'wifi_save' (encrypted)
+    let wifi_credentials = match WifiCredentialStore::new(nvs.clone()) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            log::warn!("⚠️  WiFi credential store unavailable: {:?}", e);
+            None
+        }
+    };
+    let wifi_networks = match wifi_credentials.as_ref().map(|store| store.load()) {
+        Some(Ok(Some(networks))) => networks,
+        Some(Err(e)) => {
+            log::warn!("⚠️  Failed to load WiFi networks: {:?}", e);
+            WifiConfig::default()
+        }
+        _ => WifiConfig::default(),
+    };
 
-    // Initialize WiFi manager but don't connect yet (on-demand connection)
-    let wifi = if WIFI_SSID != "YOUR_SSID" {
-        log::info!("🌐 Initializing WiFi manager (on-demand mode)...");
-        log::info!("  SSID: {}", WIFI_SSID);
-        log::info!("  Password length: {} chars", WIFI_PASSWORD.len());
+    // Initialize UART0 for CLI (USB-C connection)
+    #[cfg(not(feature = "usb-console"))]
+    log::info!("Initializing UART0 for CLI (USB-C)...");
+    #[cfg(not(feature = "usb-console"))]
+    let uart_config = UartConfig::new().baudrate(115200.into());
+    #[cfg(not(feature = "usb-console"))]
+    let mut uart = UartDriver::new(
+        peripherals.uart0,
+        peripherals.pins.gpio1, // TX (U0TXD)
+        peripherals.pins.gpio3, // RX (U0RXD)
+        Option::<esp_idf_hal::gpio::Gpio0>::None,
+        Option::<esp_idf_hal::gpio::Gpio0>::None,
+        &uart_config,
+    )?;
+
+    // Split UART into tx and rx drivers
+    #[cfg(not(feature = "usb-console"))]
+    let mut console_io = {
+        let (tx, rx) = uart.split();
+        log::info!("✅ UART0 initialized (115200 baud)");
+        UartIo { tx, rx }
+    };
+
+    // Built-in USB-Serial-JTAG port for CLI (ESP32-S3/C3 devkits without a UART bridge)
+    #[cfg(feature = "usb-console")]
+    let mut console_io = {
+        log::info!("Initializing USB-Serial-JTAG for CLI...");
+        #[cfg(esp32s3)]
+        let (d_minus, d_plus) = (peripherals.pins.gpio19, peripherals.pins.gpio20);
+        #[cfg(esp32c3)]
+        let (d_minus, d_plus) = (peripherals.pins.gpio18, peripherals.pins.gpio19);
+        let usb = UsbSerialDriver::new(
+            peripherals.usb_serial,
+            d_minus,
+            d_plus,
+            &UsbSerialConfig::new(),
+        )?;
+        log::info!("✅ USB-Serial-JTAG initialized");
+        UsbSerialIo { usb }
+    };
+
+    // First boot (nothing saved yet): ask for WiFi and broker on the console
+    // before falling back to the provisioning portal
+    if first_boot && wifi_networks.is_empty() && !force_provisioning {
+        match wizard::run(&mut console_io, &nvs) {
+            Ok(SetupOutcome::Saved) => {
+                FreeRtos::delay_ms(500);
+                unsafe { sys::esp_restart() };
+            }
+            Ok(SetupOutcome::Skipped) => {}
+            Err(e) => log::warn!("⚠️  Guided setup failed: {:?}", e),
+        }
+    }
+
+    // Without saved networks, run the SoftAP setup portal and BLE provisioning
+    // instead (both kept alive while held)
+    let mut provisioning = None;
+    let mut ble_provisioning = None;
+
+    // ESP-NOW node: readings go to a gateway, the station never joins a network
+    let espnow_role = device_config.espnow.role;
+    let mut relay_node = None;
 
-        match WifiManager::new(
+    // Initialize WiFi manager but don't connect yet (on-demand connection)
+    let use_ethernet = device_config.network.transport == NetworkTransport::Ethernet;
+    let wifi = if use_ethernet {
+        log::info!("🔌 WiFi disabled (network.transport = ethernet)");
+        if espnow_role != EspNowRole::Off {
+            log::warn!("⚠️  ESP-NOW needs network.transport wifi, relay disabled");
+        }
+        None
+    } else if espnow_role == EspNowRole::Node {
+        log::info!("📡 WiFi station unused (espnow.role = node)");
+        match EspNowNode::start(
+            &device_config.espnow,
             peripherals.modem,
             sysloop.clone(),
             nvs.clone(),
-            WIFI_SSID,
-            WIFI_PASSWORD,
         ) {
+            Ok(node) => relay_node = Some(node),
+            Err(e) => log::error!("❌ ESP-NOW node failed to start: {:?}", e),
+        }
+        None
+    } else if !wifi_networks.is_empty() && !force_provisioning {
+        log::info!("🌐 Initializing WiFi manager (on-demand mode)...");
+        for network in wifi_networks.by_priority() {
+            log::info!("  SSID: {} (priority {})", network.ssid, network.priority);
+        }
+
+        match WifiManager::new(peripherals.modem, sysloop.clone(), nvs.clone()) {
             Ok(mut wifi) => {
+                // Not connected yet: connects on-demand for MQTT publish
                 log::info!("✅ WiFi manager created");
-
-                // Disconnect immediately for on-demand usage
-                log::info!("🔌 Disconnecting WiFi (will reconnect on-demand for MQTT publish)");
-                if let Err(e) = wifi.disconnect() {
-                    log::warn!("⚠️  WiFi disconnect failed: {:?}", e);
+                if let Err(e) = wifi.set_hostname(&device_config.device.hostname) {
+                    log::warn!("⚠️  Failed to set hostname: {:?}", e);
                 }
-
                 Some(Arc::new(Mutex::new(wifi)))
             }
             Err(e) => {
@@ -99,26 +409,121 @@ fn main() -> anyhow::Result<()> {
             }
         }
     } else {
-        log::info!("WiFi disabled (update WIFI_SSID/WIFI_PASSWORD to enable)");
+        if force_provisioning {
+            log::info!("📶 Provisioning requested (button) - starting provisioning portal...");
+        } else {
+            log::info!("📶 No WiFi networks saved - starting provisioning portal...");
+        }
+        match ProvisioningPortal::start(peripherals.modem, sysloop.clone(), nvs.clone()) {
+            Ok(portal) => {
+                log::info!(
+                    "✅ Provisioning portal: join '{}' and open http://{}",
+                    portal.ap_ssid(),
+                    portal.ap_ip()
+                );
+                provisioning = Some(portal);
+            }
+            Err(e) => {
+                log::error!("❌ Provisioning portal failed: {:?}", e);
+                log::info!(
+                    "WiFi disabled (use 'wifi_save <ssid> <password>' and 'reset' to enable)"
+                );
+            }
+        }
+        match BleProvisioning::start(nvs.clone(), device_config.ble.passkey) {
+            Ok(ble) => ble_provisioning = Some(ble),
+            Err(e) => log::error!("❌ BLE provisioning failed: {:?}", e),
+        }
         None
     };
 
-    // Initialize UART0 for CLI (USB-C connection)
-    log::info!("Initializing UART0 for CLI (USB-C)...");
-    let uart_config = UartConfig::new().baudrate(115200.into());
-    let mut uart = UartDriver::new(
-        peripherals.uart0,
-        peripherals.pins.gpio1, // TX (U0TXD)
-        peripherals.pins.gpio3, // RX (U0RXD)
-        Option::<esp_idf_hal::gpio::Gpio0>::None,
-        Option::<esp_idf_hal::gpio::Gpio0>::None,
-        &uart_config,
-    )?;
-
-    // Split UART into tx and rx drivers
-    let (uart_tx, uart_rx) = uart.split();
+    // Link used by the publish path: the W5500 module, or the WiFi manager
+    // and/or the cellular modem
+    let mut use_cellular = false;
+    let network: Option<Arc<Mutex<dyn NetworkLink + Send>>> = if use_ethernet {
+        // The W5500 SPI clock and MISO pins are the USB-Serial-JTAG data lines
+        #[cfg(feature = "usb-console")]
+        let ethernet: anyhow::Result<EthernetManager> =
+            Err(anyhow::anyhow!("W5500 pins are used by the USB console"));
+        #[cfg(not(feature = "usb-console"))]
+        let ethernet = {
+            let pins = EthernetPins {
+                sclk: peripherals.pins.gpio18,
+                mosi: peripherals.pins.gpio23,
+                miso: peripherals.pins.gpio19,
+                cs: peripherals.pins.gpio27,
+                int: peripherals.pins.gpio26,
+                rst: peripherals.pins.gpio25,
+            };
+            EthernetManager::new(peripherals.spi2, pins, sysloop.clone())
+        };
+        match ethernet {
+            Ok(eth) => Some(Arc::new(Mutex::new(eth))),
+            Err(e) => {
+                log::error!("❌ Ethernet initialization failed: {:?}", e);
+                log::warn!("⚠️  Check the W5500 wiring or use 'config set network.transport wifi'");
+                None
+            }
+        }
+    } else {
+        // LTE-M/NB-IoT modem as the fallback when WiFi can't connect
+        let cellular_pin_taken = [device_config.button.gpio, device_config.led.gpio]
+            .iter()
+            .flatten()
+            .any(|gpio| CELLULAR_GPIOS.contains(gpio));
+        let cellular = if device_config.cellular.apn.is_empty() || espnow_role == EspNowRole::Node {
+            None
+        } else if cellular_pin_taken {
+            log::warn!("⚠️  Cellular: Button or LED GPIO is used by the modem, cellular disabled");
+            None
+        } else {
+            let pins = CellularPins {
+                tx: peripherals.pins.gpio27,
+                rx: peripherals.pins.gpio26,
+                pwrkey: peripherals.pins.gpio25,
+            };
+            match CellularModem::new(&device_config.cellular, peripherals.uart1, pins) {
+                Ok(modem) => {
+                    use_cellular = true;
+                    Some(Arc::new(Mutex::new(modem)) as Arc<Mutex<dyn NetworkLink + Send>>)
+                }
+                Err(e) => {
+                    log::error!("❌ Cellular modem initialization failed: {:?}", e);
+                    None
+                }
+            }
+        };
+        let wifi_link = wifi
+            .clone()
+            .map(|wifi| wifi as Arc<Mutex<dyn NetworkLink + Send>>);
+        match (wifi_link, cellular) {
+            (Some(wifi), Some(cellular)) => {
+                log::info!("📶 Network: WiFi first, cellular fallback");
+                Some(Arc::new(Mutex::new(FailoverLink::new(wifi, cellular)))
+                    as Arc<Mutex<dyn NetworkLink + Send>>)
+            }
+            (None, Some(cellular)) => {
+                log::info!("📶 Network: Cellular only (no WiFi)");
+                Some(cellular)
+            }
+            (wifi, None) => wifi,
+        }
+    };
 
-    log::info!("✅ UART0 initialized (115200 baud)");
+    // ESP-NOW gateway: publishes the nodes' readings over the persistent session
+    let mut relay_gateway = None;
+    if espnow_role == EspNowRole::Gateway && wifi.is_some() {
+        if device_config.network.mode != ConnectivityMode::Persistent {
+            log::warn!("⚠️  ESP-NOW gateway needs network.mode persistent, relay disabled");
+        } else if !mqtt_uplink {
+            log::warn!("⚠️  ESP-NOW gateway needs network.uplink mqtt, relay disabled");
+        } else {
+            match EspNowGateway::start(&device_config.espnow) {
+                Ok(gateway) => relay_gateway = Some(gateway),
+                Err(e) => log::error!("❌ ESP-NOW gateway failed to start: {:?}", e),
+            }
+        }
+    }
 
     // Initialize GPIO pins for MTU
     // Using GPIO4 for clock output and GPIO5 for data input
@@ -144,7 +549,8 @@ fn main() -> anyhow::Result<()> {
     let timer = peripherals.timer00;
 
     // Create MTU instance with default config
-    let config = MtuConfig::default();
+    let mut config = MtuConfig::default();
+    device_config.mtu.apply(&mut config);
     let mtu = Arc::new(GpioMtuTimerV2::new(config));
 
     log::info!("✅ MTU GPIO pins configured");
@@ -160,21 +566,244 @@ fn main() -> anyhow::Result<()> {
 
     log::info!("✅ MTU background thread spawned");
 
-    // MQTT will be created on-demand when publishing data
-    log::info!("📡 MQTT: On-demand mode (will connect only when publishing)");
+    // Manual read button (also wakes from deep sleep)
+    let mut button = device_config.button.gpio.and_then(|gpio| {
+        if use_ethernet && ETHERNET_GPIOS.contains(&gpio) {
+            log::warn!(
+                "⚠️  Button GPIO{} is used by the W5500 module, disabled",
+                gpio
+            );
+            return None;
+        }
+        match Button::new(gpio) {
+            Ok(button) => Some(button),
+            Err(e) => {
+                log::warn!("⚠️  Button unavailable: {:?}", e);
+                None
+            }
+        }
+    });
+
+    // Device events (MTU reads, publish outcomes) feed the status LED
+    let events = EventBus::new();
+    let led_gpio = device_config.led.gpio;
+    if led_gpio.is_some() && led_gpio == device_config.button.gpio {
+        log::warn!("⚠️  Status LED and button share a GPIO, LED disabled");
+    } else if use_ethernet && led_gpio.is_some_and(|gpio| ETHERNET_GPIOS.contains(&gpio)) {
+        log::warn!("⚠️  Status LED GPIO is used by the W5500 module, disabled");
+    } else if let Err(e) = StatusLed::spawn(
+        &device_config.led,
+        peripherals.rmt.channel0,
+        events.subscribe(),
+    ) {
+        log::warn!("⚠️  Status LED unavailable: {:?}", e);
+    }
+
+    let display_pin_taken = [device_config.button.gpio, led_gpio]
+        .iter()
+        .flatten()
+        .any(|gpio| DISPLAY_GPIOS.contains(gpio));
+    if device_config.display.display_type != DisplayType::None && display_pin_taken {
+        log::warn!(
+            "⚠️  Display: Button or LED GPIO is used by the display I2C bus, display disabled"
+        );
+    } else if let Err(e) = Display::spawn(
+        &device_config.display,
+        peripherals.i2c0,
+        peripherals.pins.gpio21,
+        peripherals.pins.gpio22,
+        events.subscribe(),
+    ) {
+        log::warn!("⚠️  Display unavailable: {:?}", e);
+    }
+
+    // Modbus RTU slave for SCADA/PLC polling
+    let modbus_pin_taken = [device_config.button.gpio, led_gpio]
+        .iter()
+        .flatten()
+        .any(|gpio| MODBUS_GPIOS.contains(gpio));
+    if device_config.modbus.address != 0 && modbus_pin_taken {
+        log::warn!(
+            "⚠️  Modbus: Button or LED GPIO is used by the RS-485 transceiver, Modbus disabled"
+        );
+    } else if let Err(e) = ModbusSlave::spawn(
+        &device_config.modbus,
+        Arc::clone(&mtu),
+        boot_count,
+        peripherals.uart2,
+        peripherals.pins.gpio17,
+        peripherals.pins.gpio16,
+        peripherals.pins.gpio15,
+    ) {
+        log::warn!("⚠️  Modbus unavailable: {:?}", e);
+    }
+
+    // BLE walk-by readout (the radio is shared with BLE provisioning)
+    let ble_readout = if !device_config.ble.readout {
+        false
+    } else if ble_provisioning.is_some() {
+        log::warn!("⚠️  BLE readout: BLE is in use by provisioning, readout disabled");
+        false
+    } else {
+        BleReadout::start(Arc::clone(&mtu), mtu_cmd_sender.clone())
+            .map_err(|e| log::warn!("⚠️  BLE readout unavailable: {:?}", e))
+            .is_ok()
+    };
+
+    // Local data log: every read cycle, whether or not it gets published
+    let storage = &device_config.storage;
+    let sd_pin_taken = [device_config.button.gpio, led_gpio]
+        .iter()
+        .flatten()
+        .any(|gpio| SD_GPIOS.contains(gpio));
+    let data_log = match storage.backend {
+        StorageBackend::None => None,
+        StorageBackend::Flash => DataLog::mount_flash(storage)
+            .map_err(|e| log::error!("❌ Storage: LittleFS mount failed: {:?}", e))
+            .ok(),
+        StorageBackend::Sd if sd_pin_taken => {
+            log::warn!("⚠️  Storage: Button or LED GPIO is used by the SD card, data log disabled");
+            None
+        }
+        StorageBackend::Sd => DataLog::mount_sd(
+            storage,
+            peripherals.spi3,
+            peripherals.pins.gpio14,
+            peripherals.pins.gpio13,
+            peripherals.pins.gpio33,
+            peripherals.pins.gpio32,
+        )
+        .map_err(|e| log::error!("❌ Storage: SD card mount failed: {:?}", e))
+        .ok(),
+    }
+    .map(|data_log| Arc::new(Mutex::new(data_log)));
+
+    // Several meters switched onto the MTU lines by an analog multiplexer
+    let meters = &device_config.meters;
+    let mut mux_pins_taken: Vec<u8> = [device_config.button.gpio, led_gpio]
+        .into_iter()
+        .flatten()
+        .collect();
+    if use_ethernet {
+        mux_pins_taken.extend_from_slice(ETHERNET_GPIOS);
+    }
+    if use_cellular {
+        mux_pins_taken.extend_from_slice(CELLULAR_GPIOS);
+    }
+    if device_config.modbus.address != 0 {
+        mux_pins_taken.extend_from_slice(MODBUS_GPIOS);
+    }
+    if storage.backend == StorageBackend::Sd {
+        mux_pins_taken.extend_from_slice(SD_GPIOS);
+    }
+    let mut meter_manager = if !meters.meters.is_empty()
+        && meters
+            .select_gpios
+            .iter()
+            .any(|gpio| mux_pins_taken.contains(gpio))
+    {
+        log::warn!(
+            "⚠️  Meters: A mux select GPIO is used by another peripheral, multi-meter reads disabled"
+        );
+        None
+    } else {
+        MeterManager::new(meters, SCHEDULED_READ_SECS).unwrap_or_else(|e| {
+            log::error!("❌ Meters: {:?}", e);
+            None
+        })
+    };
+
+    // Deep sleep: counters survive in RTC memory between wake cycles
+    let deep_sleep = device_config.power.mode == PowerMode::DeepSleep;
+    let restored = power::restore_state();
+    let sleep_state = restored.unwrap_or_default();
+    if restored.is_some() {
+        log::info!(
+            "💤 Power: Wake #{} from deep sleep ({} reads, {} published)",
+            sleep_state.wake_count,
+            sleep_state.successful_reads + sleep_state.corrupted_reads,
+            sleep_state.publish_count
+        );
+        mtu.restore_stats(sleep_state.successful_reads, sleep_state.corrupted_reads);
+    }
+
+    let persistent = device_config.network.mode == ConnectivityMode::Persistent;
+    if persistent {
+        log::info!("📡 MQTT: Persistent mode (stays connected)");
+    } else {
+        // MQTT will be created on-demand when publishing data
+        log::info!("📡 MQTT: On-demand mode (will connect only when publishing)");
+    }
 
     // Initialize CLI components
-    let mut terminal = Terminal::new(uart_tx, uart_rx);
+    let mut terminal =
+        Terminal::with_io(console_io).with_mtu(Arc::clone(&mtu), mtu_cmd_sender.clone());
     let mut command_handler =
         CommandHandler::new().with_mtu(Arc::clone(&mtu), mtu_cmd_sender.clone());
 
+    command_handler = command_handler
+        .with_config(device_config.clone())
+        .with_boot_count(boot_count);
+    if let Some(store) = config_store {
+        match store.load_aliases() {
+            Ok(aliases) => CommandParser::set_aliases(aliases),
+            Err(e) => log::warn!("⚠️  Failed to load command aliases: {:?}", e),
+        }
+        command_handler = command_handler.with_config_store(store);
+    }
+
+    if let Some(store) = wifi_credentials {
+        command_handler = command_handler.with_wifi_credentials(store);
+    }
+
+    if let Some(ref data_log) = data_log {
+        command_handler = command_handler.with_data_log(Arc::clone(data_log));
+    }
+
     // Add WiFi to command handler if available
     if let Some(ref wifi_manager) = wifi {
         command_handler = command_handler.with_wifi(Arc::clone(wifi_manager));
     }
 
+    // Shared with the MQTT control topics (remote CLI)
+    let jobs = command_handler.jobs();
+    let command_handler = Arc::new(Mutex::new(command_handler));
+
     log::info!("✅ CLI initialized");
 
+    // Local REST API mirroring the CLI (reachable while the link is up)
+    let http_api = if network.is_some() && provisioning.is_none() {
+        match HttpApi::start(
+            HTTP_API_PORT,
+            Arc::clone(&command_handler),
+            Arc::clone(&mtu),
+        ) {
+            Ok(api) => Some(api),
+            Err(e) => {
+                log::warn!("⚠️  HTTP API failed to start: {:?}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Serial console over TCP (opt-in, no authentication besides 'login')
+    let telnet = network.is_some() && provisioning.is_none() && device_config.network.telnet;
+    let telnet = telnet
+        && match spawn_telnet_server(
+            TELNET_PORT,
+            Arc::clone(&command_handler),
+            Arc::clone(&mtu),
+            mtu_cmd_sender.clone(),
+        ) {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("⚠️  Telnet CLI failed to start: {:?}", e);
+                false
+            }
+        };
+
     // Send welcome message
     terminal.write_line("")?;
     terminal.write_line("ESP32 Water Meter MTU Interface")?;
@@ -183,281 +812,456 @@ fn main() -> anyhow::Result<()> {
     terminal.write_line("MTU Clock: GPIO4 | Data: GPIO5")?;
 
     // Show WiFi/MQTT status in welcome message
+    if use_ethernet && network.is_some() {
+        if persistent {
+            terminal.write_line("Ethernet (W5500): Persistent (connecting)")?;
+        } else {
+            terminal.write_line("Ethernet (W5500): On-demand (disconnected)")?;
+        }
+    }
     if wifi.is_some() {
-        terminal.write_line("WiFi: On-demand (disconnected)")?;
-        terminal.write_line("MQTT: On-demand (will connect after MTU read)")?;
+        if persistent {
+            terminal.write_line("WiFi: Persistent (connecting)")?;
+        } else {
+            terminal.write_line("WiFi: On-demand (disconnected)")?;
+        }
+    }
+    if use_cellular {
+        terminal.write_line(&format!(
+            "Cellular: {} (APN {})",
+            if wifi.is_some() {
+                "Fallback when WiFi can't connect"
+            } else {
+                "Only link"
+            },
+            device_config.cellular.apn
+        ))?;
+    }
+    if network.is_some() && influxdb.is_some() {
+        terminal.write_line("InfluxDB: Readings written over HTTP (no MQTT control topics)")?;
+    } else if network.is_some() && coap.is_some() {
+        terminal.write_line("CoAP: Readings POSTed over UDP (no MQTT control topics)")?;
+    } else if network.is_some() && !mqtt_uplink {
+        terminal.write_line("Webhook: Readings POSTed over HTTP (no MQTT control topics)")?;
+    } else if network.is_some() {
+        if persistent {
+            terminal.write_line("MQTT: Persistent (control commands handled immediately)")?;
+        } else {
+            terminal.write_line("MQTT: On-demand (will connect after MTU read)")?;
+        }
+    }
+    if relay_node.is_some() {
+        terminal.write_line(&format!(
+            "ESP-NOW: Node on channel {} (readings relayed to the gateway)",
+            device_config.espnow.channel
+        ))?;
+    }
+    if relay_gateway.is_some() {
+        terminal.write_line(&format!(
+            "ESP-NOW: Gateway (node readings published to {})",
+            topics.relay
+        ))?;
+    }
+    if let Some(ref api) = http_api {
+        terminal.write_line(&format!(
+            "HTTP API: port {} (/status, /readings, /mtu/start, /config)",
+            api.port()
+        ))?;
+    }
+    if telnet {
+        terminal.write_line(&format!(
+            "Telnet CLI: port {} (privileged commands need 'login' once a password is set)",
+            TELNET_PORT
+        ))?;
+    }
+    if let Some(ref manager) = meter_manager {
+        terminal.write_line(&format!(
+            "Meters: {} on {} mux select line(s) (see 'meters list')",
+            manager.len(),
+            meters.select_gpios.len()
+        ))?;
+    }
+    if deep_sleep {
+        terminal.write_line(&format!(
+            "Power: Deep sleep, reading every {}s (press a key to stay awake)",
+            device_config.power.read_interval_secs
+        ))?;
+    }
+    if let Some(ref portal) = provisioning {
+        terminal.write_line(&format!(
+            "WiFi setup: join '{}' and open http://{} (or use 'wifi_save')",
+            portal.ap_ssid(),
+            portal.ap_ip()
+        ))?;
+    }
+    if let Some(ref ble) = ble_provisioning {
+        terminal.write_line(&format!(
+            "BLE setup: connect to '{}' and write the settings JSON",
+            ble.device_name()
+        ))?;
+    }
+    if ble_readout {
+        terminal.write_line(&format!(
+            "BLE readout: connect to '{}' to read the meter",
+            ble_device_name()
+        ))?;
     }
     terminal.print_prompt()?;
 
     log::info!("Entering CLI loop...");
 
-    // Helper function to publish MTU data with on-demand WiFi/MQTT connection
-    // This function connects WiFi, creates MQTT client, publishes data,
-    // waits for downlink messages, then disconnects everything
-    let publish_with_connectivity = |wifi_manager: &Arc<Mutex<WifiManager>>,
-                                     mtu_sender: &std::sync::mpsc::Sender<MtuCommand>,
-                                     message: &str,
-                                     stats: (u32, u32, usize),
-                                     baud_rate: u32,
-                                     counter: &mut u32,
-                                     control_shared: &str,
-                                     control_device: &str,
-                                     client_id: &str| {
-        let (successful, corrupted, cycles) = stats;
-
-        log::info!("📡 On-demand publish: Connecting WiFi...");
-
-        // Step 1: Connect WiFi
-        let wifi_result = if let Ok(mut wifi_guard) = wifi_manager.lock() {
-            wifi_guard.reconnect(None, None)
-        } else {
-            log::error!("❌ Failed to lock WiFi manager");
-            return;
+    // Publish pipeline: on-demand (link up → MQTT → publish → downlink → link down)
+    // or persistent (session kept open, see Publisher::poll)
+    let mut publisher = network.clone().map(|network| {
+        let publisher = Publisher::new(network, mqtt_config, &topics)
+            .with_mode(device_config.network.mode)
+            .with_device_name(&device_config.device.name)
+            .with_control(cli_downlink_handler(Arc::clone(&command_handler)))
+            .with_config_sync(config_sync_handler(Arc::clone(&command_handler)))
+            .with_telemetry(TELEMETRY_INTERVAL)
+            .with_boot_count(boot_count)
+            .with_publish_count(sleep_state.publish_count)
+            .with_events(events.clone());
+        let publisher = match crash_store {
+            Some(store) => publisher.with_crash_reports(store),
+            None => publisher,
         };
-
-        if let Err(e) = wifi_result {
-            log::error!("❌ WiFi connection failed: {:?}", e);
-            return;
-        }
-
-        log::info!("✅ WiFi connected");
-
-        // Step 2: Create MQTT client with message handler for control topic
-        log::info!("📡 Creating MQTT client...");
-
-        // Clone MTU sender for MQTT callback
-        let mqtt_mtu_sender = mtu_sender.clone();
-
-        // Clone control topics for callback
-        let callback_control_shared = control_shared.to_string();
-        let callback_control_device = control_device.to_string();
-
-        let mqtt_client = match MqttClient::new(
-            MQTT_BROKER,
-            client_id,
-            Arc::new(move |topic, data| {
-                if let Ok(msg) = std::str::from_utf8(data) {
-                    log::info!("📩 MQTT control message on {}: {}", topic, msg);
-
-                    // Accept commands from both shared and device-specific topics
-                    if topic == callback_control_shared || topic == callback_control_device {
-                        // Try to parse as JSON first
-                        if let Ok(json) = serde_json::from_str::<serde_json::Value>(msg) {
-                            // Handle JSON messages like {"baud_rate": 1200}
-                            if let Some(baud_rate) = json.get("baud_rate").and_then(|v| v.as_u64())
-                            {
-                                log::info!("MQTT: Setting baud rate to {} bps", baud_rate);
-                                let _ = mqtt_mtu_sender.send(MtuCommand::SetBaudRate {
-                                    baud_rate: baud_rate as u32,
-                                });
-                            }
-                            if let Some(cmd) = json.get("command").and_then(|v| v.as_str()) {
-                                match cmd {
-                                    "start" => {
-                                        let duration = json
-                                            .get("duration")
-                                            .and_then(|v| v.as_u64())
-                                            .unwrap_or(30);
-                                        log::info!("MQTT: Starting MTU for {}s", duration);
-                                        let _ = mqtt_mtu_sender.send(MtuCommand::Start {
-                                            duration_secs: duration,
-                                        });
-                                    }
-                                    "stop" => {
-                                        log::info!("MQTT: Stopping MTU");
-                                        let _ = mqtt_mtu_sender.send(MtuCommand::Stop);
-                                    }
-                                    _ => {
-                                        log::warn!("MQTT: Unknown JSON command: {}", cmd);
-                                    }
-                                }
-                            }
-                        } else {
-                            // Fall back to plain text commands for backwards compatibility
-                            let cmd = msg.trim().to_lowercase();
-                            match cmd.as_str() {
-                                "start" => {
-                                    log::info!("MQTT: Starting MTU (30s default)");
-                                    let _ = mqtt_mtu_sender
-                                        .send(MtuCommand::Start { duration_secs: 30 });
-                                }
-                                msg if msg.starts_with("start ") => {
-                                    if let Some(duration_str) = msg.strip_prefix("start ") {
-                                        if let Ok(duration) = duration_str.parse::<u64>() {
-                                            log::info!("MQTT: Starting MTU for {}s", duration);
-                                            let _ = mqtt_mtu_sender.send(MtuCommand::Start {
-                                                duration_secs: duration,
-                                            });
-                                        }
-                                    }
-                                }
-                                "stop" => {
-                                    log::info!("MQTT: Stopping MTU");
-                                    let _ = mqtt_mtu_sender.send(MtuCommand::Stop);
-                                }
-                                _ => {
-                                    log::warn!("MQTT: Unknown control command: {}", cmd);
-                                }
-                            }
-                        }
-                    }
-                }
-            }),
-        ) {
-            Ok(client) => client,
-            Err(e) => {
-                log::error!("❌ MQTT client creation failed: {:?}", e);
-                // Disconnect WiFi before returning
-                if let Ok(mut wifi_guard) = wifi_manager.lock() {
-                    let _ = wifi_guard.disconnect();
-                }
-                return;
-            }
+        let publisher = match webhook {
+            Some(webhook) => publisher.with_webhook(webhook),
+            None => publisher,
+        };
+        let publisher = match influxdb {
+            Some(influxdb) => publisher.with_influxdb(influxdb),
+            None => publisher,
+        };
+        let publisher = match coap {
+            Some(coap) => publisher.with_coap(coap),
+            None => publisher,
         };
+        let publisher = match data_log {
+            Some(ref data_log) => publisher.with_export(Arc::clone(data_log)),
+            None => publisher,
+        };
+        let publisher = match aws_iot {
+            Some(ref aws) => publisher
+                .with_downlink_to(
+                    &[&aws.delta_topic()],
+                    aws.delta_handler(Arc::clone(&command_handler)),
+                    &aws.update_topic(),
+                )
+                .with_reading_report(&aws.update_topic(), AwsIot::reading_report()),
+            None => publisher,
+        };
+        match azure {
+            Some(ref azure) => publisher
+                .with_login(azure.login())
+                .with_downlink_to(
+                    &[&azure.desired_topic()],
+                    azure.desired_handler(Arc::clone(&command_handler)),
+                    &azure.reported_topic(),
+                )
+                .with_reading_report(&azure.reported_topic(), AzureIot::reading_report()),
+            None => publisher,
+        }
+    });
 
-        // Step 3: Wait for MQTT connection (up to 10 seconds)
-        log::info!("⏳ Waiting for MQTT connection...");
-        for i in 0..20 {
-            if mqtt_client.is_connected() {
-                log::info!("✅ MQTT connected");
-                break;
-            }
-            std::thread::sleep(std::time::Duration::from_millis(500));
-            if i == 19 {
-                log::error!("❌ MQTT connection timeout");
-                // Disconnect WiFi and return
-                if let Ok(mut wifi_guard) = wifi_manager.lock() {
-                    let _ = wifi_guard.disconnect();
-                }
-                return;
+    // After an OTA update: read the meter and reach the network before
+    // keeping the new firmware
+    let mut ota_health = HealthCheck::start_if_pending(OTA_HEALTH_TIMEOUT, network.is_some());
+    let mut next_self_test = Instant::now();
+
+    // Track the last handled read cycle count
+    // Publish based on MTU read cycles, not message content (allows duplicate messages)
+    let reads_before_wake = sleep_state.successful_reads + sleep_state.corrupted_reads;
+    let mut last_read_cycles = u64::from(reads_before_wake);
+
+    // MTU read start/end, reported on the event bus
+    let mut read_in_progress: Option<u32> = None;
+
+    // Deep sleep: read the meter right away, publish, then sleep again
+    // (the meter manager reads each of its meters instead)
+    let mut last_console_input: Option<Instant> = None;
+    let max_awake = MAX_AWAKE_TIME
+        + Duration::from_secs(
+            SCHEDULED_READ_SECS * meter_manager.as_ref().map_or(0, |m| m.len()) as u64,
+        );
+    if deep_sleep && meter_manager.is_none() {
+        log::info!("💤 Power: Scheduled read");
+        let _ = mtu_cmd_sender.send(MtuCommand::Start {
+            duration_secs: SCHEDULED_READ_SECS,
+        });
+    }
+
+    if watchdog::subscribe_current_task() {
+        log::info!("✅ Watchdog: Main loop watched");
+    }
+
+    // Main CLI loop
+    loop {
+        watchdog::feed();
+        let (successful_reads, _, _) = mtu.get_stats();
+        match (read_in_progress, mtu.is_running()) {
+            (None, true) => {
+                events.emit(DeviceEvent::ReadStarted);
+                read_in_progress = Some(successful_reads);
             }
+            (Some(successful_before), false) => {
+                let success = successful_reads > successful_before;
+                events.emit(DeviceEvent::ReadFinished {
+                    success,
+                    register: mtu
+                        .get_last_message()
+                        .filter(|_| success)
+                        .and_then(|message| register_value(&message)),
+                });
+                read_in_progress = None;
+            }
+            _ => {}
         }
 
-        // Step 4: Subscribe to control topics (both shared and device-specific)
-        log::info!("📥 Subscribing to shared control topic: {}", control_shared);
-        if let Err(e) = mqtt_client.subscribe(control_shared, QoS::AtLeastOnce) {
-            log::warn!("⚠️  Failed to subscribe to shared control topic: {:?}", e);
+        if let Some(store) = log_store.as_mut() {
+            store.poll();
         }
 
-        log::info!("📥 Subscribing to device control topic: {}", control_device);
-        if let Err(e) = mqtt_client.subscribe(control_device, QoS::AtLeastOnce) {
-            log::warn!("⚠️  Failed to subscribe to device control topic: {:?}", e);
+        // Each new MTU read cycle (successful or corrupted count increased),
+        // attributed to its meter once for the data log, relay and publisher
+        let (successful, corrupted, cycles) = mtu.get_stats();
+        let total_reads = u64::from(successful + corrupted);
+        let mut reading = None;
+        if total_reads > last_read_cycles {
+            if let Some(message) = mtu.get_last_message() {
+                match meter_manager
+                    .as_ref()
+                    .map_or(Ok(None), |manager| manager.attribute(&message))
+                {
+                    Ok(meter) => {
+                        reading = Some(MeterReading {
+                            message: message.to_string(),
+                            baud_rate: mtu.get_baud_rate(),
+                            framing: mtu.get_framing().name(),
+                            cycles,
+                            successful,
+                            corrupted,
+                            meter,
+                        })
+                    }
+                    Err(reason) => log::warn!("⚠️  Meters: Read rejected, {}", reason),
+                }
+            }
+            last_read_cycles = total_reads;
         }
 
-        // Step 5: Publish MTU data with device identification
-        // Get device identifiers
-        let chip_id = get_chip_id();
-        let (wifi_mac, wifi_ip) = if let Ok(wifi_guard) = wifi_manager.lock() {
-            let mac = wifi_guard
-                .get_mac()
-                .unwrap_or_else(|_| "unknown".to_string());
-            let ip = wifi_guard
-                .get_ip()
-                .map(|ip| ip.to_string())
-                .unwrap_or_else(|_| "unknown".to_string());
-            (mac, ip)
-        } else {
-            ("unknown".to_string(), "unknown".to_string())
-        };
-
-        let payload = serde_json::json!({
-            "chip_id": chip_id,
-            "wifi_mac": wifi_mac,
-            "wifi_ip": wifi_ip,
-            "message": message,
-            "baud_rate": baud_rate,
-            "cycles": cycles,
-            "successful": successful,
-            "corrupted": corrupted,
-            "count": *counter,
-        });
+        // Store each new reading locally
+        if let (Some(data_log), Some(reading)) = (data_log.as_ref(), reading.as_ref()) {
+            let result = data_log
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Data log unavailable"))
+                .and_then(|mut data_log| data_log.append(reading));
+            if let Err(e) = result {
+                log::warn!("⚠️  Storage: Failed to store reading: {:?}", e);
+            }
+        }
 
-        if let Ok(json_str) = serde_json::to_string(&payload) {
-            match mqtt_client.publish(
-                MQTT_PUBLISH_TOPIC,
-                json_str.as_bytes(),
-                QoS::AtLeastOnce,
-                false,
-            ) {
-                Ok(_) => {
-                    *counter += 1;
-                    log::info!(
-                        "📤 Published #{} to {}: {}",
-                        *counter,
-                        MQTT_PUBLISH_TOPIC,
-                        message
-                    );
-                }
+        // ESP-NOW node: hand each new reading to the gateway
+        if let (Some(node), Some(reading)) = (relay_node.as_mut(), reading.as_ref()) {
+            match node.send_reading(reading) {
+                Ok(_) => events.emit(DeviceEvent::PublishSucceeded),
                 Err(e) => {
-                    log::error!("❌ MQTT publish failed: {:?}", e);
+                    log::error!("❌ ESP-NOW relay failed: {:?}", e);
+                    events.emit(DeviceEvent::LinkFailed);
                 }
             }
         }
 
-        // Step 6: Wait 5 seconds for queued downlink messages
-        log::info!("⏳ Waiting 5s for queued downlink messages...");
-        std::thread::sleep(std::time::Duration::from_secs(5));
+        // Publish when new MTU data is available
+        if let Some(publisher) = publisher.as_mut() {
+            // Persistent mode: keep the session open and answer control commands
+            publisher.poll();
 
-        // Step 7: Signal MQTT connection handler to shutdown (prevents errors/retries)
-        mqtt_client.shutdown();
+            // ESP-NOW gateway: readings received from nodes
+            if let Some(gateway) = relay_gateway.as_mut() {
+                gateway.forward(publisher);
+            }
 
-        // Drop the client (connection handler already exited cleanly)
-        drop(mqtt_client);
+            if let Some(ref reading) = reading {
+                match publisher.publish_reading(reading) {
+                    Ok(_) => {
+                        if let Some(health) = ota_health.as_mut() {
+                            health.record_link_up();
+                        }
+                    }
+                    Err(e) => log::error!("❌ Publish failed: {:?}", e),
+                }
+            }
+        }
 
-        // Step 8: Disconnect WiFi
-        log::info!("🔌 Disconnecting WiFi...");
-        if let Ok(mut wifi_guard) = wifi_manager.lock() {
-            if let Err(e) = wifi_guard.disconnect() {
-                log::warn!("⚠️  WiFi disconnect failed: {:?}", e);
+        if let Some(health) = ota_health.as_mut() {
+            if mtu.get_stats().0 > 0 {
+                health.record_mtu_read();
+            } else if !mtu.is_running() && Instant::now() >= next_self_test {
+                log::info!("🔄 OTA: Health check - starting MTU self-test read");
+                let _ = mtu_cmd_sender.send(MtuCommand::Start {
+                    duration_secs: OTA_SELF_TEST_SECS,
+                });
+                next_self_test = Instant::now() + OTA_SELF_TEST_INTERVAL;
+            }
+            if network
+                .as_ref()
+                .and_then(|link| link.lock().ok().map(|l| l.is_connected().unwrap_or(false)))
+                .unwrap_or(false)
+            {
+                health.record_link_up();
+            }
+            if health.poll() {
+                ota_health = None;
             }
         }
 
-        log::info!("✅ On-demand publish cycle complete");
-    };
+        if let Some(manager) = meter_manager.as_mut() {
+            manager.poll(&mtu, &mtu_cmd_sender);
+        }
 
-    // Track last published cycle count for on-demand publishing
-    // Publish based on MTU read cycles, not message content (allows duplicate messages)
-    let mut last_published_cycles = 0u64;
-    let mut publish_counter = 0u32;
+        match button.as_mut().and_then(|b| b.poll()) {
+            Some(ButtonEvent::ShortPress) if mtu.is_running() => {
+                log::info!("🔘 Button: MTU read already in progress");
+            }
+            Some(ButtonEvent::ShortPress) => {
+                log::info!("🔘 Button: Manual read");
+                let _ = terminal.write_line("");
+                let _ = terminal.write_line("🔘 Button: Starting MTU read");
+                let _ = terminal.print_prompt();
+                let _ = mtu_cmd_sender.send(MtuCommand::Start {
+                    duration_secs: SCHEDULED_READ_SECS,
+                });
+            }
+            Some(ButtonEvent::LongPress) => {
+                log::info!("🔘 Button: Long press - restarting into provisioning mode");
+                let _ = terminal.write_line("");
+                let _ = terminal.write_line("🔘 Button: Restarting into provisioning mode...");
+                let requested = ConfigStore::new(nvs.clone())
+                    .and_then(|mut store| store.request_provisioning());
+                match requested {
+                    Ok(_) => {
+                        if let Some(store) = log_store.as_mut() {
+                            let _ = store.flush();
+                        }
+                        FreeRtos::delay_ms(500);
+                        unsafe { sys::esp_restart() };
+                    }
+                    Err(e) => log::error!("❌ Button: Provisioning request failed: {:?}", e),
+                }
+            }
+            None => {}
+        }
 
-    // Main CLI loop
-    loop {
-        // On-demand publish: Connect WiFi/MQTT only when new MTU data is available
+        // Deep sleep once the scheduled read is done (and published, above),
+        // unless the console is in use, an OTA health check is pending or
+        // readings wait in the offline queue (it is in RAM and would not
+        // survive the sleep; it empties once they are published or given up)
+        if deep_sleep && ota_health.is_none() {
+            let (successful, corrupted, _) = mtu.get_stats();
+            let awake = Duration::from_secs(telemetry::uptime_secs());
+            let read_done = successful + corrupted > reads_before_wake
+                && !mtu.is_running()
+                && !meter_manager
+                    .as_ref()
+                    .is_some_and(|manager| manager.pending());
+            let console_idle = last_console_input
+                .map(|at| at.elapsed() >= CONSOLE_AWAKE_TIME)
+                .unwrap_or(true);
+            let queue_empty = publisher
+                .as_ref()
+                .is_none_or(|publisher| publisher.pending_count() == 0);
+            if (read_done || awake >= max_awake) && console_idle && queue_empty {
+                let _ = terminal.write_line("");
+                let _ = terminal.write_line("💤 Entering deep sleep");
+                let interval = Duration::from_secs(device_config.power.read_interval_secs.into());
+                if let Some(button) = &button {
+                    if let Err(e) = button.enable_wakeup() {
+                        log::warn!("⚠️  Button wake-up unavailable: {:?}", e);
+                    }
+                }
+                if let Some(store) = log_store.as_mut() {
+                    if let Err(e) = store.flush() {
+                        log::warn!("⚠️  Log buffer save failed: {:?}", e);
+                    }
+                }
+                power::deep_sleep(
+                    SleepState {
+                        wake_count: sleep_state.wake_count,
+                        successful_reads: successful,
+                        corrupted_reads: corrupted,
+                        publish_count: publisher.as_ref().map_or(0, |p| p.publish_count()),
+                    },
+                    interval.saturating_sub(awake),
+                );
+            }
+        }
+
+        // Drive a non-blocking connect (wifi_connect) and bring back a link
+        // that dropped unexpectedly (with backoff)
         if let Some(wifi_manager) = &wifi {
-            if let Some(current_message) = mtu.get_last_message() {
-                // Get statistics for the JSON payload
-                let (successful, corrupted, cycles) = mtu.get_stats();
-
-                // Publish if we have a new MTU read cycle (successful or corrupted count increased)
-                let total_reads = successful + corrupted;
-                let should_publish = u64::from(total_reads) > last_published_cycles;
-
-                if should_publish {
-                    let baud_rate = mtu.get_baud_rate();
-
-                    // Call on-demand publish function
-                    // This will: connect WiFi → create MQTT → publish → wait for downlink → disconnect
-                    publish_with_connectivity(
-                        wifi_manager,
-                        &mtu_cmd_sender,
-                        current_message.as_str(),
-                        (successful, corrupted, cycles),
-                        baud_rate,
-                        &mut publish_counter,
-                        MQTT_CONTROL_TOPIC_SHARED,
-                        &mqtt_control_topic_device,
-                        &mqtt_client_id,
-                    );
-
-                    // Update last published cycle count
-                    last_published_cycles = u64::from(total_reads);
+            let progress = match wifi_manager.lock() {
+                Ok(mut wifi_guard) => {
+                    let progress = wifi_guard.connect_poll();
+                    wifi_guard.maintain();
+                    progress
+                }
+                Err(_) => None,
+            };
+            if let Some(progress) = progress {
+                // A connect started from the CLI is tracked as a job
+                let job = jobs.running("wifi");
+                let line = match progress {
+                    ConnectProgress::Trying { failed, ssid } => {
+                        let line =
+                            format!("⚠️  WiFi: '{}' timed out, trying '{}'...", failed, ssid);
+                        if let Some(id) = job {
+                            jobs.progress(id, &line);
+                        }
+                        Some(line)
+                    }
+                    ConnectProgress::Connected { ssid, ip } => {
+                        let line = format!("WiFi connected to {} (IP {})", ssid, ip);
+                        match job {
+                            Some(id) => {
+                                jobs.finish(id, Ok(line));
+                                None
+                            }
+                            None => Some(format!("✅ {}", line)),
+                        }
+                    }
+                    ConnectProgress::Failed(e) => {
+                        events.emit(DeviceEvent::LinkFailed);
+                        let line = format!("WiFi connection failed: {}", e);
+                        match job {
+                            Some(id) => {
+                                jobs.finish(id, Err(line));
+                                None
+                            }
+                            None => Some(format!("❌ {}", line)),
+                        }
+                    }
+                };
+                if let Some(line) = line {
+                    let _ = terminal.write_line("");
+                    let _ = terminal.write_line(&line);
+                    let _ = terminal.print_prompt();
                 }
             }
         }
 
+        // Background jobs (wifi_scan, mqtt_connect, ...) that have finished
+        for line in jobs.take_announcements() {
+            let _ = terminal.write_line("");
+            let _ = terminal.write_line(&line);
+            let _ = terminal.print_prompt();
+        }
+
         // Read character with non-blocking timeout
         match terminal.read_char() {
             Ok(Some(ch)) => {
+                last_console_input = Some(Instant::now());
                 // Handle character and check if we got a complete command
                 match terminal.handle_char(ch) {
                     Ok(Some(command_line)) => {
@@ -467,10 +1271,14 @@ fn main() -> anyhow::Result<()> {
                         // Clone command for later pattern matching
                         let command_clone = command.clone();
 
-                        match command_handler.execute_command(command) {
+                        let result = match command_handler.lock() {
+                            Ok(mut handler) => handler.execute_console(command),
+                            Err(_) => Err(esp32_water_meter::cli::CliError::InvalidCommand),
+                        };
+                        match result {
                             Ok(response) => {
                                 if !response.is_empty() {
-                                    let _ = terminal.write_line(&response);
+                                    let _ = terminal.write_paged(&response);
                                 }
                             }
                             Err(_) => {
@@ -487,6 +1295,12 @@ fn main() -> anyhow::Result<()> {
                             esp32_water_meter::cli::CliCommand::Clear => {
                                 let _ = terminal.clear_screen();
                             }
+                            esp32_water_meter::cli::CliCommand::Pager(lines) => {
+                                terminal.set_page_lines(lines);
+                            }
+                            esp32_water_meter::cli::CliCommand::Color(on) => {
+                                terminal.set_color(on);
+                            }
                             _ => {}
                         }
 
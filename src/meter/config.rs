@@ -1,26 +1,175 @@
-use heapless::String;
+use heapless::{String, Vec};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+/// Maximum number of messages in a response script
+pub const MAX_SCRIPT_MESSAGES: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MeterType {
+    #[default]
     Sensus,
     Neptune,
 }
 
 impl MeterType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sensus" => Some(MeterType::Sensus),
+            "neptune" => Some(MeterType::Neptune),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MeterType::Sensus => "sensus",
+            MeterType::Neptune => "neptune",
+        }
+    }
+
     pub fn framing(&self) -> crate::mtu::UartFraming {
         match self {
             MeterType::Sensus => crate::mtu::UartFraming::SevenE1,
             MeterType::Neptune => crate::mtu::UartFraming::SevenE2,
         }
     }
+
+    /// Field of the message that carries the `StatusFlags`
+    pub fn flags_field(&self) -> &'static str {
+        match self {
+            MeterType::Sensus => "XT",
+            MeterType::Neptune => "MT",
+        }
+    }
+
+    /// Whether `message` has a field to carry the `StatusFlags`
+    pub fn carries_flags(&self, message: &str) -> bool {
+        message
+            .trim_end_matches('\r')
+            .split(';')
+            .any(|field| field.starts_with(self.flags_field()))
+    }
+}
+
+/// Alarm/status flags the simulator encodes into the response message.
+///
+/// Sensus messages carry them in the `XT` field, Neptune messages in `MT`
+/// (`MeterType::flags_field`). When any flag is set, the first hex digit of
+/// that field is replaced with the flag bitmask and the other digits are kept
+/// (e.g. Sensus `XT0746` with tamper + low battery is sent as `XT3746`). With
+/// no flags set the message is sent unmodified.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatusFlags(pub u8);
+
+impl StatusFlags {
+    pub const TAMPER: u8 = 0x01;
+    pub const LOW_BATTERY: u8 = 0x02;
+    pub const LEAK: u8 = 0x04;
+    pub const REVERSE_FLOW: u8 = 0x08;
+
+    /// CLI names paired with their bit
+    pub const NAMES: [(&'static str, u8); 4] = [
+        ("tamper", Self::TAMPER),
+        ("battery", Self::LOW_BATTERY),
+        ("leak", Self::LEAK),
+        ("reverse", Self::REVERSE_FLOW),
+    ];
+
+    pub fn from_name(name: &str) -> Option<u8> {
+        Self::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, bit)| *bit)
+    }
+
+    pub fn set(&mut self, bit: u8, on: bool) {
+        if on {
+            self.0 |= bit;
+        } else {
+            self.0 &= !bit;
+        }
+    }
+
+    pub fn is_set(&self, bit: u8) -> bool {
+        self.0 & bit != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Encode the flags into the flags field of a `;`-separated meter message.
+    /// None when flags are set but the message has no field to carry them.
+    pub fn apply(&self, message: &str, meter_type: MeterType) -> Option<String<256>> {
+        let mut out = String::new();
+        if self.is_empty() {
+            let _ = out.push_str(message);
+            return Some(out);
+        }
+
+        let tag = meter_type.flags_field();
+        let body = message.trim_end_matches('\r');
+        let mut encoded = false;
+        for (i, field) in body.split(';').enumerate() {
+            if i > 0 {
+                let _ = out.push(';');
+            }
+            match field.strip_prefix(tag) {
+                Some(value) if !encoded => {
+                    encoded = true;
+                    let kept = value.get(1..).unwrap_or("");
+                    let _ = out.push_str(&format!("{}{:X}{}", tag, self.0, kept));
+                }
+                _ => {
+                    let _ = out.push_str(field);
+                }
+            }
+        }
+        if !encoded {
+            return None;
+        }
+        if message.ends_with('\r') {
+            let _ = out.push('\r');
+        }
+        Some(out)
+    }
+}
+
+impl core::fmt::Display for StatusFlags {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "none");
+        }
+        let mut first = true;
+        for (name, bit) in Self::NAMES.iter() {
+            if self.is_set(*bit) {
+                if !first {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", name)?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct MeterConfig {
     pub meter_type: MeterType,
     pub response_message: String<256>,
+    /// Optional sequence of responses cycled on successive interrogations
+    /// (empty = always send `response_message`)
+    pub script: Vec<String<256>, MAX_SCRIPT_MESSAGES>,
     pub response_delay_ms: u64,
     pub enabled: bool,
+    /// Minimum time between accepted clock edges (us); closer edges are treated as ringing
+    pub debounce_us: u32,
+    /// Clock pulses to count before starting transmission
+    pub wake_up_threshold: u32,
+    /// Simulated alarm conditions encoded into the message
+    pub status_flags: StatusFlags,
 }
 
 impl Default for MeterConfig {
@@ -34,8 +183,94 @@ impl Default for MeterConfig {
         Self {
             meter_type: MeterType::Sensus,
             response_message: default_message,
+            script: Vec::new(),
             response_delay_ms: 50,
             enabled: true,
+            debounce_us: 100, // Well under half a bit period at 1200 baud (~417us)
+            wake_up_threshold: 10,
+            status_flags: StatusFlags::default(),
         }
     }
 }
+
+impl MeterConfig {
+    /// Set flags that a message sent (the response message, or any script
+    /// entry) has no field for
+    pub fn flags_dropped(&self) -> bool {
+        if self.status_flags.is_empty() {
+            return false;
+        }
+        if self.script.is_empty() {
+            !self.meter_type.carries_flags(&self.response_message)
+        } else {
+            self.script
+                .iter()
+                .any(|message| !self.meter_type.carries_flags(message))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "V;RB00000200;IB61564400;XT0746;MT0683;RR00000000\r";
+
+    #[test]
+    fn sensus_flags_go_in_xt() {
+        let flagged = StatusFlags(StatusFlags::TAMPER | StatusFlags::LOW_BATTERY)
+            .apply(MESSAGE, MeterType::Sensus)
+            .unwrap();
+        assert_eq!(
+            flagged,
+            "V;RB00000200;IB61564400;XT3746;MT0683;RR00000000\r"
+        );
+    }
+
+    #[test]
+    fn neptune_flags_go_in_mt() {
+        let flagged = StatusFlags(StatusFlags::LEAK)
+            .apply(MESSAGE, MeterType::Neptune)
+            .unwrap();
+        assert_eq!(
+            flagged,
+            "V;RB00000200;IB61564400;XT0746;MT4683;RR00000000\r"
+        );
+    }
+
+    #[test]
+    fn no_flags_leaves_the_message_alone() {
+        let message = "V;RB00000200\r";
+        assert_eq!(
+            StatusFlags(0).apply(message, MeterType::Sensus).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn message_without_the_field_is_reported() {
+        let message = "V;RB00000200;IB61564400;MT0683\r";
+        assert_eq!(
+            StatusFlags(StatusFlags::TAMPER).apply(message, MeterType::Sensus),
+            None
+        );
+        assert!(!MeterType::Sensus.carries_flags(message));
+        assert!(MeterType::Neptune.carries_flags(message));
+    }
+
+    #[test]
+    fn dropped_flags_checks_every_script_entry() {
+        let mut config = MeterConfig {
+            status_flags: StatusFlags(StatusFlags::TAMPER),
+            ..Default::default()
+        };
+        assert!(!config.flags_dropped());
+        let _ = config
+            .script
+            .push(String::try_from("V;RB00000200;XT0001").unwrap());
+        let _ = config
+            .script
+            .push(String::try_from("###GARBAGE###").unwrap());
+        assert!(config.flags_dropped());
+    }
+}
@@ -0,0 +1,93 @@
+use heapless::Deque;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Number of events kept before the oldest are overwritten
+pub const EVENT_LOG_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub enum MeterEventKind {
+    /// Wake-up threshold reached after this many clock pulses
+    WakeUp { pulses: usize },
+    /// Transmission started with this many bits queued
+    TransmitStart { bits: usize },
+    /// All bits of the message were clocked out
+    TransmitComplete { bits: usize },
+    /// Clock stopped before the message was fully sent
+    TransmitAborted { sent: usize, total: usize },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MeterEvent {
+    /// Milliseconds since the log was created (boot)
+    pub timestamp_ms: u64,
+    pub kind: MeterEventKind,
+}
+
+impl core::fmt::Display for MeterEvent {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "[{:>6}.{:03}s] ",
+            self.timestamp_ms / 1000,
+            self.timestamp_ms % 1000
+        )?;
+        match self.kind {
+            MeterEventKind::WakeUp { pulses } => {
+                write!(f, "Wake-up detected ({} pulses)", pulses)
+            }
+            MeterEventKind::TransmitStart { bits } => {
+                write!(f, "Transmission started ({} bits)", bits)
+            }
+            MeterEventKind::TransmitComplete { bits } => {
+                write!(f, "Transmission complete ({} bits)", bits)
+            }
+            MeterEventKind::TransmitAborted { sent, total } => {
+                write!(f, "Transmission ABORTED ({}/{} bits sent)", sent, total)
+            }
+        }
+    }
+}
+
+/// Timestamped ring buffer of meter events for debugging intermittent reads
+pub struct EventLog {
+    start: Instant,
+    events: Mutex<Deque<MeterEvent, EVENT_LOG_SIZE>>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            events: Mutex::new(Deque::new()),
+        }
+    }
+
+    pub fn record(&self, kind: MeterEventKind) {
+        let event = MeterEvent {
+            timestamp_ms: self.start.elapsed().as_millis() as u64,
+            kind,
+        };
+        let mut events = self.events.lock().unwrap();
+        if events.is_full() {
+            events.pop_front();
+        }
+        let _ = events.push_back(event);
+    }
+
+    /// Snapshot of the logged events, oldest first
+    pub fn events(&self) -> Vec<MeterEvent> {
+        let events = self.events.lock().unwrap();
+        events.iter().copied().collect()
+    }
+
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+}
@@ -1,27 +1,66 @@
 use super::config::{MeterConfig, MeterType};
-use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use super::event_log::{EventLog, MeterEvent, MeterEventKind};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use esp_idf_hal::gpio::{Input, Level, Output, Pin, PinDriver};
 use esp_idf_hal::task::notification::Notification;
 use heapless::String;
 use std::num::NonZeroU32;
 use std::sync::{Arc, Mutex};
 
+/// Clock silence (ms) after which an in-progress transmission is considered aborted
+const TRANSMIT_ABORT_TIMEOUT_MS: u32 = 2000;
+/// Gaps between clock edges longer than this (below 10 Hz) are pauses
+/// between reads, not clock periods
+const MAX_CLOCK_PERIOD_US: u32 = 100_000;
+/// Weight of a new period in the running average, as a shift (1/8)
+const CLOCK_AVERAGE_SHIFT: u32 = 3;
+/// Baud rates the measured clock is matched against
+const STANDARD_BAUD_RATES: &[u32] = &[300, 600, 1200, 2400, 4800, 9600, 19200];
+
+/// Clock frequency measured on the clock input
+#[derive(Debug, Clone, Copy)]
+pub struct ClockRate {
+    /// Pulses per second, from the running average of the edge period
+    pub hz: u32,
+    /// One bit is sent per clock pulse, so this is the standard baud rate
+    /// closest to `hz`
+    pub baud: u32,
+    /// Time since the last clock edge
+    pub idle_ms: u32,
+}
+
 pub struct MeterHandler {
     config: Mutex<MeterConfig>,
     pulse_count: Arc<AtomicUsize>,
     bits_transmitted: Arc<AtomicUsize>,
     messages_sent: Arc<AtomicUsize>,
     transmitting: Arc<AtomicBool>,
+    debounce_us: Arc<AtomicU32>, // Mirrored from config so the ISR can read it lock-free
+    filtered_edges: Arc<AtomicUsize>,
+    /// Running average of the clock period in microseconds (0 = not measured);
+    /// written by the ISR
+    clock_period_us: Arc<AtomicU32>,
+    /// `esp_timer` time of the last accepted clock edge (microseconds, wraps)
+    last_edge_us: Arc<AtomicU32>,
+    script_index: AtomicUsize, // Next script entry to send
+    events: EventLog,
 }
 
 impl MeterHandler {
     pub fn new(config: MeterConfig) -> Self {
+        let debounce_us = config.debounce_us;
         Self {
             config: Mutex::new(config),
             pulse_count: Arc::new(AtomicUsize::new(0)),
             bits_transmitted: Arc::new(AtomicUsize::new(0)),
             messages_sent: Arc::new(AtomicUsize::new(0)),
             transmitting: Arc::new(AtomicBool::new(false)),
+            debounce_us: Arc::new(AtomicU32::new(debounce_us)),
+            filtered_edges: Arc::new(AtomicUsize::new(0)),
+            clock_period_us: Arc::new(AtomicU32::new(0)),
+            last_edge_us: Arc::new(AtomicU32::new(0)),
+            script_index: AtomicUsize::new(0),
+            events: EventLog::new(),
         }
     }
 
@@ -42,6 +81,33 @@ impl MeterHandler {
         log::info!("Meter: Response message updated");
     }
 
+    /// Append a message to the response script, returning the new script length
+    /// (None if the script is full)
+    pub fn script_add(&self, message: String<256>) -> Option<usize> {
+        let mut config = self.config.lock().unwrap();
+        config.script.push(message).ok()?;
+        log::info!("Meter: Script entry #{} added", config.script.len());
+        Some(config.script.len())
+    }
+
+    /// Remove all script entries (falls back to the single response message)
+    pub fn script_clear(&self) {
+        let mut config = self.config.lock().unwrap();
+        config.script.clear();
+        self.script_index.store(0, Ordering::Relaxed);
+        log::info!("Meter: Script cleared");
+    }
+
+    /// Index of the script entry that will be sent on the next interrogation
+    pub fn script_position(&self) -> usize {
+        let config = self.config.lock().unwrap();
+        if config.script.is_empty() {
+            0
+        } else {
+            self.script_index.load(Ordering::Relaxed) % config.script.len()
+        }
+    }
+
     pub fn enable(&self) {
         let mut config = self.config.lock().unwrap();
         config.enabled = true;
@@ -54,6 +120,59 @@ impl MeterHandler {
         log::info!("Meter: Disabled");
     }
 
+    pub fn set_debounce_us(&self, debounce_us: u32) {
+        let mut config = self.config.lock().unwrap();
+        config.debounce_us = debounce_us;
+        self.debounce_us.store(debounce_us, Ordering::Relaxed);
+        log::info!("Meter: Clock debounce set to {}us", debounce_us);
+    }
+
+    pub fn set_wake_up_threshold(&self, pulses: u32) {
+        let mut config = self.config.lock().unwrap();
+        config.wake_up_threshold = pulses;
+        log::info!("Meter: Wake-up threshold set to {} pulses", pulses);
+    }
+
+    /// Set or clear a status flag bit (see `StatusFlags`)
+    pub fn set_status_flag(&self, bit: u8, on: bool) {
+        let mut config = self.config.lock().unwrap();
+        config.status_flags.set(bit, on);
+        log::info!("Meter: Status flags now: {}", config.status_flags);
+    }
+
+    pub fn clear_status_flags(&self) {
+        let mut config = self.config.lock().unwrap();
+        config.status_flags = Default::default();
+        log::info!("Meter: Status flags cleared");
+    }
+
+    fn wake_up_threshold(&self) -> usize {
+        let config = self.config.lock().unwrap();
+        config.wake_up_threshold as usize
+    }
+
+    /// Number of clock edges rejected by the debounce filter
+    pub fn get_filtered_edges(&self) -> usize {
+        self.filtered_edges.load(Ordering::Relaxed)
+    }
+
+    /// Measured clock rate, None before the first clock burst
+    pub fn clock_rate(&self) -> Option<ClockRate> {
+        let period_us = self.clock_period_us.load(Ordering::Relaxed);
+        if period_us == 0 {
+            return None;
+        }
+        let hz = 1_000_000 / period_us;
+        let baud = STANDARD_BAUD_RATES
+            .iter()
+            .copied()
+            .min_by_key(|baud| baud.abs_diff(hz))
+            .unwrap_or(hz);
+        let now_us = unsafe { esp_idf_svc::sys::esp_timer_get_time() } as u32;
+        let idle_ms = now_us.wrapping_sub(self.last_edge_us.load(Ordering::Relaxed)) / 1000;
+        Some(ClockRate { hz, baud, idle_ms })
+    }
+
     pub fn is_enabled(&self) -> bool {
         let config = self.config.lock().unwrap();
         config.enabled
@@ -94,13 +213,48 @@ impl MeterHandler {
         frame
     }
 
-    /// Build complete response frame buffer for all characters in the message
+    /// UART frames (one bit per entry) of the first `count` characters of the
+    /// message the next read sends, without advancing the script
+    pub fn preview_frames(
+        &self,
+        count: usize,
+    ) -> (String<256>, Vec<(char, heapless::Vec<u8, 12>)>) {
+        let config = self.config.lock().unwrap();
+        let message = if config.script.is_empty() {
+            &config.response_message
+        } else {
+            &config.script[self.script_index.load(Ordering::Relaxed) % config.script.len()]
+        };
+        let message = flagged_message(&config, message);
+        let frames = message
+            .chars()
+            .take(count)
+            .map(|ch| (ch, self.build_uart_frame(ch as u8, &config.meter_type)))
+            .collect();
+        (message, frames)
+    }
+
+    /// Build complete response frame buffer for all characters in the message.
+    /// When a script is configured, each call consumes the next script entry.
     pub fn build_response_frames(&self) -> heapless::Vec<u8, 2048> {
         let config = self.config.lock().unwrap();
         let mut frame_buffer = heapless::Vec::new();
 
+        let message = if config.script.is_empty() {
+            &config.response_message
+        } else {
+            let index = self.script_index.fetch_add(1, Ordering::Relaxed) % config.script.len();
+            log::info!(
+                "Meter: Using script entry #{} of {}",
+                index + 1,
+                config.script.len()
+            );
+            &config.script[index]
+        };
+        let message = flagged_message(&config, message);
+
         // Build frames for each character in the response message
-        for (char_index, ch) in config.response_message.chars().enumerate() {
+        for (char_index, ch) in message.chars().enumerate() {
             let char_frame = self.build_uart_frame(ch as u8, &config.meter_type);
             log::info!(
                 "Meter: Building frame for char #{}: '{}' (ASCII {}) -> {} bits",
@@ -117,7 +271,7 @@ impl MeterHandler {
         log::info!(
             "Meter: Complete frame buffer: {} total bits for {} characters",
             frame_buffer.len(),
-            config.response_message.len()
+            message.len()
         );
         frame_buffer
     }
@@ -132,11 +286,22 @@ impl MeterHandler {
         )
     }
 
+    /// Snapshot of the event log, oldest first
+    pub fn get_events(&self) -> Vec<MeterEvent> {
+        self.events.events()
+    }
+
+    pub fn clear_events(&self) {
+        self.events.clear();
+    }
+
     /// Reset meter statistics
     pub fn reset_stats(&self) {
         self.pulse_count.store(0, Ordering::Relaxed);
         self.bits_transmitted.store(0, Ordering::Relaxed);
         self.messages_sent.store(0, Ordering::Relaxed);
+        self.filtered_edges.store(0, Ordering::Relaxed);
+        self.clock_period_us.store(0, Ordering::Relaxed);
         log::info!("Meter: Statistics reset");
     }
 
@@ -160,11 +325,45 @@ impl MeterHandler {
                 let notification = Notification::new();
                 let notifier = notification.notifier();
 
+                // Debounce state for the ISR: a ringing clock line produces extra
+                // edges shortly after the real one, which would advance bit_index
+                let debounce_us = meter.debounce_us.clone();
+                let filtered_edges = meter.filtered_edges.clone();
+                let mut last_edge_us = 0u32;
+                // Clock rate measurement for `status`
+                let clock_period_us = meter.clock_period_us.clone();
+                let shared_last_edge_us = meter.last_edge_us.clone();
+
                 // Subscribe to clock pin rising edge interrupts
-                // Safety: Only accesses notification which is Send+Sync
+                // Safety: Only accesses atomics and notification which are Send+Sync
                 unsafe {
                     clock_pin
                         .subscribe(move || {
+                            // Reject edges closer than the minimum pulse width
+                            // (u32 microseconds wraps after ~71 min, wrapping_sub handles it)
+                            let now_us = esp_idf_svc::sys::esp_timer_get_time() as u32;
+                            if now_us.wrapping_sub(last_edge_us)
+                                < debounce_us.load(Ordering::Relaxed)
+                            {
+                                filtered_edges.fetch_add(1, Ordering::Relaxed);
+                                return;
+                            }
+                            let period_us = now_us.wrapping_sub(last_edge_us);
+                            last_edge_us = now_us;
+                            shared_last_edge_us.store(now_us, Ordering::Relaxed);
+
+                            // Running average over the periods within a burst
+                            if period_us <= MAX_CLOCK_PERIOD_US {
+                                let average = clock_period_us.load(Ordering::Relaxed);
+                                let average = if average == 0 {
+                                    period_us
+                                } else {
+                                    (average - (average >> CLOCK_AVERAGE_SHIFT))
+                                        + (period_us >> CLOCK_AVERAGE_SHIFT)
+                                };
+                                clock_period_us.store(average.max(1), Ordering::Relaxed);
+                            }
+
                             // Minimal ISR work - just notify task
                             notifier.notify_and_yield(NonZeroU32::new(1).unwrap());
                         })
@@ -174,7 +373,8 @@ impl MeterHandler {
                 log::info!("Meter: Clock pin interrupt configured");
 
                 // Main meter loop
-                const WAKE_UP_THRESHOLD: usize = 10; // Pulses to start transmission
+                let abort_timeout_ticks =
+                    TRANSMIT_ABORT_TIMEOUT_MS * esp_idf_svc::sys::configTICK_RATE_HZ / 1000;
                 let mut bit_index = 0usize;
                 let mut response_bits: heapless::Vec<u8, 2048> = heapless::Vec::new();
 
@@ -183,8 +383,33 @@ impl MeterHandler {
                 log::info!("Meter: Ready - waiting for clock signals");
 
                 loop {
-                    // Wait for clock pulse notification from ISR
-                    notification.wait(u32::MAX);
+                    // Wait for clock pulse notification from ISR. While transmitting,
+                    // time out so a clock that stops mid-message doesn't leave us stuck
+                    // at a stale bit index for the next interrogation.
+                    let timeout = if meter.transmitting.load(Ordering::Relaxed) {
+                        abort_timeout_ticks
+                    } else {
+                        u32::MAX
+                    };
+                    if notification.wait(timeout).is_none() {
+                        if meter.transmitting.load(Ordering::Relaxed) {
+                            meter.events.record(MeterEventKind::TransmitAborted {
+                                sent: bit_index,
+                                total: response_bits.len(),
+                            });
+                            log::warn!(
+                                "Meter: Clock stopped - transmission aborted after {}/{} bits",
+                                bit_index,
+                                response_bits.len()
+                            );
+                            meter.transmitting.store(false, Ordering::Relaxed);
+                            meter.pulse_count.store(0, Ordering::Relaxed);
+                            bit_index = 0;
+                            data_pin.set_high().ok(); // Return to idle
+                            response_bits.clear();
+                        }
+                        continue;
+                    }
 
                     // Check if meter is enabled
                     if !meter.is_enabled() {
@@ -196,9 +421,12 @@ impl MeterHandler {
 
                     // Check if we should start transmitting
                     if !meter.transmitting.load(Ordering::Relaxed) {
-                        if pulse_count >= WAKE_UP_THRESHOLD {
+                        if pulse_count >= meter.wake_up_threshold() {
                             // Build response frames if needed
                             if response_bits.is_empty() {
+                                meter.events.record(MeterEventKind::WakeUp {
+                                    pulses: pulse_count,
+                                });
                                 log::info!(
                                     "Meter: Wake-up threshold reached, building response frames"
                                 );
@@ -216,6 +444,9 @@ impl MeterHandler {
                                 meter.bits_transmitted.fetch_add(1, Ordering::Relaxed);
                                 bit_index = 1;
 
+                                meter.events.record(MeterEventKind::TransmitStart {
+                                    bits: response_bits.len(),
+                                });
                                 log::info!(
                                     "Meter: Started transmission - {} total bits to send",
                                     response_bits.len()
@@ -242,6 +473,9 @@ impl MeterHandler {
                             bit_index = 0;
                             data_pin.set_high().ok(); // Return to idle
 
+                            meter.events.record(MeterEventKind::TransmitComplete {
+                                bits: response_bits.len(),
+                            });
                             log::info!(
                                 "Meter: Transmission complete - {} bits sent",
                                 response_bits.len()
@@ -262,3 +496,20 @@ impl MeterHandler {
         log::info!("Meter: Background thread spawned successfully");
     }
 }
+
+/// `message` with the status flags encoded; sent unmodified, with a warning,
+/// when it has no field to carry them
+fn flagged_message(config: &MeterConfig, message: &str) -> String<256> {
+    config
+        .status_flags
+        .apply(message, config.meter_type)
+        .unwrap_or_else(|| {
+            log::warn!(
+                "Meter: No {} field for the status flags, message sent unmodified",
+                config.meter_type.flags_field()
+            );
+            let mut unmodified = String::new();
+            let _ = unmodified.push_str(message);
+            unmodified
+        })
+}
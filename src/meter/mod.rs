@@ -1,5 +1,9 @@
 pub mod config;
+pub mod event_log;
 pub mod handler;
+pub mod storage;
 
-pub use config::{MeterConfig, MeterType};
-pub use handler::MeterHandler;
+pub use config::{MeterConfig, MeterType, StatusFlags, MAX_SCRIPT_MESSAGES};
+pub use event_log::{MeterEvent, MeterEventKind};
+pub use handler::{ClockRate, MeterHandler};
+pub use storage::MeterStorage;
@@ -0,0 +1,115 @@
+use super::config::{MeterConfig, MeterType, StatusFlags, MAX_SCRIPT_MESSAGES};
+use crate::config_store::erase_namespace;
+use anyhow::Result;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use heapless::String;
+
+/// NVS namespace holding the meter simulator configuration
+pub const METER_NVS_NAMESPACE: &str = "meter";
+
+// NVS keys (max 15 chars)
+const KEY_TYPE: &str = "type";
+const KEY_MESSAGE: &str = "message";
+const KEY_ENABLED: &str = "enabled";
+const KEY_DEBOUNCE: &str = "debounce_us";
+const KEY_THRESHOLD: &str = "wake_thresh";
+const KEY_SCRIPT_LEN: &str = "script_len";
+const KEY_FLAGS: &str = "status_flags";
+
+/// Persists `MeterConfig` to NVS so a bench simulator keeps its setup across power cycles
+pub struct MeterStorage {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl MeterStorage {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+        let nvs = EspNvs::new(partition, METER_NVS_NAMESPACE, true)?;
+        Ok(Self { nvs })
+    }
+
+    /// Load the saved configuration, or None if nothing has been saved yet
+    pub fn load(&self) -> Result<Option<MeterConfig>> {
+        let meter_type = match self.nvs.get_u8(KEY_TYPE)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        let mut config = MeterConfig {
+            meter_type: match meter_type {
+                1 => MeterType::Neptune,
+                _ => MeterType::Sensus,
+            },
+            ..Default::default()
+        };
+
+        let mut buf = [0u8; 257]; // 256 chars + NUL
+        if let Some(message) = self.nvs.get_str(KEY_MESSAGE, &mut buf)? {
+            config.response_message = to_message(message);
+        }
+        if let Some(enabled) = self.nvs.get_u8(KEY_ENABLED)? {
+            config.enabled = enabled != 0;
+        }
+        if let Some(debounce_us) = self.nvs.get_u32(KEY_DEBOUNCE)? {
+            config.debounce_us = debounce_us;
+        }
+        if let Some(threshold) = self.nvs.get_u32(KEY_THRESHOLD)? {
+            config.wake_up_threshold = threshold;
+        }
+        if let Some(flags) = self.nvs.get_u8(KEY_FLAGS)? {
+            config.status_flags = StatusFlags(flags);
+        }
+
+        let script_len = self.nvs.get_u8(KEY_SCRIPT_LEN)?.unwrap_or(0) as usize;
+        for i in 0..script_len.min(MAX_SCRIPT_MESSAGES) {
+            if let Some(message) = self.nvs.get_str(&script_key(i), &mut buf)? {
+                let _ = config.script.push(to_message(message));
+            }
+        }
+
+        Ok(Some(config))
+    }
+
+    /// Save the full configuration (runtime counters are not persisted)
+    pub fn save(&mut self, config: &MeterConfig) -> Result<()> {
+        let meter_type = match config.meter_type {
+            MeterType::Sensus => 0,
+            MeterType::Neptune => 1,
+        };
+        self.nvs.set_u8(KEY_TYPE, meter_type)?;
+        self.nvs
+            .set_str(KEY_MESSAGE, config.response_message.as_str())?;
+        self.nvs.set_u8(KEY_ENABLED, config.enabled as u8)?;
+        self.nvs.set_u32(KEY_DEBOUNCE, config.debounce_us)?;
+        self.nvs.set_u32(KEY_THRESHOLD, config.wake_up_threshold)?;
+        self.nvs.set_u8(KEY_FLAGS, config.status_flags.0)?;
+
+        for (i, message) in config.script.iter().enumerate() {
+            self.nvs.set_str(&script_key(i), message.as_str())?;
+        }
+        // Drop entries left over from a longer script
+        for i in config.script.len()..MAX_SCRIPT_MESSAGES {
+            self.nvs.remove(&script_key(i))?;
+        }
+        self.nvs.set_u8(KEY_SCRIPT_LEN, config.script.len() as u8)?;
+
+        log::info!("Meter: Configuration saved to NVS");
+        Ok(())
+    }
+
+    /// Erase the saved configuration (`factory_reset`); defaults apply from the next boot
+    pub fn erase(&mut self) -> Result<()> {
+        erase_namespace(&self.nvs)?;
+        log::info!("Meter: Configuration erased from NVS");
+        Ok(())
+    }
+}
+
+fn script_key(index: usize) -> std::string::String {
+    format!("script{}", index)
+}
+
+fn to_message(s: &str) -> String<256> {
+    let mut message = String::new();
+    let _ = message.push_str(s);
+    message
+}
@@ -0,0 +1,432 @@
+//! Multi-meter manager
+//!
+//! Lets one ESP32 in a pit read several adjacent meters: an analog
+//! multiplexer (e.g. 74HC4052 for 4 meters, two 74HC4051 for 8) switches the
+//! MTU clock (GPIO4) and data (GPIO5) lines between meters, driven by the
+//! `meters.select` GPIOs (binary channel number, first GPIO = bit 0).
+//!
+//! Each meter has an ID, a mux channel, a read interval and an MQTT subtopic:
+//! its readings go to `<data topic>/<subtopic>` with `meter_id` in the
+//! payload. Reads are sequential: when a meter is due and no read is running,
+//! its channel is selected and a read started; the next due meter waits until
+//! it finishes. Reads started by hand (CLI, button) are attributed to the
+//! selected meter. All meters are read once at boot (every wake with deep sleep).
+//!
+//! The list doubles as a registry of the meters' identities: each entry also
+//! records the meter type (its UART framing, applied before the read) and
+//! optionally the serial number the meter reports in its `IB` field. A read
+//! whose serial differs (wrong channel, swapped meter) is rejected or published
+//! with `serial_mismatch` set, per `meters.mismatch`.
+
+use crate::meter::MeterType;
+use crate::mtu::{serial_number, GpioMtuTimerV2, MtuCommand};
+use anyhow::Result;
+use esp_idf_hal::gpio::{AnyOutputPin, Output, PinDriver};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+pub const MAX_METERS: usize = 8;
+/// Three select lines address 8 channels
+pub const MAX_SELECT_GPIOS: usize = 3;
+
+/// Output-capable GPIOs not used by the MTU (4, 5) or the console UART
+pub const SELECT_GPIOS: &[u8] = &[2, 12, 13, 14, 15, 25, 26, 27, 32, 33];
+
+/// Mux switching and line settling before a read starts
+const SELECT_SETTLE: Duration = Duration::from_millis(20);
+/// Time for the MTU thread to pick up a start command
+const START_GRACE: Duration = Duration::from_secs(1);
+
+pub const MIN_INTERVAL_SECS: u32 = 60;
+pub const MAX_INTERVAL_SECS: u32 = 86_400;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeterChannel {
+    pub id: heapless::String<16>,
+    /// Multiplexer channel (0-7)
+    pub channel: u8,
+    pub interval_secs: u32,
+    /// Appended to the data topic
+    pub subtopic: heapless::String<32>,
+    /// Sets the UART framing for this meter's reads
+    #[serde(default)]
+    pub meter_type: MeterType,
+    /// Expected `IB` serial number (empty = not checked)
+    #[serde(default)]
+    pub serial: heapless::String<16>,
+}
+
+/// What to do with a read whose serial doesn't match the registered one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SerialMismatch {
+    /// Not published
+    #[default]
+    Reject,
+    /// Published with `serial_mismatch: true`
+    Flag,
+}
+
+impl SerialMismatch {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "reject" => Some(SerialMismatch::Reject),
+            "flag" => Some(SerialMismatch::Flag),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SerialMismatch::Reject => "reject",
+            SerialMismatch::Flag => "flag",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetersSettings {
+    /// Applied at boot; mux select lines, bit 0 first (empty = single meter)
+    pub select_gpios: heapless::Vec<u8, MAX_SELECT_GPIOS>,
+    /// Applied at boot
+    pub meters: heapless::Vec<MeterChannel, MAX_METERS>,
+    #[serde(default)]
+    pub mismatch: SerialMismatch,
+}
+
+/// Letters, digits, `-` and `_` (used in topics and payloads)
+pub fn is_valid_meter_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 16
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl MetersSettings {
+    /// Add or replace the meter with `id` (a replaced meter keeps its type and serial)
+    pub fn add(
+        &mut self,
+        id: &str,
+        channel: u8,
+        interval_secs: u32,
+        subtopic: Option<&str>,
+    ) -> Result<(), &'static str> {
+        if !is_valid_meter_id(id) {
+            return Err("Meter ID must be 1-16 letters, digits, '-' or '_'");
+        }
+        if usize::from(channel) >= 1 << MAX_SELECT_GPIOS {
+            return Err("Channel must be 0-7");
+        }
+        if !(MIN_INTERVAL_SECS..=MAX_INTERVAL_SECS).contains(&interval_secs) {
+            return Err("Interval must be 60-86400 seconds");
+        }
+        let subtopic = subtopic.unwrap_or(id);
+        if subtopic.is_empty()
+            || subtopic.contains(['+', '#', ' '])
+            || subtopic.starts_with('/')
+            || subtopic.ends_with('/')
+        {
+            return Err("Subtopic must not contain '+', '#' or spaces, or start/end with '/'");
+        }
+        if self
+            .meters
+            .iter()
+            .any(|meter| meter.channel == channel && meter.id != id)
+        {
+            return Err("Channel is already used by another meter");
+        }
+
+        let existing = self.meters.iter().find(|existing| existing.id == id);
+        let mut meter = MeterChannel {
+            id: heapless::String::new(),
+            channel,
+            interval_secs,
+            subtopic: heapless::String::new(),
+            meter_type: existing.map(|m| m.meter_type).unwrap_or_default(),
+            serial: existing.map(|m| m.serial.clone()).unwrap_or_default(),
+        };
+        meter.id.push_str(id).map_err(|_| "Meter ID too long")?;
+        meter
+            .subtopic
+            .push_str(subtopic)
+            .map_err(|_| "Subtopic too long (max 32 chars)")?;
+        match self.meters.iter_mut().find(|existing| existing.id == id) {
+            Some(existing) => *existing = meter,
+            None => self
+                .meters
+                .push(meter)
+                .map_err(|_| "Meter list full (max 8)")?,
+        }
+        Ok(())
+    }
+
+    /// `meters set <id> type|serial <value>`
+    pub fn set(&mut self, id: &str, field: &str, value: &str) -> Result<(), &'static str> {
+        let meter = self
+            .meters
+            .iter_mut()
+            .find(|meter| meter.id == id)
+            .ok_or("No such meter")?;
+        match field {
+            "type" => {
+                meter.meter_type =
+                    MeterType::from_name(value).ok_or("Type must be sensus or neptune")?;
+            }
+            "serial" => {
+                let mut serial = heapless::String::new();
+                if value != "none" {
+                    if value.is_empty() || !value.chars().all(|c| c.is_ascii_alphanumeric()) {
+                        return Err("Serial must be letters and digits ('none' clears)");
+                    }
+                    serial
+                        .push_str(value)
+                        .map_err(|_| "Serial too long (max 16 chars)")?;
+                }
+                meter.serial = serial;
+            }
+            _ => return Err("Field must be type or serial"),
+        }
+        Ok(())
+    }
+
+    /// Remove the meter with `id`; false if there is none
+    pub fn remove(&mut self, id: &str) -> bool {
+        let before = self.meters.len();
+        self.meters.retain(|meter| meter.id != id);
+        self.meters.len() != before
+    }
+
+    /// `meters list` output
+    pub fn describe(&self) -> String {
+        let mut out = format!(
+            "Mux select: {}\r\n",
+            if self.select_gpios.is_empty() {
+                "(none)".to_string()
+            } else {
+                self.select_gpios
+                    .iter()
+                    .map(|gpio| format!("GPIO{}", gpio))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+        );
+        if self.meters.is_empty() {
+            out.push_str("No meters configured (single meter on the MTU pins)\r\n");
+        }
+        for meter in &self.meters {
+            out.push_str(&format!(
+                "  {:<16} channel {}  every {}s  subtopic {}  {}  serial {}\r\n",
+                meter.id,
+                meter.channel,
+                meter.interval_secs,
+                meter.subtopic,
+                meter.meter_type.name(),
+                if meter.serial.is_empty() {
+                    "(any)"
+                } else {
+                    meter.serial.as_str()
+                }
+            ));
+        }
+        if !self.meters.is_empty() {
+            out.push_str(&format!("Serial mismatch: {}\r\n", self.mismatch.name()));
+        }
+        out
+    }
+}
+
+/// The meter a reading belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeterRef {
+    pub id: String,
+    pub subtopic: String,
+    /// The message carries another serial than the registered one
+    pub serial_mismatch: bool,
+}
+
+struct ManagedMeter {
+    meter: MeterRef,
+    channel: u8,
+    meter_type: MeterType,
+    serial: Option<String>,
+    interval: Duration,
+    next_due: Instant,
+}
+
+pub struct MeterManager {
+    select: Vec<PinDriver<'static, AnyOutputPin, Output>>,
+    meters: Vec<ManagedMeter>,
+    /// Meter whose channel is switched in
+    selected: Option<usize>,
+    /// When the last read was requested
+    started_at: Option<Instant>,
+    read_secs: u64,
+    mismatch: SerialMismatch,
+}
+
+impl MeterManager {
+    /// None when no meters are configured; `read_secs` is the length of each read
+    pub fn new(settings: &MetersSettings, read_secs: u64) -> Result<Option<Self>> {
+        if settings.meters.is_empty() {
+            return Ok(None);
+        }
+        let channels = 1usize << settings.select_gpios.len();
+        if let Some(meter) = settings
+            .meters
+            .iter()
+            .find(|meter| usize::from(meter.channel) >= channels)
+        {
+            anyhow::bail!(
+                "Meter {} is on channel {}, but {} select GPIO(s) address only {} channel(s)",
+                meter.id,
+                meter.channel,
+                settings.select_gpios.len(),
+                channels
+            );
+        }
+
+        let mut select = Vec::new();
+        for &gpio in &settings.select_gpios {
+            if !SELECT_GPIOS.contains(&gpio) {
+                anyhow::bail!("GPIO{} cannot be used as a mux select line", gpio);
+            }
+            // Safety: SELECT_GPIOS excludes the MTU and console pins; conflicts
+            // with the button, LED and peripherals are checked by the caller
+            let mut pin = PinDriver::output(unsafe { AnyOutputPin::new(gpio as i32) })?;
+            pin.set_low()?;
+            select.push(pin);
+        }
+
+        let now = Instant::now();
+        let meters = settings
+            .meters
+            .iter()
+            .map(|meter| ManagedMeter {
+                meter: MeterRef {
+                    id: meter.id.to_string(),
+                    subtopic: meter.subtopic.to_string(),
+                    serial_mismatch: false,
+                },
+                channel: meter.channel,
+                meter_type: meter.meter_type,
+                serial: (!meter.serial.is_empty()).then(|| meter.serial.to_string()),
+                interval: Duration::from_secs(meter.interval_secs.into()),
+                next_due: now,
+            })
+            .collect();
+        log::info!(
+            "🔌 Meters: {} meter(s) on {} mux channel(s)",
+            settings.meters.len(),
+            channels
+        );
+        Ok(Some(Self {
+            select,
+            meters,
+            selected: None,
+            started_at: None,
+            read_secs,
+            mismatch: settings.mismatch,
+        }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.meters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.meters.is_empty()
+    }
+
+    /// Meter the MTU is currently connected to
+    pub fn selected(&self) -> Option<MeterRef> {
+        self.selected.map(|index| self.meters[index].meter.clone())
+    }
+
+    /// Meter a read of `message` belongs to, checked against the registered
+    /// serial; Err with the reason when the read is to be rejected
+    pub fn attribute(&self, message: &str) -> Result<Option<MeterRef>, String> {
+        let Some(index) = self.selected else {
+            return Ok(None);
+        };
+        let managed = &self.meters[index];
+        let mut meter = managed.meter.clone();
+        if let Some(ref expected) = managed.serial {
+            let found = serial_number(message);
+            if found != Some(expected.as_str()) {
+                let reason = format!(
+                    "serial {} does not match {} registered for {}",
+                    found.unwrap_or("(none)"),
+                    expected,
+                    meter.id
+                );
+                if self.mismatch == SerialMismatch::Reject {
+                    return Err(reason);
+                }
+                log::warn!("⚠️  Meters: Flagging read, {}", reason);
+                meter.serial_mismatch = true;
+            }
+        }
+        Ok(Some(meter))
+    }
+
+    /// Some meter is due for a read or its read is just starting (deep
+    /// sleep waits until every meter has been read)
+    pub fn pending(&self) -> bool {
+        let now = Instant::now();
+        self.started_at.is_some_and(|at| at.elapsed() < START_GRACE)
+            || self.meters.iter().any(|meter| meter.next_due <= now)
+    }
+
+    /// Call regularly from the main loop: starts the read of the next due
+    /// meter once the MTU is idle
+    pub fn poll(&mut self, mtu: &GpioMtuTimerV2, mtu_cmd_sender: &Sender<MtuCommand>) {
+        let starting = self.started_at.is_some_and(|at| at.elapsed() < START_GRACE);
+        if mtu.is_running() || starting {
+            return;
+        }
+        let now = Instant::now();
+        let Some(index) = self
+            .meters
+            .iter()
+            .enumerate()
+            .filter(|(_, meter)| meter.next_due <= now)
+            .min_by_key(|(_, meter)| meter.next_due)
+            .map(|(index, _)| index)
+        else {
+            return;
+        };
+
+        if let Err(e) = self.select_channel(self.meters[index].channel) {
+            log::warn!("⚠️  Meters: Channel select failed: {:?}", e);
+            return;
+        }
+        self.selected = Some(index);
+        self.started_at = Some(now);
+        let meter = &mut self.meters[index];
+        meter.next_due = now + meter.interval;
+        mtu.set_framing(meter.meter_type.framing());
+        log::info!(
+            "📡 Meters: Reading {} (channel {}, {})",
+            meter.meter.id,
+            meter.channel,
+            meter.meter_type.name()
+        );
+        let _ = mtu_cmd_sender.send(MtuCommand::Start {
+            duration_secs: self.read_secs,
+        });
+    }
+
+    fn select_channel(&mut self, channel: u8) -> Result<()> {
+        for (bit, pin) in self.select.iter_mut().enumerate() {
+            if channel & (1 << bit) != 0 {
+                pin.set_high()?;
+            } else {
+                pin.set_low()?;
+            }
+        }
+        std::thread::sleep(SELECT_SETTLE);
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+//! Modbus RTU slave on UART2
+//!
+//! Lets SCADA systems and PLCs read the meter over RS-485 without MQTT (DI on
+//! GPIO17, RO on GPIO16, DE/RE on GPIO15). Function codes 0x03 and 0x04 read
+//! the same register map, listed in the README (Modbus RTU).
+
+use crate::mtu::{register_value, GpioMtuTimerV2};
+use crate::telemetry;
+use anyhow::Result;
+use esp_idf_hal::delay::TickType;
+use esp_idf_hal::gpio::{Gpio15, Gpio16, Gpio17, Output, PinDriver};
+use esp_idf_hal::uart::{config::Config as UartConfig, UartDriver, UART2};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// GPIOs taken by the RS-485 transceiver (DE/RE, RX, TX)
+pub const MODBUS_GPIOS: &[u8] = &[15, 16, 17];
+
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+/// Most registers in one read request (Modbus limit)
+const MAX_READ_COUNT: u16 = 125;
+
+const MESSAGE_START: u16 = 100;
+const MESSAGE_REGISTERS: u16 = 64;
+
+/// RTU frames are at most 256 bytes
+const MAX_FRAME_LEN: usize = 256;
+
+/// Wait for the first byte of a request; bounds how stale the read tracking gets
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModbusParity {
+    None,
+    /// 8E1, the Modbus default
+    #[default]
+    Even,
+    Odd,
+}
+
+impl ModbusParity {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(ModbusParity::None),
+            "even" => Some(ModbusParity::Even),
+            "odd" => Some(ModbusParity::Odd),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModbusParity::None => "none",
+            ModbusParity::Even => "even",
+            ModbusParity::Odd => "odd",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModbusSettings {
+    /// Applied at boot; slave address 1-247, 0 = Modbus off
+    pub address: u8,
+    pub baud_rate: u32,
+    pub parity: ModbusParity,
+}
+
+impl Default for ModbusSettings {
+    fn default() -> Self {
+        Self {
+            address: 0,
+            baud_rate: 9600,
+            parity: ModbusParity::Even,
+        }
+    }
+}
+
+/// Modbus CRC-16 (poly 0xA001, init 0xFFFF), sent low byte first
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte), |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Register values at the time of a request
+struct Snapshot {
+    register: u32,
+    successful: u32,
+    corrupted: u32,
+    secs_since_read: u16,
+    baud_rate: u32,
+    running: bool,
+    uptime_secs: u32,
+    boot_count: u32,
+    free_heap_kb: u16,
+    min_free_heap_kb: u16,
+    message: Vec<u8>,
+}
+
+impl Snapshot {
+    fn register(&self, address: u16) -> Option<u16> {
+        let high = |value: u32| (value >> 16) as u16;
+        let low = |value: u32| value as u16;
+        Some(match address {
+            0 => high(self.register),
+            1 => low(self.register),
+            2 => high(self.successful),
+            3 => low(self.successful),
+            4 => high(self.corrupted),
+            5 => low(self.corrupted),
+            6 => self.secs_since_read,
+            7 => self.baud_rate.min(u32::from(u16::MAX)) as u16,
+            8 => u16::from(self.running),
+            9 => 0,
+            10 => high(self.uptime_secs),
+            11 => low(self.uptime_secs),
+            12 => high(self.boot_count),
+            13 => low(self.boot_count),
+            14 => self.free_heap_kb,
+            15 => self.min_free_heap_kb,
+            16..=99 => return None,
+            n if n < MESSAGE_START + MESSAGE_REGISTERS => {
+                let index = usize::from(n - MESSAGE_START) * 2;
+                let byte = |i: usize| u16::from(self.message.get(i).copied().unwrap_or(0));
+                (byte(index) << 8) | byte(index + 1)
+            }
+            _ => return None,
+        })
+    }
+}
+
+pub struct ModbusSlave {
+    address: u8,
+    uart: UartDriver<'static>,
+    de: PinDriver<'static, Gpio15, Output>,
+    /// Silence that ends a request (3.5 characters, at least one tick)
+    frame_gap: u32,
+    mtu: Arc<GpioMtuTimerV2>,
+    boot_count: u32,
+    last_successful: u32,
+    last_read_at: Option<Instant>,
+}
+
+impl ModbusSlave {
+    /// Take UART2 and the transceiver pins and answer requests on a
+    /// background thread
+    pub fn spawn(
+        settings: &ModbusSettings,
+        mtu: Arc<GpioMtuTimerV2>,
+        boot_count: u32,
+        uart: UART2,
+        tx: Gpio17,
+        rx: Gpio16,
+        de: Gpio15,
+    ) -> Result<()> {
+        if settings.address == 0 {
+            return Ok(());
+        }
+        let config = UartConfig::new().baudrate(settings.baud_rate.into());
+        let config = match settings.parity {
+            ModbusParity::None => config.parity_none(),
+            ModbusParity::Even => config.parity_even(),
+            ModbusParity::Odd => config.parity_odd(),
+        };
+        let uart = UartDriver::new(
+            uart,
+            tx,
+            rx,
+            Option::<Gpio16>::None,
+            Option::<Gpio17>::None,
+            &config,
+        )?;
+        let mut de = PinDriver::output(de)?;
+        de.set_low()?;
+
+        // 11 bits per character
+        let gap_ms = (3.5 * 11.0 * 1000.0 / settings.baud_rate as f32).ceil() as u64;
+        let frame_gap = TickType::new_millis(gap_ms).ticks().max(1);
+        let (last_successful, _, _) = mtu.get_stats();
+
+        log::info!(
+            "🔌 Modbus: Slave {} at {} bps, parity {} (TX GPIO17, RX GPIO16, DE GPIO15)",
+            settings.address,
+            settings.baud_rate,
+            settings.parity.name()
+        );
+        let slave = Self {
+            address: settings.address,
+            uart,
+            de,
+            frame_gap,
+            mtu,
+            boot_count,
+            last_successful,
+            last_read_at: None,
+        };
+        std::thread::Builder::new()
+            .stack_size(4096)
+            .name("modbus".to_string())
+            .spawn(move || slave.run())?;
+        Ok(())
+    }
+
+    fn run(mut self) {
+        let mut frame = [0u8; MAX_FRAME_LEN];
+        loop {
+            self.track_reads();
+            let len = match self.read_frame(&mut frame) {
+                Ok(len) => len,
+                Err(e) => {
+                    log::warn!("⚠️  Modbus: UART read failed: {:?}", e);
+                    std::thread::sleep(IDLE_TIMEOUT);
+                    continue;
+                }
+            };
+            if let Some(reply) = self.reply(&frame[..len]) {
+                if let Err(e) = self.send(&reply) {
+                    log::warn!("⚠️  Modbus: Reply failed: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Note when the successful read count last went up
+    fn track_reads(&mut self) {
+        let (successful, _, _) = self.mtu.get_stats();
+        if successful != self.last_successful {
+            self.last_successful = successful;
+            self.last_read_at = Some(Instant::now());
+        }
+    }
+
+    /// One request: bytes until the line is silent for `frame_gap`
+    /// (0 when nothing arrived within `IDLE_TIMEOUT`)
+    fn read_frame(&self, frame: &mut [u8]) -> Result<usize> {
+        let idle = TickType::new_millis(IDLE_TIMEOUT.as_millis() as u64).ticks();
+        let mut len = self.uart.read(&mut frame[..1], idle)?;
+        if len == 0 {
+            return Ok(0);
+        }
+        while len < frame.len() {
+            match self.uart.read(&mut frame[len..len + 1], self.frame_gap)? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        Ok(len)
+    }
+
+    fn send(&mut self, reply: &[u8]) -> Result<()> {
+        self.de.set_high()?;
+        let result = self
+            .uart
+            .write(reply)
+            .and_then(|_| self.uart.wait_tx_done(TickType::new_millis(100).ticks()));
+        self.de.set_low()?;
+        result?;
+        Ok(())
+    }
+
+    /// Response to a request, None if it is not for this slave (or corrupted)
+    fn reply(&self, request: &[u8]) -> Option<Vec<u8>> {
+        if request.len() < 4 {
+            return None;
+        }
+        let (body, crc) = request.split_at(request.len() - 2);
+        if crc16(body) != u16::from_le_bytes([crc[0], crc[1]]) || body[0] != self.address {
+            return None;
+        }
+
+        let function = body[1];
+        let mut response = vec![self.address];
+        match function {
+            READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS if body.len() == 6 => {
+                let start = u16::from_be_bytes([body[2], body[3]]);
+                let count = u16::from_be_bytes([body[4], body[5]]);
+                if !(1..=MAX_READ_COUNT).contains(&count) {
+                    response.extend([function | 0x80, ILLEGAL_DATA_VALUE]);
+                } else {
+                    let snapshot = self.snapshot();
+                    let values: Option<Vec<u16>> = (0..count)
+                        .map(|i| {
+                            start
+                                .checked_add(i)
+                                .and_then(|address| snapshot.register(address))
+                        })
+                        .collect();
+                    match values {
+                        Some(values) => {
+                            response.extend([function, (values.len() * 2) as u8]);
+                            for value in values {
+                                response.extend(value.to_be_bytes());
+                            }
+                        }
+                        None => response.extend([function | 0x80, ILLEGAL_DATA_ADDRESS]),
+                    }
+                }
+            }
+            _ => response.extend([function | 0x80, ILLEGAL_FUNCTION]),
+        }
+        let crc = crc16(&response);
+        response.extend(crc.to_le_bytes());
+        Some(response)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let (successful, corrupted, _) = self.mtu.get_stats();
+        let message = self.mtu.get_last_message();
+        Snapshot {
+            register: message
+                .as_deref()
+                .and_then(register_value)
+                .unwrap_or(u32::MAX),
+            successful,
+            corrupted,
+            secs_since_read: self
+                .last_read_at
+                .map(|at| at.elapsed().as_secs().min(u64::from(u16::MAX - 1)) as u16)
+                .unwrap_or(u16::MAX),
+            baud_rate: self.mtu.get_baud_rate(),
+            running: self.mtu.is_running(),
+            uptime_secs: telemetry::uptime_secs() as u32,
+            boot_count: self.boot_count,
+            free_heap_kb: (telemetry::free_heap() / 1024) as u16,
+            min_free_heap_kb: (telemetry::min_free_heap() / 1024) as u16,
+            message: message
+                .map(|m| m.trim_end().as_bytes().to_vec())
+                .unwrap_or_default(),
+        }
+    }
+}
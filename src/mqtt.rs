@@ -1,11 +1,159 @@
+use crate::network_config::{MqttConfig, MqttTlsConfig};
 use anyhow::Result;
-use esp_idf_svc::mqtt::client::{EspMqttClient, EventPayload, MqttClientConfiguration, QoS};
+use esp_idf_svc::handle::RawHandle;
+use esp_idf_svc::mqtt::client::{
+    Details, EspMqttClient, EventPayload, LwtConfiguration, MessageId, MqttClientConfiguration, QoS,
+};
+use esp_idf_svc::sys::{self, esp};
+use esp_idf_svc::tls::X509;
 use log::{info, warn};
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::time::Duration;
 
 pub type MessageCallback = Arc<dyn Fn(&str, &[u8]) + Send + Sync>;
 
+/// Called with the topic and announced size of a message too large to receive
+pub type OversizedCallback = Arc<dyn Fn(&str, usize) + Send + Sync>;
+
+/// Largest incoming message reassembled from esp-mqtt's chunks (messages
+/// larger than the receive buffer arrive in several events)
+pub const MAX_RECEIVED_LEN: usize = 8192;
+
+/// Chunk size for `MqttClient::publish_chunked`, well below common broker limits
+pub const DEFAULT_CHUNK_SIZE: usize = 2048;
+
+/// How long each chunk of a chunked publish may wait for its PUBACK
+const CHUNK_ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Publishes waiting for their broker acknowledgement, resolved by the
+/// connection handler (true on PUBACK/PUBCOMP, false if dropped from the outbox)
+type PendingAcks = Arc<Mutex<HashMap<MessageId, mpsc::Sender<bool>>>>;
+
+/// Outcome of `MqttClient::publish_confirmed`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// Acknowledged by the broker (PUBACK for QoS 1, PUBCOMP for QoS 2)
+    Delivered,
+    /// QoS 0: handed to the client, the broker does not acknowledge
+    Sent,
+    /// No acknowledgement within the timeout (may still be delivered later)
+    TimedOut,
+    /// Removed from the outbox without being acknowledged
+    Dropped,
+}
+
+impl DeliveryStatus {
+    pub fn is_delivered(&self) -> bool {
+        matches!(self, DeliveryStatus::Delivered | DeliveryStatus::Sent)
+    }
+}
+
+/// Dispatches received messages to the handlers registered for matching
+/// topic filters (MQTT `+`/`#` wildcards). Every matching handler is called.
+#[derive(Clone, Default)]
+pub struct TopicRouter {
+    routes: Arc<Mutex<Vec<(String, MessageCallback)>>>,
+    oversized: Arc<Mutex<Option<OversizedCallback>>>,
+}
+
+impl TopicRouter {
+    pub fn on(&self, filter: &str, handler: MessageCallback) {
+        self.routes
+            .lock()
+            .unwrap()
+            .push((filter.to_string(), handler));
+    }
+
+    /// Returns the number of handlers called
+    pub fn dispatch(&self, topic: &str, data: &[u8]) -> usize {
+        // Collect first so handlers may register routes without deadlocking
+        let handlers: Vec<MessageCallback> = self
+            .routes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(filter, _)| topic_matches(filter, topic))
+            .map(|(_, handler)| handler.clone())
+            .collect();
+        for handler in &handlers {
+            handler(topic, data);
+        }
+        handlers.len()
+    }
+
+    pub fn on_oversized(&self, handler: OversizedCallback) {
+        *self.oversized.lock().unwrap() = Some(handler);
+    }
+
+    /// Report a message that was dropped for exceeding `MAX_RECEIVED_LEN`
+    pub fn reject(&self, topic: &str, size: usize) {
+        let handler = self.oversized.lock().unwrap().clone();
+        if let Some(handler) = handler {
+            handler(topic, size);
+        }
+    }
+}
+
+/// A message being reassembled from esp-mqtt's chunks
+struct PartialMessage {
+    topic: String,
+    data: Vec<u8>,
+    total: usize,
+}
+
+/// Log and record a complete received message, then hand it to the router
+fn deliver(status: &MqttStatus, router: &TopicRouter, topic: &str, data: &[u8]) {
+    if let Ok(msg_str) = std::str::from_utf8(data) {
+        info!("📩 MQTT received on '{}': {}", topic, msg_str);
+        *status.last_received_topic.lock().unwrap() = topic.to_string();
+        *status.last_received_message.lock().unwrap() = msg_str.to_string();
+        *status.receive_count.lock().unwrap() += 1;
+    } else {
+        info!(
+            "📩 MQTT received on '{}': {} bytes (non-UTF8)",
+            topic,
+            data.len()
+        );
+    }
+    if router.dispatch(topic, data) == 0 {
+        info!("MQTT: No handler for '{}'", topic);
+    }
+}
+
+/// MQTT topic filter matching: `+` matches one level, a trailing `#` any number
+/// of levels (including none). Topics starting with `$` (e.g. `$SYS/...`) are
+/// not matched by a wildcard in the first level.
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && (filter.starts_with('+') || filter.starts_with('#')) {
+        return false;
+    }
+    let mut topic_levels = topic.split('/');
+    for filter_level in filter.split('/') {
+        match filter_level {
+            "#" => return true,
+            "+" => {
+                if topic_levels.next().is_none() {
+                    return false;
+                }
+            }
+            level => {
+                if topic_levels.next() != Some(level) {
+                    return false;
+                }
+            }
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+/// Retained payloads on the availability topic. "offline" is only sent by the
+/// broker (Last Will) when a session drops unexpectedly; intentional on-demand
+/// disconnects leave the device "online".
+pub const AVAILABILITY_ONLINE: &str = "online";
+pub const AVAILABILITY_OFFLINE: &str = "offline";
+
 #[derive(Clone)]
 pub struct MqttStatus {
     pub broker_url: String,
@@ -18,6 +166,8 @@ pub struct MqttStatus {
     pub subscriptions: Arc<Mutex<Vec<String>>>,
     pub publish_count: Arc<Mutex<u32>>,
     pub receive_count: Arc<Mutex<u32>>,
+    pub failed_publishes: Arc<Mutex<u32>>, // Enqueue errors and outbox drops
+    pub unacked_publishes: Arc<Mutex<u32>>, // Confirmed publishes that timed out
 }
 
 impl Default for MqttStatus {
@@ -33,6 +183,109 @@ impl Default for MqttStatus {
             subscriptions: Arc::new(Mutex::new(Vec::new())),
             publish_count: Arc::new(Mutex::new(0)),
             receive_count: Arc::new(Mutex::new(0)),
+            failed_publishes: Arc::new(Mutex::new(0)),
+            unacked_publishes: Arc::new(Mutex::new(0)),
+        }
+    }
+}
+
+/// TLS material in the form esp-mqtt needs. The client only keeps pointers to
+/// the certificates, so they are copied (NUL-terminated) and leaked once at
+/// boot and shared by every on-demand connection.
+#[derive(Clone, Copy, Default)]
+pub struct MqttTls {
+    ca_cert: Option<X509<'static>>,
+    client_cert: Option<X509<'static>>,
+    client_key: Option<X509<'static>>,
+    alpn: Option<&'static [&'static str]>,
+}
+
+// PEMs embedded at build time from the files named by MQTT_CA_CERT,
+// MQTT_CLIENT_CERT and MQTT_CLIENT_KEY (see build.rs); empty when not set.
+// Entries stored in NVS (`mqtt_cert`) take precedence.
+const EMBEDDED_CA_CERT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/mqtt_ca.pem"));
+const EMBEDDED_CLIENT_CERT: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/mqtt_client_cert.pem"));
+const EMBEDDED_CLIENT_KEY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/mqtt_client_key.pem"));
+
+impl MqttTls {
+    /// Stored PEMs, falling back to the ones embedded at build time
+    pub fn from_config(config: &MqttTlsConfig) -> Self {
+        let tls = Self {
+            ca_cert: stored_or_embedded("CA", &config.ca_cert, EMBEDDED_CA_CERT),
+            client_cert: stored_or_embedded(
+                "client certificate",
+                &config.client_cert,
+                EMBEDDED_CLIENT_CERT,
+            ),
+            client_key: stored_or_embedded("client key", &config.client_key, EMBEDDED_CLIENT_KEY),
+            alpn: config.alpn.as_ref().map(|alpn| {
+                let protocol: &'static str = Box::leak(alpn.as_str().into());
+                let protocols: &'static [&'static str] = Box::leak(Box::new([protocol]));
+                protocols
+            }),
+        };
+        if tls.client_cert.is_some() != tls.client_key.is_some() {
+            warn!("⚠️  MQTT TLS: client certificate and key must both be set, not using either");
+        }
+        tls
+    }
+
+    pub fn has_client_cert(&self) -> bool {
+        self.client_cert.is_some() && self.client_key.is_some()
+    }
+}
+
+/// TLS material shared by every client created with `MqttClient::from_config`
+static SHARED_TLS: OnceLock<MqttTls> = OnceLock::new();
+
+fn stored_or_embedded(
+    what: &str,
+    stored: &Option<Vec<u8>>,
+    embedded: &'static [u8],
+) -> Option<X509<'static>> {
+    match stored.as_deref() {
+        Some(pem) => Some(leak_pem(pem)),
+        None if !embedded.is_empty() => {
+            info!("🔐 MQTT TLS: using embedded {}", what);
+            Some(leak_pem(embedded))
+        }
+        None => None,
+    }
+}
+
+fn leak_pem(pem: &[u8]) -> X509<'static> {
+    let mut data = pem.to_vec();
+    if data.last() != Some(&0) {
+        data.push(0);
+    }
+    X509::pem_until_nul(Box::leak(data.into_boxed_slice()))
+}
+
+/// Session behaviour of a connection (see `MqttConfig`)
+#[derive(Debug, Clone, Copy)]
+pub struct MqttSessionOptions {
+    pub clean_session: bool,
+    pub keep_alive: Duration,
+    pub reconnect_timeout: Duration,
+}
+
+impl Default for MqttSessionOptions {
+    fn default() -> Self {
+        Self {
+            clean_session: true,
+            keep_alive: Duration::from_secs(30),
+            reconnect_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl MqttSessionOptions {
+    pub fn from_config(config: &MqttConfig) -> Self {
+        Self {
+            clean_session: config.clean_session,
+            keep_alive: Duration::from_secs(config.keep_alive_secs.into()),
+            reconnect_timeout: Duration::from_secs(config.reconnect_timeout_secs.into()),
         }
     }
 }
@@ -40,17 +293,76 @@ impl Default for MqttStatus {
 pub struct MqttClient {
     client: Arc<Mutex<EspMqttClient<'static>>>,
     status: MqttStatus,
+    router: TopicRouter,
+    pending_acks: PendingAcks,
 }
 
 impl MqttClient {
+    /// Create a client from the stored settings. The TLS material is converted
+    /// on the first call and reused afterwards (configuration changes apply on reset).
+    pub fn from_config(config: &MqttConfig) -> Result<Self> {
+        Self::from_config_with_login(
+            config,
+            config.username.as_deref(),
+            config.password.as_deref(),
+        )
+    }
+
+    /// `from_config` with a login computed per connection (e.g. a SAS token)
+    pub fn from_config_with_login(
+        config: &MqttConfig,
+        username: Option<&str>,
+        password: Option<&str>,
+    ) -> Result<Self> {
+        let tls = SHARED_TLS.get_or_init(|| MqttTls::from_config(&config.tls));
+        Self::new(
+            &config.broker_url,
+            &config.client_id,
+            username,
+            password,
+            tls,
+            config.availability_topic.as_deref(),
+            MqttSessionOptions::from_config(config),
+        )
+    }
+
     pub fn new(
         broker_url: &str,
         client_id: &str,
-        message_callback: MessageCallback,
+        username: Option<&str>,
+        password: Option<&str>,
+        tls: &MqttTls,
+        availability_topic: Option<&str>,
+        session: MqttSessionOptions,
     ) -> Result<Self> {
+        let use_tls = broker_url.starts_with("mqtts://") || broker_url.starts_with("wss://");
+
         info!("Initializing MQTT client...");
         info!("  Broker: {}", broker_url);
         info!("  Client ID: {}", client_id);
+        if let Some(username) = username {
+            info!("  Username: {}", username);
+        }
+        info!(
+            "  Session: {}, keepalive {}s",
+            if session.clean_session {
+                "clean"
+            } else {
+                "persistent"
+            },
+            session.keep_alive.as_secs()
+        );
+        if use_tls {
+            info!(
+                "  🔐 TLS: CA {}, client cert {}",
+                if tls.ca_cert.is_some() {
+                    "custom"
+                } else {
+                    "bundle"
+                },
+                if tls.has_client_cert() { "yes" } else { "no" }
+            );
+        }
 
         let status = MqttStatus {
             broker_url: broker_url.to_string(),
@@ -58,18 +370,55 @@ impl MqttClient {
             ..Default::default()
         };
 
-        let mqtt_config = MqttClientConfiguration {
+        let mut mqtt_config = MqttClientConfiguration {
             client_id: Some(client_id),
-            keep_alive_interval: Some(std::time::Duration::from_secs(30)),
-            reconnect_timeout: Some(std::time::Duration::from_secs(5)),
+            keep_alive_interval: Some(session.keep_alive),
+            reconnect_timeout: Some(session.reconnect_timeout),
+            disable_clean_session: !session.clean_session,
+            username,
+            password,
+            lwt: availability_topic.map(|topic| LwtConfiguration {
+                topic,
+                payload: AVAILABILITY_OFFLINE.as_bytes(),
+                qos: QoS::AtLeastOnce,
+                retain: true,
+            }),
             ..Default::default()
         };
+        if use_tls {
+            match tls.ca_cert {
+                Some(ca_cert) => mqtt_config.server_certificate = Some(ca_cert),
+                // Public brokers: verify against the ESP-IDF root certificate bundle
+                None => {
+                    mqtt_config.crt_bundle_attach = Some(esp_idf_svc::sys::esp_crt_bundle_attach)
+                }
+            }
+            if tls.has_client_cert() {
+                mqtt_config.client_certificate = tls.client_cert;
+                mqtt_config.private_key = tls.client_key;
+            }
+            mqtt_config.alpn_protocols = tls.alpn;
+        }
 
         let (client, mut connection) = EspMqttClient::new(broker_url, &mqtt_config)?;
 
+        // Transmute to 'static: esp-mqtt copies the configuration strings and
+        // the certificates are 'static, so nothing borrowed here is kept
+        let client_static: EspMqttClient<'static> = unsafe { std::mem::transmute(client) };
+        let client = Arc::new(Mutex::new(client_static));
+
         info!("MQTT client created, spawning connection handler");
 
         let status_clone = status.clone();
+        let router = TopicRouter::default();
+        let router_clone = router.clone();
+        let pending_acks = PendingAcks::default();
+        let pending_acks_clone = pending_acks.clone();
+        // Birth message is published from the handler on every (re)connect.
+        // Only a weak reference: the thread outlives `disconnect`, and a strong
+        // one would keep the client (and its esp-mqtt task) alive for good.
+        let birth_client = Arc::downgrade(&client);
+        let birth_topic = availability_topic.map(|topic| topic.to_string());
 
         // Spawn connection handler thread
         std::thread::Builder::new()
@@ -79,6 +428,7 @@ impl MqttClient {
                 info!("MQTT connection handler started");
                 let mut consecutive_errors = 0u32;
                 let mut last_error_log_time = std::time::Instant::now();
+                let mut partial: Option<PartialMessage> = None;
 
                 loop {
                     // Check if we've been signaled to shut down
@@ -98,43 +448,93 @@ impl MqttClient {
                                 );
                                 status_clone.connected.store(true, Ordering::Relaxed);
                                 consecutive_errors = 0; // Reset error counter on success
+                                let birth = birth_topic.as_ref().zip(birth_client.upgrade());
+                                if let Some((topic, client)) = birth {
+                                    if let Err(e) = client.lock().unwrap().enqueue(
+                                        topic,
+                                        QoS::AtLeastOnce,
+                                        true,
+                                        AVAILABILITY_ONLINE.as_bytes(),
+                                    ) {
+                                        warn!("⚠️  MQTT availability publish failed: {:?}", e);
+                                    }
+                                }
                             }
                             EventPayload::Disconnected => {
                                 info!("🔌 MQTT disconnected from broker");
                                 status_clone.connected.store(false, Ordering::Relaxed);
-                                // In on-demand mode, disconnect is intentional - exit thread
-                                info!("🔌 MQTT connection handler exiting (clean disconnect)");
-                                break;
+                                // Exit only after shutdown(); after disconnect() the handler
+                                // waits for reconnect(), otherwise esp-mqtt reconnects by itself
+                                if status_clone.shutdown.load(Ordering::Relaxed) {
+                                    info!("🔌 MQTT connection handler exiting (clean disconnect)");
+                                    break;
+                                }
                             }
                             EventPayload::Received {
-                                topic: Some(topic_str),
+                                topic,
                                 data,
+                                details,
                                 ..
-                            } => {
-                                if let Ok(msg_str) = std::str::from_utf8(data) {
-                                    info!("📩 MQTT received on '{}': {}", topic_str, msg_str);
-                                    *status_clone.last_received_topic.lock().unwrap() =
-                                        topic_str.to_string();
-                                    *status_clone.last_received_message.lock().unwrap() =
-                                        msg_str.to_string();
-                                    *status_clone.receive_count.lock().unwrap() += 1;
-                                } else {
-                                    info!(
-                                        "📩 MQTT received on '{}': {} bytes (non-UTF8)",
-                                        topic_str,
-                                        data.len()
-                                    );
+                            } => match details {
+                                Details::Complete => {
+                                    if let Some(topic) = topic {
+                                        deliver(&status_clone, &router_clone, topic, data);
+                                    }
                                 }
-                                message_callback(topic_str, data);
-                            }
-                            EventPayload::Received { topic: None, .. } => {
-                                // Reduce log spam for this common case
-                            }
+                                Details::InitialChunk(chunk) => {
+                                    let topic = topic.unwrap_or_default();
+                                    partial = None;
+                                    if chunk.total_data_size > MAX_RECEIVED_LEN {
+                                        warn!(
+                                            "⚠️  MQTT message on '{}' too large ({} bytes, max {}), dropped",
+                                            topic, chunk.total_data_size, MAX_RECEIVED_LEN
+                                        );
+                                        router_clone.reject(topic, chunk.total_data_size);
+                                    } else {
+                                        let mut buffer = Vec::with_capacity(chunk.total_data_size);
+                                        buffer.extend_from_slice(data);
+                                        partial = Some(PartialMessage {
+                                            topic: topic.to_string(),
+                                            data: buffer,
+                                            total: chunk.total_data_size,
+                                        });
+                                    }
+                                }
+                                Details::SubsequentChunk(chunk) => match partial.take() {
+                                    Some(mut message)
+                                        if message.data.len() == chunk.current_data_offset =>
+                                    {
+                                        message.data.extend_from_slice(data);
+                                        if message.data.len() >= message.total {
+                                            deliver(
+                                                &status_clone,
+                                                &router_clone,
+                                                &message.topic,
+                                                &message.data,
+                                            );
+                                        } else {
+                                            partial = Some(message);
+                                        }
+                                    }
+                                    // Rest of an oversized message, or a chunk went missing
+                                    _ => {}
+                                },
+                            },
                             EventPayload::Subscribed(id) => {
                                 info!("✅ MQTT subscribed (message id: {})", id);
                             }
                             EventPayload::Published(id) => {
                                 info!("✅ MQTT published (message id: {})", id);
+                                if let Some(ack) = pending_acks_clone.lock().unwrap().remove(&id) {
+                                    let _ = ack.send(true);
+                                }
+                            }
+                            EventPayload::Deleted(id) => {
+                                warn!("⚠️  MQTT message {} dropped from outbox", id);
+                                *status_clone.failed_publishes.lock().unwrap() += 1;
+                                if let Some(ack) = pending_acks_clone.lock().unwrap().remove(&id) {
+                                    let _ = ack.send(false);
+                                }
                             }
                             EventPayload::Error(e) => {
                                 // Rate limit error logging to reduce spam
@@ -160,18 +560,17 @@ impl MqttClient {
                             status_clone.connected.store(false, Ordering::Relaxed);
                             consecutive_errors += 1;
 
-                            // Check if this is an INVALID_STATE error (client intentionally disconnected)
-                            // If so, exit the thread gracefully after a few attempts
-                            let error_str = format!("{:?}", e);
-                            let is_invalid_state = error_str.contains("INVALID_STATE");
-
-                            if is_invalid_state && consecutive_errors >= 3 {
-                                // Client was intentionally disconnected (on-demand mode)
-                                // Exit thread gracefully instead of continuing to retry
-                                info!("🔌 MQTT connection handler exiting (client disconnected)");
+                            // The client was dropped (see `Drop for MqttClient`): the
+                            // connection is gone, exit instead of retrying
+                            if status_clone.shutdown.load(Ordering::Relaxed) {
+                                info!("🔌 MQTT connection handler exiting (client dropped)");
                                 break;
                             }
 
+                            // INVALID_STATE: the client was stopped on purpose (disconnect)
+                            let error_str = format!("{:?}", e);
+                            let is_invalid_state = error_str.contains("INVALID_STATE");
+
                             // Exponential backoff: 1s, 2s, 5s, 10s, 30s, then 60s max
                             let backoff_secs = match consecutive_errors {
                                 1 => 1,
@@ -201,15 +600,25 @@ impl MqttClient {
                 }
             })?;
 
-        // Transmute to 'static - the client will live for the entire program
-        let client_static: EspMqttClient<'static> = unsafe { std::mem::transmute(client) };
-
         Ok(Self {
-            client: Arc::new(Mutex::new(client_static)),
+            client,
             status,
+            router,
+            pending_acks,
         })
     }
 
+    /// Handle messages on topics matching `filter` (subscribe separately).
+    /// Register before subscribing so retained messages are not missed.
+    pub fn on(&self, filter: &str, handler: MessageCallback) {
+        self.router.on(filter, handler);
+    }
+
+    /// Notified instead of the handlers when a message exceeds `MAX_RECEIVED_LEN`
+    pub fn on_oversized(&self, handler: OversizedCallback) {
+        self.router.on_oversized(handler);
+    }
+
     pub fn get_status(&self) -> MqttStatus {
         self.status.clone()
     }
@@ -219,10 +628,15 @@ impl MqttClient {
     }
 
     pub fn publish(&self, topic: &str, data: &[u8], qos: QoS, retain: bool) -> Result<()> {
-        self.client
+        if let Err(e) = self
+            .client
             .lock()
             .unwrap()
-            .enqueue(topic, qos, retain, data)?;
+            .enqueue(topic, qos, retain, data)
+        {
+            *self.status.failed_publishes.lock().unwrap() += 1;
+            return Err(e.into());
+        }
 
         *self.status.last_published_topic.lock().unwrap() = topic.to_string();
         *self.status.publish_count.lock().unwrap() += 1;
@@ -235,6 +649,141 @@ impl MqttClient {
         Ok(())
     }
 
+    /// Publish and wait up to `timeout` for the broker to acknowledge it
+    /// (PUBACK for QoS 1, PUBCOMP for QoS 2). QoS 0 returns `Sent` at once.
+    /// Errors only if the message could not be queued.
+    pub fn publish_confirmed(
+        &self,
+        topic: &str,
+        data: &[u8],
+        qos: QoS,
+        retain: bool,
+        timeout: Duration,
+    ) -> Result<DeliveryStatus> {
+        let (ack_tx, ack_rx) = mpsc::channel();
+        let id = {
+            // Held across enqueue so the acknowledgement cannot arrive before
+            // the message id is registered
+            let mut pending = self.pending_acks.lock().unwrap();
+            let id = match self
+                .client
+                .lock()
+                .unwrap()
+                .enqueue(topic, qos, retain, data)
+            {
+                Ok(id) => id,
+                Err(e) => {
+                    *self.status.failed_publishes.lock().unwrap() += 1;
+                    return Err(e.into());
+                }
+            };
+            if !matches!(qos, QoS::AtMostOnce) {
+                pending.insert(id, ack_tx);
+            }
+            id
+        };
+
+        *self.status.last_published_topic.lock().unwrap() = topic.to_string();
+        *self.status.publish_count.lock().unwrap() += 1;
+
+        if matches!(qos, QoS::AtMostOnce) {
+            info!(
+                "📤 MQTT enqueued publish to '{}': {} bytes (QoS 0, unconfirmed)",
+                topic,
+                data.len()
+            );
+            return Ok(DeliveryStatus::Sent);
+        }
+
+        info!(
+            "⏳ MQTT waiting for acknowledgement of message {} to '{}' ({} bytes)",
+            id,
+            topic,
+            data.len()
+        );
+        let status = match ack_rx.recv_timeout(timeout) {
+            Ok(true) => DeliveryStatus::Delivered,
+            Ok(false) => DeliveryStatus::Dropped,
+            Err(_) => {
+                self.pending_acks.lock().unwrap().remove(&id);
+                *self.status.unacked_publishes.lock().unwrap() += 1;
+                DeliveryStatus::TimedOut
+            }
+        };
+        match status {
+            DeliveryStatus::Delivered => info!("✅ MQTT message {} acknowledged", id),
+            _ => warn!("⚠️  MQTT message {} not acknowledged: {:?}", id, status),
+        }
+        Ok(status)
+    }
+
+    /// Publish a payload too large for one message: a JSON manifest to `topic`,
+    /// then the data in `chunk_size` pieces to `{topic}/{transfer}/{index}`.
+    /// Each chunk waits for its PUBACK, so the outbox never holds more than
+    /// one; the transfer is aborted if a chunk is not acknowledged.
+    /// Returns the transfer ID.
+    pub fn publish_chunked(&self, topic: &str, data: &[u8], chunk_size: usize) -> Result<u32> {
+        if chunk_size == 0 {
+            return Err(anyhow::anyhow!("Chunk size must be at least 1 byte"));
+        }
+        // Unique per boot; the microsecond timer makes reuse across boots unlikely
+        let transfer = (unsafe { esp_idf_svc::sys::esp_timer_get_time() } as u32) | 1;
+        let chunks = data.len().div_ceil(chunk_size);
+
+        let manifest = serde_json::json!({
+            "transfer": transfer,
+            "size": data.len(),
+            "chunk_size": chunk_size,
+            "chunks": chunks,
+            "crc32": crc32(data),
+        })
+        .to_string();
+        info!(
+            "📤 MQTT chunked transfer {} to '{}': {} bytes in {} chunks",
+            transfer,
+            topic,
+            data.len(),
+            chunks
+        );
+        let delivery = self.publish_confirmed(
+            topic,
+            manifest.as_bytes(),
+            QoS::AtLeastOnce,
+            false,
+            CHUNK_ACK_TIMEOUT,
+        )?;
+        if !delivery.is_delivered() {
+            return Err(anyhow::anyhow!(
+                "Manifest of transfer {} not acknowledged: {:?}",
+                transfer,
+                delivery
+            ));
+        }
+
+        for (index, chunk) in data.chunks(chunk_size).enumerate() {
+            let chunk_topic = format!("{}/{}/{}", topic, transfer, index);
+            let delivery = self.publish_confirmed(
+                &chunk_topic,
+                chunk,
+                QoS::AtLeastOnce,
+                false,
+                CHUNK_ACK_TIMEOUT,
+            )?;
+            if !delivery.is_delivered() {
+                return Err(anyhow::anyhow!(
+                    "Chunk {}/{} of transfer {} not acknowledged: {:?}",
+                    index + 1,
+                    chunks,
+                    transfer,
+                    delivery
+                ));
+            }
+        }
+
+        info!("✅ MQTT chunked transfer {} complete", transfer);
+        Ok(transfer)
+    }
+
     pub fn subscribe(&self, topic: &str, qos: QoS) -> Result<()> {
         self.client.lock().unwrap().subscribe(topic, qos)?;
 
@@ -257,6 +806,39 @@ impl MqttClient {
         Ok(())
     }
 
+    /// Close the connection cleanly (DISCONNECT, so the Last Will is not
+    /// published) and stop reconnecting. The connection handler thread and
+    /// the registered handlers stay in place for `reconnect`.
+    pub fn disconnect(&self) -> Result<()> {
+        let handle = self.client.lock().unwrap().handle();
+        esp!(unsafe { sys::esp_mqtt_client_stop(handle) })?;
+        self.status.connected.store(false, Ordering::Relaxed);
+        info!("🔌 MQTT disconnected (client kept for reconnect)");
+        Ok(())
+    }
+
+    /// Connect again after `disconnect`; see `wait_connected`. Subscriptions
+    /// must be renewed unless the broker kept the session.
+    pub fn reconnect(&self) -> Result<()> {
+        let handle = self.client.lock().unwrap().handle();
+        esp!(unsafe { sys::esp_mqtt_client_start(handle) })?;
+        info!("🔄 MQTT reconnecting...");
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for the broker to accept the connection
+    pub fn wait_connected(&self, timeout: Duration) -> bool {
+        let started = std::time::Instant::now();
+        while !self.is_connected() {
+            if started.elapsed() >= timeout {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        true
+    }
+
+    /// Stop the connection handler thread for good (the client is about to be dropped)
     pub fn shutdown(&self) {
         info!("🔌 MQTT: Signaling connection handler to shutdown...");
         self.status.shutdown.store(true, Ordering::Relaxed);
@@ -267,3 +849,70 @@ impl MqttClient {
         info!("✅ MQTT: Shutdown signal sent");
     }
 }
+
+impl Drop for MqttClient {
+    /// Tell the connection handler to exit; dropping the last reference to
+    /// the esp-mqtt client then destroys it, ending the handler's wait
+    fn drop(&mut self) {
+        self.status.shutdown.store(true, Ordering::Relaxed);
+        self.status.connected.store(false, Ordering::Relaxed);
+    }
+}
+
+/// CRC-32 (IEEE 802.3, as used by zlib) of a chunked transfer, for reassembly checks
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_topic() {
+        assert!(topic_matches("istorrs/mtu/control", "istorrs/mtu/control"));
+        assert!(!topic_matches(
+            "istorrs/mtu/control",
+            "istorrs/mtu/control/x"
+        ));
+        assert!(!topic_matches("istorrs/mtu/control", "istorrs/mtu"));
+    }
+
+    #[test]
+    fn plus_matches_one_level() {
+        assert!(topic_matches("istorrs/+/control", "istorrs/mtu/control"));
+        assert!(topic_matches("istorrs/mtu/+", "istorrs/mtu/"));
+        assert!(!topic_matches("istorrs/+/control", "istorrs/a/b/control"));
+        assert!(!topic_matches("istorrs/mtu/+", "istorrs/mtu"));
+    }
+
+    #[test]
+    fn trailing_hash_matches_any_levels() {
+        assert!(topic_matches("istorrs/#", "istorrs/mtu/control"));
+        assert!(topic_matches("istorrs/#", "istorrs/mtu"));
+        assert!(topic_matches("#", "istorrs/mtu/control"));
+        assert!(!topic_matches("istorrs/#", "other/mtu"));
+    }
+
+    #[test]
+    fn hash_matches_the_parent_level() {
+        assert!(topic_matches("istorrs/mtu/#", "istorrs/mtu"));
+        assert!(topic_matches("istorrs/+/#", "istorrs/mtu"));
+    }
+
+    #[test]
+    fn dollar_topics_need_an_explicit_first_level() {
+        assert!(!topic_matches("#", "$SYS/broker/uptime"));
+        assert!(!topic_matches("+/broker/uptime", "$SYS/broker/uptime"));
+        assert!(topic_matches("$SYS/#", "$SYS/broker/uptime"));
+        assert!(topic_matches("$SYS/+/uptime", "$SYS/broker/uptime"));
+    }
+}
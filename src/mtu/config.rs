@@ -1,4 +1,5 @@
 use heapless::String;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub struct MtuConfig {
@@ -27,11 +28,14 @@ pub struct MtuConfig {
     pub corrupted_reads: u32,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum UartFraming {
     /// 7 data bits, even parity, 1 stop bit (Sensus Standard)
+    #[default]
+    #[serde(rename = "7E1")]
     SevenE1,
     /// 7 data bits, even parity, 2 stop bits (Neptune)
+    #[serde(rename = "7E2")]
     SevenE2,
 }
 
@@ -42,6 +46,22 @@ impl UartFraming {
             UartFraming::SevenE2 => 11, // 1 start + 7 data + 1 parity + 2 stop
         }
     }
+
+    /// Short name, e.g. "7E1"
+    pub fn name(self) -> &'static str {
+        match self {
+            UartFraming::SevenE1 => "7E1",
+            UartFraming::SevenE2 => "7E2",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "7E1" => Some(UartFraming::SevenE1),
+            "7E2" => Some(UartFraming::SevenE2),
+            _ => None,
+        }
+    }
 }
 
 impl MtuConfig {
@@ -1,14 +1,16 @@
-use super::config::MtuConfig;
+use super::config::{MtuConfig, UartFraming};
 use super::error::{MtuError, MtuResult};
 use super::uart_framing::{extract_char_from_frame, UartFrame};
+use crate::watchdog;
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use esp_idf_hal::gpio::{Input, Output, Pin, PinDriver};
 use esp_idf_hal::task::notification::Notification;
 use esp_idf_hal::timer::{config::Config as TimerConfig, TimerDriver, TIMER00};
 use heapless::String;
 use std::num::NonZeroU32;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 /// Commands that can be sent to the MTU background thread
 #[derive(Debug, Clone)]
@@ -21,6 +23,36 @@ pub enum MtuCommand {
     SetBaudRate { baud_rate: u32 },
 }
 
+/// Live view of an MTU read, for the WebSocket stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiveEvent {
+    /// Sampled data bit (only with live bits enabled)
+    Bit(u8),
+    /// Decoded character
+    Char(char),
+    /// Frame that failed parity/stop-bit validation or timed out
+    FrameError,
+}
+
+/// Outcome of one MTU read (see `subscribe_result`)
+#[derive(Debug, Clone)]
+pub struct ReadResult {
+    /// Decoded message, also when corrupted
+    pub message: Option<String<256>>,
+    /// Frame errors, no message, or a mismatch with the expected message
+    pub corrupted: bool,
+    pub frame_errors: usize,
+    /// Clock cycles (timer ticks) of the read
+    pub cycles: usize,
+    pub duration: Duration,
+}
+
+/// The idle MTU thread wakes this often to feed the task watchdog
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Live events buffered before new ones are dropped (the read never waits on a slow viewer)
+const LIVE_QUEUE_LEN: usize = 512;
+
 /// MTU implementation using hardware timer ISR -> Task pattern
 /// ISR handles precise timing, signals task which handles GPIO
 pub struct GpioMtuTimerV2 {
@@ -30,6 +62,11 @@ pub struct GpioMtuTimerV2 {
     last_bit: Arc<AtomicU8>,
     last_message: Mutex<Option<String<256>>>,
     message_complete: Arc<AtomicBool>, // Signals when a complete message is received
+    live_tap: Mutex<Option<SyncSender<LiveEvent>>>,
+    live_bits: AtomicBool,
+    check_expected: AtomicBool,
+    /// One-shot receivers of the next read's result
+    result_waiters: Mutex<Vec<Sender<ReadResult>>>,
 }
 
 use core::sync::atomic::AtomicU8;
@@ -43,9 +80,37 @@ impl GpioMtuTimerV2 {
             last_bit: Arc::new(AtomicU8::new(0)),
             last_message: Mutex::new(None),
             message_complete: Arc::new(AtomicBool::new(false)),
+            live_tap: Mutex::new(None),
+            live_bits: AtomicBool::new(false),
+            check_expected: AtomicBool::new(false),
+            result_waiters: Mutex::new(Vec::new()),
         }
     }
 
+    /// Receive the result of the next read that finishes (once)
+    pub fn subscribe_result(&self) -> Receiver<ReadResult> {
+        let (tx, rx) = channel();
+        self.result_waiters.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Stream decoded characters (and sampled bits, see `set_live_bits`) of
+    /// every read; replaces any earlier subscriber
+    pub fn subscribe_live(&self) -> Receiver<LiveEvent> {
+        let (tx, rx) = sync_channel(LIVE_QUEUE_LEN);
+        *self.live_tap.lock().unwrap() = Some(tx);
+        rx
+    }
+
+    /// Include every sampled bit in the live stream (debug view)
+    pub fn set_live_bits(&self, enabled: bool) {
+        self.live_bits.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn live_bits(&self) -> bool {
+        self.live_bits.load(Ordering::Relaxed)
+    }
+
     pub fn get_baud_rate(&self) -> u32 {
         let config = self.config.lock().unwrap();
         config.baud_rate
@@ -56,12 +121,53 @@ impl GpioMtuTimerV2 {
         config.baud_rate = baud_rate;
     }
 
+    pub fn get_framing(&self) -> UartFraming {
+        self.config.lock().unwrap().framing
+    }
+
+    /// Takes effect with the next frame (set it before starting a read)
+    pub fn set_framing(&self, framing: UartFraming) {
+        self.config.lock().unwrap().framing = framing;
+    }
+
+    /// Count clean messages that differ from `expected` as corrupted (bench
+    /// testing against a known meter); None turns the check off
+    pub fn set_expected_message(&self, expected: Option<String<256>>) {
+        let mut config = self.config.lock().unwrap();
+        match expected {
+            Some(expected) => {
+                log::info!("MTU: Expected message set to: {}", expected.trim_end());
+                config.expected_message = expected;
+                self.check_expected.store(true, Ordering::Relaxed);
+            }
+            None => {
+                log::info!("MTU: Expected message check disabled");
+                self.check_expected.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// None while the check is off
+    pub fn get_expected_message(&self) -> Option<String<256>> {
+        if !self.check_expected.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some(self.config.lock().unwrap().expected_message.clone())
+    }
+
     pub fn get_stats(&self) -> (u32, u32, usize) {
         let config = self.config.lock().unwrap();
         let cycles = self.clock_cycles.load(Ordering::Relaxed);
         (config.successful_reads, config.corrupted_reads, cycles)
     }
 
+    /// Continue counting from statistics kept across deep sleep
+    pub fn restore_stats(&self, successful_reads: u32, corrupted_reads: u32) {
+        let mut config = self.config.lock().unwrap();
+        config.successful_reads = successful_reads;
+        config.corrupted_reads = corrupted_reads;
+    }
+
     pub fn reset_stats(&self) {
         let mut config = self.config.lock().unwrap();
         config.successful_reads = 0;
@@ -135,9 +241,14 @@ impl GpioMtuTimerV2 {
                 }
                 log::info!("MTU: Timer ISR subscription created (persistent)");
 
+                if watchdog::subscribe_current_task() {
+                    log::info!("MTU: Thread watched by the task watchdog");
+                }
+
                 // MTU thread loop - waits for commands
                 loop {
-                    match cmd_rx.recv() {
+                    watchdog::feed();
+                    match cmd_rx.recv_timeout(COMMAND_POLL_INTERVAL) {
                         Ok(MtuCommand::Start { duration_secs }) => {
                             log::info!("MTU: Received Start command for {} seconds", duration_secs);
 
@@ -182,7 +293,10 @@ impl GpioMtuTimerV2 {
                                 );
                             }
                         }
-                        Err(_) => {
+                        Err(RecvTimeoutError::Timeout) => {
+                            // Idle: loop around to feed the watchdog
+                        }
+                        Err(RecvTimeoutError::Disconnected) => {
                             // Channel closed - exit thread
                             log::info!("MTU: Command channel closed, thread exiting");
                             break;
@@ -239,6 +353,12 @@ impl GpioMtuTimerV2 {
         let uart_last_message_clone = uart_last_message.clone();
         let uart_frame_errors = Arc::new(Mutex::new(0usize));
         let uart_frame_errors_clone = uart_frame_errors.clone();
+        let live_tap = self.live_tap.lock().unwrap().clone();
+        let live_bits = if self.live_bits.load(Ordering::Relaxed) {
+            live_tap.clone()
+        } else {
+            None
+        };
 
         let uart_handle = std::thread::Builder::new()
             .stack_size(8192)
@@ -250,6 +370,7 @@ impl GpioMtuTimerV2 {
                     bit_receiver,
                     uart_last_message_clone,
                     uart_frame_errors_clone,
+                    live_tap,
                 );
             })
             .map_err(|_| MtuError::GpioError)?;
@@ -335,6 +456,9 @@ impl GpioMtuTimerV2 {
                         if bit_sender.send(bit).is_err() {
                             // Channel closed - UART task ended
                         }
+                        if let Some(tap) = &live_bits {
+                            let _ = tap.try_send(LiveEvent::Bit(bit));
+                        }
 
                         // Log first 20 samples for debugging
                         if sample_count <= 20 {
@@ -347,6 +471,7 @@ impl GpioMtuTimerV2 {
 
             // Log status every second
             if start.elapsed().as_secs() > last_log_time.elapsed().as_secs() {
+                watchdog::feed();
                 let current_cycles = self.clock_cycles.load(Ordering::Relaxed);
                 let cycles_per_sec = current_cycles - last_cycles;
                 last_cycles = current_cycles;
@@ -422,7 +547,31 @@ impl GpioMtuTimerV2 {
         let mut config = self.config.lock().unwrap();
 
         // Message is corrupted if we have frame errors OR no message received
-        let is_corrupted = frame_errors > 0 || received_message.is_none();
+        let mut is_corrupted = frame_errors > 0 || received_message.is_none();
+
+        // ...or, with an expected message set, if it reads differently
+        if let Some(ref msg) = received_message {
+            let expected = config.expected_message.trim_end();
+            if !is_corrupted
+                && self.check_expected.load(Ordering::Relaxed)
+                && msg.trim_end() != expected
+            {
+                log::warn!(
+                    "MTU: Message does not match expected - Expected: '{}', Received: '{}'",
+                    expected,
+                    msg.trim_end()
+                );
+                is_corrupted = true;
+            }
+        }
+
+        let result = ReadResult {
+            message: received_message.clone(),
+            corrupted: is_corrupted,
+            frame_errors,
+            cycles: total_cycles,
+            duration: start.elapsed(),
+        };
 
         if let Some(msg) = received_message {
             log::info!("  Received message: '{}'", msg.as_str());
@@ -471,6 +620,9 @@ impl GpioMtuTimerV2 {
         }
         drop(config);
 
+        for waiter in self.result_waiters.lock().unwrap().drain(..) {
+            let _ = waiter.send(result.clone());
+        }
         Ok(())
     }
 
@@ -483,7 +635,14 @@ impl GpioMtuTimerV2 {
         bit_receiver: Receiver<u8>,
         last_message: Arc<Mutex<Option<String<256>>>>,
         frame_error_count: Arc<Mutex<usize>>,
+        live_tap: Option<SyncSender<LiveEvent>>,
     ) {
+        let live = |event| {
+            if let Some(tap) = &live_tap {
+                let _ = tap.try_send(event);
+            }
+        };
+
         log::info!("UART: Framing task started");
 
         // Wait for idle line (consecutive 1-bits) to synchronize to frame boundaries
@@ -579,6 +738,7 @@ impl GpioMtuTimerV2 {
             if bits_received != frame_size {
                 // Incomplete frame
                 frame_errors += 1;
+                live(LiveEvent::FrameError);
                 continue;
             }
 
@@ -589,6 +749,7 @@ impl GpioMtuTimerV2 {
                         Ok(ch) => {
                             frames_decoded += 1;
                             let _ = received_chars.push(ch);
+                            live(LiveEvent::Char(ch));
 
                             log::info!(
                                 "UART: Frame {} -> char: {:?} (ASCII {}), message length: {}",
@@ -622,6 +783,7 @@ impl GpioMtuTimerV2 {
                         }
                         Err(e) => {
                             frame_errors += 1;
+                            live(LiveEvent::FrameError);
                             log::warn!(
                                 "UART: Frame validation error: {:?}, bits: {:?}",
                                 e,
@@ -632,6 +794,7 @@ impl GpioMtuTimerV2 {
                 }
                 Err(e) => {
                     frame_errors += 1;
+                    live(LiveEvent::FrameError);
                     log::warn!(
                         "UART: Frame creation error: {:?}, {} bits received",
                         e,
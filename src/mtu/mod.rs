@@ -10,5 +10,24 @@ pub use config::UartFraming;
 pub use error::{MtuError, MtuResult};
 pub use gpio_mtu::GpioMtu;
 pub use gpio_mtu_timer::GpioMtuTimer;
-pub use gpio_mtu_timer_v2::{GpioMtuTimerV2, MtuCommand};
+pub use gpio_mtu_timer_v2::{GpioMtuTimerV2, LiveEvent, MtuCommand, ReadResult};
 pub use uart_framing::{extract_char_from_frame, UartFrame};
+
+/// Register value of a meter response: 200 for "V;RB00000200;IB61564400;..."
+pub fn register_value(message: &str) -> Option<u32> {
+    message
+        .split(';')
+        .find_map(|field| field.strip_prefix("RB"))?
+        .trim_end()
+        .parse()
+        .ok()
+}
+
+/// Meter serial number of a meter response: "61564400" for "V;RB00000200;IB61564400;..."
+pub fn serial_number(message: &str) -> Option<&str> {
+    message
+        .split(';')
+        .find_map(|field| field.strip_prefix("IB"))
+        .map(str::trim_end)
+        .filter(|serial| !serial.is_empty())
+}
@@ -0,0 +1,80 @@
+//! Transport-independent network link used by the MQTT publish path
+//!
+//! `WifiManager`, `EthernetManager` and `CellularModem` implement
+//! `NetworkLink`; which one is used is chosen at boot from the
+//! `network.transport` and `cellular.apn` config keys (WiFi plus cellular go
+//! through `connectivity::FailoverLink`).
+
+use crate::wifi::{LinkStats, WifiManager};
+use anyhow::Result;
+use std::net::Ipv4Addr;
+
+pub trait NetworkLink {
+    /// Short name for logs and payloads ("wifi", "ethernet", "cellular")
+    fn name(&self) -> &'static str;
+
+    /// Bring the link up (blocking until an IP address is assigned)
+    fn connect(&mut self) -> Result<()>;
+
+    fn disconnect(&mut self) -> Result<()>;
+
+    fn is_connected(&self) -> Result<bool>;
+
+    fn get_ip(&self) -> Result<Ipv4Addr>;
+
+    /// Interface MAC address, formatted `aa:bb:cc:dd:ee:ff`
+    fn get_mac(&self) -> Result<String>;
+
+    /// Signal strength in dBm, for wireless links
+    fn get_rssi(&self) -> Option<i8> {
+        None
+    }
+
+    /// Radio channel, for wireless links
+    fn get_channel(&self) -> Option<u8> {
+        None
+    }
+
+    /// Connection statistics, where the link tracks them
+    fn link_stats(&self) -> Option<LinkStats> {
+        None
+    }
+}
+
+impl NetworkLink for WifiManager {
+    fn name(&self) -> &'static str {
+        "wifi"
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        self.reconnect(None, None)
+    }
+
+    fn disconnect(&mut self) -> Result<()> {
+        WifiManager::disconnect(self)
+    }
+
+    fn is_connected(&self) -> Result<bool> {
+        WifiManager::is_connected(self)
+    }
+
+    fn get_ip(&self) -> Result<Ipv4Addr> {
+        WifiManager::get_ip(self)
+    }
+
+    fn get_mac(&self) -> Result<String> {
+        WifiManager::get_mac(self)
+    }
+
+    fn get_rssi(&self) -> Option<i8> {
+        WifiManager::get_rssi(self).ok()
+    }
+
+    fn get_channel(&self) -> Option<u8> {
+        WifiManager::get_channel(self).ok()
+    }
+
+    fn link_stats(&self) -> Option<LinkStats> {
+        Some(WifiManager::link_stats(self))
+    }
+}
@@ -1,41 +1,483 @@
+use crate::payloads::PayloadFormat;
 use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
 
+/// When the network link and MQTT session are up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectivityMode {
+    /// Connect for each publish, then disconnect (lowest power)
+    #[default]
+    OnDemand,
+    /// Stay connected; control commands arrive immediately
+    Persistent,
+}
+
+impl ConnectivityMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "on_demand" => Some(ConnectivityMode::OnDemand),
+            "persistent" => Some(ConnectivityMode::Persistent),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConnectivityMode::OnDemand => "on_demand",
+            ConnectivityMode::Persistent => "persistent",
+        }
+    }
+}
+
+/// Link used for the MQTT publish path
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkTransport {
+    #[default]
+    Wifi,
+    /// W5500 SPI Ethernet module (for installs inside metal meter pits)
+    Ethernet,
+}
+
+impl NetworkTransport {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wifi" => Some(NetworkTransport::Wifi),
+            "ethernet" => Some(NetworkTransport::Ethernet),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NetworkTransport::Wifi => "wifi",
+            NetworkTransport::Ethernet => "ethernet",
+        }
+    }
+}
+
+/// Where readings are sent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Uplink {
+    #[default]
+    Mqtt,
+    /// HTTP POST to `webhook.url` (no control topics, status or telemetry)
+    Webhook,
+    /// Line protocol to an InfluxDB v2 server (`influx.*`; no control topics or status)
+    Influxdb,
+    /// Confirmable CoAP POST to `coap.url` (no control topics, status or telemetry)
+    Coap,
+}
+
+impl Uplink {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mqtt" => Some(Uplink::Mqtt),
+            "webhook" => Some(Uplink::Webhook),
+            "influxdb" => Some(Uplink::Influxdb),
+            "coap" => Some(Uplink::Coap),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Uplink::Mqtt => "mqtt",
+            Uplink::Webhook => "webhook",
+            Uplink::Influxdb => "influxdb",
+            Uplink::Coap => "coap",
+        }
+    }
+}
+
+/// Maximum number of stored WiFi networks
+pub const MAX_WIFI_NETWORKS: usize = 5;
+
+/// Authentication used to join a network
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WifiAuth {
+    Open,
+    /// WPA2-Personal (also joins WPA2/WPA3 transition networks)
+    #[default]
+    Wpa2,
+    /// WPA3-Personal (SAE)
+    Wpa3,
+    /// 802.1X (EAP-PEAP/TTLS), see `EnterpriseCredentials`
+    Wpa2Enterprise,
+}
+
+impl WifiAuth {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "open" => Some(WifiAuth::Open),
+            "wpa2" => Some(WifiAuth::Wpa2),
+            "wpa3" => Some(WifiAuth::Wpa3),
+            "wpa2-ent" => Some(WifiAuth::Wpa2Enterprise),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WifiAuth::Open => "open",
+            WifiAuth::Wpa2 => "wpa2",
+            WifiAuth::Wpa3 => "wpa3",
+            WifiAuth::Wpa2Enterprise => "wpa2-ent",
+        }
+    }
+}
+
+/// 802.1X login for WPA2-Enterprise networks. The EAP password is the
+/// network's `password`; the CA certificate is stored separately.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WifiConfig {
+pub struct EnterpriseCredentials {
+    /// Outer (anonymous) identity
+    pub identity: heapless::String<64>,
+    /// Inner identity used for the PEAP/TTLS phase 2 login
+    pub username: heapless::String<64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WifiNetwork {
     pub ssid: heapless::String<32>,
     pub password: heapless::String<64>,
+    /// Higher priority networks are tried first
+    pub priority: u8,
+    #[serde(default)]
+    pub auth: WifiAuth,
+    /// Set for `WifiAuth::Wpa2Enterprise` networks
+    #[serde(default)]
+    pub enterprise: Option<EnterpriseCredentials>,
+}
+
+/// Fixed IPv4 settings for networks without DHCP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaticIpConfig {
+    pub ip: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    /// Netmask as a prefix length (24 = 255.255.255.0)
+    pub prefix_len: u8,
+    pub dns: Option<Ipv4Addr>,
+}
+
+impl StaticIpConfig {
+    /// Parse `<ip>/<prefix>` (prefix defaults to 24), a gateway and an optional DNS server
+    pub fn parse(ip_cidr: &str, gateway: &str, dns: Option<&str>) -> Result<Self, &'static str> {
+        let (ip, prefix_len) = match ip_cidr.split_once('/') {
+            Some((ip, prefix)) => (ip, prefix.parse::<u8>().map_err(|_| "Invalid prefix")?),
+            None => (ip_cidr, 24),
+        };
+        if !(1..=32).contains(&prefix_len) {
+            return Err("Prefix must be 1-32");
+        }
+
+        let config = Self {
+            ip: ip.parse().map_err(|_| "Invalid IP address")?,
+            gateway: gateway.parse().map_err(|_| "Invalid gateway address")?,
+            prefix_len,
+            dns: dns
+                .map(|dns| dns.parse().map_err(|_| "Invalid DNS address"))
+                .transpose()?,
+        };
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WifiConfig {
+    pub networks: heapless::Vec<WifiNetwork, MAX_WIFI_NETWORKS>,
+    /// Static IPv4 settings (DHCP when None), applied to every network
+    #[serde(default)]
+    pub static_ip: Option<StaticIpConfig>,
+}
+
+impl WifiConfig {
+    /// Add a network, or update its password/priority/auth if the SSID is already stored
+    pub fn add(
+        &mut self,
+        ssid: &str,
+        password: &str,
+        priority: u8,
+        auth: WifiAuth,
+    ) -> Result<(), &'static str> {
+        if auth == WifiAuth::Wpa2Enterprise {
+            return Err("WPA2-Enterprise needs an identity and username (wifi_save_ent)");
+        }
+        let network = new_network(ssid, password, priority, auth, None)?;
+        self.upsert(network)
+    }
+
+    /// Add or update a WPA2-Enterprise network
+    pub fn add_enterprise(
+        &mut self,
+        ssid: &str,
+        identity: &str,
+        username: &str,
+        password: &str,
+        priority: u8,
+    ) -> Result<(), &'static str> {
+        let mut enterprise = EnterpriseCredentials {
+            identity: heapless::String::new(),
+            username: heapless::String::new(),
+        };
+        enterprise
+            .identity
+            .push_str(identity)
+            .map_err(|_| "Identity too long (max 64 chars)")?;
+        enterprise
+            .username
+            .push_str(username)
+            .map_err(|_| "Username too long (max 64 chars)")?;
+
+        let network = new_network(
+            ssid,
+            password,
+            priority,
+            WifiAuth::Wpa2Enterprise,
+            Some(enterprise),
+        )?;
+        self.upsert(network)
+    }
+
+    fn upsert(&mut self, network: WifiNetwork) -> Result<(), &'static str> {
+        if let Some(existing) = self.networks.iter_mut().find(|n| n.ssid == network.ssid) {
+            *existing = network;
+            Ok(())
+        } else {
+            self.networks
+                .push(network)
+                .map_err(|_| "Network list full (max 5)")
+        }
+    }
+
+    /// Remove a network by SSID. Returns false if it wasn't stored.
+    pub fn remove(&mut self, ssid: &str) -> bool {
+        let before = self.networks.len();
+        self.networks.retain(|n| n.ssid != ssid);
+        self.networks.len() != before
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+
+    /// Networks in connection order: highest priority first, ties in insertion order
+    pub fn by_priority(&self) -> Vec<&WifiNetwork> {
+        let mut networks: Vec<&WifiNetwork> = self.networks.iter().collect();
+        networks.sort_by_key(|n| core::cmp::Reverse(n.priority));
+        networks
+    }
+}
+
+fn new_network(
+    ssid: &str,
+    password: &str,
+    priority: u8,
+    auth: WifiAuth,
+    enterprise: Option<EnterpriseCredentials>,
+) -> Result<WifiNetwork, &'static str> {
+    let mut network = WifiNetwork {
+        ssid: heapless::String::new(),
+        password: heapless::String::new(),
+        priority,
+        auth,
+        enterprise,
+    };
+    network
+        .ssid
+        .push_str(ssid)
+        .map_err(|_| "SSID too long (max 32 chars)")?;
+    network
+        .password
+        .push_str(password)
+        .map_err(|_| "Password too long (max 64 chars)")?;
+    Ok(network)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MqttConfig {
     pub broker_url: heapless::String<128>,
-    pub client_id: heapless::String<32>,
+    /// Up to 32 chars are configurable; the MTU app appends the chip ID
+    pub client_id: heapless::String<48>,
     pub username: Option<heapless::String<32>>,
     pub password: Option<heapless::String<64>>,
+    /// Used when `broker_url` is `mqtts://`
+    #[serde(default)]
+    pub tls: MqttTlsConfig,
+    /// Start each connection with a clean session. When false the broker keeps
+    /// the subscriptions and queues QoS 1 messages between connections.
+    #[serde(default = "default_clean_session")]
+    pub clean_session: bool,
+    /// MQTT keepalive interval in seconds
+    #[serde(default = "default_keep_alive_secs")]
+    pub keep_alive_secs: u16,
+    /// Delay before esp-mqtt retries a failed connection, in seconds
+    #[serde(default = "default_reconnect_timeout_secs")]
+    pub reconnect_timeout_secs: u16,
+    /// Minimum seconds between reading publishes; readings in between are dropped (0 = off)
+    #[serde(default)]
+    pub min_publish_interval_secs: u16,
+    /// Drop a reading whose message equals the last published one
+    #[serde(default)]
+    pub dedup_readings: bool,
+    /// On-demand mode: how long to stay connected after publishing for
+    /// control messages, in seconds (0 = only those already received). Ends
+    /// early once a control message has been handled and its reply acknowledged.
+    #[serde(default = "default_downlink_wait_secs")]
+    pub downlink_wait_secs: u16,
+    /// Encoding of readings, status and telemetry
+    #[serde(default)]
+    pub format: PayloadFormat,
+    /// Retained "online"/"offline" (Last Will) topic; set per device at runtime
+    #[serde(skip)]
+    pub availability_topic: Option<String>,
+}
+
+/// TLS settings for `mqtts://` brokers. The PEM blobs are too large for the
+/// JSON config section, so `ConfigStore` keeps them under their own NVS keys.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MqttTlsConfig {
+    /// Broker CA certificate; without one the built-in root bundle is used
+    #[serde(skip)]
+    pub ca_cert: Option<Vec<u8>>,
+    /// Client certificate for mutual TLS (needs `client_key`)
+    #[serde(skip)]
+    pub client_cert: Option<Vec<u8>>,
+    #[serde(skip)]
+    pub client_key: Option<Vec<u8>>,
+    /// ALPN protocol, e.g. "x-amzn-mqtt-ca" for AWS IoT on port 443
+    pub alpn: Option<heapless::String<32>>,
+}
+
+fn default_clean_session() -> bool {
+    true
+}
+
+fn default_keep_alive_secs() -> u16 {
+    30
+}
+
+fn default_reconnect_timeout_secs() -> u16 {
+    5
+}
+
+fn default_downlink_wait_secs() -> u16 {
+    5
 }
 
+impl MqttConfig {
+    pub fn is_tls(&self) -> bool {
+        self.broker_url.starts_with("mqtts://") || self.broker_url.starts_with("wss://")
+    }
+}
+
+/// MQTT topics of the MTU app. Each may contain the placeholders `{chip_id}`
+/// (e.g. `24:0a:c4:12:34:56`) and `{hostname}`, filled in by `expand`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MtuMqttTopics {
+    /// Meter readings are published here
     pub readings: heapless::String<64>,
+    /// Retained device status document (`StatusPayload`)
     pub status: heapless::String<64>,
+    /// Retained "online"/"offline" availability
+    pub availability: heapless::String<64>,
+    /// Periodic health metrics (`TelemetryPayload`)
+    pub telemetry: heapless::String<64>,
+    /// Log buffer uploads (`log upload`)
+    pub logs: heapless::String<64>,
+    /// Crash reports (`CrashPayload`), after an abnormal reset
+    pub crash: heapless::String<64>,
+    /// Stored readings as CSV (`export upload`)
+    pub export: heapless::String<64>,
+    /// Readings forwarded from ESP-NOW nodes (`RelayedReadingPayload`, gateway only)
+    pub relay: heapless::String<64>,
+    /// Broadcast control commands (all devices)
+    pub control: heapless::String<64>,
+    /// Control commands for this device only
+    pub control_device: heapless::String<64>,
+    /// Replies to control commands
+    pub response: heapless::String<64>,
+    /// Retained settings to apply (remote config sync)
+    pub config_desired: heapless::String<64>,
+    /// Settings in effect after each desired document
+    pub config_reported: heapless::String<64>,
 }
 
-impl Default for WifiConfig {
-    fn default() -> Self {
-        let mut ssid = heapless::String::new();
-        let mut password = heapless::String::new();
-        let _ = ssid.push_str("YOUR_SSID");
-        let _ = password.push_str("YOUR_PASSWORD");
+/// `MtuMqttTopics` with the placeholders filled in
+#[derive(Debug, Clone)]
+pub struct DeviceTopics {
+    pub readings: String,
+    pub status: String,
+    pub availability: String,
+    pub telemetry: String,
+    pub logs: String,
+    pub crash: String,
+    pub export: String,
+    pub relay: String,
+    pub control: String,
+    pub control_device: String,
+    pub response: String,
+    pub config_desired: String,
+    pub config_reported: String,
+}
+
+/// Placeholders accepted in topic templates
+pub const TOPIC_PLACEHOLDERS: &[&str] = &["{chip_id}", "{hostname}"];
+
+impl MtuMqttTopics {
+    /// Defaults of firmware that stored this section before it was used;
+    /// such sections are treated as unset
+    pub fn is_legacy_default(&self) -> bool {
+        self.readings == "watermeter/mtu/readings" && self.status == "watermeter/mtu/status"
+    }
+
+    pub fn expand(&self, chip_id: &str, hostname: &str) -> DeviceTopics {
+        DeviceTopics {
+            readings: expand_topic(&self.readings, chip_id, hostname),
+            status: expand_topic(&self.status, chip_id, hostname),
+            availability: expand_topic(&self.availability, chip_id, hostname),
+            telemetry: expand_topic(&self.telemetry, chip_id, hostname),
+            logs: expand_topic(&self.logs, chip_id, hostname),
+            crash: expand_topic(&self.crash, chip_id, hostname),
+            export: expand_topic(&self.export, chip_id, hostname),
+            relay: expand_topic(&self.relay, chip_id, hostname),
+            control: expand_topic(&self.control, chip_id, hostname),
+            control_device: expand_topic(&self.control_device, chip_id, hostname),
+            response: expand_topic(&self.response, chip_id, hostname),
+            config_desired: expand_topic(&self.config_desired, chip_id, hostname),
+            config_reported: expand_topic(&self.config_reported, chip_id, hostname),
+        }
+    }
+}
+
+pub fn expand_topic(template: &str, chip_id: &str, hostname: &str) -> String {
+    template
+        .replace("{chip_id}", chip_id)
+        .replace("{hostname}", hostname)
+}
 
-        Self { ssid, password }
+/// Non-empty, no wildcards, and only known `{...}` placeholders
+pub fn is_valid_topic_template(template: &str) -> bool {
+    if template.is_empty() || template.contains(['+', '#']) {
+        return false;
     }
+    let stripped = TOPIC_PLACEHOLDERS
+        .iter()
+        .fold(template.to_string(), |topic, placeholder| {
+            topic.replace(placeholder, "")
+        });
+    !stripped.contains(['{', '}'])
 }
 
 impl Default for MqttConfig {
     fn default() -> Self {
         let mut broker_url = heapless::String::new();
         let mut client_id = heapless::String::new();
-        let _ = broker_url.push_str("mqtt://broker.hivemq.com:1883");
+        let _ = broker_url.push_str("mqtt://test.mosquitto.org:1883");
         let _ = client_id.push_str("esp32-mtu");
 
         Self {
@@ -43,6 +485,15 @@ impl Default for MqttConfig {
             client_id,
             username: None,
             password: None,
+            tls: MqttTlsConfig::default(),
+            clean_session: default_clean_session(),
+            keep_alive_secs: default_keep_alive_secs(),
+            reconnect_timeout_secs: default_reconnect_timeout_secs(),
+            min_publish_interval_secs: 0,
+            dedup_readings: false,
+            downlink_wait_secs: default_downlink_wait_secs(),
+            format: PayloadFormat::default(),
+            availability_topic: None,
         }
     }
 }
@@ -51,9 +502,87 @@ impl Default for MtuMqttTopics {
     fn default() -> Self {
         let mut readings = heapless::String::new();
         let mut status = heapless::String::new();
-        let _ = readings.push_str("watermeter/mtu/readings");
-        let _ = status.push_str("watermeter/mtu/status");
+        let mut availability = heapless::String::new();
+        let mut telemetry = heapless::String::new();
+        let mut logs = heapless::String::new();
+        let mut crash = heapless::String::new();
+        let mut export = heapless::String::new();
+        let mut relay = heapless::String::new();
+        let mut control = heapless::String::new();
+        let mut control_device = heapless::String::new();
+        let mut response = heapless::String::new();
+        let mut config_desired = heapless::String::new();
+        let mut config_reported = heapless::String::new();
+        let _ = readings.push_str("istorrs/mtu/data");
+        let _ = status.push_str("istorrs/mtu/{chip_id}/status");
+        let _ = availability.push_str("istorrs/mtu/{chip_id}/availability");
+        let _ = telemetry.push_str("istorrs/mtu/{chip_id}/telemetry");
+        let _ = logs.push_str("istorrs/mtu/{chip_id}/logs");
+        let _ = crash.push_str("istorrs/mtu/{chip_id}/crash");
+        let _ = export.push_str("istorrs/mtu/{chip_id}/export");
+        let _ = relay.push_str("istorrs/mtu/{chip_id}/relay");
+        let _ = control.push_str("istorrs/mtu/control");
+        let _ = control_device.push_str("istorrs/mtu/{chip_id}/control");
+        let _ = response.push_str("istorrs/mtu/{chip_id}/response");
+        let _ = config_desired.push_str("istorrs/mtu/{chip_id}/config/desired");
+        let _ = config_reported.push_str("istorrs/mtu/{chip_id}/config/reported");
+
+        Self {
+            readings,
+            status,
+            availability,
+            telemetry,
+            logs,
+            crash,
+            export,
+            relay,
+            control,
+            control_device,
+            response,
+            config_desired,
+            config_reported,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_placeholders() {
+        assert_eq!(
+            expand_topic("istorrs/mtu/{chip_id}/control", "24:0a:c4:12:34:56", "pit3"),
+            "istorrs/mtu/24:0a:c4:12:34:56/control"
+        );
+        assert_eq!(
+            expand_topic("{hostname}/{chip_id}/{hostname}", "ab", "pit3"),
+            "pit3/ab/pit3"
+        );
+        assert_eq!(
+            expand_topic("istorrs/mtu/data", "ab", "pit3"),
+            "istorrs/mtu/data"
+        );
+    }
+
+    #[test]
+    fn accepts_known_placeholders() {
+        assert!(is_valid_topic_template("istorrs/mtu/data"));
+        assert!(is_valid_topic_template("istorrs/mtu/{chip_id}/control"));
+        assert!(is_valid_topic_template("{hostname}/{chip_id}"));
+    }
+
+    #[test]
+    fn rejects_empty_and_wildcards() {
+        assert!(!is_valid_topic_template(""));
+        assert!(!is_valid_topic_template("istorrs/+/data"));
+        assert!(!is_valid_topic_template("istorrs/#"));
+    }
 
-        Self { readings, status }
+    #[test]
+    fn rejects_unknown_or_broken_placeholders() {
+        assert!(!is_valid_topic_template("istorrs/{device}/data"));
+        assert!(!is_valid_topic_template("istorrs/{chip_id/data"));
+        assert!(!is_valid_topic_template("istorrs/chip_id}/data"));
     }
 }
@@ -0,0 +1,150 @@
+//! OTA image state and post-update health check
+//!
+//! With `CONFIG_BOOTLOADER_APP_ROLLBACK_ENABLE` a freshly written image boots
+//! in the `pending_verify` state. `HealthCheck` keeps it there until the
+//! network link has come up and the MTU has completed one successful read,
+//! then marks it valid. If that does not happen within the timeout the image
+//! is marked invalid and the bootloader reverts to the previous one.
+//!
+//! Images flashed over serial into a factory partition are not subject to
+//! the check.
+
+use esp_idf_svc::sys;
+use std::ffi::CStr;
+use std::time::{Duration, Instant};
+
+/// OTA state of the running image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageState {
+    /// Factory partition or no OTA data (serial flash)
+    Factory,
+    New,
+    PendingVerify,
+    Valid,
+    Invalid,
+    Aborted,
+    Undefined,
+}
+
+impl ImageState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageState::Factory => "factory",
+            ImageState::New => "new",
+            ImageState::PendingVerify => "pending_verify",
+            ImageState::Valid => "valid",
+            ImageState::Invalid => "invalid",
+            ImageState::Aborted => "aborted",
+            ImageState::Undefined => "undefined",
+        }
+    }
+}
+
+/// Label of the partition the app runs from, e.g. "ota_0"
+pub fn running_partition() -> String {
+    let partition = unsafe { sys::esp_ota_get_running_partition() };
+    if partition.is_null() {
+        return "unknown".to_string();
+    }
+    unsafe { CStr::from_ptr((*partition).label.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub fn image_state() -> ImageState {
+    let partition = unsafe { sys::esp_ota_get_running_partition() };
+    let mut state: sys::esp_ota_img_states_t = 0;
+    if partition.is_null()
+        || unsafe { sys::esp_ota_get_state_partition(partition, &mut state) } != sys::ESP_OK
+    {
+        return ImageState::Factory;
+    }
+    match state {
+        sys::esp_ota_img_states_t_ESP_OTA_IMG_NEW => ImageState::New,
+        sys::esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY => ImageState::PendingVerify,
+        sys::esp_ota_img_states_t_ESP_OTA_IMG_VALID => ImageState::Valid,
+        sys::esp_ota_img_states_t_ESP_OTA_IMG_INVALID => ImageState::Invalid,
+        sys::esp_ota_img_states_t_ESP_OTA_IMG_ABORTED => ImageState::Aborted,
+        _ => ImageState::Undefined,
+    }
+}
+
+/// App version embedded in the image header (`CARGO_PKG_VERSION` unless overridden)
+pub fn app_version() -> String {
+    let desc = unsafe { sys::esp_app_get_description() };
+    if desc.is_null() {
+        return env!("CARGO_PKG_VERSION").to_string();
+    }
+    unsafe { CStr::from_ptr((*desc).version.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Post-update check of a `pending_verify` image
+pub struct HealthCheck {
+    deadline: Instant,
+    require_link: bool,
+    link_ok: bool,
+    mtu_ok: bool,
+}
+
+impl HealthCheck {
+    /// Start the check if the running image awaits verification. Without a
+    /// network link (none configured) only the MTU read is required.
+    pub fn start_if_pending(timeout: Duration, require_link: bool) -> Option<Self> {
+        if image_state() != ImageState::PendingVerify {
+            return None;
+        }
+        log::info!(
+            "🔄 OTA: New firmware {} on {} pending verification ({}s)",
+            app_version(),
+            running_partition(),
+            timeout.as_secs()
+        );
+        Some(Self {
+            deadline: Instant::now() + timeout,
+            require_link,
+            link_ok: false,
+            mtu_ok: false,
+        })
+    }
+
+    pub fn record_link_up(&mut self) {
+        if !self.link_ok {
+            log::info!("✅ OTA: Health check - network link up");
+            self.link_ok = true;
+        }
+    }
+
+    pub fn record_mtu_read(&mut self) {
+        if !self.mtu_ok {
+            log::info!("✅ OTA: Health check - MTU read successful");
+            self.mtu_ok = true;
+        }
+    }
+
+    /// Mark the image valid once healthy, or roll back (reboots) after the
+    /// deadline. Returns true when the check is finished.
+    pub fn poll(&mut self) -> bool {
+        if self.mtu_ok && (self.link_ok || !self.require_link) {
+            match unsafe { sys::esp_ota_mark_app_valid_cancel_rollback() } {
+                sys::ESP_OK => log::info!("✅ OTA: Firmware marked valid"),
+                err => log::error!("❌ OTA: Failed to mark firmware valid: {}", err),
+            }
+            return true;
+        }
+        if Instant::now() < self.deadline {
+            return false;
+        }
+
+        log::error!(
+            "❌ OTA: Health check failed (link: {}, MTU read: {}) - rolling back",
+            self.link_ok,
+            self.mtu_ok
+        );
+        let err = unsafe { sys::esp_ota_mark_app_invalid_rollback_and_reboot() };
+        // Only returns if there is no other image to go back to
+        log::error!("❌ OTA: Rollback failed: {}", err);
+        true
+    }
+}
@@ -0,0 +1,359 @@
+//! MQTT payload schema
+//!
+//! Every published document carries `schema` so consumers can detect layout
+//! changes. Bump `PAYLOAD_SCHEMA_VERSION` when a field is removed, renamed or
+//! changes type; adding a field does not need a bump.
+
+use crate::crash::CrashReport;
+use crate::wifi::LinkStats;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+pub const PAYLOAD_SCHEMA_VERSION: u8 = 1;
+
+/// Encoding of the published documents (readings, status, telemetry).
+/// CBOR carries the same fields as JSON in roughly half the bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    #[default]
+    Json,
+    Cbor,
+}
+
+impl PayloadFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(PayloadFormat::Json),
+            "cbor" => Some(PayloadFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PayloadFormat::Json => "json",
+            PayloadFormat::Cbor => "cbor",
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, payload: &T) -> Result<Vec<u8>> {
+        Ok(match self {
+            PayloadFormat::Json => serde_json::to_vec(payload)?,
+            PayloadFormat::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(payload, &mut buf)?;
+                buf
+            }
+        })
+    }
+}
+
+/// Identification of the device and the link it published over
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfo {
+    /// Name set with `config set device.name` (None if not set)
+    pub device_name: Option<String>,
+    /// Base MAC address from eFuse
+    pub chip_id: String,
+    /// "wifi", "ethernet" or "cellular"
+    pub transport: &'static str,
+    // The wifi_* keys are kept for existing consumers and hold the active link's values
+    pub wifi_mac: String,
+    pub wifi_ip: String,
+    pub wifi_rssi: Option<i8>,
+    pub wifi_channel: Option<u8>,
+    /// WiFi connection statistics (None over Ethernet)
+    pub link_stats: Option<LinkStats>,
+}
+
+/// Published to the data topic after each MTU read cycle
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadingPayload {
+    pub schema: u8,
+    /// UTC time of the publish (ISO 8601), None if the clock is not set
+    pub timestamp: Option<String>,
+    /// When the reading was taken (ISO 8601), None if the clock was not set;
+    /// earlier than `timestamp` when the reading waited in the offline queue
+    pub read_at: Option<String>,
+    #[serde(flatten)]
+    pub device: DeviceInfo,
+    /// Raw meter response string
+    pub message: String,
+    pub baud_rate: u32,
+    pub cycles: usize,
+    pub successful: u32,
+    pub corrupted: u32,
+    /// Readings published before this one since boot
+    pub count: u32,
+    /// ID from `meters add` when several meters share the device (None with one meter)
+    pub meter_id: Option<String>,
+    /// The message's serial differs from the one registered for `meter_id`
+    pub serial_mismatch: bool,
+    /// Set when several queued readings are published in one session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch: Option<BatchInfo>,
+}
+
+/// Place of a reading in a batch of queued readings published in one session
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchInfo {
+    /// Same for every reading of the batch
+    pub id: String,
+    /// Position in the batch, from 1 (oldest reading)
+    pub index: usize,
+    pub size: usize,
+}
+
+/// Published to the config reported topic after each desired document
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigReportedPayload {
+    pub schema: u8,
+    /// UTC time of the publish (ISO 8601), None if the clock is not set
+    pub timestamp: Option<String>,
+    /// `version` of the desired document, echoed (None if it had none)
+    pub version: Option<serde_json::Value>,
+    pub baud_rate: u32,
+    /// UART framing, "7E1" or "7E2"
+    pub framing: &'static str,
+    /// `power.read_interval` in seconds
+    pub read_interval: u32,
+    /// Desired settings that were not applied, with the reason
+    pub errors: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Published to the relay topic by an ESP-NOW gateway for each reading
+/// received from a node
+#[derive(Debug, Clone, Serialize)]
+pub struct RelayedReadingPayload {
+    pub schema: u8,
+    /// UTC time of the publish (ISO 8601), None if the gateway's clock is not set
+    pub timestamp: Option<String>,
+    /// Chip ID of the node that read the meter
+    pub node: String,
+    /// Node's sequence number for the reading
+    pub seq: u32,
+    /// Chip ID of the gateway that published it
+    pub gateway: String,
+    /// Raw meter response string
+    pub message: String,
+    pub baud_rate: u32,
+    /// UART framing, e.g. "7E1"
+    pub framing: &'static str,
+    pub cycles: usize,
+    pub successful: u32,
+    pub corrupted: u32,
+}
+
+/// Device health snapshot, published retained to the status topic
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusPayload {
+    pub schema: u8,
+    pub timestamp: Option<String>,
+    #[serde(flatten)]
+    pub device: DeviceInfo,
+    /// Firmware (crate) version
+    pub firmware: &'static str,
+    pub uptime_secs: u64,
+    /// Boots since the counter was created (0 if the config store is unavailable)
+    pub boot_count: u32,
+    /// Why the chip last reset, e.g. "power_on", "brownout", "task_watchdog"
+    pub reset_reason: &'static str,
+    /// Readings published since boot
+    pub publish_count: u32,
+    /// Readings dropped by `mqtt.min_interval` / `mqtt.dedup` since boot
+    pub suppressed_count: u32,
+    /// MTU settings and statistics of the last published reading (None before the first)
+    pub baud_rate: Option<u32>,
+    /// UART framing, e.g. "7E1"
+    pub framing: Option<&'static str>,
+    /// Successful reads as a percentage of all reads since boot
+    pub success_rate: Option<f32>,
+    /// UTC time the last reading was published (ISO 8601)
+    pub last_reading_at: Option<String>,
+}
+
+/// Periodic health metrics, published to the telemetry topic
+#[derive(Debug, Clone, Serialize)]
+pub struct TelemetryPayload {
+    pub schema: u8,
+    pub timestamp: Option<String>,
+    #[serde(flatten)]
+    pub device: DeviceInfo,
+    /// Free heap in bytes
+    pub free_heap: u32,
+    /// Lowest free heap since boot in bytes
+    pub min_free_heap: u32,
+    pub uptime_secs: u64,
+    /// Boots since the counter was created (0 if the config store is unavailable)
+    pub boot_count: u32,
+    /// Why the chip last reset, e.g. "power_on", "brownout", "task_watchdog"
+    pub reset_reason: &'static str,
+}
+
+/// Published to the crash topic on the first connection after an abnormal reset
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashPayload {
+    pub schema: u8,
+    pub timestamp: Option<String>,
+    #[serde(flatten)]
+    pub device: DeviceInfo,
+    /// Firmware (crate) version now running; after an OTA rollback not the one that crashed
+    pub firmware: &'static str,
+    #[serde(flatten)]
+    pub crash: CrashReport,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    // The field names are the published schema (docs/mqtt-control.md); a
+    // failure here means consumers break unless PAYLOAD_SCHEMA_VERSION is bumped
+    const DEVICE_FIELDS: &[&str] = &[
+        "device_name",
+        "chip_id",
+        "transport",
+        "wifi_mac",
+        "wifi_ip",
+        "wifi_rssi",
+        "wifi_channel",
+        "link_stats",
+    ];
+
+    fn device() -> DeviceInfo {
+        DeviceInfo {
+            device_name: Some("Pit 3".to_string()),
+            chip_id: "24:0a:c4:12:34:56".to_string(),
+            transport: "wifi",
+            wifi_mac: "24:0a:c4:12:34:57".to_string(),
+            wifi_ip: "192.168.1.119".to_string(),
+            wifi_rssi: Some(-67),
+            wifi_channel: Some(6),
+            link_stats: None,
+        }
+    }
+
+    fn reading() -> ReadingPayload {
+        ReadingPayload {
+            schema: PAYLOAD_SCHEMA_VERSION,
+            timestamp: None,
+            read_at: None,
+            device: device(),
+            message: "V;RB00000200".to_string(),
+            baud_rate: 1200,
+            cycles: 15,
+            successful: 2,
+            corrupted: 0,
+            count: 5,
+            meter_id: None,
+            serial_mismatch: false,
+            batch: None,
+        }
+    }
+
+    /// Top-level keys of the JSON document, sorted
+    fn keys<T: Serialize>(payload: &T) -> Vec<String> {
+        let Value::Object(map) = serde_json::to_value(payload).unwrap() else {
+            panic!("payload is not a JSON object");
+        };
+        let mut keys: Vec<String> = map.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    fn expected(fields: &[&str]) -> Vec<String> {
+        let mut keys: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn schema_version() {
+        assert_eq!(PAYLOAD_SCHEMA_VERSION, 1);
+        assert_eq!(serde_json::to_value(reading()).unwrap()["schema"], 1);
+    }
+
+    #[test]
+    fn device_info_fields() {
+        assert_eq!(keys(&device()), expected(DEVICE_FIELDS));
+    }
+
+    #[test]
+    fn reading_fields() {
+        let mut fields = DEVICE_FIELDS.to_vec();
+        fields.extend([
+            "schema",
+            "timestamp",
+            "read_at",
+            "message",
+            "baud_rate",
+            "cycles",
+            "successful",
+            "corrupted",
+            "count",
+            "meter_id",
+            "serial_mismatch",
+        ]);
+        assert_eq!(keys(&reading()), expected(&fields));
+    }
+
+    #[test]
+    fn reading_batch_only_when_set() {
+        let mut payload = reading();
+        payload.batch = Some(BatchInfo {
+            id: "5c01e9a2".to_string(),
+            index: 2,
+            size: 3,
+        });
+        let value = serde_json::to_value(&payload).unwrap();
+        assert_eq!(
+            value["batch"],
+            serde_json::json!({"id": "5c01e9a2", "index": 2, "size": 3})
+        );
+        assert!(keys(&reading()).iter().all(|key| key != "batch"));
+    }
+
+    #[test]
+    fn cbor_carries_the_json_fields() {
+        let cbor = PayloadFormat::Cbor.encode(&reading()).unwrap();
+        let decoded: Value = ciborium::from_reader(cbor.as_slice()).unwrap();
+        assert_eq!(decoded, serde_json::to_value(reading()).unwrap());
+    }
+
+    #[test]
+    fn status_fields() {
+        let payload = StatusPayload {
+            schema: PAYLOAD_SCHEMA_VERSION,
+            timestamp: None,
+            device: device(),
+            firmware: "0.1.0",
+            uptime_secs: 86412,
+            boot_count: 17,
+            reset_reason: "power_on",
+            publish_count: 5,
+            suppressed_count: 0,
+            baud_rate: None,
+            framing: None,
+            success_rate: None,
+            last_reading_at: None,
+        };
+        let mut fields = DEVICE_FIELDS.to_vec();
+        fields.extend([
+            "schema",
+            "timestamp",
+            "firmware",
+            "uptime_secs",
+            "boot_count",
+            "reset_reason",
+            "publish_count",
+            "suppressed_count",
+            "baud_rate",
+            "framing",
+            "success_rate",
+            "last_reading_at",
+        ]);
+        assert_eq!(keys(&payload), expected(&fields));
+    }
+}
@@ -0,0 +1,129 @@
+//! Deep-sleep scheduling between reads
+//!
+//! In `deep_sleep` mode the device wakes on the RTC timer, reads the meter,
+//! publishes and goes back to sleep until the next scheduled read. RAM is
+//! lost in deep sleep, so the counters that would otherwise restart at zero
+//! are kept in RTC slow memory, which survives it (but not a power cycle).
+
+use esp_idf_svc::sys;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Shortest sleep, even when the wake cycle overran the read interval
+pub const MIN_SLEEP: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerMode {
+    /// Stay awake; reads are started from the CLI or MQTT
+    #[default]
+    AlwaysOn,
+    /// Read, publish, then deep-sleep until the next read
+    DeepSleep,
+}
+
+impl PowerMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "always_on" => Some(PowerMode::AlwaysOn),
+            "deep_sleep" => Some(PowerMode::DeepSleep),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowerMode::AlwaysOn => "always_on",
+            PowerMode::DeepSleep => "deep_sleep",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PowerSettings {
+    /// Applied at boot
+    pub mode: PowerMode,
+    /// Time from one scheduled read to the next in deep-sleep mode
+    pub read_interval_secs: u32,
+}
+
+impl Default for PowerSettings {
+    fn default() -> Self {
+        Self {
+            mode: PowerMode::AlwaysOn,
+            read_interval_secs: 3600,
+        }
+    }
+}
+
+/// Counters carried from one wake cycle to the next
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SleepState {
+    /// Wake cycles since power-on
+    pub wake_count: u32,
+    pub successful_reads: u32,
+    pub corrupted_reads: u32,
+    pub publish_count: u32,
+}
+
+/// Marks `RTC_STATE` as written by this firmware (zeroed after power-on)
+const RTC_MAGIC: u32 = 0x5754_4d31;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct RtcState {
+    magic: u32,
+    wake_count: u32,
+    successful_reads: u32,
+    corrupted_reads: u32,
+    publish_count: u32,
+}
+
+#[link_section = ".rtc.data"]
+static mut RTC_STATE: RtcState = RtcState {
+    magic: 0,
+    wake_count: 0,
+    successful_reads: 0,
+    corrupted_reads: 0,
+    publish_count: 0,
+};
+
+/// True if this boot is a wake from deep sleep (RTC timer or button)
+pub fn woke_from_sleep() -> bool {
+    let cause = unsafe { sys::esp_sleep_get_wakeup_cause() };
+    cause != sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_UNDEFINED
+}
+
+/// Counters saved before the last deep sleep (None after power-on or reset)
+pub fn restore_state() -> Option<SleepState> {
+    // Safety: only the main task touches RTC_STATE
+    let rtc = unsafe { core::ptr::addr_of!(RTC_STATE).read_volatile() };
+    if rtc.magic != RTC_MAGIC || !woke_from_sleep() {
+        return None;
+    }
+    Some(SleepState {
+        wake_count: rtc.wake_count,
+        successful_reads: rtc.successful_reads,
+        corrupted_reads: rtc.corrupted_reads,
+        publish_count: rtc.publish_count,
+    })
+}
+
+/// Save the counters to RTC memory and deep-sleep for `duration`
+pub fn deep_sleep(state: SleepState, duration: Duration) -> ! {
+    let rtc = RtcState {
+        magic: RTC_MAGIC,
+        wake_count: state.wake_count.wrapping_add(1),
+        successful_reads: state.successful_reads,
+        corrupted_reads: state.corrupted_reads,
+        publish_count: state.publish_count,
+    };
+    unsafe { core::ptr::addr_of_mut!(RTC_STATE).write_volatile(rtc) };
+
+    let duration = duration.max(MIN_SLEEP);
+    log::info!("💤 Power: Deep sleep for {}s", duration.as_secs());
+    unsafe {
+        sys::esp_sleep_enable_timer_wakeup(duration.as_micros() as u64);
+        sys::esp_deep_sleep_start()
+    }
+}
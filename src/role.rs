@@ -0,0 +1,66 @@
+//! Device role selection for the dual-role firmware (`dual_app`)
+//!
+//! The role is stored in NVS and applied at boot, since the MTU and meter
+//! simulator drive the same GPIO pins in opposite directions.
+
+use anyhow::Result;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+
+/// NVS namespace holding the selected role
+pub const ROLE_NVS_NAMESPACE: &str = "role";
+const KEY_ROLE: &str = "role";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceRole {
+    /// Read meters: clock out on GPIO4, data in on GPIO5
+    Mtu,
+    /// Simulate a meter: clock in on GPIO4, data out on GPIO5
+    Meter,
+}
+
+impl DeviceRole {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mtu" => Some(DeviceRole::Mtu),
+            "meter" => Some(DeviceRole::Meter),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeviceRole::Mtu => "mtu",
+            DeviceRole::Meter => "meter",
+        }
+    }
+}
+
+pub struct RoleStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl RoleStore {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+        let nvs = EspNvs::new(partition, ROLE_NVS_NAMESPACE, true)?;
+        Ok(Self { nvs })
+    }
+
+    /// Stored role, or None if never set
+    pub fn load(&self) -> Result<Option<DeviceRole>> {
+        Ok(match self.nvs.get_u8(KEY_ROLE)? {
+            Some(1) => Some(DeviceRole::Meter),
+            Some(_) => Some(DeviceRole::Mtu),
+            None => None,
+        })
+    }
+
+    pub fn save(&mut self, role: DeviceRole) -> Result<()> {
+        let value = match role {
+            DeviceRole::Mtu => 0,
+            DeviceRole::Meter => 1,
+        };
+        self.nvs.set_u8(KEY_ROLE, value)?;
+        log::info!("Role: Saved role '{}'", role.name());
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+//! Status LED driven by the event bus
+//!
+//! A plain LED on a GPIO (blink patterns only) or one WS2812 addressable LED
+//! (patterns plus colour), so installers can see at a glance what the
+//! device is doing:
+//!
+//! ```text
+//! idle            50 ms flash every 5 s       dim green
+//! reading         fast blink (100/100 ms)     blue
+//! publish ok      solid for 2 s               green
+//! WiFi error      2 flashes every 2 s         red
+//! MQTT error      3 flashes every 2 s         orange
+//! ```
+//!
+//! Errors stay shown until the next read or successful publish.
+
+use crate::events::DeviceEvent;
+use anyhow::Result;
+use esp_idf_hal::gpio::{AnyOutputPin, Output, PinDriver};
+use esp_idf_hal::rmt::config::TransmitConfig;
+use esp_idf_hal::rmt::{FixedLengthSignal, PinState, Pulse, TxRmtDriver, CHANNEL0};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Output-capable GPIOs not used by the MTU (4, 5) or the console UART
+pub const LED_GPIOS: &[u8] = &[
+    0, 2, 12, 13, 14, 15, 16, 17, 18, 19, 21, 22, 23, 25, 26, 27, 32, 33,
+];
+
+/// Pattern update rate
+const TICK: Duration = Duration::from_millis(50);
+const PUBLISH_OK_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedType {
+    /// Plain LED, active high
+    #[default]
+    Gpio,
+    Ws2812,
+}
+
+impl LedType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gpio" => Some(LedType::Gpio),
+            "ws2812" => Some(LedType::Ws2812),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LedType::Gpio => "gpio",
+            LedType::Ws2812 => "ws2812",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LedSettings {
+    /// Applied at boot; None = no LED
+    pub gpio: Option<u8>,
+    pub led_type: LedType,
+}
+
+/// What the LED currently shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LedState {
+    Idle,
+    Reading,
+    PublishOk,
+    LinkError,
+    MqttError,
+}
+
+impl LedState {
+    fn after(self, event: DeviceEvent) -> Self {
+        match event {
+            DeviceEvent::ReadStarted => LedState::Reading,
+            DeviceEvent::ReadFinished { .. } if self == LedState::Reading => LedState::Idle,
+            DeviceEvent::ReadFinished { .. } => self,
+            DeviceEvent::PublishSucceeded => LedState::PublishOk,
+            DeviceEvent::LinkFailed => LedState::LinkError,
+            DeviceEvent::MqttFailed => LedState::MqttError,
+            // The failed attempt is already showing
+            DeviceEvent::PublishAbandoned => self,
+        }
+    }
+
+    /// Colour at `elapsed` into the pattern, None = off
+    fn color(self, elapsed: Duration) -> Option<(u8, u8, u8)> {
+        let ms = elapsed.as_millis() as u64;
+        match self {
+            LedState::Idle => (ms % 5000 < 50).then_some((0, 16, 0)),
+            LedState::Reading => (ms % 200 < 100).then_some((0, 0, 64)),
+            LedState::PublishOk => Some((0, 64, 0)),
+            LedState::LinkError => flashes(ms, 2).then_some((64, 0, 0)),
+            LedState::MqttError => flashes(ms, 3).then_some((64, 24, 0)),
+        }
+    }
+}
+
+/// `count` 150 ms flashes at the start of every 2 s period
+fn flashes(ms: u64, count: u64) -> bool {
+    let phase = ms % 2000;
+    phase < count * 300 && phase % 300 < 150
+}
+
+enum LedDriver {
+    Gpio(PinDriver<'static, AnyOutputPin, Output>),
+    Ws2812 {
+        tx: TxRmtDriver<'static>,
+        bit0: (Pulse, Pulse),
+        bit1: (Pulse, Pulse),
+    },
+}
+
+impl LedDriver {
+    fn set(&mut self, color: Option<(u8, u8, u8)>) -> Result<()> {
+        match self {
+            LedDriver::Gpio(pin) => match color {
+                Some(_) => pin.set_high()?,
+                None => pin.set_low()?,
+            },
+            LedDriver::Ws2812 { tx, bit0, bit1 } => {
+                let (r, g, b) = color.unwrap_or((0, 0, 0));
+                // WS2812 takes green, red, blue, most significant bit first
+                let grb = (u32::from(g) << 16) | (u32::from(r) << 8) | u32::from(b);
+                let mut signal = FixedLengthSignal::<24>::new();
+                for i in 0..24 {
+                    let bit = grb & (1 << (23 - i)) != 0;
+                    signal.set(i, if bit { bit1 } else { bit0 })?;
+                }
+                tx.start_blocking(&signal)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct StatusLed;
+
+impl StatusLed {
+    /// Take the LED pin (and RMT channel 0 for a WS2812) and run the
+    /// patterns on a background thread
+    pub fn spawn(
+        settings: &LedSettings,
+        rmt_channel: CHANNEL0,
+        events: Receiver<DeviceEvent>,
+    ) -> Result<()> {
+        let Some(gpio) = settings.gpio else {
+            return Ok(());
+        };
+        if !LED_GPIOS.contains(&gpio) {
+            anyhow::bail!("GPIO{} cannot be used for the status LED", gpio);
+        }
+        // Safety: LED_GPIOS excludes every pin claimed elsewhere by the MTU app
+        let pin = unsafe { AnyOutputPin::new(gpio as i32) };
+        let driver = match settings.led_type {
+            LedType::Gpio => LedDriver::Gpio(PinDriver::output(pin)?),
+            LedType::Ws2812 => {
+                let tx =
+                    TxRmtDriver::new(rmt_channel, pin, &TransmitConfig::new().clock_divider(1))?;
+                let ticks = tx.counter_clock()?;
+                let ns = Duration::from_nanos;
+                let bit0 = (
+                    Pulse::new_with_duration(ticks, PinState::High, &ns(350))?,
+                    Pulse::new_with_duration(ticks, PinState::Low, &ns(800))?,
+                );
+                let bit1 = (
+                    Pulse::new_with_duration(ticks, PinState::High, &ns(700))?,
+                    Pulse::new_with_duration(ticks, PinState::Low, &ns(600))?,
+                );
+                LedDriver::Ws2812 { tx, bit0, bit1 }
+            }
+        };
+
+        log::info!(
+            "💡 Status LED on GPIO{} ({})",
+            gpio,
+            settings.led_type.name()
+        );
+        std::thread::Builder::new()
+            .stack_size(4096)
+            .name("status_led".to_string())
+            .spawn(move || Self::run(driver, events))?;
+        Ok(())
+    }
+
+    fn run(mut driver: LedDriver, events: Receiver<DeviceEvent>) {
+        let mut state = LedState::Idle;
+        let mut since = Instant::now();
+        let mut shown = None;
+
+        loop {
+            match events.recv_timeout(TICK) {
+                Ok(event) => {
+                    let next = state.after(event);
+                    if next != state || next == LedState::PublishOk {
+                        state = next;
+                        since = Instant::now();
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if state == LedState::PublishOk && since.elapsed() >= PUBLISH_OK_TIME {
+                state = LedState::Idle;
+                since = Instant::now();
+            }
+
+            let color = state.color(since.elapsed());
+            if color != shown {
+                if let Err(e) = driver.set(color) {
+                    log::warn!("⚠️  Status LED update failed: {:?}", e);
+                }
+                shown = color;
+            }
+        }
+        let _ = driver.set(None);
+    }
+}
@@ -0,0 +1,386 @@
+//! Local data log of every MTU reading
+//!
+//! Appends one line per read cycle (CSV or JSON lines) to the internal
+//! LittleFS `storage` partition or to an SD card, whether or not it could be
+//! published, so sites without connectivity still build an audit trail.
+//! Files are rotated by size: `readings.csv` is renamed to `readings.1.csv`
+//! when full, and so on, keeping `max_files` files:
+//!
+//! ```text
+//! timestamp,uptime_secs,message,baud_rate,framing,successful,corrupted
+//! 2025-06-01T14:03:27Z,86412,V;RB00000200;IB61564400;...,1200,7E1,5,0
+//! ```
+//!
+//! SD card wiring (VSPI host, separate from the W5500 on SPI2): SCLK GPIO14,
+//! MOSI GPIO13, MISO GPIO33, CS GPIO32.
+//!
+//! `export <n>` prints the last readings as CSV (whatever the stored format);
+//! `request_export` has the publisher send them to the export topic instead.
+
+use crate::connectivity::MeterReading;
+use crate::telemetry;
+use crate::timekeeping;
+use anyhow::Result;
+use esp_idf_hal::gpio::{AnyIOPin, Gpio13, Gpio14, Gpio32, Gpio33};
+use esp_idf_hal::sd::spi::SdSpiHostDriver;
+use esp_idf_hal::sd::{SdCardConfiguration, SdCardDriver};
+use esp_idf_hal::spi::{Dma, SpiDriver, SpiDriverConfig, SPI3};
+use esp_idf_svc::fs::fatfs::Fatfs;
+use esp_idf_svc::io::vfs::MountedFatfs;
+use esp_idf_svc::sys;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// LittleFS partition in partitions.csv
+const FLASH_PARTITION: &str = "storage";
+const FLASH_MOUNT_POINT: &str = "/data";
+const SD_MOUNT_POINT: &str = "/sdcard";
+/// Files open at once on the SD card
+const SD_MAX_FILES: usize = 4;
+
+/// Base name of the log files
+const FILE_STEM: &str = "readings";
+
+/// Most readings returned by one export (bounds the RAM used)
+pub const MAX_EXPORT_READINGS: usize = 500;
+
+/// Readings to publish to the export topic, 0 = none requested
+static EXPORT_REQUEST: AtomicUsize = AtomicUsize::new(0);
+
+pub const CSV_HEADER: &str = "timestamp,uptime_secs,message,baud_rate,framing,successful,corrupted";
+
+/// GPIOs taken by the SD card (not available for the button or LED)
+pub const SD_GPIOS: &[u8] = &[13, 14, 32, 33];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// Readings are not stored
+    #[default]
+    None,
+    /// LittleFS on the internal `storage` partition
+    Flash,
+    /// FAT-formatted SD card over SPI
+    Sd,
+}
+
+impl StorageBackend {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(StorageBackend::None),
+            "flash" => Some(StorageBackend::Flash),
+            "sd" => Some(StorageBackend::Sd),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StorageBackend::None => "none",
+            StorageBackend::Flash => "flash",
+            StorageBackend::Sd => "sd",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(LogFormat::Csv),
+            "jsonl" => Some(LogFormat::Jsonl),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogFormat::Csv => "csv",
+            LogFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageSettings {
+    /// Applied at boot
+    pub backend: StorageBackend,
+    pub format: LogFormat,
+    /// A file is rotated once it would grow past this size
+    pub max_file_kb: u32,
+    /// Files kept, including the one being written
+    pub max_files: u8,
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::None,
+            format: LogFormat::Csv,
+            max_file_kb: 64,
+            max_files: 4,
+        }
+    }
+}
+
+/// One stored line in JSON lines format
+#[derive(Serialize, Deserialize)]
+struct StoredReading {
+    timestamp: Option<String>,
+    uptime_secs: u64,
+    message: String,
+    baud_rate: u32,
+    framing: String,
+    successful: u32,
+    corrupted: u32,
+}
+
+impl StoredReading {
+    /// The reading as a CSV line (see `CSV_HEADER`), without line break
+    fn csv_line(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{}",
+            self.timestamp.as_deref().unwrap_or_default(),
+            self.uptime_secs,
+            csv_field(&self.message),
+            self.baud_rate,
+            self.framing,
+            self.successful,
+            self.corrupted
+        )
+    }
+}
+
+type SdCard = MountedFatfs<Fatfs<SdCardDriver<SdSpiHostDriver<'static, SpiDriver<'static>>>>>;
+
+pub struct DataLog {
+    dir: &'static str,
+    format: LogFormat,
+    max_file_size: u64,
+    max_files: u8,
+    /// Keeps the SD card mounted while held (LittleFS stays registered)
+    _sd_card: Option<Box<SdCard>>,
+}
+
+impl DataLog {
+    /// Mount (formatting on first use) the LittleFS `storage` partition
+    pub fn mount_flash(settings: &StorageSettings) -> Result<Self> {
+        let base_path = std::ffi::CString::new(FLASH_MOUNT_POINT)?;
+        let label = std::ffi::CString::new(FLASH_PARTITION)?;
+        let mut conf = sys::littlefs::esp_vfs_littlefs_conf_t {
+            base_path: base_path.as_ptr(),
+            partition_label: label.as_ptr(),
+            ..Default::default()
+        };
+        conf.set_format_if_mount_failed(1);
+        sys::esp!(unsafe { sys::littlefs::esp_vfs_littlefs_register(&conf) })?;
+
+        let (mut total, mut used) = (0usize, 0usize);
+        sys::esp!(unsafe {
+            sys::littlefs::esp_littlefs_info(label.as_ptr(), &mut total, &mut used)
+        })?;
+        log::info!(
+            "💾 Storage: LittleFS on '{}' mounted at {} ({} of {} KB used)",
+            FLASH_PARTITION,
+            FLASH_MOUNT_POINT,
+            used / 1024,
+            total / 1024
+        );
+        Ok(Self::new(settings, FLASH_MOUNT_POINT, None))
+    }
+
+    /// Mount the FAT file system of an SD card on the VSPI host
+    pub fn mount_sd(
+        settings: &StorageSettings,
+        spi: SPI3,
+        sclk: Gpio14,
+        mosi: Gpio13,
+        miso: Gpio33,
+        cs: Gpio32,
+    ) -> Result<Self> {
+        let spi_driver = SpiDriver::new(
+            spi,
+            sclk,
+            mosi,
+            Some(miso),
+            &SpiDriverConfig::new().dma(Dma::Auto(4096)),
+        )?;
+        let host = SdSpiHostDriver::new(
+            spi_driver,
+            Some(cs),
+            AnyIOPin::none(),
+            AnyIOPin::none(),
+            AnyIOPin::none(),
+            None,
+        )?;
+        let card = SdCardDriver::new_spi(host, &SdCardConfiguration::new())?;
+        let mounted =
+            MountedFatfs::mount(Fatfs::new_sdcard(0, card)?, SD_MOUNT_POINT, SD_MAX_FILES)?;
+        log::info!("💾 Storage: SD card mounted at {}", SD_MOUNT_POINT);
+        Ok(Self::new(settings, SD_MOUNT_POINT, Some(Box::new(mounted))))
+    }
+
+    fn new(settings: &StorageSettings, dir: &'static str, sd_card: Option<Box<SdCard>>) -> Self {
+        Self {
+            dir,
+            format: settings.format,
+            max_file_size: u64::from(settings.max_file_kb) * 1024,
+            max_files: settings.max_files.max(1),
+            _sd_card: sd_card,
+        }
+    }
+
+    /// `readings.csv` (index 0), `readings.1.csv`, ...
+    fn file_path(&self, index: u8) -> PathBuf {
+        let ext = self.format.name();
+        let name = match index {
+            0 => format!("{}.{}", FILE_STEM, ext),
+            n => format!("{}.{}.{}", FILE_STEM, n, ext),
+        };
+        PathBuf::from(self.dir).join(name)
+    }
+
+    /// Append one reading, rotating the files first if it would not fit
+    pub fn append(&mut self, reading: &MeterReading) -> Result<()> {
+        let line = self.format_line(reading)?;
+        let path = self.file_path(0);
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 > self.max_file_size {
+            self.rotate()?;
+        }
+
+        let is_new = !path.exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if is_new && self.format == LogFormat::Csv {
+            writeln!(file, "{}", CSV_HEADER)?;
+        }
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Drop the oldest file and shift the others up by one
+    fn rotate(&self) -> Result<()> {
+        let oldest = self.file_path(self.max_files - 1);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for index in (0..self.max_files - 1).rev() {
+            let from = self.file_path(index);
+            if from.exists() {
+                fs::rename(&from, self.file_path(index + 1))?;
+            }
+        }
+        log::info!("💾 Storage: Rotated {} files", FILE_STEM);
+        Ok(())
+    }
+
+    fn format_line(&self, reading: &MeterReading) -> Result<String> {
+        let stored = StoredReading {
+            timestamp: timekeeping::now_iso8601(),
+            uptime_secs: telemetry::uptime_secs(),
+            message: reading.message.clone(),
+            baud_rate: reading.baud_rate,
+            framing: reading.framing.to_string(),
+            successful: reading.successful,
+            corrupted: reading.corrupted,
+        };
+        Ok(match self.format {
+            LogFormat::Csv => format!("{}\n", stored.csv_line()),
+            LogFormat::Jsonl => format!("{}\n", serde_json::to_string(&stored)?),
+        })
+    }
+
+    /// The last `count` stored readings, oldest first, as CSV lines (see
+    /// `CSV_HEADER`) whatever the stored format
+    pub fn last_readings_csv(&self, count: usize) -> Result<Vec<String>> {
+        let count = count.min(MAX_EXPORT_READINGS);
+        // Newest file first, until enough lines are collected
+        let mut newest_first = Vec::new();
+        for index in 0..self.max_files {
+            let wanted = count - newest_first.len();
+            if wanted == 0 {
+                break;
+            }
+            let file = match File::open(self.file_path(index)) {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+            let mut tail = VecDeque::with_capacity(wanted);
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                if line.is_empty() || line == CSV_HEADER {
+                    continue;
+                }
+                if tail.len() == wanted {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+            newest_first.extend(tail.into_iter().rev());
+        }
+
+        Ok(newest_first
+            .into_iter()
+            .rev()
+            .filter_map(|line| match self.format {
+                LogFormat::Csv => Some(line),
+                LogFormat::Jsonl => serde_json::from_str::<StoredReading>(&line)
+                    .ok()
+                    .map(|stored| stored.csv_line()),
+            })
+            .collect())
+    }
+
+    /// `last_readings_csv` as a CSV document with header
+    pub fn export_csv(&self, count: usize) -> Result<String> {
+        let mut csv = format!("{}\n", CSV_HEADER);
+        for line in self.last_readings_csv(count)? {
+            csv.push_str(&line);
+            csv.push('\n');
+        }
+        Ok(csv)
+    }
+
+    /// Bytes stored in all log files
+    pub fn stored_bytes(&self) -> u64 {
+        (0..self.max_files)
+            .filter_map(|index| fs::metadata(self.file_path(index)).ok())
+            .map(|m| m.len())
+            .sum()
+    }
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Ask the publisher to send the last `count` readings to the export topic
+/// with the next MQTT session
+pub fn request_export(count: usize) {
+    EXPORT_REQUEST.store(count.min(MAX_EXPORT_READINGS), Ordering::Relaxed);
+}
+
+/// Readings requested by `request_export`, once per request
+pub fn take_export_request() -> Option<usize> {
+    match EXPORT_REQUEST.swap(0, Ordering::Relaxed) {
+        0 => None,
+        count => Some(count),
+    }
+}
@@ -0,0 +1,94 @@
+//! Device health metrics
+//!
+//! Free heap, uptime and the reason for the last reset, reported on the
+//! telemetry topic and by the `status` command. Field devices that reboot
+//! or slowly leak memory show up here long before they stop reading.
+//! `sys` adds the FreeRTOS task list with each task's unused stack.
+
+use esp_idf_svc::sys;
+use std::ffi::CStr;
+
+/// Currently free heap in bytes
+pub fn free_heap() -> u32 {
+    unsafe { sys::esp_get_free_heap_size() }
+}
+
+/// Lowest free heap since boot in bytes
+pub fn min_free_heap() -> u32 {
+    unsafe { sys::esp_get_minimum_free_heap_size() }
+}
+
+/// Largest heap block that can be allocated at once; far below the free
+/// heap means fragmentation
+pub fn largest_free_block() -> usize {
+    unsafe { sys::heap_caps_get_largest_free_block(sys::MALLOC_CAP_8BIT) }
+}
+
+pub fn uptime_secs() -> u64 {
+    (unsafe { sys::esp_timer_get_time() } / 1_000_000) as u64
+}
+
+/// Why the chip last reset, e.g. "power_on" or "task_watchdog"
+pub fn reset_reason() -> &'static str {
+    match unsafe { sys::esp_reset_reason() } {
+        sys::esp_reset_reason_t_ESP_RST_POWERON => "power_on",
+        sys::esp_reset_reason_t_ESP_RST_EXT => "external_pin",
+        sys::esp_reset_reason_t_ESP_RST_SW => "software",
+        sys::esp_reset_reason_t_ESP_RST_PANIC => "panic",
+        sys::esp_reset_reason_t_ESP_RST_INT_WDT => "interrupt_watchdog",
+        sys::esp_reset_reason_t_ESP_RST_TASK_WDT => "task_watchdog",
+        sys::esp_reset_reason_t_ESP_RST_WDT => "watchdog",
+        sys::esp_reset_reason_t_ESP_RST_DEEPSLEEP => "deep_sleep",
+        sys::esp_reset_reason_t_ESP_RST_BROWNOUT => "brownout",
+        sys::esp_reset_reason_t_ESP_RST_SDIO => "sdio",
+        _ => "unknown",
+    }
+}
+
+/// One FreeRTOS task
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub name: String,
+    /// "running", "ready", "blocked", "suspended" or "deleted"
+    pub state: &'static str,
+    pub priority: u32,
+    /// Least unused stack since the task started, in bytes; close to 0 means
+    /// the stack is about to overflow
+    pub stack_free_min: u32,
+    /// Core the task is pinned to, None if it runs on either
+    pub core: Option<i32>,
+}
+
+/// All FreeRTOS tasks, by name (needs `CONFIG_FREERTOS_USE_TRACE_FACILITY`)
+pub fn tasks() -> Vec<TaskInfo> {
+    // Room for tasks created between the count and the snapshot
+    let capacity = unsafe { sys::uxTaskGetNumberOfTasks() } + 4;
+    let mut statuses = Vec::with_capacity(capacity as usize);
+    let count = unsafe {
+        sys::uxTaskGetSystemState(statuses.as_mut_ptr(), capacity, core::ptr::null_mut())
+    };
+    // Safety: uxTaskGetSystemState initialized the first `count` entries
+    unsafe { statuses.set_len(count as usize) };
+
+    let mut tasks: Vec<TaskInfo> = statuses
+        .iter()
+        .map(|status: &sys::TaskStatus_t| TaskInfo {
+            name: unsafe { CStr::from_ptr(status.pcTaskName) }
+                .to_string_lossy()
+                .into_owned(),
+            state: match status.eCurrentState {
+                sys::eTaskState_eRunning => "running",
+                sys::eTaskState_eReady => "ready",
+                sys::eTaskState_eBlocked => "blocked",
+                sys::eTaskState_eSuspended => "suspended",
+                _ => "deleted",
+            },
+            priority: status.uxCurrentPriority,
+            stack_free_min: status.usStackHighWaterMark,
+            // tskNO_AFFINITY is 0x7FFFFFFF
+            core: (status.xCoreID >= 0 && status.xCoreID < 2).then_some(status.xCoreID),
+        })
+        .collect();
+    tasks.sort_by(|a, b| a.name.cmp(&b.name));
+    tasks
+}
@@ -0,0 +1,119 @@
+//! Wall-clock time via SNTP
+//!
+//! The ESP32 has no battery-backed clock, so system time starts at the epoch
+//! on every boot. `sync` sets it from pool.ntp.org once WiFi is up; the clock
+//! then keeps running until the next reboot. Until the first sync `now`
+//! returns None so readings are never stamped with a 1970 date.
+
+use anyhow::Result;
+use esp_idf_svc::sntp::{EspSntp, SyncStatus};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Resync after this long to correct RTC drift
+pub const RESYNC_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+/// Any earlier system time means the clock was never set (2024-01-01)
+const MIN_VALID_UNIX_SECS: u64 = 1_704_067_200;
+
+/// Unix time of the last successful sync (0 = never)
+static LAST_SYNC: AtomicU64 = AtomicU64::new(0);
+
+/// True if the clock was never synced or the last sync is older than `RESYNC_INTERVAL`
+pub fn needs_sync() -> bool {
+    match last_sync() {
+        Some(last) => SystemTime::now()
+            .duration_since(last)
+            .map(|age| age >= RESYNC_INTERVAL)
+            .unwrap_or(true),
+        None => true,
+    }
+}
+
+/// Run SNTP until the clock is set or `timeout` expires. Requires a network
+/// connection; returns whether the sync completed.
+pub fn sync(timeout: Duration) -> Result<bool> {
+    log::info!("🕐 Time: Syncing via SNTP...");
+    let sntp = EspSntp::new_default()?;
+    let started = Instant::now();
+
+    while sntp.get_sync_status() != SyncStatus::Completed {
+        if started.elapsed() >= timeout {
+            log::warn!("⚠️  Time: SNTP sync timed out after {:?}", timeout);
+            return Ok(false);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    let unix_secs = unix_secs(SystemTime::now());
+    LAST_SYNC.store(unix_secs, Ordering::Relaxed);
+    log::info!(
+        "✅ Time: Synced, now {}",
+        iso8601(SystemTime::now()).as_str()
+    );
+    Ok(true)
+}
+
+/// Current wall-clock time, or None until the clock has been set
+pub fn now() -> Option<SystemTime> {
+    let now = SystemTime::now();
+    if unix_secs(now) >= MIN_VALID_UNIX_SECS {
+        Some(now)
+    } else {
+        None
+    }
+}
+
+/// `now()` as seconds since the Unix epoch
+pub fn now_unix_secs() -> Option<u64> {
+    now().map(unix_secs)
+}
+
+/// `now()` as an ISO 8601 UTC timestamp
+pub fn now_iso8601() -> Option<String> {
+    now().map(iso8601)
+}
+
+/// Time of the last successful SNTP sync since boot
+pub fn last_sync() -> Option<SystemTime> {
+    match LAST_SYNC.load(Ordering::Relaxed) {
+        0 => None,
+        secs => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+    }
+}
+
+/// Format as `YYYY-MM-DDTHH:MM:SSZ`
+pub fn iso8601(time: SystemTime) -> String {
+    let secs = unix_secs(time);
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let time_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        (time_of_day % 3600) / 60,
+        time_of_day % 60
+    )
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Days since 1970-01-01 to a (year, month, day) date (Howard Hinnant's algorithm)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,105 @@
+//! Task watchdog for the main loop and the MTU thread
+//!
+//! Both tasks subscribe to the ESP-IDF task watchdog and feed it on every
+//! loop iteration. A task that stops feeding for `timeout_secs` (e.g. stuck
+//! on a deadlocked mutex) makes the watchdog reset the chip, or only print
+//! the stuck tasks and their backtraces with `WatchdogAction::Log`. The
+//! timeout must exceed the longest blocking step of the main loop (a full
+//! publish cycle, a WiFi connect).
+
+use anyhow::Result;
+use esp_idf_svc::sys;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Idle tasks of both cores stay watched, as in the ESP-IDF default
+const IDLE_CORE_MASK: u32 = 0b11;
+
+/// Set by `configure`; tasks only subscribe while it is set
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchdogAction {
+    /// Panic, which resets the chip (reset reason `task_watchdog`)
+    #[default]
+    Reset,
+    /// Print the stuck tasks and keep running
+    Log,
+}
+
+impl WatchdogAction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "reset" => Some(WatchdogAction::Reset),
+            "log" => Some(WatchdogAction::Log),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatchdogAction::Reset => "reset",
+            WatchdogAction::Log => "log",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchdogSettings {
+    /// Applied at boot; 0 = main loop and MTU thread not watched
+    pub timeout_secs: u32,
+    pub action: WatchdogAction,
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 120,
+            action: WatchdogAction::Reset,
+        }
+    }
+}
+
+/// Apply the timeout and action; call once at boot, before the tasks subscribe
+pub fn configure(settings: &WatchdogSettings) -> Result<()> {
+    if settings.timeout_secs == 0 {
+        log::info!("Watchdog: Main loop and MTU thread not watched (watchdog.timeout 0)");
+        return Ok(());
+    }
+    let config = sys::esp_task_wdt_config_t {
+        timeout_ms: settings.timeout_secs * 1000,
+        idle_core_mask: IDLE_CORE_MASK,
+        trigger_panic: settings.action == WatchdogAction::Reset,
+    };
+    sys::esp!(unsafe { sys::esp_task_wdt_reconfigure(&config) })?;
+    ENABLED.store(true, Ordering::Relaxed);
+    log::info!(
+        "✅ Watchdog: {}s timeout, action {}",
+        settings.timeout_secs,
+        settings.action.name()
+    );
+    Ok(())
+}
+
+/// Watch the calling task; it must call `feed` at least every timeout from
+/// now on. Returns false if the watchdog is not configured.
+pub fn subscribe_current_task() -> bool {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return false;
+    }
+    match sys::esp!(unsafe { sys::esp_task_wdt_add(core::ptr::null_mut()) }) {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("⚠️  Watchdog: Failed to watch task: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Reset the calling task's watchdog timer (no-op for unwatched tasks)
+pub fn feed() {
+    if ENABLED.load(Ordering::Relaxed) {
+        unsafe { sys::esp_task_wdt_reset() };
+    }
+}
@@ -0,0 +1,106 @@
+//! HTTP webhook uplink
+//!
+//! For users who run a simple REST collector instead of an MQTT broker: with
+//! `network.uplink = webhook` each reading is POSTed as the same JSON document
+//! that goes to the MQTT data topic. HTTPS URLs are verified against the
+//! ESP-IDF certificate bundle; `webhook.auth` is sent as the `Authorization`
+//! header (e.g. `Bearer <token>`).
+
+use anyhow::Result;
+use esp_idf_svc::http::client::{Configuration as HttpClientConfig, EspHttpConnection};
+use esp_idf_svc::http::Method;
+use esp_idf_svc::io::{Read, Write};
+use esp_idf_svc::sys;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Connect plus response timeout for one POST
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookSettings {
+    /// `http://` or `https://` collector URL (empty = not set)
+    pub url: heapless::String<128>,
+    /// `Authorization` header value (empty = none)
+    pub auth: heapless::String<128>,
+}
+
+/// `http://` or `https://` with a host
+pub fn is_valid_url(url: &str) -> bool {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"));
+    matches!(rest, Some(rest) if !rest.is_empty() && !rest.starts_with('/') && !rest.contains(' '))
+}
+
+pub struct Webhook {
+    url: String,
+    auth: Option<String>,
+}
+
+impl Webhook {
+    /// None when no URL is configured
+    pub fn from_settings(settings: &WebhookSettings) -> Option<Self> {
+        if settings.url.is_empty() {
+            return None;
+        }
+        Some(Self {
+            url: settings.url.to_string(),
+            auth: (!settings.auth.is_empty()).then(|| settings.auth.to_string()),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// POST `body` as JSON; any status outside 2xx is an error
+    pub fn post_json(&self, body: &[u8]) -> Result<u16> {
+        post(&self.url, "application/json", self.auth.as_deref(), body)
+    }
+}
+
+/// POST `body` to `url` (HTTPS verified against the certificate bundle) and
+/// return the status; any status outside 2xx is an error
+pub fn post(url: &str, content_type: &str, auth: Option<&str>, body: &[u8]) -> Result<u16> {
+    let mut connection = EspHttpConnection::new(&HttpClientConfig {
+        timeout: Some(REQUEST_TIMEOUT),
+        crt_bundle_attach: Some(sys::esp_crt_bundle_attach),
+        ..Default::default()
+    })?;
+
+    let content_length = body.len().to_string();
+    let mut headers = vec![
+        ("Content-Type", content_type),
+        ("Content-Length", content_length.as_str()),
+    ];
+    if let Some(auth) = auth {
+        headers.push(("Authorization", auth));
+    }
+
+    connection.initiate_request(Method::Post, url, &headers)?;
+    connection
+        .write_all(body)
+        .map_err(|e| anyhow::anyhow!("HTTP request failed: {:?}", e))?;
+    connection.initiate_response()?;
+    let status = connection.status();
+
+    // Drain the response body so the connection closes cleanly; kept for the
+    // error message (InfluxDB explains rejected lines there)
+    let mut response = Vec::new();
+    let mut buf = [0u8; 64];
+    while let Ok(n @ 1..) = connection.read(&mut buf) {
+        if response.len() < 256 {
+            response.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    if !(200..300).contains(&status) {
+        return Err(anyhow::anyhow!(
+            "HTTP {}: {}",
+            status,
+            String::from_utf8_lossy(&response).trim()
+        ));
+    }
+    Ok(status)
+}
@@ -0,0 +1,140 @@
+//! BLE provisioning over a custom GATT service
+//!
+//! Alternative to the captive portal for sealed enclosures: a phone (e.g.
+//! nRF Connect) writes a JSON document to the settings characteristic,
+//!
+//! ```text
+//! {"ssid":"MyNetwork","password":"secret","mqtt_broker":"mqtt://host:1883"}
+//! ```
+//!
+//! and reads or subscribes to the status characteristic for the result.
+//! Accepted settings are saved to NVS and the device reboots into station mode.
+//!
+//! The settings characteristic is only writable over an authenticated link:
+//! the phone pairs with the passkey `ble.passkey`, or with a random one
+//! printed on the console at boot when none is set.
+
+use super::provisioning::{schedule_restart, ProvisioningSettings};
+use crate::device;
+use anyhow::Result;
+use esp32_nimble::enums::{AuthReq, SecurityIOCap};
+use esp32_nimble::utilities::BleUuid;
+use esp32_nimble::{uuid128, BLEAdvertisementData, BLEDevice, NimbleProperties};
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_svc::sys;
+use log::info;
+
+const SERVICE_UUID: BleUuid = uuid128!("6e400001-7b3a-4c57-9a3e-5741544552ab");
+/// Write: JSON settings document
+const SETTINGS_UUID: BleUuid = uuid128!("6e400002-7b3a-4c57-9a3e-5741544552ab");
+/// Read/notify: "waiting", "ok: rebooting" or "error: <reason>"
+const STATUS_UUID: BleUuid = uuid128!("6e400003-7b3a-4c57-9a3e-5741544552ab");
+
+/// Longest settings document accepted in a single write
+const MAX_SETTINGS_LEN: usize = 512;
+
+/// Advertised name: the default device name, "WaterMeter-" and the last two
+/// bytes of the MAC address
+pub fn ble_device_name() -> heapless::String<32> {
+    let mut device_name = heapless::String::<32>::new();
+    let _ = device_name.push_str(&device::default_name());
+    device_name
+}
+
+/// Keeps the GATT service advertising while held
+pub struct BleProvisioning {
+    device_name: heapless::String<32>,
+}
+
+impl BleProvisioning {
+    /// `passkey`: 6-digit pairing passkey, None = random for this boot
+    pub fn start(nvs: EspDefaultNvsPartition, passkey: Option<u32>) -> Result<Self> {
+        let device_name = ble_device_name();
+
+        info!("📡 BLE: Starting provisioning service '{}'...", device_name);
+        let ble_device = BLEDevice::take();
+        BLEDevice::set_device_name(&device_name)
+            .map_err(|e| anyhow::anyhow!("BLE device name: {:?}", e))?;
+
+        // Passkey entry on the phone (we "display" it), so the pairing is
+        // authenticated (MITM protection) and the link encrypted
+        let passkey = passkey.unwrap_or_else(|| {
+            let passkey = unsafe { sys::esp_random() } % 1_000_000;
+            info!("🔑 BLE: Pairing passkey for this boot: {:06}", passkey);
+            passkey
+        });
+        ble_device
+            .security()
+            .set_auth(AuthReq::Bond | AuthReq::Mitm | AuthReq::Sc)
+            .set_passkey(passkey)
+            .set_io_cap(SecurityIOCap::DisplayOnly)
+            .resolve_rpa();
+
+        let server = ble_device.get_server();
+        server.advertise_on_disconnect(true);
+        server.on_connect(|_, desc| {
+            info!("📡 BLE: Client connected ({})", desc.address());
+        });
+
+        let service = server.create_service(SERVICE_UUID);
+        let status = service.lock().create_characteristic(
+            STATUS_UUID,
+            NimbleProperties::READ | NimbleProperties::NOTIFY,
+        );
+        status.lock().set_value(b"waiting");
+
+        let settings = service.lock().create_characteristic(
+            SETTINGS_UUID,
+            NimbleProperties::WRITE | NimbleProperties::WRITE_ENC | NimbleProperties::WRITE_AUTHEN,
+        );
+        settings.lock().on_write(move |args| {
+            let data = args.recv_data();
+            let reply = if data.len() > MAX_SETTINGS_LEN {
+                "error: settings too long".to_string()
+            } else {
+                match apply_settings(data, &nvs) {
+                    Ok(ssid) => {
+                        info!("✅ BLE: Saved settings for '{}', rebooting", ssid);
+                        schedule_restart();
+                        "ok: rebooting".to_string()
+                    }
+                    Err(e) => {
+                        log::warn!("⚠️  BLE: Rejected settings: {:?}", e);
+                        format!("error: {}", e)
+                    }
+                }
+            };
+            status.lock().set_value(reply.as_bytes()).notify();
+        });
+
+        ble_device
+            .get_advertising()
+            .lock()
+            .set_data(
+                BLEAdvertisementData::new()
+                    .name(&device_name)
+                    .add_service_uuid(SERVICE_UUID),
+            )
+            .map_err(|e| anyhow::anyhow!("BLE advertising data: {:?}", e))?;
+        ble_device
+            .get_advertising()
+            .lock()
+            .start()
+            .map_err(|e| anyhow::anyhow!("BLE advertising: {:?}", e))?;
+
+        info!("✅ BLE: Advertising as '{}'", device_name);
+        Ok(Self { device_name })
+    }
+
+    pub fn device_name(&self) -> &str {
+        self.device_name.as_str()
+    }
+}
+
+/// Parse and persist a settings document. Returns the saved SSID.
+fn apply_settings(data: &[u8], nvs: &EspDefaultNvsPartition) -> Result<String> {
+    let settings: ProvisioningSettings =
+        serde_json::from_slice(data).map_err(|e| anyhow::anyhow!("invalid JSON: {}", e))?;
+    settings.save(nvs)?;
+    Ok(settings.ssid)
+}
@@ -0,0 +1,167 @@
+//! Encrypted WiFi credential storage (the stored network list)
+//!
+//! Credentials are sealed with ChaCha20-Poly1305 under a key derived from the
+//! chip's factory MAC, so they never sit in NVS as clear text and a flash image
+//! copied to another board can't be decrypted there. Anyone with the original
+//! board can still derive the key - enable ESP-IDF flash/NVS encryption where
+//! that matters.
+//!
+//! `seal`/`unseal` apply the same scheme to other secrets (the MQTT client
+//! private key), each under its own key context.
+
+use crate::network_config::{WifiAuth, WifiConfig};
+use anyhow::Result;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// NVS namespace holding the encrypted credentials
+pub const WIFI_CREDS_NVS_NAMESPACE: &str = "wifi_creds";
+const KEY_CREDS: &str = "creds";
+/// PEM CA certificate for WPA2-Enterprise servers (public, not encrypted)
+const KEY_CA_CERT: &str = "ca_cert";
+
+/// Largest CA certificate accepted
+pub const MAX_CA_CERT_LEN: usize = 4096;
+
+const NONCE_LEN: usize = 12;
+const KEY_CONTEXT: &[u8] = b"esp32-water-meter/wifi-creds/v1";
+
+/// Single-network layout written by earlier firmware
+#[derive(Serialize, Deserialize)]
+struct LegacyCredentials {
+    ssid: heapless::String<32>,
+    password: heapless::String<64>,
+}
+
+pub struct WifiCredentialStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl WifiCredentialStore {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+        let nvs = EspNvs::new(partition, WIFI_CREDS_NVS_NAMESPACE, true)?;
+        Ok(Self { nvs })
+    }
+
+    /// Saved networks, or None if nothing was saved or it can't be decrypted
+    pub fn load(&self) -> Result<Option<WifiConfig>> {
+        // Sized from the stored blob: enterprise credentials can exceed a small fixed buffer
+        let mut buf = vec![0u8; self.nvs.blob_len(KEY_CREDS)?.unwrap_or(0)];
+        let blob = match self.nvs.get_blob(KEY_CREDS, &mut buf)? {
+            Some(blob) if blob.len() > NONCE_LEN => blob,
+            _ => return Ok(None),
+        };
+
+        let Some(plaintext) = unseal(KEY_CONTEXT, blob) else {
+            log::warn!("WiFi: Stored credentials failed to decrypt (different chip?)");
+            return Ok(None);
+        };
+
+        if let Ok(config) = serde_json::from_slice::<WifiConfig>(&plaintext) {
+            return Ok(Some(config));
+        }
+        match serde_json::from_slice::<LegacyCredentials>(&plaintext) {
+            Ok(legacy) => {
+                let mut config = WifiConfig::default();
+                let _ = config.add(&legacy.ssid, &legacy.password, 0, WifiAuth::Wpa2);
+                Ok(Some(config))
+            }
+            Err(e) => {
+                log::warn!("WiFi: Stored credentials are malformed: {:?}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn save(&mut self, config: &WifiConfig) -> Result<()> {
+        let plaintext = serde_json::to_vec(config)?;
+        self.nvs
+            .set_blob(KEY_CREDS, &seal(KEY_CONTEXT, &plaintext)?)?;
+
+        log::info!(
+            "WiFi: {} network(s) saved (encrypted)",
+            config.networks.len()
+        );
+        Ok(())
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        self.nvs.remove(KEY_CREDS)?;
+        Ok(())
+    }
+
+    /// CA certificate (PEM) used to verify WPA2-Enterprise servers
+    pub fn load_ca_cert(&self) -> Result<Option<Vec<u8>>> {
+        let len = match self.nvs.blob_len(KEY_CA_CERT)? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        let mut buf = vec![0u8; len];
+        Ok(self
+            .nvs
+            .get_blob(KEY_CA_CERT, &mut buf)?
+            .map(|pem| pem.to_vec()))
+    }
+
+    pub fn save_ca_cert(&mut self, pem: &[u8]) -> Result<()> {
+        if pem.len() > MAX_CA_CERT_LEN {
+            return Err(anyhow::anyhow!(
+                "CA certificate too large (max {} bytes)",
+                MAX_CA_CERT_LEN
+            ));
+        }
+        self.nvs.set_blob(KEY_CA_CERT, pem)?;
+        log::info!("WiFi: CA certificate saved ({} bytes)", pem.len());
+        Ok(())
+    }
+
+    pub fn clear_ca_cert(&mut self) -> Result<()> {
+        self.nvs.remove(KEY_CA_CERT)?;
+        Ok(())
+    }
+}
+
+/// Encrypt `plaintext` under the per-chip key for `context`; the blob is the
+/// random nonce followed by the ciphertext
+pub fn seal(context: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    unsafe {
+        sys::esp_fill_random(nonce.as_mut_ptr() as *mut core::ffi::c_void, NONCE_LEN);
+    }
+    let ciphertext = ChaCha20Poly1305::new(&device_key(context))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| anyhow::anyhow!("Credential encryption failed"))?;
+
+    let mut blob = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    blob.extend_from_slice(&nonce);
+    blob.extend_from_slice(&ciphertext);
+    Ok(blob)
+}
+
+/// Decrypt a `seal` blob; None if it is malformed, was sealed on another chip
+/// or under another context
+pub fn unseal(context: &[u8], blob: &[u8]) -> Option<Vec<u8>> {
+    if blob.len() <= NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = blob.split_at(NONCE_LEN);
+    ChaCha20Poly1305::new(&device_key(context))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .ok()
+}
+
+/// Per-chip key: SHA-256 over a context string and the factory MAC
+fn device_key(context: &[u8]) -> Key {
+    let mut mac = [0u8; 6];
+    unsafe {
+        sys::esp_efuse_mac_get_default(mac.as_mut_ptr());
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(context);
+    hasher.update(mac);
+    hasher.finalize()
+}
@@ -0,0 +1,708 @@
+use super::WifiCredentialStore;
+use crate::network_config::{
+    EnterpriseCredentials, StaticIpConfig, WifiAuth, WifiConfig, WifiNetwork,
+};
+use anyhow::Result;
+use esp_idf_hal::modem::Modem;
+use esp_idf_svc::eventloop::{EspSubscription, EspSystemEventLoop, System};
+use esp_idf_svc::ipv4;
+use esp_idf_svc::netif::{EspNetif, NetifConfiguration};
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_svc::sys;
+use esp_idf_svc::wifi::{
+    AuthMethod, BlockingWifi, ClientConfiguration, Configuration, EspWifi, WifiEvent,
+};
+use log::info;
+use serde::Serialize;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// First retry after an unexpected disconnect; doubles up to the maximum
+const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(60);
+
+/// How long a non-blocking attempt may take (association + DHCP) before
+/// moving to the next candidate
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A network found by `WifiManager::scan`
+#[derive(Debug, Clone)]
+pub struct ScannedNetwork {
+    pub ssid: heapless::String<32>,
+    pub rssi: i8,
+    pub channel: u8,
+    pub auth_method: Option<AuthMethod>,
+}
+
+impl ScannedNetwork {
+    pub fn auth_name(&self) -> &'static str {
+        match self.auth_method {
+            None | Some(AuthMethod::None) => "Open",
+            Some(AuthMethod::WEP) => "WEP",
+            Some(AuthMethod::WPA) => "WPA",
+            Some(AuthMethod::WPA2Personal) => "WPA2",
+            Some(AuthMethod::WPAWPA2Personal) => "WPA/WPA2",
+            Some(AuthMethod::WPA2Enterprise) => "WPA2-Ent",
+            Some(AuthMethod::WPA3Personal) => "WPA3",
+            Some(AuthMethod::WPA2WPA3Personal) => "WPA2/WPA3",
+            Some(_) => "Other",
+        }
+    }
+}
+
+/// Connection statistics since boot
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct LinkStats {
+    /// Connect attempts made by `reconnect`/`connect_start` (auto-reconnects excluded)
+    pub connect_attempts: u32,
+    /// Of those, attempts that did not end with an IP address
+    pub connect_failures: u32,
+    /// Association events reported by the driver
+    pub connects: u32,
+    pub disconnects: u32,
+    pub reconnect_attempts: u32,
+    /// Cumulative time associated with an access point
+    pub online_secs: u64,
+    /// 802.11 reason code of the most recent disconnect (see `disconnect_reason_name`)
+    pub last_disconnect_reason: Option<u16>,
+}
+
+/// State shared with the WiFi event handler
+#[derive(Default)]
+struct LinkEvents {
+    connects: AtomicU32,
+    disconnects: AtomicU32,
+    /// Reason code of the last disconnect, 0 = none yet
+    last_disconnect_reason: AtomicU32,
+    /// Uptime (ms) when the current association started, 0 = offline
+    online_since_ms: AtomicU64,
+    /// Completed associations, in ms
+    online_total_ms: AtomicU64,
+    /// Set while a connection made by `connect_to` should be kept up
+    keep_alive: AtomicBool,
+    /// The link dropped while `keep_alive` was set
+    lost: AtomicBool,
+}
+
+/// Reported by `WifiManager::connect_poll`
+#[derive(Debug, Clone)]
+pub enum ConnectProgress {
+    /// `failed` timed out, now trying `ssid`
+    Trying {
+        failed: heapless::String<32>,
+        ssid: heapless::String<32>,
+    },
+    Connected {
+        ssid: heapless::String<32>,
+        ip: Ipv4Addr,
+    },
+    Failed(String),
+}
+
+/// A connect started by `connect_start`
+struct PendingConnect {
+    candidates: Vec<WifiNetwork>,
+    index: usize,
+    started: Instant,
+}
+
+// SAFETY: WifiManager wraps ESP-IDF WiFi which is thread-safe
+unsafe impl Send for WifiManager {}
+unsafe impl Sync for WifiManager {}
+
+pub struct WifiManager {
+    wifi: Box<BlockingWifi<EspWifi<'static>>>,
+    networks: WifiConfig,
+    /// SSID of the network currently (or last) connected
+    active_ssid: Option<heapless::String<32>>,
+    /// Station interface was set up with a static IP (fixed until reboot)
+    static_ip: bool,
+    /// NUL-terminated PEM for WPA2-Enterprise; the EAP client keeps a pointer to it
+    ca_cert: Option<Vec<u8>>,
+    link: Arc<LinkEvents>,
+    _wifi_events: EspSubscription<'static, System>,
+    reconnect_attempts: u32,
+    connect_attempts: u32,
+    connect_failures: u32,
+    reconnect_delay: Duration,
+    next_reconnect: Instant,
+    pending: Option<PendingConnect>,
+}
+
+impl WifiManager {
+    /// Start WiFi in station mode without connecting; use `reconnect` or
+    /// `connect_start` to join a network saved with `wifi_save`
+    pub fn new(
+        modem: Modem,
+        sysloop: EspSystemEventLoop,
+        nvs: EspDefaultNvsPartition,
+    ) -> Result<Self> {
+        let store = WifiCredentialStore::new(nvs.clone())?;
+        let networks = store.load()?.unwrap_or_default();
+        let ca_cert = store.load_ca_cert()?.map(nul_terminated);
+        info!("🔐 WiFi: {} saved network(s)", networks.networks.len());
+
+        info!("🌐 WiFi: Creating EspWifi instance...");
+        let mut esp_wifi = EspWifi::new(modem, sysloop.clone(), Some(nvs))?;
+        info!("✅ WiFi: EspWifi created");
+
+        if let Some(ref static_ip) = networks.static_ip {
+            info!(
+                "🌐 WiFi: Using static IP {}/{} (gateway {})",
+                static_ip.ip, static_ip.prefix_len, static_ip.gateway
+            );
+            esp_wifi.swap_netif_sta(static_netif(static_ip)?)?;
+        }
+
+        // Count link events and flag drops of a connection we want kept up
+        let link = Arc::new(LinkEvents::default());
+        let events = Arc::clone(&link);
+        let wifi_events = sysloop.subscribe::<WifiEvent, _>(move |event| match event {
+            WifiEvent::StaConnected(_) => {
+                events.connects.fetch_add(1, Ordering::Relaxed);
+                events.online_since_ms.store(uptime_ms(), Ordering::Relaxed);
+            }
+            WifiEvent::StaDisconnected(disconnected) => {
+                events.disconnects.fetch_add(1, Ordering::Relaxed);
+                events
+                    .last_disconnect_reason
+                    .store(disconnected.reason() as u32, Ordering::Relaxed);
+                let since = events.online_since_ms.swap(0, Ordering::Relaxed);
+                if since != 0 {
+                    events
+                        .online_total_ms
+                        .fetch_add(uptime_ms().saturating_sub(since), Ordering::Relaxed);
+                }
+                if events.keep_alive.load(Ordering::Relaxed) {
+                    events.lost.store(true, Ordering::Relaxed);
+                }
+            }
+            _ => {}
+        })?;
+
+        info!("🌐 WiFi: Wrapping in BlockingWifi...");
+        let mut wifi = BlockingWifi::wrap(esp_wifi, sysloop)?;
+        info!("✅ WiFi: Wrapped");
+
+        // A client configuration is required before starting in station mode
+        wifi.set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
+
+        info!("🌐 WiFi: Starting...");
+        wifi.start()?;
+        info!("✅ WiFi: Started");
+
+        Ok(Self {
+            wifi: Box::new(wifi),
+            static_ip: networks.static_ip.is_some(),
+            networks,
+            active_ssid: None,
+            ca_cert,
+            link,
+            _wifi_events: wifi_events,
+            reconnect_attempts: 0,
+            connect_attempts: 0,
+            connect_failures: 0,
+            reconnect_delay: RECONNECT_DELAY_MIN,
+            next_reconnect: Instant::now(),
+            pending: None,
+        })
+    }
+
+    /// Connect to the given network, or with `ssid == None` try the stored
+    /// networks in priority order until one connects. Blocks until the link
+    /// is up; see `connect_start` for the non-blocking variant.
+    pub fn reconnect(&mut self, ssid: Option<&str>, password: Option<&str>) -> Result<()> {
+        info!("WiFi reconnect requested");
+        self.pending = None;
+
+        let candidates = self.candidates(ssid, password)?;
+        for network in candidates.iter() {
+            match self.connect_to(network) {
+                Ok(_) => return Ok(()),
+                Err(e) => {
+                    log::warn!("⚠️  WiFi: '{}' failed: {:?}", network.ssid, e);
+                }
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "None of the {} candidate networks could be connected",
+            candidates.len()
+        ))
+    }
+
+    /// Begin connecting without waiting for the result; drive it with
+    /// `connect_poll`. Arguments are as for `reconnect`.
+    pub fn connect_start(&mut self, ssid: Option<&str>, password: Option<&str>) -> Result<()> {
+        let candidates = self.candidates(ssid, password)?;
+        self.pending = None;
+        self.start_attempt(candidates, 0)
+    }
+
+    /// Advance a connect begun with `connect_start`. Returns progress to report,
+    /// or None while nothing changed (or nothing is pending).
+    pub fn connect_poll(&mut self) -> Option<ConnectProgress> {
+        let pending = self.pending.as_ref()?;
+
+        if self.wifi.is_connected().unwrap_or(false)
+            && self.wifi.wifi().sta_netif().is_up().unwrap_or(false)
+        {
+            let network = pending.candidates[pending.index].clone();
+            self.pending = None;
+            let ip = self.get_ip().unwrap_or(Ipv4Addr::UNSPECIFIED);
+            info!(
+                "✅ WiFi: Connected to '{}', IP address: {}",
+                network.ssid, ip
+            );
+            self.connected(network.ssid.clone());
+            return Some(ConnectProgress::Connected {
+                ssid: network.ssid,
+                ip,
+            });
+        }
+
+        if pending.started.elapsed() < CONNECT_TIMEOUT {
+            return None;
+        }
+
+        let failed = pending.candidates[pending.index].ssid.clone();
+        log::warn!("⚠️  WiFi: '{}' timed out", failed);
+        self.connect_failures += 1;
+        let pending = self.pending.take()?;
+        let next = pending.index + 1;
+        if next >= pending.candidates.len() {
+            return Some(ConnectProgress::Failed(format!(
+                "None of the {} candidate networks could be connected",
+                pending.candidates.len()
+            )));
+        }
+
+        let ssid = pending.candidates[next].ssid.clone();
+        match self.start_attempt(pending.candidates, next) {
+            Ok(_) => Some(ConnectProgress::Trying { failed, ssid }),
+            Err(e) => Some(ConnectProgress::Failed(format!("{:?}", e))),
+        }
+    }
+
+    /// True while a `connect_start` connect is in progress
+    pub fn is_connecting(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Networks to try in order: the given one (with the stored password,
+    /// auth and enterprise login as fallback), or all stored ones by priority
+    fn candidates(&self, ssid: Option<&str>, password: Option<&str>) -> Result<Vec<WifiNetwork>> {
+        let ssid = match ssid {
+            Some(ssid) => ssid,
+            None if self.networks.is_empty() => {
+                return Err(anyhow::anyhow!("No WiFi networks configured"))
+            }
+            None => return Ok(self.networks.by_priority().into_iter().cloned().collect()),
+        };
+
+        let stored = self.networks.networks.iter().find(|n| n.ssid == ssid);
+        let mut network = match stored {
+            Some(network) => network.clone(),
+            None => {
+                let password = password.unwrap_or("");
+                let auth = if password.is_empty() {
+                    WifiAuth::Open
+                } else {
+                    WifiAuth::Wpa2
+                };
+                let mut manual = WifiConfig::default();
+                manual
+                    .add(ssid, password, 0, auth)
+                    .map_err(|e| anyhow::anyhow!(e))?;
+                manual.networks[0].clone()
+            }
+        };
+        if let Some(password) = password {
+            network.password.clear();
+            network
+                .password
+                .push_str(password)
+                .map_err(|_| anyhow::anyhow!("Password too long"))?;
+        }
+        Ok(vec![network])
+    }
+
+    fn start_attempt(&mut self, candidates: Vec<WifiNetwork>, index: usize) -> Result<()> {
+        self.connect_attempts += 1;
+        if let Err(e) = self.configure(&candidates[index]) {
+            self.connect_failures += 1;
+            return Err(e);
+        }
+        info!(
+            "🌐 WiFi: Connecting to '{}' ({}, non-blocking)...",
+            candidates[index].ssid,
+            candidates[index].auth.name()
+        );
+        if let Err(e) = self.wifi.wifi_mut().connect() {
+            self.connect_failures += 1;
+            return Err(e.into());
+        }
+        self.pending = Some(PendingConnect {
+            candidates,
+            index,
+            started: Instant::now(),
+        });
+        Ok(())
+    }
+
+    fn connect_to(&mut self, network: &WifiNetwork) -> Result<()> {
+        self.connect_attempts += 1;
+        let result = self.connect_blocking(network);
+        if result.is_err() {
+            self.connect_failures += 1;
+        }
+        result
+    }
+
+    fn connect_blocking(&mut self, network: &WifiNetwork) -> Result<()> {
+        self.configure(network)?;
+
+        info!(
+            "🌐 WiFi: Connecting to '{}' ({})...",
+            network.ssid,
+            network.auth.name()
+        );
+        self.wifi.connect()?;
+        info!("✅ WiFi: Connected");
+
+        self.wifi.wait_netif_up()?;
+
+        let ip_info = self.wifi.wifi().sta_netif().get_ip_info()?;
+        info!("📡 WiFi: IP info: {:?}", ip_info);
+        info!("🌐 WiFi: IP address: {}", ip_info.ip);
+
+        self.connected(network.ssid.clone());
+        Ok(())
+    }
+
+    /// Leave the current network and load the station configuration for `network`
+    fn configure(&mut self, network: &WifiNetwork) -> Result<()> {
+        // Open networks must not be given a password; enterprise networks
+        // pass theirs to the EAP client instead
+        let password = match network.auth {
+            WifiAuth::Open | WifiAuth::Wpa2Enterprise => heapless::String::new(),
+            _ => network.password.clone(),
+        };
+
+        let auth_method = match network.auth {
+            WifiAuth::Open => AuthMethod::None,
+            WifiAuth::Wpa2 => AuthMethod::WPA2Personal,
+            WifiAuth::Wpa3 => AuthMethod::WPA3Personal,
+            WifiAuth::Wpa2Enterprise => AuthMethod::WPA2Enterprise,
+        };
+
+        let wifi_configuration = Configuration::Client(ClientConfiguration {
+            ssid: network.ssid.clone(),
+            auth_method,
+            password,
+            ..Default::default()
+        });
+
+        // Drops from here on are intentional until the new link is up
+        self.link.keep_alive.store(false, Ordering::Relaxed);
+        self.link.lost.store(false, Ordering::Relaxed);
+
+        // Disconnect if currently connected
+        if self.wifi.is_connected().unwrap_or(false) {
+            info!("Disconnecting from current network...");
+            let _ = self.wifi.disconnect();
+        }
+
+        self.wifi.set_configuration(&wifi_configuration)?;
+        match (network.auth, network.enterprise.as_ref()) {
+            (WifiAuth::Wpa2Enterprise, Some(credentials)) => {
+                self.enable_enterprise(credentials, &network.password)?
+            }
+            (WifiAuth::Wpa2Enterprise, None) => {
+                return Err(anyhow::anyhow!(
+                    "No enterprise credentials for '{}'",
+                    network.ssid
+                ))
+            }
+            _ => sys::esp!(unsafe { sys::esp_wifi_sta_enterprise_disable() })?,
+        }
+        Ok(())
+    }
+
+    /// Bookkeeping once a link is up
+    fn connected(&mut self, ssid: heapless::String<32>) {
+        self.active_ssid = Some(ssid);
+        self.reconnect_delay = RECONNECT_DELAY_MIN;
+        self.link.keep_alive.store(true, Ordering::Relaxed);
+    }
+
+    /// Retry a dropped link with exponential backoff. Call regularly from the
+    /// main loop; intentional disconnects are not retried.
+    pub fn maintain(&mut self) {
+        if !self.link.lost.load(Ordering::Relaxed) {
+            return;
+        }
+
+        if self.wifi.is_connected().unwrap_or(false) {
+            info!("✅ WiFi: Link restored");
+            self.link.lost.store(false, Ordering::Relaxed);
+            self.reconnect_delay = RECONNECT_DELAY_MIN;
+            return;
+        }
+
+        let now = Instant::now();
+        if now < self.next_reconnect {
+            return;
+        }
+
+        info!(
+            "🔄 WiFi: Link lost, reconnecting (next retry in {}s)",
+            self.reconnect_delay.as_secs()
+        );
+        // Non-blocking: the event handler reports the outcome
+        if let Err(e) = self.wifi.wifi_mut().connect() {
+            log::warn!("⚠️  WiFi: Reconnect attempt failed: {:?}", e);
+        }
+        self.reconnect_attempts += 1;
+        self.next_reconnect = now + self.reconnect_delay;
+        self.reconnect_delay = (self.reconnect_delay * 2).min(RECONNECT_DELAY_MAX);
+    }
+
+    pub fn link_stats(&self) -> LinkStats {
+        let mut online_ms = self.link.online_total_ms.load(Ordering::Relaxed);
+        let since = self.link.online_since_ms.load(Ordering::Relaxed);
+        if since != 0 {
+            online_ms += uptime_ms().saturating_sub(since);
+        }
+        let reason = self.link.last_disconnect_reason.load(Ordering::Relaxed);
+
+        LinkStats {
+            connect_attempts: self.connect_attempts,
+            connect_failures: self.connect_failures,
+            connects: self.link.connects.load(Ordering::Relaxed),
+            disconnects: self.link.disconnects.load(Ordering::Relaxed),
+            reconnect_attempts: self.reconnect_attempts,
+            online_secs: online_ms / 1000,
+            last_disconnect_reason: if reason == 0 {
+                None
+            } else {
+                Some(reason as u16)
+            },
+        }
+    }
+
+    /// Hand the 802.1X login to the EAP client (PEAP/TTLS are negotiated with the server)
+    fn enable_enterprise(&self, credentials: &EnterpriseCredentials, password: &str) -> Result<()> {
+        unsafe {
+            sys::esp!(sys::esp_eap_client_set_identity(
+                credentials.identity.as_ptr(),
+                credentials.identity.len() as i32
+            ))?;
+            sys::esp!(sys::esp_eap_client_set_username(
+                credentials.username.as_ptr(),
+                credentials.username.len() as i32
+            ))?;
+            sys::esp!(sys::esp_eap_client_set_password(
+                password.as_ptr(),
+                password.len() as i32
+            ))?;
+            match self.ca_cert {
+                Some(ref pem) => {
+                    sys::esp!(sys::esp_eap_client_set_ca_cert(
+                        pem.as_ptr(),
+                        pem.len() as i32
+                    ))?;
+                }
+                None => {
+                    sys::esp_eap_client_clear_ca_cert();
+                    log::warn!("⚠️  WiFi: No CA certificate - server identity is not verified");
+                }
+            }
+            sys::esp!(sys::esp_wifi_sta_enterprise_enable())?;
+        }
+        Ok(())
+    }
+
+    /// Replace the CA certificate used for WPA2-Enterprise (next connect)
+    pub fn set_ca_cert(&mut self, pem: Option<Vec<u8>>) {
+        unsafe {
+            sys::esp_eap_client_clear_ca_cert();
+        }
+        self.ca_cert = pem.map(nul_terminated);
+    }
+
+    pub fn has_ca_cert(&self) -> bool {
+        self.ca_cert.is_some()
+    }
+
+    /// Replace the stored networks used by `reconnect(None, None)`.
+    /// A changed static IP setting only takes effect after a reboot.
+    pub fn set_networks(&mut self, networks: WifiConfig) {
+        self.networks = networks;
+    }
+
+    pub fn networks(&self) -> &WifiConfig {
+        &self.networks
+    }
+
+    /// The stored profile in use, if the active network is one of them
+    pub fn active_profile(&self) -> Option<&WifiNetwork> {
+        let active = self.active_ssid.as_ref()?;
+        self.networks.networks.iter().find(|n| n.ssid == *active)
+    }
+
+    /// Scan for visible networks, strongest first
+    pub fn scan(&mut self) -> Result<Vec<ScannedNetwork>> {
+        info!("🔍 WiFi: Scanning...");
+        let mut networks: Vec<ScannedNetwork> = self
+            .wifi
+            .scan()?
+            .into_iter()
+            .map(|ap| ScannedNetwork {
+                ssid: ap.ssid,
+                rssi: ap.signal_strength,
+                channel: ap.channel,
+                auth_method: ap.auth_method,
+            })
+            .collect();
+        networks.sort_by_key(|network| core::cmp::Reverse(network.rssi));
+        info!("✅ WiFi: Found {} networks", networks.len());
+        Ok(networks)
+    }
+
+    pub fn is_connected(&self) -> Result<bool> {
+        Ok(self.wifi.is_connected()?)
+    }
+
+    pub fn is_static_ip(&self) -> bool {
+        self.static_ip
+    }
+
+    pub fn get_ip(&self) -> Result<Ipv4Addr> {
+        let ip_info = self.wifi.wifi().sta_netif().get_ip_info()?;
+        Ok(ip_info.ip)
+    }
+
+    /// Signal strength of the connected access point in dBm
+    pub fn get_rssi(&self) -> Result<i8> {
+        Ok(ap_info()?.rssi)
+    }
+
+    /// Primary channel of the connected access point
+    pub fn get_channel(&self) -> Result<u8> {
+        Ok(ap_info()?.primary)
+    }
+
+    pub fn get_ssid(&self) -> Result<heapless::String<32>> {
+        if let Configuration::Client(config) = self.wifi.get_configuration()? {
+            Ok(config.ssid)
+        } else {
+            Ok(heapless::String::new())
+        }
+    }
+
+    /// Station interface MAC address, formatted `aa:bb:cc:dd:ee:ff`
+    pub fn get_mac(&self) -> Result<String> {
+        let mac = self.wifi.wifi().sta_netif().get_mac()?;
+        Ok(format!(
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+        ))
+    }
+
+    /// DHCP hostname of the station interface
+    pub fn get_hostname(&self) -> Result<String> {
+        let hostname = self.wifi.wifi().sta_netif().get_hostname()?;
+        Ok(hostname.as_str().to_string())
+    }
+
+    /// Set the DHCP hostname (takes effect on the next connect)
+    pub fn set_hostname(&mut self, hostname: &str) -> Result<()> {
+        self.wifi
+            .wifi_mut()
+            .sta_netif_mut()
+            .set_hostname(hostname)?;
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self) -> Result<()> {
+        self.link.keep_alive.store(false, Ordering::Relaxed);
+        self.link.lost.store(false, Ordering::Relaxed);
+        if self.wifi.is_connected().unwrap_or(false) {
+            info!("🔌 WiFi: Disconnecting...");
+            self.wifi.disconnect()?;
+            info!("✅ WiFi: Disconnected");
+        }
+        Ok(())
+    }
+}
+
+/// Station interface with fixed IPv4 settings instead of a DHCP client
+fn static_netif(static_ip: &StaticIpConfig) -> Result<EspNetif> {
+    let conf = NetifConfiguration {
+        ip_configuration: Some(ipv4::Configuration::Client(
+            ipv4::ClientConfiguration::Fixed(ipv4::ClientSettings {
+                ip: static_ip.ip,
+                subnet: ipv4::Subnet {
+                    gateway: static_ip.gateway,
+                    mask: ipv4::Mask(static_ip.prefix_len),
+                },
+                dns: static_ip.dns,
+                secondary_dns: None,
+            }),
+        )),
+        ..NetifConfiguration::wifi_default_client()
+    };
+    Ok(EspNetif::new_with_conf(&conf)?)
+}
+
+/// Name of an ESP-IDF `wifi_err_reason_t` disconnect code
+pub fn disconnect_reason_name(reason: u16) -> &'static str {
+    match reason {
+        1 => "UNSPECIFIED",
+        2 => "AUTH_EXPIRE",
+        3 => "AUTH_LEAVE",
+        4 => "ASSOC_EXPIRE",
+        8 => "ASSOC_LEAVE",
+        15 => "4WAY_HANDSHAKE_TIMEOUT",
+        200 => "BEACON_TIMEOUT",
+        201 => "NO_AP_FOUND",
+        202 => "AUTH_FAIL",
+        203 => "ASSOC_FAIL",
+        204 => "HANDSHAKE_TIMEOUT",
+        205 => "CONNECTION_FAIL",
+        _ => "OTHER",
+    }
+}
+
+/// Milliseconds since boot
+fn uptime_ms() -> u64 {
+    (unsafe { sys::esp_timer_get_time() } / 1000) as u64
+}
+
+/// Rough link-quality label for an RSSI reading
+pub fn rssi_quality(rssi: i8) -> &'static str {
+    match rssi {
+        -50..=0 => "excellent",
+        -60..=-51 => "good",
+        -70..=-61 => "fair",
+        _ => "weak",
+    }
+}
+
+/// Record of the access point the station is associated with
+fn ap_info() -> Result<sys::wifi_ap_record_t> {
+    let mut info = sys::wifi_ap_record_t::default();
+    sys::esp!(unsafe { sys::esp_wifi_sta_get_ap_info(&mut info) })?;
+    Ok(info)
+}
+
+/// mbedTLS expects PEM input to include the terminating NUL in its length
+fn nul_terminated(mut pem: Vec<u8>) -> Vec<u8> {
+    if pem.last() != Some(&0) {
+        pem.push(0);
+    }
+    pem
+}
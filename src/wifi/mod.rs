@@ -0,0 +1,11 @@
+pub mod ble_provisioning;
+pub mod credentials;
+pub mod manager;
+pub mod provisioning;
+
+pub use ble_provisioning::{ble_device_name, BleProvisioning};
+pub use credentials::WifiCredentialStore;
+pub use manager::{
+    disconnect_reason_name, rssi_quality, ConnectProgress, LinkStats, ScannedNetwork, WifiManager,
+};
+pub use provisioning::{ProvisioningPortal, ProvisioningSettings};
@@ -0,0 +1,309 @@
+//! SoftAP provisioning with a captive portal
+//!
+//! Used when no WiFi network has been saved: the device opens an access point
+//! named `WaterMeter-XXXX`, answers every DNS query with its own address so
+//! phones pop up the setup page, and serves a form for WiFi/MQTT settings.
+//! Submitting the form saves to NVS and reboots into station mode.
+
+use super::WifiCredentialStore;
+use crate::config_store::ConfigStore;
+use crate::device;
+use crate::network_config::WifiAuth;
+use anyhow::Result;
+use esp_idf_hal::modem::Modem;
+use esp_idf_svc::eventloop::EspSystemEventLoop;
+use esp_idf_svc::http::server::{Configuration as HttpConfig, EspHttpServer};
+use esp_idf_svc::http::Method;
+use esp_idf_svc::io::{Read, Write};
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_svc::wifi::{
+    AccessPointConfiguration, AuthMethod, BlockingWifi, Configuration, EspWifi,
+};
+use log::info;
+use serde::Deserialize;
+use std::net::{Ipv4Addr, UdpSocket};
+
+const MAX_FORM_LEN: usize = 1024;
+
+const FORM_HTML: &str = r#"<!DOCTYPE html>
+<html><head><meta name="viewport" content="width=device-width,initial-scale=1">
+<title>Water Meter Setup</title></head>
+<body style="font-family:sans-serif;max-width:24em;margin:auto">
+<h2>Water Meter Setup</h2>
+<form method="post" action="/save">
+<h3>WiFi</h3>
+<p>SSID<br><input name="ssid" maxlength="32" required></p>
+<p>Password<br><input name="password" type="password" maxlength="64"></p>
+<h3>MQTT (optional)</h3>
+<p>Broker URL<br><input name="mqtt_broker" placeholder="mqtt://host:1883" maxlength="128"></p>
+<p>Username<br><input name="mqtt_username" maxlength="32"></p>
+<p>Password<br><input name="mqtt_password" type="password" maxlength="64"></p>
+<p><input type="submit" value="Save and reboot"></p>
+</form></body></html>"#;
+
+/// Keeps the access point, HTTP server and DNS responder alive while held
+pub struct ProvisioningPortal {
+    _wifi: Box<BlockingWifi<EspWifi<'static>>>,
+    _server: EspHttpServer<'static>,
+    ap_ssid: heapless::String<32>,
+    ap_ip: Ipv4Addr,
+}
+
+impl ProvisioningPortal {
+    pub fn start(
+        modem: Modem,
+        sysloop: EspSystemEventLoop,
+        nvs: EspDefaultNvsPartition,
+    ) -> Result<Self> {
+        let mut ap_ssid = heapless::String::<32>::new();
+        let _ = ap_ssid.push_str(&device::default_name());
+
+        info!("📶 Provisioning: Starting access point '{}'...", ap_ssid);
+        let esp_wifi = EspWifi::new(modem, sysloop.clone(), Some(nvs.clone()))?;
+        let mut wifi = BlockingWifi::wrap(esp_wifi, sysloop)?;
+        wifi.set_configuration(&Configuration::AccessPoint(AccessPointConfiguration {
+            ssid: ap_ssid.clone(),
+            auth_method: AuthMethod::None,
+            channel: 1,
+            ..Default::default()
+        }))?;
+        wifi.start()?;
+        wifi.wait_netif_up()?;
+
+        let ap_ip = wifi.wifi().ap_netif().get_ip_info()?.ip;
+        info!("✅ Provisioning: Access point up at {}", ap_ip);
+
+        let mut server = EspHttpServer::new(&HttpConfig {
+            uri_match_wildcard: true,
+            ..Default::default()
+        })?;
+
+        server.fn_handler::<anyhow::Error, _>("/save", Method::Post, move |mut req| {
+            let len = (req.content_len().unwrap_or(0) as usize).min(MAX_FORM_LEN);
+            let mut body = vec![0u8; len];
+            req.read_exact(&mut body)
+                .map_err(|e| anyhow::anyhow!("Failed to read form: {:?}", e))?;
+            let form = String::from_utf8_lossy(&body);
+
+            let settings = parse_form(&form);
+            let page = match settings.save(&nvs) {
+                Ok(_) => {
+                    info!(
+                        "✅ Provisioning: Saved settings for '{}', rebooting",
+                        settings.ssid
+                    );
+                    schedule_restart();
+                    format!(
+                        "<html><body><h2>Saved</h2><p>Rebooting to connect to {}...</p></body></html>",
+                        html_escape(&settings.ssid)
+                    )
+                }
+                Err(e) => {
+                    log::warn!("⚠️  Provisioning: Rejected form: {:?}", e);
+                    format!(
+                        "<html><body><h2>Error</h2><p>{}</p><a href=\"/\">Back</a></body></html>",
+                        html_escape(&e.to_string())
+                    )
+                }
+            };
+            req.into_ok_response()?.write_all(page.as_bytes())?;
+            Ok(())
+        })?;
+
+        // Everything else (including OS captive-portal probes) gets the form
+        server.fn_handler::<anyhow::Error, _>("/*", Method::Get, |req| {
+            req.into_ok_response()?.write_all(FORM_HTML.as_bytes())?;
+            Ok(())
+        })?;
+
+        spawn_dns_responder(ap_ip)?;
+
+        Ok(Self {
+            _wifi: Box::new(wifi),
+            _server: server,
+            ap_ssid,
+            ap_ip,
+        })
+    }
+
+    pub fn ap_ssid(&self) -> &str {
+        self.ap_ssid.as_str()
+    }
+
+    pub fn ap_ip(&self) -> Ipv4Addr {
+        self.ap_ip
+    }
+}
+
+/// Settings accepted by the provisioning channels (captive portal, BLE)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProvisioningSettings {
+    pub ssid: String,
+    pub password: String,
+    /// Empty keeps the current broker
+    pub mqtt_broker: String,
+    /// Empty clears the stored username
+    pub mqtt_username: String,
+    /// Empty clears the stored password
+    pub mqtt_password: String,
+}
+
+impl ProvisioningSettings {
+    /// Validate and persist: the network is added to the encrypted WiFi list,
+    /// MQTT settings go to the config store
+    pub fn save(&self, nvs: &EspDefaultNvsPartition) -> Result<()> {
+        if self.ssid.is_empty() {
+            return Err(anyhow::anyhow!("SSID is required"));
+        }
+
+        let mut credentials = WifiCredentialStore::new(nvs.clone())?;
+        let mut networks = credentials.load()?.unwrap_or_default();
+        // Open networks are detected by the missing password
+        let auth = if self.password.is_empty() {
+            WifiAuth::Open
+        } else {
+            WifiAuth::Wpa2
+        };
+        networks
+            .add(&self.ssid, &self.password, 0, auth)
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        let mut config_store = ConfigStore::new(nvs.clone())?;
+        let mut config = config_store.load()?.unwrap_or_default();
+        if !self.mqtt_broker.is_empty() {
+            config
+                .set("mqtt.broker", &self.mqtt_broker)
+                .map_err(|e| anyhow::anyhow!("mqtt.broker: {}", e))?;
+        }
+        config
+            .set("mqtt.username", &self.mqtt_username)
+            .map_err(|e| anyhow::anyhow!("mqtt.username: {}", e))?;
+        config
+            .set("mqtt.password", &self.mqtt_password)
+            .map_err(|e| anyhow::anyhow!("mqtt.password: {}", e))?;
+
+        credentials.save(&networks)?;
+        config_store.save(&config)?;
+        Ok(())
+    }
+}
+
+/// Reboot shortly after provisioning so the reply can reach the client first
+pub(crate) fn schedule_restart() {
+    std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_secs(2));
+        unsafe {
+            esp_idf_svc::sys::esp_restart();
+        }
+    });
+}
+
+fn parse_form(form: &str) -> ProvisioningSettings {
+    let field = |name: &str| form_value(form, name).unwrap_or_default();
+    ProvisioningSettings {
+        ssid: field("ssid"),
+        password: field("password"),
+        mqtt_broker: field("mqtt_broker"),
+        mqtt_username: field("mqtt_username"),
+        mqtt_password: field("mqtt_password"),
+    }
+}
+
+/// Look up a field in an `application/x-www-form-urlencoded` body
+fn form_value(form: &str, name: &str) -> Option<String> {
+    form.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if url_decode(key) == name {
+            Some(url_decode(value))
+        } else {
+            None
+        }
+    })
+}
+
+fn url_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = |b: u8| (b as char).to_digit(16);
+                match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        out.push((high * 16 + low) as u8);
+                        i += 2;
+                    }
+                    _ => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Answer every DNS A query with the access point's address
+fn spawn_dns_responder(ap_ip: Ipv4Addr) -> Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:53")?;
+    std::thread::Builder::new()
+        .name("captive_dns".into())
+        .stack_size(4096)
+        .spawn(move || {
+            let mut buf = [0u8; 512];
+            loop {
+                let (len, src) = match socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(_) => continue,
+                };
+                if let Some(reply) = dns_reply(&buf[..len], ap_ip) {
+                    let _ = socket.send_to(&reply, src);
+                }
+            }
+        })?;
+    Ok(())
+}
+
+fn dns_reply(query: &[u8], ip: Ipv4Addr) -> Option<Vec<u8>> {
+    // Header (12 bytes) followed by a single question: QNAME, QTYPE, QCLASS
+    if query.len() < 12 || query[4..6] != [0, 1] {
+        return None;
+    }
+    let mut end = 12;
+    while end < query.len() && query[end] != 0 {
+        end += query[end] as usize + 1;
+    }
+    let question_end = end + 5; // terminating zero + QTYPE + QCLASS
+    if question_end > query.len() {
+        return None;
+    }
+
+    // Only A queries get the portal address; anything else (AAAA, HTTPS, ...)
+    // gets NOERROR with no answer so clients fall back to IPv4
+    let is_a = query[question_end - 4..question_end - 2] == [0, 1];
+
+    let mut reply = Vec::with_capacity(question_end + 16);
+    reply.extend_from_slice(&query[0..2]); // ID
+    reply.extend_from_slice(&[0x81, 0x80]); // Standard response, recursion available
+    reply.extend_from_slice(&[0, 1, 0, is_a as u8, 0, 0, 0, 0]); // 1 question, 0 or 1 answer
+    reply.extend_from_slice(&query[12..question_end]);
+    if !is_a {
+        return Some(reply);
+    }
+    reply.extend_from_slice(&[0xC0, 0x0C]); // Name: pointer to the question
+    reply.extend_from_slice(&[0, 1, 0, 1]); // Type A, class IN
+    reply.extend_from_slice(&60u32.to_be_bytes()); // TTL
+    reply.extend_from_slice(&[0, 4]);
+    reply.extend_from_slice(&ip.octets());
+    Some(reply)
+}